@@ -0,0 +1 @@
+-- The legacy local_endpoint_url key is not restored; local_model_url keeps the value
@@ -0,0 +1,9 @@
+-- Older builds stored the local model server under local_endpoint_url.
+-- Copy it to the canonical local_model_url key unless that already has a value.
+INSERT INTO settings (setting_key, setting_value)
+SELECT 'local_model_url', setting_value FROM settings
+WHERE setting_key = 'local_endpoint_url' AND setting_value != ''
+ON CONFLICT(setting_key) DO UPDATE SET setting_value = excluded.setting_value
+WHERE settings.setting_value = '';
+
+DELETE FROM settings WHERE setting_key = 'local_endpoint_url';
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_activities_project;
+CREATE INDEX IF NOT EXISTS idx_chunks_project ON document_chunks(project_id);
+DROP INDEX IF EXISTS idx_chunks_project_vectorized;
@@ -0,0 +1,7 @@
+-- Vectorization looks chunks up by (project_id, is_vectorized); the composite
+-- index replaces the single-column project index, which is its prefix.
+CREATE INDEX IF NOT EXISTS idx_chunks_project_vectorized ON document_chunks(project_id, is_vectorized);
+DROP INDEX IF EXISTS idx_chunks_project;
+
+-- Project document listings filter by project and order by id.
+CREATE INDEX IF NOT EXISTS idx_activities_project ON projects_activities(project_id, id);
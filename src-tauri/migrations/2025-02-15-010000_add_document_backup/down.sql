@@ -0,0 +1 @@
+ALTER TABLE projects_activities DROP COLUMN backup_document_text;
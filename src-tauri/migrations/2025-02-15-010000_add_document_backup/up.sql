@@ -0,0 +1,2 @@
+-- Holds a document's text from before its last LLM cleanup so it can be restored
+ALTER TABLE projects_activities ADD COLUMN backup_document_text TEXT;
@@ -0,0 +1,10 @@
+-- Earlier texts of each document, newest last, so edits can be undone
+CREATE TABLE IF NOT EXISTS document_versions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    document_id INTEGER NOT NULL,
+    full_document_text TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (document_id) REFERENCES projects_activities(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_document_versions_document ON document_versions(document_id, id);
@@ -0,0 +1 @@
+ALTER TABLE projects_activities DROP COLUMN exclude_from_rag;
@@ -0,0 +1,2 @@
+-- Documents the assistant should never see: they are kept out of chunking and the vector index
+ALTER TABLE projects_activities ADD COLUMN exclude_from_rag INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- Facts about the data itself, such as the app version that last wrote it
+CREATE TABLE IF NOT EXISTS app_metadata (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Documents saved with touch_document whose chunks and vectors have not caught up with their text yet
+ALTER TABLE projects_activities ADD COLUMN is_dirty INTEGER NOT NULL DEFAULT 0;
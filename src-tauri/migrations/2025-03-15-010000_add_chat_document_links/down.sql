@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS idx_chat_document_links_document;
+DROP INDEX IF EXISTS idx_chat_document_links_chat;
+DROP TABLE IF EXISTS chat_document_links;
@@ -0,0 +1,14 @@
+-- Documents saved from chat messages, so each side can find the other
+CREATE TABLE IF NOT EXISTS chat_document_links (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    chat_id INTEGER NOT NULL,
+    message_id INTEGER NOT NULL,
+    document_id INTEGER NOT NULL,
+    created_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (chat_id) REFERENCES chats(id) ON DELETE CASCADE,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE,
+    FOREIGN KEY (document_id) REFERENCES projects_activities(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_chat_document_links_chat ON chat_document_links(chat_id);
+CREATE INDEX IF NOT EXISTS idx_chat_document_links_document ON chat_document_links(document_id);
@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS idx_activities_fingerprint;
+ALTER TABLE projects_activities DROP COLUMN content_fingerprint;
@@ -0,0 +1,5 @@
+-- Normalized hash of each document's plain text, so duplicate checks on import
+-- compare one indexed column instead of re-reading every document.
+-- Existing rows are filled in by the app the first time it opens the database.
+ALTER TABLE projects_activities ADD COLUMN content_fingerprint INTEGER;
+CREATE INDEX IF NOT EXISTS idx_activities_fingerprint ON projects_activities(project_id, content_fingerprint);
@@ -0,0 +1,426 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use log::{error, info, warn};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager, State};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::configuration::data_directory::{data_directory, remove_data, VECTOR_DIRECTORIES};
+use crate::configuration::database::{
+    close_vector_database, is_newer_version, open_database, placeholder_database, schema_version, APP_VERSION,
+    DATABASE_FILE_NAME,
+};
+use crate::configuration::settings::Settings;
+use crate::configuration::state::AppState;
+use crate::engine::project_vector_engine::close_all_project_vectors;
+use crate::engine::rate_limiter::configure_rate_limits;
+use crate::repository::settings_repository::get_settings;
+use crate::error::{HeelixError, HeelixResult};
+
+/// Archive entry describing what wrote the backup
+const MANIFEST_FILE: &str = "manifest.json";
+/// Bumped when the archive layout changes in a way older imports cannot read
+const BACKUP_FORMAT_VERSION: u32 = 1;
+/// Folder in the data directory for the copy taken before an import replaces the data
+const SAFETY_BACKUP_DIRECTORY: &str = "backups";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackupManifest {
+    pub format_version: u32,
+    pub app_version: String,
+    /// Latest database migration applied when the backup was taken
+    pub schema_version: String,
+    pub created_at: String,
+}
+
+fn zip_error(error: zip::result::ZipError) -> io::Error {
+    match error {
+        zip::result::ZipError::Io(error) => error,
+        other => io::Error::new(io::ErrorKind::InvalidData, other.to_string()),
+    }
+}
+
+fn add_file(zip: &mut ZipWriter<File>, name: &str, path: &Path) -> io::Result<()> {
+    let options = FileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(fs::metadata(path)?.len() >= u32::MAX as u64);
+    zip.start_file(name, options).map_err(zip_error)?;
+    io::copy(&mut File::open(path)?, zip)?;
+    Ok(())
+}
+
+/// Add every file under `dir` with entry names starting with `prefix/`
+fn add_directory(zip: &mut ZipWriter<File>, prefix: &str, dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            add_directory(zip, &name, &entry.path())?;
+        } else {
+            add_file(zip, &name, &entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Write the manifest, the database file and the vector index directories in
+/// `data_dir` to a zip at `archive_path`
+fn write_archive(archive_path: &Path, data_dir: &Path, manifest: &BackupManifest) -> io::Result<()> {
+    let mut zip = ZipWriter::new(File::create(archive_path)?);
+    zip.start_file(MANIFEST_FILE, FileOptions::default()).map_err(zip_error)?;
+    zip.write_all(&serde_json::to_vec_pretty(manifest)?)?;
+    add_file(&mut zip, DATABASE_FILE_NAME, &data_dir.join(DATABASE_FILE_NAME))?;
+    for directory in VECTOR_DIRECTORIES {
+        let path = data_dir.join(directory);
+        if path.is_dir() {
+            add_directory(&mut zip, directory, &path)?;
+        }
+    }
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+fn read_manifest(archive: &mut ZipArchive<File>) -> HeelixResult<BackupManifest> {
+    let entry = archive
+        .by_name(MANIFEST_FILE)
+        .map_err(|_| HeelixError::InvalidInput("The file is not a Heelix backup: it has no manifest".to_string()))?;
+    serde_json::from_reader(entry)
+        .map_err(|e| HeelixError::InvalidInput(format!("The backup manifest could not be read: {}", e)))
+}
+
+/// Refuse backups this version cannot restore safely
+fn validate_manifest(manifest: &BackupManifest) -> HeelixResult<()> {
+    if manifest.format_version > BACKUP_FORMAT_VERSION {
+        return Err(HeelixError::InvalidInput(format!(
+            "This backup uses format {}, which this version of Heelix cannot read",
+            manifest.format_version
+        )));
+    }
+    if is_newer_version(&manifest.app_version, APP_VERSION) {
+        return Err(HeelixError::InvalidInput(format!(
+            "This backup was made by Heelix {}. Install Heelix {} or later to restore it.",
+            manifest.app_version, manifest.app_version
+        )));
+    }
+    Ok(())
+}
+
+/// Whether an archive entry belongs in the data directory. Anything else,
+/// including paths escaping it, is skipped.
+fn is_data_entry(name: &Path) -> bool {
+    name == Path::new(DATABASE_FILE_NAME)
+        || VECTOR_DIRECTORIES.iter().any(|directory| name.starts_with(directory))
+}
+
+fn extract_archive(archive: &mut ZipArchive<File>, data_dir: &Path) -> io::Result<()> {
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index).map_err(zip_error)?;
+        let name = match entry.enclosed_name() {
+            Some(name) => name.to_path_buf(),
+            None => continue,
+        };
+        if entry.is_dir() || !is_data_entry(&name) {
+            continue;
+        }
+        let target = data_dir.join(name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(target)?)?;
+    }
+    Ok(())
+}
+
+fn resolve_data_directory(app_handle: &AppHandle) -> HeelixResult<PathBuf> {
+    data_directory(app_handle)
+        .ok_or_else(|| HeelixError::Internal("The app data directory could not be resolved".to_string()))
+}
+
+/// Write the vector indexes to disk and drop them, so the files are complete
+/// and the next search reopens them
+async fn close_vector_indexes() -> HeelixResult<()> {
+    close_all_project_vectors()
+        .await
+        .map_err(|e| HeelixError::Internal(format!("Failed to save the vector indexes: {}", e)))?;
+    close_vector_database()
+        .await
+        .map_err(|e| HeelixError::Internal(format!("Failed to save the vector index: {}", e)))
+}
+
+/// Archive the data directory. The caller has closed the connection, so the
+/// database file holds every write and nothing changes it meanwhile.
+fn export_data(data_dir: &Path, archive_path: &Path) -> HeelixResult<BackupManifest> {
+    let db = Connection::open(data_dir.join(DATABASE_FILE_NAME))?;
+    let manifest = BackupManifest {
+        format_version: BACKUP_FORMAT_VERSION,
+        app_version: APP_VERSION.to_string(),
+        schema_version: schema_version(&db)?,
+        created_at: chrono::Utc::now().to_rfc3339(),
+    };
+    drop(db);
+    write_archive(archive_path, data_dir, &manifest)
+        .map_err(|e| HeelixError::Internal(format!("Failed to write the backup: {}", e)))?;
+    Ok(manifest)
+}
+
+/// Replace the data in `data_dir` with the archive's and open the result
+fn restore_archive(archive: &mut ZipArchive<File>, data_dir: &Path) -> HeelixResult<Connection> {
+    remove_data(data_dir)
+        .and_then(|_| extract_archive(archive, data_dir))
+        .map_err(|e| HeelixError::Internal(format!("Failed to restore the backup files: {}", e)))?;
+    open_database(data_dir).map_err(|e| HeelixError::Database(e.to_string()))
+}
+
+fn reopen_database(data_dir: &Path) -> HeelixResult<Connection> {
+    open_database(data_dir).map_err(|e| HeelixError::Database(e.to_string()))
+}
+
+/// The connection to give back after releasing it: the database in `data_dir`,
+/// or a placeholder when it cannot be opened
+fn reopen_or_placeholder(data_dir: &Path) -> Connection {
+    reopen_database(data_dir).unwrap_or_else(|e| {
+        error!("Failed to reopen the database in {}: {}", data_dir.display(), e);
+        placeholder_database()
+    })
+}
+
+/// Clear what a failed restore left in `data_dir` and start an empty database there
+fn empty_database(data_dir: &Path) -> Connection {
+    if let Err(e) = remove_data(data_dir) {
+        warn!("Failed to clear {} after a failed import: {}", data_dir.display(), e);
+    }
+    reopen_or_placeholder(data_dir)
+}
+
+/// Restore `archive` over the closed data in `data_dir`. With `had_database`
+/// the current data is saved to the `backups` folder first and put back if the
+/// restore fails. Without one there is nothing to save or put back, and a
+/// failed restore leaves an empty database. Returns the outcome with the path
+/// of the safety backup, and the connection to use after.
+fn import_data(
+    archive: &mut ZipArchive<File>,
+    data_dir: &Path,
+    had_database: bool,
+) -> (HeelixResult<Option<PathBuf>>, Connection) {
+    let safety_path = if had_database {
+        let safety_dir = data_dir.join(SAFETY_BACKUP_DIRECTORY);
+        let safety_path = safety_dir.join(format!(
+            "before-import-{}.zip",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+        let saved = fs::create_dir_all(&safety_dir)
+            .map_err(|e| HeelixError::Internal(format!("Failed to create {}: {}", safety_dir.display(), e)))
+            .and_then(|_| export_data(data_dir, &safety_path));
+        if let Err(e) = saved {
+            return (Err(e), reopen_or_placeholder(data_dir));
+        }
+        Some(safety_path)
+    } else {
+        None
+    };
+
+    let e = match restore_archive(archive, data_dir) {
+        Ok(db) => return (Ok(safety_path), db),
+        Err(e) => e,
+    };
+    let safety_path = match safety_path {
+        Some(safety_path) => safety_path,
+        None => {
+            warn!("Importing into {} failed with no previous database to put back", data_dir.display());
+            return (Err(e), empty_database(data_dir));
+        }
+    };
+    warn!("Importing failed, putting back {}", safety_path.display());
+    let rolled_back = File::open(&safety_path)
+        .map_err(zip::result::ZipError::Io)
+        .and_then(ZipArchive::new)
+        .map_err(|e| HeelixError::Internal(format!("Failed to reopen the safety backup: {}", e)))
+        .and_then(|mut safety| restore_archive(&mut safety, data_dir));
+    match rolled_back {
+        Ok(db) => (Err(e), db),
+        Err(rollback_error) => {
+            let message = format!(
+                "{}. The previous data is saved in {}.",
+                rollback_error,
+                safety_path.display()
+            );
+            (Err(HeelixError::Internal(message)), empty_database(data_dir))
+        }
+    }
+}
+
+/// Write the database, every vector index and a manifest of the app and schema
+/// versions to one zip at `path`, for moving all data to another machine
+#[tauri::command]
+pub async fn export_all(app_handle: AppHandle, path: String) -> HeelixResult<BackupManifest> {
+    let data_dir = resolve_data_directory(&app_handle)?;
+    close_vector_indexes().await?;
+
+    // The lock is held only to close the connection. Calls made while the
+    // archive is written wait for the reopened one.
+    let app_state: State<AppState> = app_handle.state();
+    if !app_state.release_db()? {
+        return Err(HeelixError::Internal("The database is not open".to_string()));
+    }
+    let archive_path = PathBuf::from(path.trim());
+    let export_dir = data_dir.clone();
+    let (exported, db) = tauri::async_runtime::spawn_blocking(move || {
+        let exported = export_data(&export_dir, &archive_path);
+        match reopen_database(&export_dir) {
+            Ok(db) => (exported, db),
+            Err(e) => (exported.and(Err(e)), placeholder_database()),
+        }
+    })
+    .await
+    .unwrap_or_else(|e| {
+        (Err(HeelixError::Internal(format!("Exporting failed: {}", e))), reopen_or_placeholder(&data_dir))
+    });
+    app_state.return_db(db);
+    let manifest = exported?;
+    info!("Exported all data to {}", path);
+    Ok(manifest)
+}
+
+/// Replace all data with a backup made by `export_all`. The current data is
+/// first saved to the `backups` folder of the data directory, and put back if
+/// the backup cannot be restored.
+#[tauri::command]
+pub async fn import_all(app_handle: AppHandle, path: String) -> HeelixResult<BackupManifest> {
+    let data_dir = resolve_data_directory(&app_handle)?;
+    let file = File::open(path.trim())
+        .map_err(|e| HeelixError::InvalidInput(format!("{} could not be opened: {}", path, e)))?;
+    let mut archive = ZipArchive::new(file)
+        .map_err(|e| HeelixError::InvalidInput(format!("The file is not a Heelix backup: {}", e)))?;
+    let manifest = read_manifest(&mut archive)?;
+    validate_manifest(&manifest)?;
+    if archive.by_name(DATABASE_FILE_NAME).is_err() {
+        return Err(HeelixError::InvalidInput("The backup does not contain a database".to_string()));
+    }
+
+    close_vector_indexes().await?;
+    let app_state: State<AppState> = app_handle.state();
+    let had_database = app_state.release_db()?;
+    let import_dir = data_dir.clone();
+    let (imported, db) =
+        tauri::async_runtime::spawn_blocking(move || import_data(&mut archive, &import_dir, had_database))
+            .await
+            .unwrap_or_else(|e| {
+                (Err(HeelixError::Internal(format!("Importing failed: {}", e))), reopen_or_placeholder(&data_dir))
+            });
+    // The imported settings may carry other rate limits
+    if imported.is_ok() {
+        if let Ok(rows) = get_settings(&db) {
+            configure_rate_limits(&Settings::from_setting_rows(&rows));
+        }
+    }
+    app_state.return_db(db);
+    match imported? {
+        Some(safety_path) => info!(
+            "Imported data from {} (Heelix {}, schema {}); previous data saved to {}",
+            path,
+            manifest.app_version,
+            manifest.schema_version,
+            safety_path.display()
+        ),
+        None => info!(
+            "Imported data from {} (Heelix {}, schema {}) with no previous database",
+            path, manifest.app_version, manifest.schema_version
+        ),
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(app_version: &str) -> BackupManifest {
+        BackupManifest {
+            format_version: BACKUP_FORMAT_VERSION,
+            app_version: app_version.to_string(),
+            schema_version: "2025-03-05-010000".to_string(),
+            created_at: "2025-03-06T10:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_archive_round_trip() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        let archive_dir = tempfile::tempdir().unwrap();
+        let archive_path = archive_dir.path().join("backup.zip");
+        fs::write(from.path().join(DATABASE_FILE_NAME), b"sqlite").unwrap();
+        fs::create_dir_all(from.path().join("vectors").join("project_1")).unwrap();
+        fs::write(from.path().join("vectors").join("project_1").join("chunks.hnsw.data"), b"vectors").unwrap();
+        fs::create_dir_all(from.path().join(SAFETY_BACKUP_DIRECTORY)).unwrap();
+        fs::write(from.path().join(SAFETY_BACKUP_DIRECTORY).join("old.zip"), b"old").unwrap();
+
+        write_archive(&archive_path, from.path(), &manifest(APP_VERSION)).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        assert_eq!(read_manifest(&mut archive).unwrap().app_version, APP_VERSION);
+
+        fs::write(to.path().join(format!("{}-wal", DATABASE_FILE_NAME)), b"stale").unwrap();
+        remove_data(to.path()).unwrap();
+        extract_archive(&mut archive, to.path()).unwrap();
+        assert_eq!(fs::read(to.path().join(DATABASE_FILE_NAME)).unwrap(), b"sqlite");
+        assert!(to.path().join("vectors").join("project_1").join("chunks.hnsw.data").exists());
+        assert!(!to.path().join(format!("{}-wal", DATABASE_FILE_NAME)).exists());
+        assert!(!to.path().join(SAFETY_BACKUP_DIRECTORY).exists());
+    }
+
+    #[test]
+    fn test_rejects_backups_from_newer_versions() {
+        assert!(validate_manifest(&manifest(APP_VERSION)).is_ok());
+        assert!(validate_manifest(&manifest("999.0.0")).is_err());
+        let newer_format = BackupManifest { format_version: BACKUP_FORMAT_VERSION + 1, ..manifest(APP_VERSION) };
+        assert!(validate_manifest(&newer_format).is_err());
+    }
+
+    #[test]
+    fn test_only_data_entries_are_extracted() {
+        assert!(is_data_entry(Path::new(DATABASE_FILE_NAME)));
+        assert!(is_data_entry(Path::new("vectors/project_1/chunks.hnsw.graph")));
+        assert!(!is_data_entry(Path::new(MANIFEST_FILE)));
+        assert!(!is_data_entry(Path::new("data_directory")));
+    }
+
+    /// An archive whose database file SQLite cannot open
+    fn unreadable_backup(dir: &Path) -> ZipArchive<File> {
+        let source = dir.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join(DATABASE_FILE_NAME), b"not a database file at all").unwrap();
+        let archive_path = dir.join("broken.zip");
+        write_archive(&archive_path, &source, &manifest(APP_VERSION)).unwrap();
+        ZipArchive::new(File::open(archive_path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_failed_import_puts_the_previous_database_back() {
+        let scratch = tempfile::tempdir().unwrap();
+        let data_dir = tempfile::tempdir().unwrap();
+        let db = reopen_database(data_dir.path()).unwrap();
+        db.execute("INSERT INTO settings (setting_key, setting_value) VALUES ('kept', 'yes')", []).unwrap();
+        drop(db);
+
+        let (imported, db) = import_data(&mut unreadable_backup(scratch.path()), data_dir.path(), true);
+        assert!(imported.is_err());
+        let kept: String = db.query_row("SELECT setting_value FROM settings WHERE setting_key = 'kept'", [], |row| row.get(0)).unwrap();
+        assert_eq!(kept, "yes");
+        assert_eq!(fs::read_dir(data_dir.path().join(SAFETY_BACKUP_DIRECTORY)).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_failed_import_without_a_database_starts_an_empty_one() {
+        let scratch = tempfile::tempdir().unwrap();
+        let data_dir = tempfile::tempdir().unwrap();
+
+        let (imported, db) = import_data(&mut unreadable_backup(scratch.path()), data_dir.path(), false);
+        assert!(imported.is_err());
+        assert!(get_settings(&db).is_ok());
+        assert!(!data_dir.path().join(SAFETY_BACKUP_DIRECTORY).exists());
+    }
+}
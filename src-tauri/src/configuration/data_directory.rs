@@ -0,0 +1,324 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::{error, info, warn};
+use rusqlite::Connection;
+use sysinfo::Disks;
+use tauri::{AppHandle, Manager, State};
+
+use crate::configuration::database::{
+    close_vector_database, open_database, placeholder_database, DATABASE_FILE_NAME,
+};
+use crate::configuration::state::AppState;
+use crate::engine::project_vector_engine::close_all_project_vectors;
+use crate::error::{HeelixError, HeelixResult};
+
+/// File in the platform app data directory naming the data directory chosen
+/// with `set_data_directory`. It stays behind when the data moves, so the next
+/// launch can find the database before opening it.
+const DATA_DIRECTORY_OVERRIDE_FILE: &str = "data_directory";
+/// Vector index directories that move with the database
+pub const VECTOR_DIRECTORIES: [&str; 2] = ["vectors", "hnsw"];
+/// Free space required on the target beyond the size of the copied files
+const FREE_SPACE_MARGIN_BYTES: u64 = 100 * 1024 * 1024;
+
+fn default_data_directory(app_handle: &AppHandle) -> HeelixResult<PathBuf> {
+    app_handle
+        .path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| HeelixError::Internal("The app data directory could not be resolved".to_string()))
+}
+
+fn read_override(default_dir: &Path) -> Option<PathBuf> {
+    let path = fs::read_to_string(default_dir.join(DATA_DIRECTORY_OVERRIDE_FILE)).ok()?;
+    let path = path.trim();
+    if path.is_empty() {
+        None
+    } else {
+        Some(PathBuf::from(path))
+    }
+}
+
+/// Record `data_dir` for future launches; moving back to the default removes the override
+fn write_override(default_dir: &Path, data_dir: &Path) -> io::Result<()> {
+    let override_file = default_dir.join(DATA_DIRECTORY_OVERRIDE_FILE);
+    if data_dir == default_dir {
+        if override_file.exists() {
+            fs::remove_file(override_file)?;
+        }
+        return Ok(());
+    }
+    fs::create_dir_all(default_dir)?;
+    fs::write(override_file, data_dir.to_string_lossy().as_bytes())
+}
+
+/// Directory holding the database and vector indexes: the one chosen with
+/// `set_data_directory`, or the platform app data directory
+pub fn data_directory(app_handle: &AppHandle) -> Option<PathBuf> {
+    let default_dir = app_handle.path_resolver().app_data_dir()?;
+    Some(read_override(&default_dir).unwrap_or(default_dir))
+}
+
+fn directory_size(path: &Path) -> io::Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() {
+            directory_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(total)
+}
+
+fn copy_directory(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_directory(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Bytes the database file and vector indexes in `data_dir` take up
+fn data_size(data_dir: &Path) -> io::Result<u64> {
+    let mut total = fs::metadata(data_dir.join(DATABASE_FILE_NAME)).map(|m| m.len()).unwrap_or(0);
+    for directory in VECTOR_DIRECTORIES {
+        let path = data_dir.join(directory);
+        if path.is_dir() {
+            total += directory_size(&path)?;
+        }
+    }
+    Ok(total)
+}
+
+fn copy_data(from: &Path, to: &Path) -> io::Result<()> {
+    let database = from.join(DATABASE_FILE_NAME);
+    if database.exists() {
+        fs::copy(&database, to.join(DATABASE_FILE_NAME))?;
+    }
+    for directory in VECTOR_DIRECTORIES {
+        let path = from.join(directory);
+        if path.is_dir() {
+            copy_directory(&path, &to.join(directory))?;
+        }
+    }
+    Ok(())
+}
+
+/// Delete the database, its journal files and the vector indexes from `data_dir`
+pub fn remove_data(data_dir: &Path) -> io::Result<()> {
+    for suffix in ["", "-wal", "-shm"] {
+        let path = data_dir.join(format!("{}{}", DATABASE_FILE_NAME, suffix));
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+    }
+    for directory in VECTOR_DIRECTORIES {
+        let path = data_dir.join(directory);
+        if path.is_dir() {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Create `dir` if needed and prove a file can be written there
+fn check_writable(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let probe = dir.join(".heelix_write_test");
+    fs::write(&probe, b"ok")?;
+    fs::remove_file(probe)
+}
+
+/// Free bytes on the disk holding `dir`, None when no mounted disk contains it
+fn available_space(dir: &Path) -> Option<u64> {
+    let dir = dir.canonicalize().ok()?;
+    let disks = Disks::new_with_refreshed_list();
+    disks
+        .list()
+        .iter()
+        .filter(|disk| dir.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// Reject a target the data cannot be copied into
+fn validate_target(current: &Path, target: &Path) -> HeelixResult<()> {
+    if !target.is_absolute() {
+        return Err(HeelixError::InvalidInput("Choose an absolute folder path for the data directory".to_string()));
+    }
+    if target.starts_with(current) {
+        return Err(HeelixError::InvalidInput(
+            "The new data directory cannot be inside the current one".to_string(),
+        ));
+    }
+    if target.join(DATABASE_FILE_NAME).exists() {
+        return Err(HeelixError::InvalidInput(format!(
+            "{} already contains a Heelix database",
+            target.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Copy the closed database's data to `target`, open the copy and keep using
+/// `target` on future launches. A failed move deletes the partial copy, so the
+/// same target can be chosen again. Runs off the async runtime.
+fn move_database(default_dir: &Path, current: &Path, target: &Path) -> HeelixResult<Connection> {
+    let moved = copy_data(current, target)
+        .map_err(|e| HeelixError::Internal(format!("Failed to copy the data files: {}", e)))
+        .and_then(|_| open_database(target).map_err(|e| HeelixError::Database(e.to_string())))
+        .and_then(|db| {
+            write_override(default_dir, target)
+                .map_err(|e| HeelixError::Internal(format!("Failed to save the data directory: {}", e)))?;
+            Ok(db)
+        });
+    if moved.is_err() {
+        warn!("Moving the data directory failed, reopening {}", current.display());
+        if let Err(e) = remove_data(target) {
+            warn!("Failed to remove the partial copy in {}: {}", target.display(), e);
+        }
+    }
+    moved
+}
+
+/// Open the database left in `data_dir` after a failed move, or a placeholder
+/// when it cannot be opened either
+fn reopen_database(data_dir: &Path) -> Connection {
+    open_database(data_dir).unwrap_or_else(|e| {
+        error!("Failed to reopen the database in {}: {}", data_dir.display(), e);
+        placeholder_database()
+    })
+}
+
+/// The directory currently holding the database and vector indexes
+#[tauri::command]
+pub fn get_data_directory(app_handle: AppHandle) -> HeelixResult<String> {
+    data_directory(&app_handle)
+        .map(|dir| dir.display().to_string())
+        .ok_or_else(|| HeelixError::Internal("The app data directory could not be resolved".to_string()))
+}
+
+/// Copy the database and vector indexes to `path`, switch to the copy and keep
+/// using it on future launches. The old files are left in place.
+#[tauri::command]
+pub async fn set_data_directory(app_handle: AppHandle, path: String) -> HeelixResult<String> {
+    let default_dir = default_data_directory(&app_handle)?;
+    let current = data_directory(&app_handle).unwrap_or_else(|| default_dir.clone());
+    let target = PathBuf::from(path.trim());
+    if target == current {
+        return Ok(current.display().to_string());
+    }
+    validate_target(&current, &target)?;
+    check_writable(&target).map_err(|e| {
+        HeelixError::InvalidInput(format!("{} is not writable: {}", target.display(), e))
+    })?;
+
+    let required = data_size(&current)
+        .map_err(|e| HeelixError::Internal(format!("Failed to measure the data directory: {}", e)))?;
+    match available_space(&target) {
+        Some(available) if available < required + FREE_SPACE_MARGIN_BYTES => {
+            return Err(HeelixError::InvalidInput(format!(
+                "{} needs {} MB free but only {} MB is available",
+                target.display(),
+                (required + FREE_SPACE_MARGIN_BYTES) / (1024 * 1024),
+                available / (1024 * 1024)
+            )));
+        }
+        Some(_) => {}
+        None => warn!("Could not determine free space for {}", target.display()),
+    }
+
+    // Indexes are written to disk and dropped so the copies are complete and
+    // the next search opens them from the new location
+    close_all_project_vectors()
+        .await
+        .map_err(|e| HeelixError::Internal(format!("Failed to save the vector indexes: {}", e)))?;
+    close_vector_database()
+        .await
+        .map_err(|e| HeelixError::Internal(format!("Failed to save the vector index: {}", e)))?;
+
+    // The lock is held only to close the connection. Calls made during the copy
+    // wait for the reopened one instead of blocking on the lock.
+    let app_state: State<AppState> = app_handle.state();
+    app_state.release_db()?;
+    let (from, to) = (current.clone(), target.clone());
+    let (moved, db) = tauri::async_runtime::spawn_blocking(move || match move_database(&default_dir, &from, &to) {
+        Ok(db) => (Ok(()), db),
+        Err(e) => (Err(e), reopen_database(&from)),
+    })
+    .await
+    .unwrap_or_else(|e| {
+        let moved = Err(HeelixError::Internal(format!("Moving the data directory failed: {}", e)));
+        (moved, reopen_database(&current))
+    });
+    app_state.return_db(db);
+    moved?;
+    info!("Moved the data directory from {} to {}", current.display(), target.display());
+    Ok(target.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_override_round_trip() {
+        let default_dir = tempfile::tempdir().unwrap();
+        let chosen = default_dir.path().join("elsewhere");
+        assert_eq!(read_override(default_dir.path()), None);
+        write_override(default_dir.path(), &chosen).unwrap();
+        assert_eq!(read_override(default_dir.path()), Some(chosen));
+        write_override(default_dir.path(), default_dir.path()).unwrap();
+        assert_eq!(read_override(default_dir.path()), None);
+    }
+
+    #[test]
+    fn test_copies_database_and_vector_trees() {
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        fs::write(from.path().join(DATABASE_FILE_NAME), b"sqlite").unwrap();
+        fs::create_dir_all(from.path().join("vectors").join("project_1")).unwrap();
+        fs::write(from.path().join("vectors").join("project_1").join("chunks.hnsw.data"), b"vectors").unwrap();
+        fs::write(from.path().join("unrelated.log"), b"log").unwrap();
+
+        assert_eq!(data_size(from.path()).unwrap(), 13);
+        copy_data(from.path(), to.path()).unwrap();
+        assert!(to.path().join(DATABASE_FILE_NAME).exists());
+        assert!(to.path().join("vectors").join("project_1").join("chunks.hnsw.data").exists());
+        assert!(!to.path().join("unrelated.log").exists());
+    }
+
+    #[test]
+    fn test_failed_move_removes_the_partial_copy() {
+        let default_dir = tempfile::tempdir().unwrap();
+        let from = tempfile::tempdir().unwrap();
+        let to = tempfile::tempdir().unwrap();
+        fs::write(from.path().join(DATABASE_FILE_NAME), b"not a database file at all").unwrap();
+        fs::create_dir_all(from.path().join("vectors")).unwrap();
+        fs::write(from.path().join("vectors").join("chunks.hnsw.data"), b"vectors").unwrap();
+
+        assert!(move_database(default_dir.path(), from.path(), to.path()).is_err());
+        assert!(!to.path().join(DATABASE_FILE_NAME).exists());
+        assert!(!to.path().join("vectors").exists());
+        assert!(validate_target(from.path(), to.path()).is_ok());
+        assert_eq!(read_override(default_dir.path()), None);
+    }
+
+    #[test]
+    fn test_rejects_targets_inside_the_current_directory() {
+        let current = PathBuf::from("/data/heelix");
+        assert!(validate_target(&current, Path::new("/data/heelix/moved")).is_err());
+        assert!(validate_target(&current, Path::new("relative/dir")).is_err());
+        assert!(validate_target(&current, Path::new("/mnt/other")).is_ok());
+    }
+}
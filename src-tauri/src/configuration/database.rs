@@ -1,47 +1,184 @@
 use std::fs;
+use std::path::Path;
 use std::sync::Arc;
 
 use diesel::sqlite::SqliteConnection;
 use diesel::Connection;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use log::info;
+use rusqlite::OptionalExtension;
 use tauri::AppHandle;
 use tokio::sync::Mutex;
 
+use crate::configuration::data_directory::data_directory;
+use crate::configuration::state::ServiceAccess;
 use crate::engine::similarity_search_engine::{SimilaritySearch, SyncSimilaritySearch};
+use crate::repository::document_text::compress_stored_documents;
+use crate::repository::project_repository::backfill_content_fingerprints;
+use crate::repository::settings_repository::get_embedding_model;
 use crate::HNSW;
 
 pub type SyncVectorDatabase = Arc<Mutex<Option<SimilaritySearch>>>;
 
+/// Schema migrations from `src-tauri/migrations`, applied in order at startup.
+/// Diesel records each applied version in `__diesel_schema_migrations`, so an
+/// existing install only runs the ones it has not seen. Every schema change
+/// (new tables, columns, indexes, data fixes) goes in a new migration directory.
 const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
 
+pub const DATABASE_FILE_NAME: &str = "heelixnotes.sqlite";
+/// Version of the running app, recorded in `app_metadata` whenever it opens the data
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+const APP_VERSION_KEY: &str = "app_version";
+/// Set in `app_metadata` once documents stored as TEXT have been compressed
+const DOCUMENTS_COMPRESSED_KEY: &str = "documents_compressed";
+/// Set in `app_metadata` once documents saved before `content_fingerprint` have one
+const FINGERPRINTS_BACKFILLED_KEY: &str = "content_fingerprints_backfilled";
+
 pub fn initialize_database(
     app_handle: &AppHandle,
 ) -> Result<rusqlite::Connection, Box<dyn std::error::Error>> {
-    let app_dir = app_handle
-        .path_resolver()
-        .app_data_dir()
-        .expect("The app data directory should exist.");
-    fs::create_dir_all(&app_dir).expect("The app data directory should be created.");
-    let sqlite_path = app_dir.join("heelixnotes.sqlite");
+    let data_dir = data_directory(app_handle).ok_or("The app data directory could not be resolved")?;
+    open_database(&data_dir)
+}
+
+/// Open the database in `data_dir`, creating it if needed, and apply pending migrations
+pub fn open_database(data_dir: &Path) -> Result<rusqlite::Connection, Box<dyn std::error::Error>> {
+    fs::create_dir_all(data_dir)?;
+    let sqlite_path = data_dir.join(DATABASE_FILE_NAME);
     info!("SQLITE_PATH: {}", sqlite_path.display());
     let db = rusqlite::Connection::open(sqlite_path.clone())?;
     let user_pragma = db.prepare("PRAGMA user_version")?;
     drop(user_pragma);
+    check_data_compatibility(&db)?;
     let mut connection_diesel =
         SqliteConnection::establish(sqlite_path.display().to_string().as_str())
             .unwrap_or_else(|_| panic!("Error connecting to {}", "database"));
-    connection_diesel
+    let applied = connection_diesel
         .run_pending_migrations(MIGRATIONS)
-        .unwrap();
+        .map_err(|e| format!("Database migration failed: {}", e))?;
+    for version in &applied {
+        info!("Applied database migration {}", version);
+    }
+    info!("Database schema version: {}", schema_version(&db)?);
+    record_app_version(&db)?;
+    // Documents saved before compression existed; later ones are written compressed
+    run_once(&db, DOCUMENTS_COMPRESSED_KEY, compress_stored_documents)?;
+    run_once(&db, FINGERPRINTS_BACKFILLED_KEY, backfill_content_fingerprints)?;
     Ok(db)
 }
 
+/// A connection without tables, for when the database could not be reopened
+/// after releasing it. Calls fail with an error until the app is restarted.
+pub fn placeholder_database() -> rusqlite::Connection {
+    rusqlite::Connection::open_in_memory().expect("SQLite could not open an in-memory database")
+}
+
+/// Apply a data fix a migration cannot express in SQL, the first time a
+/// database is opened with it. Completion is recorded under `key` in
+/// `app_metadata` in the same transaction, so later opens skip it.
+fn run_once(
+    db: &rusqlite::Connection,
+    key: &str,
+    fix: impl FnOnce(&rusqlite::Connection) -> Result<usize, rusqlite::Error>,
+) -> Result<(), rusqlite::Error> {
+    let tx = db.unchecked_transaction()?;
+    let done: bool = tx.query_row("SELECT EXISTS(SELECT 1 FROM app_metadata WHERE key = ?1)", [key], |row| {
+        row.get(0)
+    })?;
+    if !done {
+        let changed = fix(&tx)?;
+        info!("Applied one-time data fix {} to {} rows", key, changed);
+        tx.execute("INSERT INTO app_metadata (key, value) VALUES (?1, ?2)", [key, APP_VERSION])?;
+    }
+    tx.commit()
+}
+
+/// Numeric parts of a version like "1.2.10", ignoring pre-release and build
+/// suffixes, so versions compare part by part rather than as text
+fn version_parts(version: &str) -> Vec<u64> {
+    version
+        .split(['-', '+'])
+        .next()
+        .unwrap_or_default()
+        .split('.')
+        .map(|part| part.trim().parse().unwrap_or(0))
+        .collect()
+}
+
+pub fn is_newer_version(version: &str, than: &str) -> bool {
+    version_parts(version) > version_parts(than)
+}
+
+/// App version that last wrote the database, None before `app_metadata` existed
+fn stored_app_version(db: &rusqlite::Connection) -> Result<Option<String>, rusqlite::Error> {
+    let has_metadata: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'app_metadata')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_metadata {
+        return Ok(None);
+    }
+    db.query_row(
+        "SELECT value FROM app_metadata WHERE key = ?1",
+        [APP_VERSION_KEY],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Refuse data written by a newer version of the app: its migrations and
+/// vector formats may not be readable here, and writing to it could corrupt it
+fn check_data_compatibility(db: &rusqlite::Connection) -> Result<(), Box<dyn std::error::Error>> {
+    match stored_app_version(db)? {
+        Some(stored) if is_newer_version(&stored, APP_VERSION) => Err(format!(
+            "This data was last opened by Heelix {}, which is newer than this version ({}). \
+             Install Heelix {} or later to keep using it; older versions cannot safely read it.",
+            stored, APP_VERSION, stored
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+fn record_app_version(db: &rusqlite::Connection) -> Result<(), rusqlite::Error> {
+    db.execute(
+        "INSERT INTO app_metadata (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        [APP_VERSION_KEY, APP_VERSION],
+    )?;
+    Ok(())
+}
+
+/// Latest applied migration version, or "none" on a database that has not been migrated
+pub fn schema_version(db: &rusqlite::Connection) -> Result<String, rusqlite::Error> {
+    let version: Option<String> = db.query_row(
+        "SELECT MAX(version) FROM __diesel_schema_migrations",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(version.unwrap_or_else(|| "none".to_string()))
+}
+
 pub async fn drop_database_handle() {
     let mut db = HNSW.lock().await;
     *db = None;
 }
 
+/// Save the activity index to disk and drop it, so the next access reopens it
+/// from the current data directory
+pub async fn close_vector_database() -> anyhow::Result<()> {
+    let mut db = HNSW.lock().await;
+    if let Some(hnsw) = db.as_ref() {
+        hnsw.sync().await?;
+        // Answered after the save, so the files are complete once this returns
+        hnsw.count().await?;
+    }
+    *db = None;
+    Ok(())
+}
+
 pub async fn get_vector_db(
     app_handle: &AppHandle,
 ) -> Result<SyncSimilaritySearch, Box<dyn std::error::Error>> {
@@ -61,12 +198,85 @@ pub async fn get_vector_db(
 fn initialize_vector_database<'a>(
     app_handle: &AppHandle,
 ) -> Result<SimilaritySearch, Box<dyn std::error::Error>> {
-    let app_dir = app_handle
-        .path_resolver()
-        .app_data_dir()
-        .expect("The app data directory should exist.");
-    let hnsw_db_path = app_dir.join("hnsw");
+    let data_dir = data_directory(app_handle).ok_or("The app data directory could not be resolved")?;
+    let hnsw_db_path = data_dir.join("hnsw");
     let collection_name = "activity_vectors";
-    let hnsw = SimilaritySearch::open(hnsw_db_path.to_str().unwrap(), collection_name)?;
+    let embedding_model = app_handle.db(get_embedding_model)?;
+    let hnsw = SimilaritySearch::open(hnsw_db_path.to_str().unwrap(), collection_name, &embedding_model)?;
     Ok(hnsw)
 }
+
+/// An in-memory database with every migration applied, for tests that read or
+/// write the real schema rather than a hand-written copy of it
+#[cfg(test)]
+pub fn test_database() -> rusqlite::Connection {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static NEXT_DATABASE: AtomicUsize = AtomicUsize::new(0);
+
+    // A named in-memory database is shared by the connections of this process,
+    // so diesel can migrate the one rusqlite keeps open
+    let uri = format!(
+        "file:heelix_test_{}?mode=memory&cache=shared",
+        NEXT_DATABASE.fetch_add(1, Ordering::SeqCst)
+    );
+    let db = rusqlite::Connection::open(&uri).unwrap();
+    SqliteConnection::establish(&uri)
+        .unwrap()
+        .run_pending_migrations(MIGRATIONS)
+        .unwrap();
+    db
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compares_versions_numerically() {
+        assert!(is_newer_version("0.10.0", "0.9.3"));
+        assert!(is_newer_version("1.0.1", "1.0"));
+        assert!(!is_newer_version("0.0.1-beta", "0.0.1"));
+        assert!(!is_newer_version("0.9.3", "0.10.0"));
+    }
+
+    #[test]
+    fn test_refuses_data_written_by_a_newer_version() {
+        // Written before app_metadata existed
+        let db = rusqlite::Connection::open_in_memory().unwrap();
+        assert!(check_data_compatibility(&db).is_ok());
+
+        let db = test_database();
+        assert!(check_data_compatibility(&db).is_ok());
+        record_app_version(&db).unwrap();
+        assert_eq!(stored_app_version(&db).unwrap().as_deref(), Some(APP_VERSION));
+        assert!(check_data_compatibility(&db).is_ok());
+
+        db.execute("UPDATE app_metadata SET value = '999.0.0' WHERE key = ?1", [APP_VERSION_KEY]).unwrap();
+        let error = check_data_compatibility(&db).unwrap_err().to_string();
+        assert!(error.contains("Heelix 999.0.0"));
+    }
+
+    #[test]
+    fn test_data_fix_runs_once_per_database() {
+        let db = test_database();
+        let mut runs = 0;
+        for _ in 0..2 {
+            run_once(&db, "test_fix", |_| {
+                runs += 1;
+                Ok(0)
+            })
+            .unwrap();
+        }
+        assert_eq!(runs, 1);
+
+        // A failed fix is not recorded, so the next open tries again
+        let failing = run_once(&db, "failing_fix", |_| Err(rusqlite::Error::InvalidQuery));
+        assert!(failing.is_err());
+        run_once(&db, "failing_fix", |_| {
+            runs += 1;
+            Ok(0)
+        })
+        .unwrap();
+        assert_eq!(runs, 2);
+    }
+}
@@ -0,0 +1,62 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+
+const REDACTED: &str = "[REDACTED]";
+
+lazy_static! {
+    // `?key=...` / `&api_key=...` query parameters (Gemini puts its key in the URL)
+    static ref QUERY_KEY_PARAM: Regex =
+        Regex::new(r"(?i)([?&](?:key|api_key|apikey|access_token)=)[^&\s\x22'()]+").unwrap();
+    // Authorization / provider key headers as they appear in debug output
+    static ref KEY_HEADER: Regex =
+        Regex::new(r#"(?i)((?:authorization|x-api-key|x-goog-api-key)["']?\s*[:=]\s*["']?(?:bearer\s+)?)[^\s"',}]+"#).unwrap();
+    static ref BEARER_TOKEN: Regex = Regex::new(r"(?i)(bearer\s+)[A-Za-z0-9._~+/=\-]+").unwrap();
+    // OpenAI (`sk-...`, `sk-proj-...`) and Anthropic (`sk-ant-...`) keys
+    static ref SK_KEY: Regex = Regex::new(r"sk-[A-Za-z0-9_\-]{16,}").unwrap();
+    // Google API keys
+    static ref GOOGLE_KEY: Regex = Regex::new(r"AIza[0-9A-Za-z_\-]{20,}").unwrap();
+}
+
+/// Strip API keys and key-bearing query parameters from a log line.
+/// Applied to every record by the log formatter so secrets never reach stdout or the webview.
+pub fn redact_secrets(message: &str) -> String {
+    let redacted = QUERY_KEY_PARAM.replace_all(message, format!("${{1}}{}", REDACTED).as_str());
+    let redacted = KEY_HEADER.replace_all(&redacted, format!("${{1}}{}", REDACTED).as_str());
+    let redacted = BEARER_TOKEN.replace_all(&redacted, format!("${{1}}{}", REDACTED).as_str());
+    let redacted = SK_KEY.replace_all(&redacted, REDACTED);
+    let redacted = GOOGLE_KEY.replace_all(&redacted, REDACTED);
+    redacted.into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacts_query_key() {
+        let line = "error sending request for url (https://generativelanguage.googleapis.com/v1/models/gemini-2.0-flash:generateContent?key=abc123&alt=sse)";
+        let redacted = redact_secrets(line);
+        assert!(!redacted.contains("abc123"));
+        assert!(redacted.contains("?key=[REDACTED]&alt=sse"));
+    }
+
+    #[test]
+    fn test_redacts_provider_keys() {
+        let line = "key sk-ant-REDACTED and AIzaSyA1234567890abcdefghijklmnopqrstu";
+        let redacted = redact_secrets(line);
+        assert_eq!(redacted, "key [REDACTED] and [REDACTED]");
+    }
+
+    #[test]
+    fn test_redacts_headers() {
+        let redacted = redact_secrets(r#"{"authorization": "Bearer abc.def", "x-api-key": "secret"}"#);
+        assert!(!redacted.contains("abc.def"));
+        assert!(!redacted.contains("secret"));
+    }
+
+    #[test]
+    fn test_leaves_plain_text_untouched() {
+        let line = "Vectorized 12 chunks for document 3 in project 1";
+        assert_eq!(redact_secrets(line), line);
+    }
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::info;
+use rusqlite::Connection;
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::configuration::data_directory::data_directory;
+use crate::configuration::database::DATABASE_FILE_NAME;
+use crate::configuration::state::ServiceAccess;
+use crate::error::{HeelixError, HeelixResult};
+
+#[derive(Debug, Serialize)]
+pub struct TableRowCount {
+    pub name: String,
+    pub rows: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DatabaseSize {
+    /// Bytes on disk, including the write-ahead log when there is one
+    pub size_bytes: u64,
+    pub tables: Vec<TableRowCount>,
+}
+
+fn database_path(app_handle: &AppHandle) -> HeelixResult<PathBuf> {
+    data_directory(app_handle)
+        .map(|dir| dir.join(DATABASE_FILE_NAME))
+        .ok_or_else(|| HeelixError::Internal("The app data directory could not be resolved".to_string()))
+}
+
+/// Size of the database file plus its `-wal` file, which holds writes not yet checkpointed
+fn file_size(path: &Path) -> u64 {
+    let wal = PathBuf::from(format!("{}-wal", path.display()));
+    [path, wal.as_path()]
+        .iter()
+        .filter_map(|file| fs::metadata(file).ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn table_row_counts(db: &Connection) -> rusqlite::Result<Vec<TableRowCount>> {
+    let mut stmt = db.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    names
+        .into_iter()
+        .map(|name| {
+            let rows = db.query_row(
+                &format!("SELECT COUNT(*) FROM \"{}\"", name.replace('"', "\"\"")),
+                [],
+                |row| row.get(0),
+            )?;
+            Ok(TableRowCount { name, rows })
+        })
+        .collect()
+}
+
+fn database_size(db: &Connection, path: &Path) -> rusqlite::Result<DatabaseSize> {
+    Ok(DatabaseSize {
+        size_bytes: file_size(path),
+        tables: table_row_counts(db)?,
+    })
+}
+
+/// Rebuild the database without free pages and fold the write-ahead log back
+/// into it. Returns the number of bytes the files shrank by.
+fn vacuum(db: &Connection, path: &Path) -> rusqlite::Result<u64> {
+    let before = file_size(path);
+    db.execute_batch("VACUUM")?;
+    // Answers one row (busy, log pages, checkpointed pages) in any journal mode
+    db.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+    Ok(before.saturating_sub(file_size(path)))
+}
+
+/// Bytes the database takes on disk and how many rows each table holds
+#[tauri::command]
+pub fn get_database_size(app_handle: AppHandle) -> HeelixResult<DatabaseSize> {
+    let path = database_path(&app_handle)?;
+    Ok(app_handle.db(|db| database_size(db, &path))?)
+}
+
+/// Reclaim the space left by deleted documents, versions and chunks. VACUUM
+/// rewrites the whole file, so it runs on a blocking thread; other database
+/// calls wait until it finishes.
+#[tauri::command]
+pub async fn vacuum_database(app_handle: AppHandle) -> HeelixResult<u64> {
+    let path = database_path(&app_handle)?;
+    let freed = tokio::task::spawn_blocking(move || app_handle.db(|db| vacuum(db, &path)))
+        .await
+        .map_err(|e| HeelixError::Internal(format!("Vacuuming the database failed: {}", e)))??;
+    info!("Vacuumed the database, freed {} bytes", freed);
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::database::open_database;
+
+    #[test]
+    fn test_vacuum_reclaims_deleted_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(DATABASE_FILE_NAME);
+        let db = open_database(dir.path()).unwrap();
+        for _ in 0..200 {
+            db.execute("INSERT INTO activity_full_text (edited_full_text) VALUES (?1)", ["x".repeat(4096)]).unwrap();
+        }
+        db.execute("DELETE FROM activity_full_text WHERE id > 10", []).unwrap();
+
+        let size = database_size(&db, &path).unwrap();
+        let rows = |name: &str| size.tables.iter().find(|table| table.name == name).map(|table| table.rows);
+        assert_eq!(rows("activity_full_text"), Some(10));
+        assert_eq!(rows("projects_activities"), Some(0));
+
+        let freed = vacuum(&db, &path).unwrap();
+        assert!(freed > 0);
+        assert_eq!(database_size(&db, &path).unwrap().size_bytes, size.size_bytes - freed);
+    }
+}
@@ -1,3 +1,7 @@
+pub mod data_directory;
+pub mod backup;
 pub mod database;
+pub mod logging;
+pub mod maintenance;
 pub mod state;
 pub mod settings;
\ No newline at end of file
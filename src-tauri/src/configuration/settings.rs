@@ -1,7 +1,135 @@
-use rusqlite_from_row::FromRow;
 use serde_derive::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Debug, FromRow, Clone)]
+use crate::engine::project_vector_engine::DEFAULT_VECTOR_CACHE_SIZE;
+use crate::engine::similarity_search_engine::DEFAULT_RAG_TOP_K;
+use crate::entity::setting::Setting;
+
+/// Providers the frontend can select as `api_choice`
+pub const API_CHOICES: [&str; 4] = ["claude", "openai", "gemini", "local"];
+pub const API_CHOICE_KEY: &str = "api_choice";
+pub const DEFAULT_API_CHOICE: &str = "claude";
+/// Chunks retrieved from a project's vector index for a prompt. Every
+/// provider and `preview_retrieval` use the same value; the upper bound is the
+/// HNSW search width.
+pub const RAG_TOP_K_KEY: &str = "rag_top_k";
+pub const MIN_RAG_TOP_K: i32 = 1;
+pub const MAX_RAG_TOP_K: i32 = 50;
+/// How many distinct documents retrieved chunks may come from in one prompt.
+/// Broad questions want more supporting documents, focused ones fewer.
+pub const MAX_CONTEXT_DOCUMENTS_KEY: &str = "max_context_documents";
+pub const DEFAULT_MAX_CONTEXT_DOCUMENTS: u32 = 4;
+pub const MIN_MAX_CONTEXT_DOCUMENTS: u32 = 1;
+pub const MAX_MAX_CONTEXT_DOCUMENTS: u32 = 20;
+/// Canonical settings key for the local model server. Older builds wrote
+/// `local_endpoint_url`; a migration copies that value over.
+pub const LOCAL_MODEL_URL_KEY: &str = "local_model_url";
+pub const DEFAULT_LOCAL_MODEL_URL: &str = "http://localhost:11434";
+/// Optional OpenAI-compatible gateway (Azure OpenAI, OpenRouter, a proxy).
+/// Empty means the default OpenAI endpoint.
+pub const OPENAI_BASE_URL_KEY: &str = "openai_base_url";
+/// Sent as the `OpenAI-Organization` header when set, for org-scoped keys
+pub const OPENAI_ORG_ID_KEY: &str = "openai_org_id";
+/// Sent as the `OpenAI-Project` header when set
+pub const OPENAI_PROJECT_ID_KEY: &str = "openai_project_id";
+/// Cap on chat response length; 0 keeps each engine's default
+pub const MAX_OUTPUT_TOKENS_KEY: &str = "max_output_tokens";
+/// How many project vector indices stay open in memory at once
+pub const VECTOR_CACHE_SIZE_KEY: &str = "vector_cache_size";
+pub const MIN_VECTOR_CACHE_SIZE: u32 = 1;
+pub const MAX_VECTOR_CACHE_SIZE: u32 = 50;
+/// Rate voice notes are downsampled to; 0 keeps the input device's rate
+pub const RECORDING_SAMPLE_RATE_KEY: &str = "recording_sample_rate";
+pub const MIN_RECORDING_SAMPLE_RATE: u32 = 8000;
+pub const MAX_RECORDING_SAMPLE_RATE: u32 = 48000;
+/// Providers tried in order when the selected one fails with a retryable
+/// error. Stored comma-separated.
+pub const FALLBACK_PROVIDERS_KEY: &str = "fallback_providers";
+/// Name new chats from their first exchange in the background
+pub const AUTO_NAME_CHATS_KEY: &str = "auto_name_chats";
+/// Times a failed embeddings request is retried before the batch is split
+/// to find the chunks that cannot be embedded
+pub const EMBEDDING_RETRIES_KEY: &str = "embedding_retries";
+pub const DEFAULT_EMBEDDING_RETRIES: u32 = 2;
+pub const MAX_EMBEDDING_RETRIES: u32 = 5;
+/// Mask emails, phone numbers, card numbers and denylisted terms in document
+/// context before it is sent to a cloud provider
+pub const REDACT_PII_KEY: &str = "redact_pii_before_send";
+/// Extra terms to mask when redaction is on. Stored comma-separated.
+pub const REDACTION_DENYLIST_KEY: &str = "redaction_denylist";
+/// Who the assistant says it is, at the start of every system prompt.
+/// `{provider}` is replaced with the model family answering.
+pub const ASSISTANT_PERSONA_KEY: &str = "assistant_persona";
+/// Fall back to OCR when a PDF has next to no extractable text. Off by
+/// default since it is slow and needs tesseract and poppler installed.
+pub const OCR_SCANNED_PDFS_KEY: &str = "ocr_scanned_pdfs";
+/// Hide the window to the tray when it is closed instead of quitting
+pub const CLOSE_TO_TRAY_KEY: &str = "close_to_tray";
+/// Match answer sentences to the retrieved chunks they came from. Off by
+/// default since it embeds every answer and its chunks again.
+pub const ATTRIBUTE_SOURCES_KEY: &str = "attribute_sources";
+/// OpenAI model that embeds chunks and queries. Each project index records
+/// the model that built it and refuses searches once this no longer matches.
+pub const EMBEDDING_MODEL_KEY: &str = "embedding_model";
+pub const DEFAULT_EMBEDDING_MODEL: &str = "text-embedding-3-small";
+/// Transcribe voice notes in windows while recording and show the partial
+/// text. Off by default since every window is an extra Whisper request.
+pub const LIVE_TRANSCRIPTION_KEY: &str = "live_transcription";
+/// Store document HTML zstd-compressed. Existing documents are compressed at
+/// startup while it is on; turning it off only affects documents saved later.
+pub const COMPRESS_DOCUMENTS_KEY: &str = "compress_documents";
+/// Send the best-ranked chunks to the model together with the chunks around
+/// them in their document. Off by default since it makes prompts longer.
+pub const EXPAND_CHUNK_CONTEXT_KEY: &str = "expand_chunk_context";
+/// Retrieve project chunks for every message of a chat rather than only the
+/// first, searching with the latest user message. Off by default.
+pub const RAG_ON_EVERY_TURN_KEY: &str = "rag_on_every_turn";
+/// Model each provider names new chats with. Empty keeps the provider's
+/// built-in naming model.
+pub const NAMING_MODEL_CLAUDE_KEY: &str = "naming_model_claude";
+pub const NAMING_MODEL_OPENAI_KEY: &str = "naming_model_openai";
+pub const NAMING_MODEL_GEMINI_KEY: &str = "naming_model_gemini";
+pub const NAMING_MODEL_LOCAL_KEY: &str = "naming_model_local";
+/// Longest chat name, in characters, the naming call asks for
+pub const NAMING_MAX_CHARS_KEY: &str = "naming_max_chars";
+pub const DEFAULT_NAMING_MAX_CHARS: u32 = 18;
+pub const MIN_NAMING_MAX_CHARS: u32 = 10;
+pub const MAX_NAMING_MAX_CHARS: u32 = 80;
+/// Requests and estimated input tokens per minute each cloud provider is sent,
+/// shared by chat and embedding calls. 0 leaves the provider unlimited.
+pub const RATE_LIMIT_RPM_CLAUDE_KEY: &str = "rate_limit_rpm_claude";
+pub const RATE_LIMIT_TPM_CLAUDE_KEY: &str = "rate_limit_tpm_claude";
+pub const RATE_LIMIT_RPM_OPENAI_KEY: &str = "rate_limit_rpm_openai";
+pub const RATE_LIMIT_TPM_OPENAI_KEY: &str = "rate_limit_tpm_openai";
+pub const RATE_LIMIT_RPM_GEMINI_KEY: &str = "rate_limit_rpm_gemini";
+pub const RATE_LIMIT_TPM_GEMINI_KEY: &str = "rate_limit_tpm_gemini";
+/// Embedding vectorization is what bursts, so OpenAI starts at its entry-tier limits
+pub const DEFAULT_RATE_LIMIT_RPM_OPENAI: u32 = 3000;
+pub const DEFAULT_RATE_LIMIT_TPM_OPENAI: u32 = 1_000_000;
+/// Seconds a document saved with `touch_document` must go without further
+/// edits before it is re-chunked and re-vectorized
+pub const REINDEX_DELAY_SECS_KEY: &str = "reindex_delay_secs";
+pub const DEFAULT_REINDEX_DELAY_SECS: u32 = 5;
+pub const MIN_REINDEX_DELAY_SECS: u32 = 1;
+pub const MAX_REINDEX_DELAY_SECS: u32 = 300;
+/// Largest file accepted by document import and audio reads. Text files
+/// over it are imported truncated instead of rejected.
+pub const MAX_IMPORT_BYTES_KEY: &str = "max_import_bytes";
+pub const DEFAULT_MAX_IMPORT_BYTES: u64 = 50 * 1024 * 1024;
+pub const MIN_MAX_IMPORT_BYTES: u64 = 1024 * 1024;
+pub const MAX_MAX_IMPORT_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+/// OpenAI-compatible server voice notes are transcribed with (Groq, Azure, a
+/// local Whisper server), including the version path. Empty means OpenAI.
+pub const TRANSCRIPTION_BASE_URL_KEY: &str = "transcription_base_url";
+pub const DEFAULT_TRANSCRIPTION_BASE_URL: &str = "https://api.openai.com/v1";
+/// Bearer token for the transcription server. Empty sends the OpenAI key to
+/// OpenAI's server and no key to any other.
+pub const TRANSCRIPTION_API_KEY_KEY: &str = "transcription_api_key";
+/// Model name sent with each transcription request, e.g. `whisper-large-v3`
+pub const TRANSCRIPTION_MODEL_KEY: &str = "transcription_model";
+pub const DEFAULT_TRANSCRIPTION_MODEL: &str = "whisper-1";
+pub const DEFAULT_ASSISTANT_PERSONA: &str = "You are Heelix chat app that is powered by {provider}. Heelix chat is developed by Heelix Technologies. Only identify yourself as such.";
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Settings {
     pub is_dev_mode: bool,
     pub interval: String,
@@ -13,4 +141,591 @@ pub struct Settings {
     pub local_model_url: String,
     pub vectorization_enabled: bool,
     pub rag_top_k: i32,
-}
\ No newline at end of file
+    #[serde(default = "default_max_context_documents")]
+    pub max_context_documents: u32,
+    #[serde(default)]
+    pub openai_base_url: String,
+    #[serde(default)]
+    pub openai_org_id: String,
+    #[serde(default)]
+    pub openai_project_id: String,
+    #[serde(default)]
+    pub max_output_tokens: u32,
+    #[serde(default = "default_vector_cache_size")]
+    pub vector_cache_size: u32,
+    #[serde(default)]
+    pub recording_sample_rate: u32,
+    #[serde(default)]
+    pub fallback_providers: Vec<String>,
+    #[serde(default = "default_auto_name_chats")]
+    pub auto_name_chats: bool,
+    #[serde(default = "default_embedding_retries")]
+    pub embedding_retries: u32,
+    #[serde(default)]
+    pub redact_pii_before_send: bool,
+    #[serde(default)]
+    pub redaction_denylist: Vec<String>,
+    #[serde(default = "default_assistant_persona")]
+    pub assistant_persona: String,
+    #[serde(default)]
+    pub ocr_scanned_pdfs: bool,
+    #[serde(default = "default_close_to_tray")]
+    pub close_to_tray: bool,
+    #[serde(default)]
+    pub attribute_sources: bool,
+    #[serde(default = "default_embedding_model")]
+    pub embedding_model: String,
+    #[serde(default)]
+    pub live_transcription: bool,
+    #[serde(default = "default_compress_documents")]
+    pub compress_documents: bool,
+    #[serde(default)]
+    pub expand_chunk_context: bool,
+    #[serde(default)]
+    pub rag_on_every_turn: bool,
+    #[serde(default)]
+    pub naming_model_claude: String,
+    #[serde(default)]
+    pub naming_model_openai: String,
+    #[serde(default)]
+    pub naming_model_gemini: String,
+    #[serde(default)]
+    pub naming_model_local: String,
+    #[serde(default = "default_naming_max_chars")]
+    pub naming_max_chars: u32,
+    #[serde(default)]
+    pub rate_limit_rpm_claude: u32,
+    #[serde(default)]
+    pub rate_limit_tpm_claude: u32,
+    #[serde(default = "default_rate_limit_rpm_openai")]
+    pub rate_limit_rpm_openai: u32,
+    #[serde(default = "default_rate_limit_tpm_openai")]
+    pub rate_limit_tpm_openai: u32,
+    #[serde(default)]
+    pub rate_limit_rpm_gemini: u32,
+    #[serde(default)]
+    pub rate_limit_tpm_gemini: u32,
+    #[serde(default = "default_reindex_delay_secs")]
+    pub reindex_delay_secs: u32,
+    #[serde(default = "default_max_import_bytes")]
+    pub max_import_bytes: u64,
+    #[serde(default)]
+    pub transcription_base_url: String,
+    #[serde(default)]
+    pub transcription_api_key: String,
+    #[serde(default = "default_transcription_model")]
+    pub transcription_model: String,
+}
+
+fn default_max_context_documents() -> u32 {
+    DEFAULT_MAX_CONTEXT_DOCUMENTS
+}
+
+fn default_vector_cache_size() -> u32 {
+    DEFAULT_VECTOR_CACHE_SIZE as u32
+}
+
+fn default_auto_name_chats() -> bool {
+    true
+}
+
+fn default_close_to_tray() -> bool {
+    true
+}
+
+fn default_compress_documents() -> bool {
+    true
+}
+
+fn default_naming_max_chars() -> u32 {
+    DEFAULT_NAMING_MAX_CHARS
+}
+
+fn default_rate_limit_rpm_openai() -> u32 {
+    DEFAULT_RATE_LIMIT_RPM_OPENAI
+}
+
+fn default_rate_limit_tpm_openai() -> u32 {
+    DEFAULT_RATE_LIMIT_TPM_OPENAI
+}
+
+fn default_reindex_delay_secs() -> u32 {
+    DEFAULT_REINDEX_DELAY_SECS
+}
+
+fn default_max_import_bytes() -> u64 {
+    DEFAULT_MAX_IMPORT_BYTES
+}
+
+fn default_embedding_retries() -> u32 {
+    DEFAULT_EMBEDDING_RETRIES
+}
+
+fn default_embedding_model() -> String {
+    DEFAULT_EMBEDDING_MODEL.to_string()
+}
+
+fn default_transcription_model() -> String {
+    DEFAULT_TRANSCRIPTION_MODEL.to_string()
+}
+
+fn default_assistant_persona() -> String {
+    DEFAULT_ASSISTANT_PERSONA.to_string()
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            is_dev_mode: false,
+            interval: "20".to_string(),
+            auto_start: false,
+            api_choice: DEFAULT_API_CHOICE.to_string(),
+            api_key_claude: String::new(),
+            api_key_open_ai: String::new(),
+            api_key_gemini: String::new(),
+            local_model_url: DEFAULT_LOCAL_MODEL_URL.to_string(),
+            vectorization_enabled: false,
+            rag_top_k: DEFAULT_RAG_TOP_K as i32,
+            max_context_documents: default_max_context_documents(),
+            openai_base_url: String::new(),
+            openai_org_id: String::new(),
+            openai_project_id: String::new(),
+            max_output_tokens: 0,
+            vector_cache_size: default_vector_cache_size(),
+            recording_sample_rate: 0,
+            fallback_providers: Vec::new(),
+            auto_name_chats: default_auto_name_chats(),
+            embedding_retries: default_embedding_retries(),
+            redact_pii_before_send: false,
+            redaction_denylist: Vec::new(),
+            assistant_persona: default_assistant_persona(),
+            ocr_scanned_pdfs: false,
+            close_to_tray: default_close_to_tray(),
+            attribute_sources: false,
+            embedding_model: default_embedding_model(),
+            live_transcription: false,
+            compress_documents: default_compress_documents(),
+            expand_chunk_context: false,
+            rag_on_every_turn: false,
+            naming_model_claude: String::new(),
+            naming_model_openai: String::new(),
+            naming_model_gemini: String::new(),
+            naming_model_local: String::new(),
+            naming_max_chars: default_naming_max_chars(),
+            rate_limit_rpm_claude: 0,
+            rate_limit_tpm_claude: 0,
+            rate_limit_rpm_openai: default_rate_limit_rpm_openai(),
+            rate_limit_tpm_openai: default_rate_limit_tpm_openai(),
+            rate_limit_rpm_gemini: 0,
+            rate_limit_tpm_gemini: 0,
+            reindex_delay_secs: default_reindex_delay_secs(),
+            max_import_bytes: default_max_import_bytes(),
+            transcription_base_url: String::new(),
+            transcription_api_key: String::new(),
+            transcription_model: default_transcription_model(),
+        }
+    }
+}
+
+impl Settings {
+    /// Assemble typed settings from the raw key/value rows. Missing, empty or
+    /// unparsable values fall back to the defaults.
+    pub fn from_setting_rows(rows: &[Setting]) -> Settings {
+        let mut settings = Settings::default();
+        for row in rows {
+            let value = row.setting_value.as_str();
+            if value.is_empty() {
+                continue;
+            }
+            match row.setting_key.as_str() {
+                "is_dev_mode" => settings.is_dev_mode = value == "true",
+                "interval" => settings.interval = value.to_string(),
+                "auto_start" => settings.auto_start = value == "true",
+                API_CHOICE_KEY => settings.api_choice = value.to_string(),
+                "api_key_claude" => settings.api_key_claude = value.to_string(),
+                "api_key_open_ai" => settings.api_key_open_ai = value.to_string(),
+                "api_key_gemini" => settings.api_key_gemini = value.to_string(),
+                LOCAL_MODEL_URL_KEY => settings.local_model_url = value.to_string(),
+                "vectorization_enabled" => settings.vectorization_enabled = value == "true",
+                RAG_TOP_K_KEY => {
+                    if let Ok(top_k) = value.parse() {
+                        settings.rag_top_k = top_k;
+                    }
+                }
+                MAX_CONTEXT_DOCUMENTS_KEY => {
+                    if let Ok(max_context_documents) = value.parse() {
+                        settings.max_context_documents = max_context_documents;
+                    }
+                }
+                OPENAI_BASE_URL_KEY => settings.openai_base_url = value.to_string(),
+                OPENAI_ORG_ID_KEY => settings.openai_org_id = value.to_string(),
+                OPENAI_PROJECT_ID_KEY => settings.openai_project_id = value.to_string(),
+                MAX_OUTPUT_TOKENS_KEY => {
+                    if let Ok(max_output_tokens) = value.parse() {
+                        settings.max_output_tokens = max_output_tokens;
+                    }
+                }
+                VECTOR_CACHE_SIZE_KEY => {
+                    if let Ok(vector_cache_size) = value.parse() {
+                        settings.vector_cache_size = vector_cache_size;
+                    }
+                }
+                RECORDING_SAMPLE_RATE_KEY => {
+                    if let Ok(recording_sample_rate) = value.parse() {
+                        settings.recording_sample_rate = recording_sample_rate;
+                    }
+                }
+                FALLBACK_PROVIDERS_KEY => settings.fallback_providers = parse_comma_list(value),
+                AUTO_NAME_CHATS_KEY => settings.auto_name_chats = value == "true",
+                EMBEDDING_RETRIES_KEY => {
+                    if let Ok(embedding_retries) = value.parse() {
+                        settings.embedding_retries = embedding_retries;
+                    }
+                }
+                REDACT_PII_KEY => settings.redact_pii_before_send = value == "true",
+                REDACTION_DENYLIST_KEY => settings.redaction_denylist = parse_comma_list(value),
+                ASSISTANT_PERSONA_KEY => settings.assistant_persona = value.to_string(),
+                OCR_SCANNED_PDFS_KEY => settings.ocr_scanned_pdfs = value == "true",
+                CLOSE_TO_TRAY_KEY => settings.close_to_tray = value == "true",
+                ATTRIBUTE_SOURCES_KEY => settings.attribute_sources = value == "true",
+                EMBEDDING_MODEL_KEY => settings.embedding_model = value.to_string(),
+                LIVE_TRANSCRIPTION_KEY => settings.live_transcription = value == "true",
+                COMPRESS_DOCUMENTS_KEY => settings.compress_documents = value != "false",
+                EXPAND_CHUNK_CONTEXT_KEY => settings.expand_chunk_context = value == "true",
+                RAG_ON_EVERY_TURN_KEY => settings.rag_on_every_turn = value == "true",
+                NAMING_MODEL_CLAUDE_KEY => settings.naming_model_claude = value.to_string(),
+                NAMING_MODEL_OPENAI_KEY => settings.naming_model_openai = value.to_string(),
+                NAMING_MODEL_GEMINI_KEY => settings.naming_model_gemini = value.to_string(),
+                NAMING_MODEL_LOCAL_KEY => settings.naming_model_local = value.to_string(),
+                NAMING_MAX_CHARS_KEY => {
+                    if let Ok(naming_max_chars) = value.parse() {
+                        settings.naming_max_chars = naming_max_chars;
+                    }
+                }
+                RATE_LIMIT_RPM_CLAUDE_KEY => {
+                    if let Ok(limit) = value.parse() {
+                        settings.rate_limit_rpm_claude = limit;
+                    }
+                }
+                RATE_LIMIT_TPM_CLAUDE_KEY => {
+                    if let Ok(limit) = value.parse() {
+                        settings.rate_limit_tpm_claude = limit;
+                    }
+                }
+                RATE_LIMIT_RPM_OPENAI_KEY => {
+                    if let Ok(limit) = value.parse() {
+                        settings.rate_limit_rpm_openai = limit;
+                    }
+                }
+                RATE_LIMIT_TPM_OPENAI_KEY => {
+                    if let Ok(limit) = value.parse() {
+                        settings.rate_limit_tpm_openai = limit;
+                    }
+                }
+                RATE_LIMIT_RPM_GEMINI_KEY => {
+                    if let Ok(limit) = value.parse() {
+                        settings.rate_limit_rpm_gemini = limit;
+                    }
+                }
+                RATE_LIMIT_TPM_GEMINI_KEY => {
+                    if let Ok(limit) = value.parse() {
+                        settings.rate_limit_tpm_gemini = limit;
+                    }
+                }
+                REINDEX_DELAY_SECS_KEY => {
+                    if let Ok(reindex_delay_secs) = value.parse() {
+                        settings.reindex_delay_secs = reindex_delay_secs;
+                    }
+                }
+                MAX_IMPORT_BYTES_KEY => {
+                    if let Ok(max_import_bytes) = value.parse() {
+                        settings.max_import_bytes = max_import_bytes;
+                    }
+                }
+                TRANSCRIPTION_BASE_URL_KEY => settings.transcription_base_url = value.to_string(),
+                TRANSCRIPTION_API_KEY_KEY => settings.transcription_api_key = value.to_string(),
+                TRANSCRIPTION_MODEL_KEY => settings.transcription_model = value.to_string(),
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    /// Returns the names of the fields that would be rejected on save.
+    /// Empty URLs are allowed and fall back to the default endpoints.
+    pub fn invalid_fields(&self) -> Vec<String> {
+        let mut rejected = Vec::new();
+
+        if self.interval.trim().parse::<u32>().is_err() {
+            rejected.push("interval".to_string());
+        }
+        if !API_CHOICES.contains(&self.api_choice.as_str()) {
+            rejected.push("api_choice".to_string());
+        }
+        if !self.local_model_url.is_empty() && !is_http_url(&self.local_model_url) {
+            rejected.push("local_model_url".to_string());
+        }
+        if !self.openai_base_url.is_empty() && !is_http_url(&self.openai_base_url) {
+            rejected.push("openai_base_url".to_string());
+        }
+        if !self.transcription_base_url.is_empty() && !is_http_url(&self.transcription_base_url) {
+            rejected.push(TRANSCRIPTION_BASE_URL_KEY.to_string());
+        }
+        if !(MIN_RAG_TOP_K..=MAX_RAG_TOP_K).contains(&self.rag_top_k) {
+            rejected.push("rag_top_k".to_string());
+        }
+        if !(MIN_MAX_CONTEXT_DOCUMENTS..=MAX_MAX_CONTEXT_DOCUMENTS).contains(&self.max_context_documents) {
+            rejected.push(MAX_CONTEXT_DOCUMENTS_KEY.to_string());
+        }
+        if !(MIN_VECTOR_CACHE_SIZE..=MAX_VECTOR_CACHE_SIZE).contains(&self.vector_cache_size) {
+            rejected.push(VECTOR_CACHE_SIZE_KEY.to_string());
+        }
+        if self.recording_sample_rate != 0
+            && !(MIN_RECORDING_SAMPLE_RATE..=MAX_RECORDING_SAMPLE_RATE).contains(&self.recording_sample_rate)
+        {
+            rejected.push(RECORDING_SAMPLE_RATE_KEY.to_string());
+        }
+        if self.fallback_providers.iter().any(|provider| !API_CHOICES.contains(&provider.as_str())) {
+            rejected.push(FALLBACK_PROVIDERS_KEY.to_string());
+        }
+        if self.embedding_retries > MAX_EMBEDDING_RETRIES {
+            rejected.push(EMBEDDING_RETRIES_KEY.to_string());
+        }
+        if !(MIN_NAMING_MAX_CHARS..=MAX_NAMING_MAX_CHARS).contains(&self.naming_max_chars) {
+            rejected.push(NAMING_MAX_CHARS_KEY.to_string());
+        }
+        if !(MIN_REINDEX_DELAY_SECS..=MAX_REINDEX_DELAY_SECS).contains(&self.reindex_delay_secs) {
+            rejected.push(REINDEX_DELAY_SECS_KEY.to_string());
+        }
+        if !(MIN_MAX_IMPORT_BYTES..=MAX_MAX_IMPORT_BYTES).contains(&self.max_import_bytes) {
+            rejected.push(MAX_IMPORT_BYTES_KEY.to_string());
+        }
+
+        rejected
+    }
+
+    /// Flatten into the key/value rows stored in the `settings` table
+    pub fn to_setting_rows(&self) -> Vec<Setting> {
+        let row = |key: &str, value: String| Setting {
+            setting_key: key.to_string(),
+            setting_value: value,
+        };
+        vec![
+            row("interval", self.interval.clone()),
+            row("is_dev_mode", self.is_dev_mode.to_string()),
+            row("auto_start", self.auto_start.to_string()),
+            row(API_CHOICE_KEY, self.api_choice.clone()),
+            row("api_key_claude", self.api_key_claude.clone()),
+            row("api_key_open_ai", self.api_key_open_ai.clone()),
+            row("api_key_gemini", self.api_key_gemini.clone()),
+            row(LOCAL_MODEL_URL_KEY, self.local_model_url.clone()),
+            row("vectorization_enabled", self.vectorization_enabled.to_string()),
+            row(RAG_TOP_K_KEY, self.rag_top_k.to_string()),
+            row(MAX_CONTEXT_DOCUMENTS_KEY, self.max_context_documents.to_string()),
+            row(OPENAI_BASE_URL_KEY, self.openai_base_url.clone()),
+            row(OPENAI_ORG_ID_KEY, self.openai_org_id.clone()),
+            row(OPENAI_PROJECT_ID_KEY, self.openai_project_id.clone()),
+            row(MAX_OUTPUT_TOKENS_KEY, self.max_output_tokens.to_string()),
+            row(VECTOR_CACHE_SIZE_KEY, self.vector_cache_size.to_string()),
+            row(RECORDING_SAMPLE_RATE_KEY, self.recording_sample_rate.to_string()),
+            row(FALLBACK_PROVIDERS_KEY, self.fallback_providers.join(",")),
+            row(AUTO_NAME_CHATS_KEY, self.auto_name_chats.to_string()),
+            row(EMBEDDING_RETRIES_KEY, self.embedding_retries.to_string()),
+            row(REDACT_PII_KEY, self.redact_pii_before_send.to_string()),
+            row(REDACTION_DENYLIST_KEY, self.redaction_denylist.join(",")),
+            row(ASSISTANT_PERSONA_KEY, self.assistant_persona.clone()),
+            row(OCR_SCANNED_PDFS_KEY, self.ocr_scanned_pdfs.to_string()),
+            row(CLOSE_TO_TRAY_KEY, self.close_to_tray.to_string()),
+            row(ATTRIBUTE_SOURCES_KEY, self.attribute_sources.to_string()),
+            row(EMBEDDING_MODEL_KEY, self.embedding_model.clone()),
+            row(LIVE_TRANSCRIPTION_KEY, self.live_transcription.to_string()),
+            row(COMPRESS_DOCUMENTS_KEY, self.compress_documents.to_string()),
+            row(EXPAND_CHUNK_CONTEXT_KEY, self.expand_chunk_context.to_string()),
+            row(RAG_ON_EVERY_TURN_KEY, self.rag_on_every_turn.to_string()),
+            row(NAMING_MODEL_CLAUDE_KEY, self.naming_model_claude.clone()),
+            row(NAMING_MODEL_OPENAI_KEY, self.naming_model_openai.clone()),
+            row(NAMING_MODEL_GEMINI_KEY, self.naming_model_gemini.clone()),
+            row(NAMING_MODEL_LOCAL_KEY, self.naming_model_local.clone()),
+            row(NAMING_MAX_CHARS_KEY, self.naming_max_chars.to_string()),
+            row(RATE_LIMIT_RPM_CLAUDE_KEY, self.rate_limit_rpm_claude.to_string()),
+            row(RATE_LIMIT_TPM_CLAUDE_KEY, self.rate_limit_tpm_claude.to_string()),
+            row(RATE_LIMIT_RPM_OPENAI_KEY, self.rate_limit_rpm_openai.to_string()),
+            row(RATE_LIMIT_TPM_OPENAI_KEY, self.rate_limit_tpm_openai.to_string()),
+            row(RATE_LIMIT_RPM_GEMINI_KEY, self.rate_limit_rpm_gemini.to_string()),
+            row(RATE_LIMIT_TPM_GEMINI_KEY, self.rate_limit_tpm_gemini.to_string()),
+            row(REINDEX_DELAY_SECS_KEY, self.reindex_delay_secs.to_string()),
+            row(MAX_IMPORT_BYTES_KEY, self.max_import_bytes.to_string()),
+            row(TRANSCRIPTION_BASE_URL_KEY, self.transcription_base_url.clone()),
+            row(TRANSCRIPTION_API_KEY_KEY, self.transcription_api_key.clone()),
+            row(TRANSCRIPTION_MODEL_KEY, self.transcription_model.clone()),
+        ]
+    }
+}
+
+/// Split a stored comma-separated list, dropping blanks
+pub fn parse_comma_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn is_http_url(value: &str) -> bool {
+    match reqwest::Url::parse(value.trim()) {
+        Ok(url) => matches!(url.scheme(), "http" | "https") && url.host_str().is_some(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn valid_settings() -> Settings {
+        Settings::default()
+    }
+
+    #[test]
+    fn test_valid_settings_pass() {
+        assert!(valid_settings().invalid_fields().is_empty());
+    }
+
+    #[test]
+    fn test_round_trips_through_rows() {
+        let settings = Settings {
+            vectorization_enabled: true,
+            rag_top_k: 8,
+            max_context_documents: 10,
+            api_choice: "gemini".to_string(),
+            max_output_tokens: 16000,
+            vector_cache_size: 12,
+            recording_sample_rate: 16000,
+            fallback_providers: vec!["openai".to_string(), "local".to_string()],
+            auto_name_chats: false,
+            embedding_retries: 0,
+            redact_pii_before_send: true,
+            redaction_denylist: vec!["Project Falcon".to_string(), "Acme".to_string()],
+            assistant_persona: "You are Acme Assistant.".to_string(),
+            ocr_scanned_pdfs: true,
+            close_to_tray: false,
+            attribute_sources: true,
+            embedding_model: "text-embedding-3-large".to_string(),
+            live_transcription: true,
+            compress_documents: false,
+            expand_chunk_context: true,
+            rag_on_every_turn: true,
+            naming_model_openai: "gpt-4.1-nano".to_string(),
+            naming_max_chars: 40,
+            rate_limit_rpm_claude: 50,
+            rate_limit_tpm_openai: 0,
+            reindex_delay_secs: 30,
+            openai_org_id: "org-acme".to_string(),
+            max_import_bytes: 10 * 1024 * 1024,
+            transcription_base_url: "https://api.groq.com/openai/v1".to_string(),
+            transcription_api_key: "gsk-test".to_string(),
+            transcription_model: "whisper-large-v3".to_string(),
+            ..Settings::default()
+        };
+        let loaded = Settings::from_setting_rows(&settings.to_setting_rows());
+        assert!(loaded.vectorization_enabled);
+        assert_eq!(loaded.rag_top_k, 8);
+        assert_eq!(loaded.max_context_documents, 10);
+        assert_eq!(loaded.max_output_tokens, 16000);
+        assert_eq!(loaded.vector_cache_size, 12);
+        assert_eq!(loaded.recording_sample_rate, 16000);
+        assert_eq!(loaded.api_choice, "gemini");
+        assert_eq!(loaded.fallback_providers, vec!["openai", "local"]);
+        assert!(!loaded.auto_name_chats);
+        assert_eq!(loaded.embedding_retries, 0);
+        assert!(loaded.redact_pii_before_send);
+        assert_eq!(loaded.redaction_denylist, vec!["Project Falcon", "Acme"]);
+        assert_eq!(loaded.assistant_persona, "You are Acme Assistant.");
+        assert!(loaded.ocr_scanned_pdfs);
+        assert!(!loaded.close_to_tray);
+        assert!(loaded.attribute_sources);
+        assert_eq!(loaded.embedding_model, "text-embedding-3-large");
+        assert!(loaded.live_transcription);
+        assert!(!loaded.compress_documents);
+        assert!(loaded.expand_chunk_context);
+        assert!(loaded.rag_on_every_turn);
+        assert_eq!(loaded.naming_model_openai, "gpt-4.1-nano");
+        assert!(loaded.naming_model_claude.is_empty());
+        assert_eq!(loaded.naming_max_chars, 40);
+        assert_eq!(loaded.rate_limit_rpm_claude, 50);
+        assert_eq!(loaded.rate_limit_tpm_openai, 0);
+        assert_eq!(loaded.rate_limit_rpm_openai, DEFAULT_RATE_LIMIT_RPM_OPENAI);
+        assert_eq!(loaded.reindex_delay_secs, 30);
+        assert_eq!(loaded.openai_org_id, "org-acme");
+        assert!(loaded.openai_project_id.is_empty());
+        assert_eq!(loaded.max_import_bytes, 10 * 1024 * 1024);
+        assert_eq!(loaded.transcription_base_url, "https://api.groq.com/openai/v1");
+        assert_eq!(loaded.transcription_api_key, "gsk-test");
+        assert_eq!(loaded.transcription_model, "whisper-large-v3");
+    }
+
+    #[test]
+    fn test_missing_and_bad_rows_use_defaults() {
+        let rows = vec![Setting {
+            setting_key: "rag_top_k".to_string(),
+            setting_value: "lots".to_string(),
+        }];
+        let loaded = Settings::from_setting_rows(&rows);
+        assert_eq!(loaded.rag_top_k, DEFAULT_RAG_TOP_K as i32);
+        assert_eq!(loaded.max_context_documents, DEFAULT_MAX_CONTEXT_DOCUMENTS);
+        assert_eq!(loaded.local_model_url, DEFAULT_LOCAL_MODEL_URL);
+        assert!(loaded.auto_name_chats);
+        assert_eq!(loaded.embedding_retries, DEFAULT_EMBEDDING_RETRIES);
+        assert_eq!(loaded.assistant_persona, DEFAULT_ASSISTANT_PERSONA);
+        assert_eq!(loaded.embedding_model, DEFAULT_EMBEDDING_MODEL);
+        assert!(loaded.close_to_tray);
+        assert!(loaded.compress_documents);
+        assert!(!loaded.expand_chunk_context);
+        assert!(!loaded.rag_on_every_turn);
+        assert_eq!(loaded.naming_max_chars, DEFAULT_NAMING_MAX_CHARS);
+        assert_eq!(loaded.rate_limit_tpm_openai, DEFAULT_RATE_LIMIT_TPM_OPENAI);
+        assert_eq!(loaded.rate_limit_rpm_claude, 0);
+        assert_eq!(loaded.reindex_delay_secs, DEFAULT_REINDEX_DELAY_SECS);
+        assert_eq!(loaded.max_import_bytes, DEFAULT_MAX_IMPORT_BYTES);
+        assert!(loaded.transcription_base_url.is_empty());
+        assert!(loaded.transcription_api_key.is_empty());
+        assert_eq!(loaded.transcription_model, DEFAULT_TRANSCRIPTION_MODEL);
+    }
+
+    #[test]
+    fn test_rejected_fields_are_listed() {
+        let settings = Settings {
+            interval: "often".to_string(),
+            api_choice: "mistral".to_string(),
+            local_model_url: "localhost 11434".to_string(),
+            openai_base_url: "ftp://proxy".to_string(),
+            transcription_base_url: "groq".to_string(),
+            rag_top_k: 0,
+            max_context_documents: 0,
+            fallback_providers: vec!["openai".to_string(), "mistral".to_string()],
+            embedding_retries: 9,
+            naming_max_chars: 500,
+            reindex_delay_secs: 0,
+            max_import_bytes: 0,
+            ..valid_settings()
+        };
+        assert_eq!(
+            settings.invalid_fields(),
+            vec![
+                "interval",
+                "api_choice",
+                "local_model_url",
+                "openai_base_url",
+                "transcription_base_url",
+                "rag_top_k",
+                "max_context_documents",
+                "fallback_providers",
+                "embedding_retries",
+                "naming_max_chars",
+                "reindex_delay_secs",
+                "max_import_bytes"
+            ]
+        );
+    }
+}
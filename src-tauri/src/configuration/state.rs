@@ -1,8 +1,52 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard};
+
 use rusqlite::Connection;
 use tauri::{AppHandle, Manager, State};
 
+use crate::error::HeelixResult;
+
+#[derive(Default)]
 pub struct AppState {
-    pub db: std::sync::Mutex<Option<Connection>>,
+    pub db: Mutex<Option<Connection>>,
+    /// Set while the connection is closed for file work done outside the lock
+    db_released: AtomicBool,
+    /// Notified when a released connection is given back
+    db_returned: Condvar,
+}
+
+impl AppState {
+    /// Lock the connection slot, waiting while the connection is released
+    fn lock_db(&self) -> MutexGuard<'_, Option<Connection>> {
+        self.db_returned
+            .wait_while(self.db.lock().unwrap(), |_| self.db_released.load(Ordering::SeqCst))
+            .unwrap()
+    }
+
+    /// Close the connection so its files can be copied or replaced without
+    /// holding the lock. Database calls wait until `return_db`. Returns whether
+    /// a database was open.
+    pub fn release_db(&self) -> HeelixResult<bool> {
+        let mut db_slot = self.lock_db();
+        let db = match db_slot.take() {
+            Some(db) => db,
+            None => return Ok(false),
+        };
+        // Closing checkpoints pending writes into the database file
+        if let Err((db, e)) = db.close() {
+            *db_slot = Some(db);
+            return Err(e.into());
+        }
+        self.db_released.store(true, Ordering::SeqCst);
+        Ok(true)
+    }
+
+    /// Put a connection back after `release_db` and wake the waiting calls
+    pub fn return_db(&self, db: Connection) {
+        *self.db.lock().unwrap() = Some(db);
+        self.db_released.store(false, Ordering::SeqCst);
+        self.db_returned.notify_all();
+    }
 }
 
 pub trait ServiceAccess {
@@ -14,7 +58,7 @@ pub trait ServiceAccess {
 impl ServiceAccess for AppHandle {
     fn db<F, TResult>(&self, operation: F) -> TResult where F: FnOnce(&Connection) -> TResult {
         let app_state: State<AppState> = self.state();
-        let db_connection_guard = app_state.db.lock().unwrap();
+        let db_connection_guard = app_state.lock_db();
         let db = db_connection_guard.as_ref().unwrap();
 
         operation(db)
@@ -22,9 +66,9 @@ impl ServiceAccess for AppHandle {
 
     fn db_mut<F, TResult>(&self, operation: F) -> TResult where F: FnOnce(&mut Connection) -> TResult {
         let app_state: State<AppState> = self.state();
-        let mut db_connection_guard = app_state.db.lock().unwrap();
+        let mut db_connection_guard = app_state.lock_db();
         let db = db_connection_guard.as_mut().unwrap();
 
         operation(db)
     }
-}
\ No newline at end of file
+}
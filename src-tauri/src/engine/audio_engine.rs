@@ -9,8 +9,122 @@ pub static IS_RECORDING: AtomicBool = AtomicBool::new(false);
 pub static RECORDING_PATH: once_cell::sync::Lazy<Arc<std::sync::Mutex<Option<String>>>> = 
     once_cell::sync::Lazy::new(|| Arc::new(std::sync::Mutex::new(None)));
 
-/// Record audio to a WAV file 
-pub fn record_audio(file_path: &str) -> Result<(), String> {
+/// Samples of a live recording not yet taken by the live transcriber. None
+/// unless the recording was started with `live`.
+static LIVE_SAMPLES: once_cell::sync::Lazy<std::sync::Mutex<Option<LiveSamples>>> =
+    once_cell::sync::Lazy::new(|| std::sync::Mutex::new(None));
+
+/// Audio written to a live recording, at the rate of its WAV file
+pub struct LiveSamples {
+    pub sample_rate: u32,
+    pub samples: Vec<i16>,
+}
+
+/// Take up to `max_millis` of audio from the front of the live recording's
+/// buffer once it holds at least `min_millis`. Returns the sample rate and samples.
+pub fn take_live_samples(min_millis: u32, max_millis: u32) -> Option<(u32, Vec<i16>)> {
+    let mut live = LIVE_SAMPLES.lock().unwrap();
+    let live = live.as_mut()?;
+    let samples_in = |millis: u32| (u64::from(live.sample_rate) * u64::from(millis) / 1000) as usize;
+    let (min_samples, max_samples) = (samples_in(min_millis), samples_in(max_millis));
+    if live.samples.is_empty() || live.samples.len() < min_samples {
+        return None;
+    }
+    let count = live.samples.len().min(max_samples);
+    Some((live.sample_rate, live.samples.drain(..count).collect()))
+}
+
+/// Drop the live buffer once the live transcriber has taken the last of it
+pub fn end_live_samples() {
+    *LIVE_SAMPLES.lock().unwrap() = None;
+}
+
+fn to_i16(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * 32767.0) as i16
+}
+
+/// Streaming linear-interpolation resampler for mono audio
+pub struct LinearResampler {
+    /// Input samples advanced per output sample
+    step: f64,
+    /// Position of the next output sample, in input samples after `prev`
+    phase: f64,
+    prev: Option<f32>,
+}
+
+impl LinearResampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        LinearResampler {
+            step: f64::from(input_rate) / f64::from(output_rate),
+            phase: 0.0,
+            prev: None,
+        }
+    }
+
+    /// Feed the next block of input and append the resampled output to `out`
+    pub fn process(&mut self, input: &[f32], out: &mut Vec<f32>) {
+        for &current in input {
+            let prev = match self.prev {
+                Some(prev) => prev,
+                None => {
+                    self.prev = Some(current);
+                    continue;
+                }
+            };
+            while self.phase <= 1.0 {
+                out.push(prev + (current - prev) * self.phase as f32);
+                self.phase += self.step;
+            }
+            self.phase -= 1.0;
+            self.prev = Some(current);
+        }
+    }
+}
+
+/// Rate the WAV is written at: the target when it is below the device rate,
+/// otherwise the device rate. Recordings are never upsampled.
+pub fn effective_sample_rate(device_rate: u32, target_rate: Option<u32>) -> u32 {
+    match target_rate {
+        Some(target) if target > 0 && target < device_rate => target,
+        _ => device_rate,
+    }
+}
+
+/// WAV writer plus the resampler feeding it
+struct RecordingSink {
+    writer: hound::WavWriter<std::io::BufWriter<std::fs::File>>,
+    resampler: Option<LinearResampler>,
+    buffer: Vec<f32>,
+    /// Also hand written samples to the live transcriber
+    live: bool,
+}
+
+impl RecordingSink {
+    fn write(&mut self, samples: impl Iterator<Item = f32>) {
+        let output: Vec<i16> = match self.resampler.as_mut() {
+            Some(resampler) => {
+                let input: Vec<f32> = samples.collect();
+                self.buffer.clear();
+                resampler.process(&input, &mut self.buffer);
+                self.buffer.iter().copied().map(to_i16).collect()
+            }
+            None => samples.map(to_i16).collect(),
+        };
+        for &sample in &output {
+            self.writer.write_sample(sample).unwrap();
+        }
+        if self.live {
+            if let Some(live) = LIVE_SAMPLES.lock().unwrap().as_mut() {
+                live.samples.extend_from_slice(&output);
+            }
+        }
+    }
+}
+
+/// Record audio to a WAV file, downsampled to `target_rate` when it is set
+/// and lower than the device's native rate. With `live` the samples are also
+/// buffered for `take_live_samples`.
+pub fn record_audio(file_path: &str, target_rate: Option<u32>, live: bool) -> Result<(), String> {
     use hound::{WavSpec, WavWriter};
     use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
     
@@ -22,19 +136,32 @@ pub fn record_audio(file_path: &str) -> Result<(), String> {
     // Get supported config
     let config = device.default_input_config()
         .map_err(|e| format!("Default config not supported: {}", e))?;
+    let device_rate = config.sample_rate().0;
+    let output_rate = effective_sample_rate(device_rate, target_rate);
     
     // Set up WAV writer - using mono (1 channel) instead of stereo
     let spec = WavSpec {
         channels: 1, // Force mono recording
-        sample_rate: config.sample_rate().0,
+        sample_rate: output_rate,
         bits_per_sample: 16,
         sample_format: hound::SampleFormat::Int,
     };
     
-    let writer = Arc::new(std::sync::Mutex::new(
-        WavWriter::create(file_path, spec)
-            .map_err(|e| format!("Failed to create WAV file: {}", e))?
-    ));
+    let sink = Arc::new(std::sync::Mutex::new(RecordingSink {
+        writer: WavWriter::create(file_path, spec)
+            .map_err(|e| format!("Failed to create WAV file: {}", e))?,
+        resampler: (output_rate != device_rate).then(|| LinearResampler::new(device_rate, output_rate)),
+        buffer: Vec::new(),
+        live,
+    }));
+    *LIVE_SAMPLES.lock().unwrap() = if live {
+        Some(LiveSamples {
+            sample_rate: output_rate,
+            samples: Vec::new(),
+        })
+    } else {
+        None
+    };
     
     // Create a modified configuration that forces mono
     let stream_config = cpal::StreamConfig {
@@ -48,16 +175,14 @@ pub fn record_audio(file_path: &str) -> Result<(), String> {
         eprintln!("an error occurred on stream: {}", err);
     };
     
-    let writer_clone = writer.clone();
+    let sink_clone = sink.clone();
     let stream = match config.sample_format() {
         cpal::SampleFormat::I16 => device.build_input_stream(
             &stream_config,
             move |data: &[i16], _: &_| {
                 if IS_RECORDING.load(Ordering::SeqCst) {
-                    let mut writer = writer_clone.lock().unwrap();
-                    for &sample in data {
-                        writer.write_sample(sample).unwrap();
-                    }
+                    let mut sink = sink_clone.lock().unwrap();
+                    sink.write(data.iter().map(|&sample| f32::from(sample) / 32768.0));
                 }
             },
             err_fn,
@@ -67,12 +192,8 @@ pub fn record_audio(file_path: &str) -> Result<(), String> {
             &stream_config,
             move |data: &[f32], _: &_| {
                 if IS_RECORDING.load(Ordering::SeqCst) {
-                    let mut writer = writer_clone.lock().unwrap();
-                    for &sample in data {
-                        // Convert f32 to i16
-                        let sample = (sample * 32767.0) as i16;
-                        writer.write_sample(sample).unwrap();
-                    }
+                    let mut sink = sink_clone.lock().unwrap();
+                    sink.write(data.iter().copied());
                 }
             },
             err_fn,
@@ -95,8 +216,10 @@ pub fn record_audio(file_path: &str) -> Result<(), String> {
     Ok(())
 }
 
-/// Start a new audio recording
-pub async fn start_recording() -> Result<String, String> {
+/// Start a new audio recording. `target_rate` is the `recording_sample_rate`
+/// setting, None to keep the device's native rate. `live` buffers the audio
+/// for the live transcriber as it is recorded.
+pub async fn start_recording(target_rate: Option<u32>, live: bool) -> Result<String, String> {
     // Check if already recording
     if IS_RECORDING.load(Ordering::SeqCst) {
         return Err("Already recording".to_string());
@@ -122,7 +245,7 @@ pub async fn start_recording() -> Result<String, String> {
     // Start recording in a separate thread
     let file_path_clone = file_path_str.clone();
     std::thread::spawn(move || {
-        if let Err(err) = record_audio(&file_path_clone) {
+        if let Err(err) = record_audio(&file_path_clone, target_rate, live) {
             eprintln!("Error recording audio: {}", err);
             IS_RECORDING.store(false, Ordering::SeqCst);
         }
@@ -162,3 +285,41 @@ pub fn read_audio_file(file_path: &str) -> Result<Vec<u8>, String> {
     std::fs::read(file_path)
         .map_err(|err| format!("Failed to read audio file: {}", err))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resampler_downsamples_by_rate_ratio() {
+        let mut resampler = LinearResampler::new(48000, 16000);
+        let input: Vec<f32> = (0..4800).map(|i| (i as f32 / 4800.0)).collect();
+        let mut out = Vec::new();
+        // Block boundaries must not change the result
+        for block in input.chunks(333) {
+            resampler.process(block, &mut out);
+        }
+        assert!((1599..=1601).contains(&out.len()));
+        // A linear ramp stays a ramp: every third input sample
+        assert!((out[100] - input[300]).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_live_samples_are_taken_in_windows() {
+        *LIVE_SAMPLES.lock().unwrap() = Some(LiveSamples { sample_rate: 1000, samples: (0..10).collect() });
+        assert_eq!(take_live_samples(20, 20), None);
+        assert_eq!(take_live_samples(4, 6), Some((1000, (0..6).collect())));
+        assert_eq!(take_live_samples(0, 100), Some((1000, (6..10).collect())));
+        assert_eq!(take_live_samples(0, 100), None);
+        end_live_samples();
+        assert_eq!(take_live_samples(0, 100), None);
+    }
+
+    #[test]
+    fn test_effective_rate_never_upsamples() {
+        assert_eq!(effective_sample_rate(48000, Some(16000)), 16000);
+        assert_eq!(effective_sample_rate(16000, Some(44100)), 16000);
+        assert_eq!(effective_sample_rate(44100, None), 44100);
+        assert_eq!(effective_sample_rate(44100, Some(0)), 44100);
+    }
+}
@@ -0,0 +1,602 @@
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::configuration::state::ServiceAccess;
+use crate::engine::chat_engine::{emit_chat_event, name_conversation, ChatCompletion, ClaudeProvider, Message};
+use crate::engine::context_budget::{
+    document_budget, select_documents, ContextDocument, CONTEXT_DOCUMENTS_EVENT,
+};
+use crate::engine::chat_engine_gemini::{name_conversation_gemini, GeminiProvider};
+use crate::engine::chat_engine_local::{name_conversation_local, LocalProvider};
+use crate::engine::chat_engine_openai::{generate_conversation_name, OpenAIProvider};
+use crate::engine::chat_provider::{retrieve_context, run_chat, ChatProvider, ChatRequest};
+use crate::engine::model_capabilities::{sampling_for, Sampling};
+use crate::engine::provider_status::record_request_outcome;
+use crate::engine::source_attribution::attribute_sources;
+use crate::error::{HeelixError, HeelixResult};
+use crate::repository::chat_db_repository::{
+    get_chat_name, get_messages_by_chat_id, update_chat, update_message_content,
+};
+use crate::repository::project_repository::get_activity_plain_text;
+use crate::repository::settings_repository::{
+    get_api_choice, get_assistant_persona, get_auto_name_chats, get_fallback_providers, get_max_output_tokens, is_provider_configured,
+};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningEffort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReasoningEffort::Low => "low",
+            ReasoningEffort::Medium => "medium",
+            ReasoningEffort::High => "high",
+        }
+    }
+
+    /// Claude `thinking.budget_tokens` for this effort level (the API minimum is 1024)
+    pub fn claude_budget_tokens(&self) -> usize {
+        match self {
+            ReasoningEffort::Low => 2048,
+            ReasoningEffort::Medium => 8192,
+            ReasoningEffort::High => 16384,
+        }
+    }
+}
+
+/// Shape of the answer. `JsonObject` is honoured by the OpenAI and Gemini engines.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+}
+
+impl Default for ResponseFormat {
+    fn default() -> Self {
+        ResponseFormat::Text
+    }
+}
+
+const MARKDOWN_INSTRUCTIONS: &str = "Provide answers in markdown format.";
+/// Used instead of the markdown instructions for JSON answers
+const JSON_INSTRUCTIONS: &str =
+    "Respond with a single valid JSON object and nothing else: no markdown, no code fences, no commentary.";
+
+/// The `assistant_persona` setting with `{provider}` replaced by
+/// `provider_label`, e.g. "OpenAI LLM"
+pub fn assistant_persona(app_handle: &tauri::AppHandle, provider_label: &str) -> HeelixResult<String> {
+    let persona = app_handle.db(get_assistant_persona)?;
+    Ok(persona.replace("{provider}", provider_label))
+}
+
+/// Base system prompt for a chat: the persona, then how to format the answer
+pub fn chat_system_prompt(
+    app_handle: &tauri::AppHandle,
+    provider_label: &str,
+    response_format: ResponseFormat,
+) -> HeelixResult<String> {
+    let instructions = match response_format {
+        ResponseFormat::Text => MARKDOWN_INSTRUCTIONS,
+        ResponseFormat::JsonObject => JSON_INSTRUCTIONS,
+    };
+    Ok(format!("{} {}", assistant_persona(app_handle, provider_label)?, instructions))
+}
+
+/// Parse error when `completion` is not valid JSON
+pub fn invalid_json_reason(completion: &str) -> Option<String> {
+    serde_json::from_str::<serde_json::Value>(completion.trim())
+        .err()
+        .map(|e| e.to_string())
+}
+
+/// Follow-up user message asking the model to fix an answer that did not parse
+pub fn json_correction_prompt(reason: &str) -> String {
+    format!(
+        "Your previous reply was not valid JSON ({}). Reply again with only the corrected JSON object.",
+        reason
+    )
+}
+
+/// Optional per-request tuning shared by every provider. Engines ignore the
+/// tuning options their selected model does not support.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct PromptOptions {
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Image files to attach to the latest user message. Rejected for models
+    /// without vision, except local models, which drop them with a warning.
+    #[serde(default)]
+    pub image_paths: Vec<String>,
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    /// Randomness of the answer; clamped to the provider's range, skipped for
+    /// models that do not take it
+    pub temperature: Option<f32>,
+    /// Nucleus sampling cutoff, 0-1
+    pub top_p: Option<f32>,
+}
+
+impl PromptOptions {
+    /// Sampling parameters to send to `provider` for `model_id`
+    pub fn sampling(&self, provider: &str, model_id: &str) -> Sampling {
+        sampling_for(provider, model_id, self.temperature, self.top_p)
+    }
+}
+
+/// Payload of the `provider_fallback` event, emitted before the next provider
+/// in the fallback chain is tried
+#[derive(Serialize, Clone)]
+pub struct ProviderFallback {
+    pub from: String,
+    pub to: String,
+    pub reason: String,
+}
+
+/// Providers to try after `provider`: the configured fallback order without
+/// the primary, duplicates, or (with images attached) Gemini, which rejects them
+pub fn fallback_chain(provider: &str, fallback_providers: Vec<String>, has_images: bool) -> Vec<String> {
+    let mut chain: Vec<String> = Vec::new();
+    for fallback in fallback_providers {
+        if fallback == provider || chain.contains(&fallback) || (has_images && fallback == "gemini") {
+            continue;
+        }
+        chain.push(fallback);
+    }
+    chain
+}
+
+/// Load the documents in `activity_ids` and keep as many as fit the context
+/// window of `model_id`, emitting `context_documents` with what was kept and dropped
+fn budget_activity_context(
+    app_handle: &tauri::AppHandle,
+    activity_ids: &[i64],
+    model_id: &str,
+    conversation_history: &[Message],
+    chat_id: Option<i64>,
+) -> HeelixResult<String> {
+    if activity_ids.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut documents = Vec::new();
+    for &document_id in activity_ids {
+        if documents.iter().any(|document: &ContextDocument| document.document_id == document_id) {
+            continue;
+        }
+        match app_handle.db(|db| get_activity_plain_text(db, document_id)) {
+            Ok((name, text)) => documents.push(ContextDocument { document_id, name, text }),
+            Err(rusqlite::Error::QueryReturnedNoRows) => {
+                warn!("Selected document {} no longer exists, skipping it", document_id)
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    let max_output_tokens = app_handle.db(get_max_output_tokens)?;
+    let budget = document_budget(model_id, max_output_tokens, conversation_history);
+    let prompt = conversation_history.last().map_or("", |message| message.content.as_str());
+    let selection = select_documents(documents, prompt, budget);
+    if !selection.dropped.is_empty() {
+        info!(
+            "Context budget of {} tokens: kept {} documents, dropped {}",
+            budget,
+            selection.included.len(),
+            selection.dropped.len()
+        );
+    }
+    emit_chat_event(app_handle, CONTEXT_DOCUMENTS_EVENT, chat_id, selection.clone())?;
+    Ok(selection.text)
+}
+
+/// Reject a prompt whose last message has no text before any embeddings or
+/// completion request is made. A message carrying images is a question about them.
+fn check_prompt_not_empty(conversation_history: &[Message], has_images: bool) -> HeelixResult<()> {
+    let empty = conversation_history
+        .last()
+        .map_or(true, |message| message.content.trim().is_empty());
+    if empty && !has_images {
+        return Err(HeelixError::InvalidInput("The message is empty".to_string()));
+    }
+    Ok(())
+}
+
+/// Reject naming input with no text; any name given to it would be made up
+pub fn check_naming_input(user_input: &str) -> HeelixResult<()> {
+    if user_input.trim().is_empty() {
+        return Err(HeelixError::InvalidInput("There is no text to name the conversation from".to_string()));
+    }
+    Ok(())
+}
+
+/// Single entry point for chat: routes to the engine for `provider`
+/// ("claude", "openai", "gemini" or "local"). When that fails with a retryable
+/// error, the providers in the `fallback_providers` setting that have keys
+/// configured are tried in order with their default models.
+///
+/// `activity_ids` are the documents selected as context; they are loaded and
+/// trimmed to the selected model's context window here. Retrieval searches
+/// `project_id`, or all of `project_ids` when given.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_prompt(
+    app_handle: tauri::AppHandle,
+    provider: String,
+    conversation_history: Vec<Message>,
+    is_first_message: bool,
+    activity_ids: Vec<i64>,
+    model_id: Option<String>,
+    project_id: Option<i64>,
+    project_ids: Option<Vec<i64>>,
+    chat_id: Option<i64>,
+    options: Option<PromptOptions>,
+) -> HeelixResult<ChatCompletion> {
+    let has_images = options.as_ref().map_or(false, |o| !o.image_paths.is_empty());
+    check_prompt_not_empty(&conversation_history, has_images)?;
+    let combined_activity_text = budget_activity_context(
+        &app_handle,
+        &activity_ids,
+        &model_for(&provider, model_id.as_deref())?,
+        &conversation_history,
+        chat_id,
+    )?;
+    let fallback_providers = app_handle.db(get_fallback_providers)?;
+    let chain = fallback_chain(&provider, fallback_providers, has_images);
+
+    let mut request = ChatRequest {
+        conversation_history,
+        is_first_message,
+        combined_activity_text,
+        model_id,
+        project_ids: project_ids
+            .filter(|ids| !ids.is_empty())
+            .unwrap_or_else(|| project_id.into_iter().collect()),
+        chat_id,
+        options,
+        retrieved_context: None,
+    };
+    // Retrieved once, so the sources are emitted before any answer streams
+    // and are not searched for again when a fallback provider steps in
+    request.retrieved_context = Some(retrieve_context(&app_handle, &request).await?);
+    let mut current = provider;
+    let mut remaining = chain.into_iter();
+    loop {
+        let result = send_prompt_to_provider(&app_handle, &current, request.clone()).await;
+        record_request_outcome(&current, &result);
+
+        let error = match result {
+            Ok(completion) => {
+                spawn_auto_name(&app_handle, &current, chat_id, &request.conversation_history);
+                spawn_source_attribution(&app_handle, chat_id, &completion.text);
+                return Ok(completion);
+            }
+            Err(error) if error.is_retryable() => error,
+            Err(error) => return Err(error),
+        };
+        let next = loop {
+            match remaining.next() {
+                Some(candidate) if app_handle.db(|db| is_provider_configured(db, &candidate))? => {
+                    break candidate
+                }
+                Some(candidate) => info!("Skipping fallback provider {} without an API key", candidate),
+                None => return Err(error),
+            }
+        };
+
+        warn!("{} failed ({}), falling back to {}", current, error, next);
+        emit_chat_event(
+            &app_handle,
+            "provider_fallback",
+            chat_id,
+            ProviderFallback {
+                from: current.clone(),
+                to: next.clone(),
+                reason: error.to_string(),
+            },
+        )?;
+        current = next;
+        // Fallback providers answer with their default models
+        request.model_id = None;
+    }
+}
+
+async fn send_prompt_to_provider(
+    app_handle: &tauri::AppHandle,
+    provider: &str,
+    request: ChatRequest,
+) -> HeelixResult<ChatCompletion> {
+    let has_images = request.options.as_ref().map_or(false, |o| !o.image_paths.is_empty());
+    let chat_provider: Box<dyn ChatProvider> = match provider {
+        "claude" => Box::new(ClaudeProvider::new(app_handle)?),
+        "openai" => Box::new(OpenAIProvider::new(app_handle)?),
+        "gemini" => {
+            if has_images {
+                return Err(HeelixError::InvalidInput(
+                    "Image attachments are only supported for Claude and OpenAI models".to_string(),
+                ));
+            }
+            Box::new(GeminiProvider::new(app_handle)?)
+        }
+        // Ollama has no reasoning, image or JSON support here; only sampling is applied
+        "local" => {
+            if has_images {
+                warn!("Local models are text-only, dropping attached images");
+            }
+            Box::new(LocalProvider::new(app_handle)?)
+        }
+        other => return Err(HeelixError::InvalidInput(format!("Unknown provider: {}", other))),
+    };
+    run_chat(app_handle, chat_provider.as_ref(), request).await
+}
+
+/// Model `provider`'s engine answers a request for `model_id` with
+pub fn model_for(provider: &str, model_id: Option<&str>) -> HeelixResult<String> {
+    match provider {
+        "claude" => Ok(ClaudeProvider::model_for(model_id)),
+        "openai" => Ok(OpenAIProvider::model_for(model_id)),
+        "gemini" => Ok(GeminiProvider::model_for(model_id)),
+        "local" => Ok(LocalProvider::model_for(model_id)),
+        other => Err(HeelixError::InvalidInput(format!("Unknown provider: {}", other))),
+    }
+}
+
+/// The model a prompt sent to `provider` with `model_id` would be answered
+/// by, so the UI can show it before sending. `provider` defaults to
+/// `api_choice`. Claude and OpenAI answer ids they do not support with
+/// their default model, Gemini always uses one model and Ollama takes any
+/// name. A fallback provider, if one has to step in, uses its default.
+#[tauri::command]
+pub fn resolve_model(
+    app_handle: tauri::AppHandle,
+    provider: Option<String>,
+    model_id: Option<String>,
+) -> HeelixResult<String> {
+    let provider = match provider {
+        Some(provider) => provider,
+        None => app_handle.db(get_api_choice)?,
+    };
+    model_for(&provider, model_id.as_deref())
+}
+
+/// Emitted with the new name when a chat has been named automatically
+pub const CHAT_NAMED_EVENT: &str = "chat_named";
+
+/// Names the frontend and the naming engines give chats that have no real name yet
+const PLACEHOLDER_CHAT_NAMES: [&str; 3] = ["New Chat", "Unnamed Chat", "Unnamed Conversation"];
+
+fn is_placeholder_chat_name(name: &str) -> bool {
+    let name = name.trim();
+    name.is_empty() || PLACEHOLDER_CHAT_NAMES.contains(&name)
+}
+
+/// Name `chat_id` from its first prompt in the background, so the answer that
+/// was just streamed is not held up by the naming call
+fn spawn_auto_name(
+    app_handle: &tauri::AppHandle,
+    provider: &str,
+    chat_id: Option<i64>,
+    conversation_history: &[Message],
+) {
+    let first_prompt = conversation_history
+        .iter()
+        .find(|message| message.role == "user")
+        .map(|message| message.content.clone());
+    let (chat_id, first_prompt) = match (chat_id, first_prompt) {
+        (Some(chat_id), Some(first_prompt)) => (chat_id, first_prompt),
+        _ => return,
+    };
+    let app_handle = app_handle.clone();
+    let provider = provider.to_string();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = auto_name_chat(&app_handle, &provider, chat_id, first_prompt).await {
+            warn!("Could not name chat {}: {}", chat_id, e);
+        }
+    });
+}
+
+/// Attribute the answer to its retrieved chunks in the background; the spans
+/// arrive as an event after the answer is shown
+fn spawn_source_attribution(app_handle: &tauri::AppHandle, chat_id: Option<i64>, answer: &str) {
+    let app_handle = app_handle.clone();
+    let answer = answer.to_string();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = attribute_sources(&app_handle, chat_id, &answer).await {
+            warn!("Could not attribute sources for chat {:?}: {}", chat_id, e);
+        }
+    });
+}
+
+/// Give `chat_id` a generated name when `auto_name_chats` is on and the chat
+/// still has a placeholder name
+async fn auto_name_chat(
+    app_handle: &tauri::AppHandle,
+    provider: &str,
+    chat_id: i64,
+    first_prompt: String,
+) -> HeelixResult<()> {
+    if !app_handle.db(get_auto_name_chats)? {
+        return Ok(());
+    }
+    let current_name = app_handle.db(|db| get_chat_name(db, chat_id))?;
+    if !is_placeholder_chat_name(&current_name) {
+        return Ok(());
+    }
+
+    let generated = name_with_provider(app_handle.clone(), provider, first_prompt).await?;
+    let name = generated.trim().trim_matches('"').trim();
+    if is_placeholder_chat_name(name) {
+        return Ok(());
+    }
+    app_handle.db(|db| update_chat(db, chat_id, name))?;
+    emit_chat_event(app_handle, CHAT_NAMED_EVENT, Some(chat_id), name.to_string())?;
+    Ok(())
+}
+
+/// Follow-up turn asking the model to pick up where a truncated answer stopped
+const CONTINUE_PROMPT: &str =
+    "Continue exactly where your previous answer stopped. Do not repeat anything you already wrote.";
+
+/// Continue the last assistant message of `chat_id` after it stopped at the
+/// output limit, using the provider and model that wrote it. The continuation
+/// streams as `llm_response` on its own; the stored message is updated with
+/// the merged text, which is returned.
+#[tauri::command]
+pub async fn continue_response(
+    app_handle: tauri::AppHandle,
+    chat_id: i64,
+    provider: String,
+    model_id: Option<String>,
+) -> HeelixResult<ChatCompletion> {
+    let stored = app_handle.db(|db| get_messages_by_chat_id(db, chat_id))?;
+    let partial = stored
+        .last()
+        .filter(|message| message.role == "assistant")
+        .cloned()
+        .ok_or_else(|| {
+            HeelixError::InvalidInput("The last message in this chat is not an answer that can be continued".to_string())
+        })?;
+
+    let mut history: Vec<Message> = stored
+        .into_iter()
+        .map(|message| Message {
+            role: message.role,
+            content: message.content,
+        })
+        .collect();
+    history.push(Message {
+        role: "user".to_string(),
+        content: CONTINUE_PROMPT.to_string(),
+    });
+
+    let request = ChatRequest {
+        conversation_history: history,
+        is_first_message: false,
+        combined_activity_text: String::new(),
+        model_id,
+        project_ids: Vec::new(),
+        chat_id: Some(chat_id),
+        options: None,
+        retrieved_context: None,
+    };
+    let continuation = send_prompt_to_provider(&app_handle, &provider, request).await?;
+
+    let text = format!("{}{}", partial.content, continuation.text);
+    app_handle.db(|db| update_message_content(db, partial.id, &text))?;
+    Ok(ChatCompletion {
+        text,
+        truncated: continuation.truncated,
+    })
+}
+
+/// Instructions for a conversation-naming call asking for at most `max_chars` characters
+pub fn naming_instructions(max_chars: u32) -> String {
+    format!(
+        "Name the conversation based on the user input. Use a total of {} characters or less, without quotation marks. Use proper English, don't skip spaces between words. You only need to answer with the name.",
+        max_chars
+    )
+}
+
+/// Output budget for a chat name of up to `max_chars` characters
+pub fn naming_max_tokens(max_chars: u32) -> u32 {
+    (max_chars / 2).max(20)
+}
+
+/// Short title for `user_input` from `provider`'s conversation-naming call
+pub async fn name_with_provider(
+    app_handle: tauri::AppHandle,
+    provider: &str,
+    user_input: String,
+) -> HeelixResult<String> {
+    match provider {
+        "claude" => name_conversation(app_handle, user_input).await,
+        "openai" => generate_conversation_name(app_handle, user_input).await,
+        "gemini" => name_conversation_gemini(app_handle, user_input).await,
+        "local" => name_conversation_local(app_handle, user_input).await,
+        other => Err(HeelixError::InvalidInput(format!("Unknown provider: {}", other))),
+    }
+}
+
+/// Short title for `user_input` from `provider`, or from the provider selected
+/// as `api_choice` when none is given
+#[tauri::command]
+pub async fn name_conversation_unified(
+    app_handle: tauri::AppHandle,
+    user_input: String,
+    provider: Option<String>,
+) -> HeelixResult<String> {
+    let provider = match provider {
+        Some(provider) => provider,
+        None => app_handle.db(get_api_choice)?,
+    };
+    name_with_provider(app_handle, &provider, user_input).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalid_json_reason() {
+        assert!(invalid_json_reason(" {\"title\": \"Notes\"}\n").is_none());
+        assert!(invalid_json_reason("```json\n{}\n```").is_some());
+        assert!(invalid_json_reason("Here you go: {}").is_some());
+    }
+
+    #[test]
+    fn test_fallback_chain_skips_primary_and_duplicates() {
+        let fallbacks = vec!["claude", "gemini", "openai", "gemini", "local"]
+            .into_iter()
+            .map(String::from)
+            .collect::<Vec<_>>();
+        assert_eq!(fallback_chain("claude", fallbacks.clone(), false), vec!["gemini", "openai", "local"]);
+        assert_eq!(fallback_chain("claude", fallbacks, true), vec!["openai", "local"]);
+    }
+
+    #[test]
+    fn test_empty_prompts_are_rejected() {
+        let message = |content: &str| Message {
+            role: "user".to_string(),
+            content: content.to_string(),
+        };
+        assert!(check_prompt_not_empty(&[message("What changed?")], false).is_ok());
+        assert!(check_prompt_not_empty(&[message("What changed?"), message(" \n\t ")], false).is_err());
+        assert!(check_prompt_not_empty(&[], false).is_err());
+        assert!(check_prompt_not_empty(&[message("")], true).is_ok());
+        assert!(check_naming_input("  ").is_err());
+        assert!(check_naming_input("Trip budget").is_ok());
+    }
+
+    #[test]
+    fn test_placeholder_chat_names() {
+        assert!(is_placeholder_chat_name(" New Chat "));
+        assert!(is_placeholder_chat_name(""));
+        assert!(!is_placeholder_chat_name("Trip budget"));
+    }
+
+    #[test]
+    fn test_model_for_matches_the_engine_defaults() {
+        let defaults: Vec<_> = ["claude", "openai", "gemini", "local"]
+            .iter()
+            .map(|provider| model_for(provider, None).unwrap())
+            .collect();
+        assert_eq!(defaults, vec!["claude-sonnet-4-5", "gpt-5", "gemini-2.0-flash", "llama3.3:70b"]);
+        assert_eq!(model_for("claude", Some("claude-haiku-4-5")).unwrap(), "claude-haiku-4-5");
+        assert_eq!(model_for("claude", Some("gpt-5")).unwrap(), "claude-sonnet-4-5");
+        assert_eq!(model_for("gemini", Some("gemini-2.5-pro")).unwrap(), "gemini-2.0-flash");
+        assert_eq!(model_for("local", Some("qwen3:8b")).unwrap(), "qwen3:8b");
+        assert!(model_for("mistral", None).is_err());
+    }
+
+    #[test]
+    fn test_response_format_defaults_to_text() {
+        let options: PromptOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(options.response_format, ResponseFormat::Text);
+        let options: PromptOptions =
+            serde_json::from_str(r#"{"response_format": "json_object"}"#).unwrap();
+        assert_eq!(options.response_format, ResponseFormat::JsonObject);
+    }
+}
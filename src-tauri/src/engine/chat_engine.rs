@@ -1,30 +1,276 @@
-use futures::StreamExt;
-use log::{debug, error};
+use futures::future::BoxFuture;
+use futures::{Stream, StreamExt};
+use log::{debug, error, info};
 use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
 use serde_json;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tauri::{AppHandle, Manager};
 
 use crate::configuration::state::ServiceAccess;
-use crate::engine::similarity_search_engine::DEFAULT_RAG_TOP_K;
-use crate::engine::project_vector_engine::search_project_vectors;
-use crate::repository::settings_repository::get_setting;
-use crate::repository::chunk_repository::{get_chunks_by_ids, get_chunk_sources, ChunkSource};
+use crate::error::{HeelixError, HeelixResult};
+use crate::engine::chat_dispatch::{check_naming_input, naming_instructions, naming_max_tokens, PromptOptions};
+use crate::engine::chat_provider::{
+    delta_stream, run_chat, ChatProvider, ChatRequest, CompletionParams, Delta, DeltaStream, SystemPrompt,
+};
+use crate::engine::model_capabilities::{capabilities_for, max_output_tokens_for};
+use crate::engine::rate_limiter::{acquire, estimate_prompt_tokens};
+use crate::configuration::settings::NAMING_MODEL_CLAUDE_KEY;
+use crate::repository::settings_repository::{
+    get_max_output_tokens, get_naming_max_chars, get_naming_model, get_setting_or_default,
+};
 
 #[derive(Serialize)]
 struct ClaudeRequest {
     model: String,
     max_tokens: usize,
-    messages: Vec<Message>,
-    system: String,
+    messages: Vec<ClaudeMessage>,
+    system: Vec<ClaudeSystemBlock>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    thinking: Option<ClaudeThinking>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
 }
 
-#[derive(Serialize, Deserialize)]
-pub struct Message {
+impl ClaudeRequest {
+    /// Text tokens the request sends, for the rate limiter. Images are not counted.
+    fn estimated_input_tokens(&self) -> usize {
+        let system = self.system.iter().map(|block| block.text.as_str());
+        let messages = self.messages.iter().flat_map(|message| match &message.content {
+            ClaudeContent::Text(text) => vec![text.as_str()],
+            ClaudeContent::Blocks(blocks) => blocks
+                .iter()
+                .filter_map(|block| match block {
+                    ClaudeContentBlock::Text { text, .. } => Some(text.as_str()),
+                    ClaudeContentBlock::Image { .. } => None,
+                })
+                .collect(),
+        });
+        estimate_prompt_tokens(system.chain(messages))
+    }
+}
+
+/// Prompt caching breakpoint: everything up to and including the marked block
+/// is cached for a few minutes and billed at the cache-read rate on reuse
+#[derive(Serialize, Clone, Copy)]
+struct CacheControl {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+const EPHEMERAL_CACHE: CacheControl = CacheControl { kind: "ephemeral" };
+
+#[derive(Serialize)]
+struct ClaudeSystemBlock {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_control: Option<CacheControl>,
+}
+
+impl ClaudeSystemBlock {
+    fn text(text: impl Into<String>, cache_control: Option<CacheControl>) -> Self {
+        ClaudeSystemBlock {
+            kind: "text",
+            text: text.into(),
+            cache_control,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ClaudeMessage {
     role: String,
-    content: String,
+    content: ClaudeContent,
+}
+
+/// Plain text, or content blocks when the message carries images
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ClaudeContent {
+    Text(String),
+    Blocks(Vec<ClaudeContentBlock>),
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClaudeContentBlock {
+    Text {
+        text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+    Image { source: ClaudeImageSource },
+}
+
+#[derive(Serialize)]
+struct ClaudeImageSource {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    media_type: &'static str,
+    data: String,
+}
+
+#[derive(Serialize)]
+struct ClaudeThinking {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    budget_tokens: usize,
+}
+
+/// A single conversation turn, shared by all chat engines
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+/// Final text of a chat answer. `truncated` is set when the provider stopped
+/// at the output token limit, so the answer can be continued.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ChatCompletion {
+    pub text: String,
+    pub truncated: bool,
+}
+
+/// Payload of the streamed chat events (`llm_response`, `output_tokens`,
+/// `llm_sources`), tagged with the chat the stream belongs to
+#[derive(Serialize, Clone)]
+pub struct ChatEventPayload<T> {
+    pub chat_id: Option<i64>,
+    pub content: T,
+}
+
+/// Longest gap allowed between streamed chunks before a response is abandoned
+pub const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// `stream.next()` that fails with a "stream stalled" provider error when
+/// nothing arrives for `STREAM_IDLE_TIMEOUT`. Text streamed before the stall
+/// has already been emitted, so the UI keeps the partial answer.
+pub async fn next_or_stall<S>(stream: &mut S, provider: &'static str) -> HeelixResult<Option<S::Item>>
+where
+    S: Stream + Unpin,
+{
+    tokio::time::timeout(STREAM_IDLE_TIMEOUT, stream.next())
+        .await
+        .map_err(|_| {
+            error!("{} stream stalled for {}s, aborting", provider, STREAM_IDLE_TIMEOUT.as_secs());
+            HeelixError::provider(
+                provider,
+                format!(
+                    "Stream stalled: no response for {} seconds. The partial answer was kept.",
+                    STREAM_IDLE_TIMEOUT.as_secs()
+                ),
+            )
+        })
+}
+
+/// Streamed text is emitted at most this often...
+const STREAM_EMIT_INTERVAL: Duration = Duration::from_millis(50);
+/// ...unless at least this many bytes are waiting
+const STREAM_EMIT_BYTES: usize = 200;
+
+/// Content of an `llm_response` event: text appended since the previous event.
+/// `replace` is set on the first event of each response, telling the UI to drop
+/// what an earlier attempt (a failed provider, a rejected JSON answer) showed.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct StreamedText {
+    pub delta: String,
+    pub replace: bool,
+}
+
+/// Accumulated answer text that releases what was added since the last release
+/// when enough time has passed or enough text is waiting
+#[derive(Default)]
+struct ThrottledText {
+    text: String,
+    emitted: usize,
+    last_emit: Option<Instant>,
+}
+
+impl ThrottledText {
+    fn push(&mut self, delta: &str, now: Instant) -> Option<StreamedText> {
+        self.text.push_str(delta);
+        let due = match self.last_emit {
+            None => true,
+            Some(last) => {
+                now.duration_since(last) >= STREAM_EMIT_INTERVAL || self.text.len() - self.emitted >= STREAM_EMIT_BYTES
+            }
+        };
+        if due {
+            self.flush(now)
+        } else {
+            None
+        }
+    }
+
+    fn flush(&mut self, now: Instant) -> Option<StreamedText> {
+        if self.emitted == self.text.len() && self.last_emit.is_some() {
+            return None;
+        }
+        let streamed = StreamedText {
+            delta: self.text[self.emitted..].to_string(),
+            replace: self.last_emit.is_none(),
+        };
+        self.emitted = self.text.len();
+        self.last_emit = Some(now);
+        Some(streamed)
+    }
+}
+
+/// A response being streamed to the UI as throttled `llm_response` deltas
+pub struct ResponseStream<'a> {
+    app_handle: &'a AppHandle,
+    chat_id: Option<i64>,
+    text: ThrottledText,
+}
+
+impl<'a> ResponseStream<'a> {
+    pub fn new(app_handle: &'a AppHandle, chat_id: Option<i64>) -> Self {
+        ResponseStream {
+            app_handle,
+            chat_id,
+            text: ThrottledText::default(),
+        }
+    }
+
+    /// Add streamed text, emitting it now if an event is due
+    pub fn push(&mut self, delta: &str) -> HeelixResult<()> {
+        let streamed = self.text.push(delta, Instant::now());
+        self.emit(streamed)
+    }
+
+    /// Emit whatever is still pending and return the full text
+    pub fn finish(mut self) -> HeelixResult<String> {
+        let streamed = self.text.flush(Instant::now());
+        self.emit(streamed)?;
+        Ok(self.text.text)
+    }
+
+    fn emit(&self, streamed: Option<StreamedText>) -> HeelixResult<()> {
+        match streamed {
+            Some(streamed) => emit_chat_event(self.app_handle, "llm_response", self.chat_id, streamed)
+                .map_err(|e| HeelixError::Internal(format!("Failed to emit response: {}", e))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Emit a chat event to the main window wrapped in a `ChatEventPayload`
+pub fn emit_chat_event<T: Serialize + Clone>(
+    app_handle: &AppHandle,
+    event: &str,
+    chat_id: Option<i64>,
+    content: T,
+) -> tauri::Result<()> {
+    app_handle
+        .get_window("main")
+        .expect("Failed to get main window")
+        .emit(event, ChatEventPayload { chat_id, content })
 }
 
 #[derive(Deserialize)]
@@ -45,334 +291,353 @@ struct Content {
 }
 
 const ANTHROPIC_URL: &str = "https://api.anthropic.com/v1/messages";
+/// Response length when the `max_output_tokens` setting is unset
+const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 4096;
 const ANTRHOPIC_MODEL: &str = "claude-haiku-4-5";
 const ANTRHOPIC_MAIN_MODEL: &str = "claude-sonnet-4-5";
 const ANTRHOPIC_MODEL_CHEAP: &str = "claude-haiku-4-5";
 
-#[tauri::command]
-pub async fn send_prompt_to_llm(
-    app_handle: tauri::AppHandle,
-    conversation_history: Vec<Message>,
-    is_first_message: bool,
-    combined_activity_text: String,
-    model_id: Option<String>,
-    project_id: Option<i64>, // Project ID for chunk-based retrieval
-) -> Result<(), String> {
-    let setting =
-        app_handle.db(|db| get_setting(db, "api_key_claude").expect("Failed on api_key_claude"));
-    let setting_openai =
-        app_handle.db(|db| get_setting(db, "api_key_open_ai").expect("Failed on api_key_open_ai"));
-    let rag_top_k: usize = app_handle
-        .db(|db| get_setting(db, "rag_top_k"))
-        .map(|s| s.setting_value.parse().unwrap_or(DEFAULT_RAG_TOP_K))
-        .unwrap_or(DEFAULT_RAG_TOP_K);
-
-    // Configure client with keep-alive and proper timeouts
-    let client = Client::builder()
-        .timeout(Duration::from_secs(180))  // Increased timeout
-        .tcp_keepalive(Duration::from_secs(60))  // Keep connection alive for 60 seconds
-        .pool_idle_timeout(Duration::from_secs(90))  // Allow connections to stay in pool
-        .pool_max_idle_per_host(2)  // Keep up to 2 idle connections per host
-        .connect_timeout(Duration::from_secs(30))
-        .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
-
-    let model_to_use = match model_id.as_deref() {
-        Some("claude-haiku-4-5") => "claude-haiku-4-5",
-        Some("claude-3-5-sonnet-20241022") => "claude-3-5-sonnet-20241022",
-        _ => "claude-sonnet-4-5", // Default to Claude Sonnet 4.5
+/// Put a cache breakpoint on the last text block of `message`
+fn add_cache_breakpoint(message: &mut ClaudeMessage) {
+    let content = std::mem::replace(&mut message.content, ClaudeContent::Text(String::new()));
+    message.content = match content {
+        ClaudeContent::Text(text) => ClaudeContent::Blocks(vec![ClaudeContentBlock::Text {
+            text,
+            cache_control: Some(EPHEMERAL_CACHE),
+        }]),
+        ClaudeContent::Blocks(mut blocks) => {
+            if let Some(ClaudeContentBlock::Text { cache_control, .. }) = blocks.last_mut() {
+                *cache_control = Some(EPHEMERAL_CACHE);
+            }
+            ClaudeContent::Blocks(blocks)
+        }
     };
-    let mut filtered_context = String::new();
-    let window_titles: Vec<String> = Vec::new();
-    if is_first_message {
-        let user_prompt = conversation_history
-            .last()
-            .map(|msg| msg.content.clone())
-            .unwrap_or_default();
-        debug!("User Prompt: {}", user_prompt);
-
-        let mut context = String::new();
-
-        // Use per-project vector index if project_id is provided
-        if let Some(pid) = project_id {
-            debug!("Using per-project vector search for project {}", pid);
-            
-            // Search directly in project's vector index
-            match search_project_vectors(&app_handle, pid, &user_prompt, rag_top_k, &setting_openai.setting_value).await {
-                Ok(similar_chunk_ids) if !similar_chunk_ids.is_empty() => {
-                    let chunk_ids_to_fetch: Vec<i64> = similar_chunk_ids
-                        .iter()
-                        .map(|(id, _)| *id)
-            .collect();
+}
 
-                    debug!("Retrieved {} similar chunks from project index", chunk_ids_to_fetch.len());
-                    
-                    // Get chunk content
-                    let chunks = app_handle
-                        .db(|conn| get_chunks_by_ids(conn, &chunk_ids_to_fetch))
-                        .map_err(|e| format!("Failed to get chunk content: {}", e))?;
-                    
-                    // Get source information for citations
-                    let sources: Vec<ChunkSource> = app_handle
-                        .db(|conn| get_chunk_sources(conn, &chunk_ids_to_fetch))
-                        .unwrap_or_else(|e| {
-                            error!("Failed to get chunk sources: {}", e);
-                            vec![]
-                        });
-                    
-                    // Emit sources to frontend
-                    if !sources.is_empty() {
-                        if let Err(e) = app_handle
-                            .get_window("main")
-                            .expect("Failed to get main window")
-                            .emit("llm_sources", &sources)
-                        {
-                            error!("Failed to emit sources: {}", e);
-                        }
-                    }
-                    
-                    // Build context from chunks (no relevance filtering needed - chunks are small)
-                    for (index, chunk) in chunks.iter().enumerate() {
-                        context.push_str(&format!(
-                            "Chunk {} (from document {}):\n{}\n\n",
-                            index + 1, chunk.document_id, chunk.chunk_text
-                        ));
-                    }
-                    
-                    // Set filtered_context directly since chunks are already relevant
-                    filtered_context = context.clone();
+/// Claude through the Messages API, streamed as server-sent events
+pub struct ClaudeProvider {
+    client: Client,
+    api_key: String,
+    /// The `max_output_tokens` setting
+    configured_max_tokens: u32,
+}
+
+impl ClaudeProvider {
+    pub fn new(app_handle: &AppHandle) -> HeelixResult<Self> {
+        let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_claude", ""));
+        if api_key.is_empty() {
+            return Err(HeelixError::MissingApiKey("Claude"));
+        }
+
+        // Configure client with keep-alive and proper timeouts
+        let client = Client::builder()
+            .timeout(Duration::from_secs(180))  // Increased timeout
+            .tcp_keepalive(Duration::from_secs(60))  // Keep connection alive for 60 seconds
+            .pool_idle_timeout(Duration::from_secs(90))  // Allow connections to stay in pool
+            .pool_max_idle_per_host(2)  // Keep up to 2 idle connections per host
+            .connect_timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
+
+        Ok(ClaudeProvider {
+            client,
+            api_key,
+            configured_max_tokens: app_handle.db(get_max_output_tokens)?,
+        })
+    }
+
+    /// Model that answers a request for `model_id`: the two other supported
+    /// Claude models by name, Sonnet 4.5 for anything else
+    pub fn model_for(model_id: Option<&str>) -> String {
+        match model_id {
+            Some("claude-haiku-4-5") => "claude-haiku-4-5",
+            Some("claude-3-5-sonnet-20241022") => "claude-3-5-sonnet-20241022",
+            _ => ANTRHOPIC_MAIN_MODEL,
+        }
+        .to_string()
+    }
+
+    fn request_body(&self, messages: &[Message], system: &SystemPrompt, params: &CompletionParams) -> ClaudeRequest {
+        let model = params.model.as_str();
+
+        // The fixed instructions start every turn's prompt, so they carry the system
+        // breakpoint. Retrieved chunks are not sent again on the next turn, so
+        // caching them would never pay off.
+        let mut system_blocks = vec![ClaudeSystemBlock::text(system.instructions.clone(), Some(EPHEMERAL_CACHE))];
+        if !system.context.is_empty() {
+            system_blocks.push(ClaudeSystemBlock::text(system.context.clone(), None));
+        }
+
+        // Attach images to the message being sent, ahead of its text as Claude recommends
+        let last_user_index = messages.iter().rposition(|m| m.role == "user");
+        let mut claude_messages: Vec<ClaudeMessage> = messages
+            .iter()
+            .enumerate()
+            .map(|(index, msg)| {
+                let mut blocks: Vec<ClaudeContentBlock> = Vec::new();
+                if Some(index) == last_user_index {
+                    blocks.extend(params.images.iter().map(|image| ClaudeContentBlock::Image {
+                        source: ClaudeImageSource {
+                            kind: "base64",
+                            media_type: image.media_type,
+                            data: image.data.clone(),
+                        },
+                    }));
                 }
-                Ok(_) => {
-                    debug!("No vectorized chunks found for project, falling back to legacy search");
+                // Always blocks, so a message is sent the same way when the
+                // breakpoint has moved on to a later one
+                blocks.push(ClaudeContentBlock::Text {
+                    text: msg.content.clone(),
+                    cache_control: None,
+                });
+                ClaudeMessage {
+                    role: msg.role.clone(),
+                    content: ClaudeContent::Blocks(blocks),
                 }
+            })
+            .collect();
+        // Cache the conversation so far; the next turn sends it unchanged and reads it
+        // back. With retrieved chunks in the system prompt the next turn starts
+        // differently, so nothing is written.
+        if system.context.is_empty() {
+            if let Some(last) = claude_messages.last_mut() {
+                add_cache_breakpoint(last);
+            }
+        }
+
+        // Extended thinking spends budget_tokens out of max_tokens, so raise the cap to keep room for the answer
+        let thinking = params
+            .reasoning_effort
+            .filter(|_| capabilities_for(model).reasoning)
+            .map(|effort| ClaudeThinking {
+                kind: "enabled",
+                budget_tokens: effort.claude_budget_tokens(),
+            });
+        let answer_tokens = max_output_tokens_for(model, self.configured_max_tokens, Some(DEFAULT_MAX_OUTPUT_TOKENS))
+            .unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS) as usize;
+        let max_tokens = answer_tokens + thinking.as_ref().map_or(0, |t| t.budget_tokens);
+        let max_tokens = match capabilities_for(model).max_output_tokens as usize {
+            0 => max_tokens,
+            model_max => max_tokens.min(model_max),
+        };
+
+        ClaudeRequest {
+            model: model.to_string(),
+            max_tokens,
+            messages: claude_messages,
+            system: system_blocks,
+            stream: true,
+            // Claude rejects sampling changes while thinking
+            temperature: params.sampling.temperature.filter(|_| thinking.is_none()),
+            top_p: params.sampling.top_p.filter(|_| thinking.is_none()),
+            thinking,
+        }
+    }
+
+    /// Post `request_body`, retrying connection failures with exponential backoff
+    async fn send(&self, request_body: &ClaudeRequest) -> HeelixResult<Response> {
+        let mut attempt = 0;
+        let max_retries = 3;
+        let mut delay = Duration::from_secs(1);
+
+        loop {
+            let response = self
+                .client
+                .post(ANTHROPIC_URL)
+                .header("Content-Type", "application/json")
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", "2023-06-01")
+                .header("Connection", "keep-alive")
+                .json(request_body)
+                .send()
+                .await;
+
+            match response {
+                Ok(resp) => return Ok(resp),
                 Err(e) => {
-                    debug!("Project vector search failed: {}, falling back to legacy search", e);
+                    if attempt < max_retries {
+                        attempt += 1;
+                        error!(
+                            "Request to Claude API failed: {}. Retrying... (Attempt {}/{})",
+                            e, attempt, max_retries
+                        );
+                        tokio::time::sleep(delay).await;
+                        delay *= 2;  // Exponential backoff
+                    } else {
+                        error!("Request failed after {} attempts: {}", max_retries, e);
+                        return Err(HeelixError::Network(
+                            "Claude API appears to be down right now - please try again later".to_string(),
+                        ));
+                    }
                 }
             }
         }
+    }
+}
 
-        // No project selected = no RAG
-        if project_id.is_none() {
-            debug!("No project selected, skipping RAG retrieval");
-        }
+impl ChatProvider for ClaudeProvider {
+    fn id(&self) -> &'static str {
+        "claude"
+    }
 
-        // RAG retrieval complete - filtered_context already set from chunk search above
+    fn name(&self) -> &'static str {
+        "Claude"
     }
 
-    // Build system prompt - include RAG context only on first message
-    let system_prompt = if !filtered_context.is_empty() {
-        format!(
-            "You are Heelix chat app that is powered by Anthropic LLM. Heelix chat is developed by Heelix Technologies. Only identify yourself as such. Provide answers in markdown format.\n\n\
-            The following document chunks were retrieved from the user's project and may help answer their question. Use them if relevant, otherwise ignore them:\n\n{}",
-            filtered_context
-        )
-    } else {
-        "You are Heelix chat app that is powered by Anthropic LLM. Heelix chat is developed by Heelix Technologies. Only identify yourself as such. Provide answers in markdown format.".to_string()
-    };
+    fn persona_label(&self) -> &'static str {
+        "Anthropic LLM"
+    }
 
-    // Build messages array using Claude's native multi-turn format
-    let mut messages: Vec<Message> = conversation_history
-        .iter()
-        .map(|msg| Message {
-            role: msg.role.clone(),
-            content: msg.content.clone(),
-        })
-        .collect();
-
-    // Add combined_activity_text to first user message if no RAG context
-    if !combined_activity_text.is_empty() && filtered_context.is_empty() {
-        if let Some(first_user_msg) = messages.iter_mut().find(|m| m.role == "user") {
-            first_user_msg.content = format!(
-                "{}\n\nContext from selected documents:\n{}",
-                first_user_msg.content, combined_activity_text
-            );
-        }
+    fn resolve_model(&self, model_id: Option<&str>) -> String {
+        ClaudeProvider::model_for(model_id)
     }
 
-    let request_body = ClaudeRequest {
-        model: model_to_use.to_string(),
-        max_tokens: 4096,
-        messages,
-        system: system_prompt,
-        stream: true,
-    };
+    fn supports_images(&self) -> bool {
+        true
+    }
 
-    let mut attempt = 0;
-    let max_retries = 3;
-    let mut delay = Duration::from_secs(1);
-
-    loop {
-        let response = client
-            .post(ANTHROPIC_URL)
-            .header("Content-Type", "application/json")
-            .header("x-api-key", &setting.setting_value)
-            .header("anthropic-version", "2023-06-01")
-            .header("Connection", "keep-alive")
-            .json(&request_body)
-            .send()
-            .await;
-
-        match response {
-            Ok(resp) => {
-                return handle_success_response(resp, app_handle, window_titles.clone()).await;
-            }
-            Err(e) => {
-                if attempt < max_retries {
-                    attempt += 1;
-                    error!(
-                        "Request to Claude API failed: {}. Retrying... (Attempt {}/{})",
-                        e, attempt, max_retries
-                    );
-                    tokio::time::sleep(delay).await;
-                    delay *= 2;  // Exponential backoff
-                } else {
-                    let error_message =
-                        "Apologies, Claude API appears to be down right now - please try again later or switch to OpenAI for the time being";
-                    error!("Request failed after {} attempts: {}", max_retries, e);
-                    app_handle
-                        .get_window("main")
-                        .expect("Failed to get main window")
-                        .emit("llm_response", error_message.to_string())
-                        .map_err(|emit_err| {
-                            format!("Failed to emit error message: {}", emit_err)
-                        })?;
-                    return Err(error_message.to_string());
-                }
+    fn stream_completion<'a>(
+        &'a self,
+        messages: &'a [Message],
+        system: &'a SystemPrompt,
+        params: &'a CompletionParams,
+    ) -> BoxFuture<'a, HeelixResult<DeltaStream<'a>>> {
+        Box::pin(async move {
+            let request_body = self.request_body(messages, system, params);
+            let response = self.send(&request_body).await?;
+            if !response.status().is_success() {
+                let error_message = response.text().await?;
+                error!("Claude API error: {}", error_message);
+                return Err(HeelixError::provider("Claude", error_message));
             }
-        }
+
+            let mut events = ClaudeEvents::default();
+            Ok(delta_stream(response.bytes_stream(), "Claude", move |chunk| match chunk {
+                Ok(chunk) => events.feed(&chunk),
+                Err(e) => vec![Err(HeelixError::Network(format!("Failed to read chunk: {}", e)))],
+            }))
+        })
     }
 }
 
-async fn handle_success_response(
-    response: Response,
-    app_handle: AppHandle,
-    window_titles: Vec<String>,
-) -> Result<(), String> {
-    if response.status().is_success() {
-        let mut stream = response.bytes_stream();
-        let mut completion = String::new();
-        let mut input_tokens = 0;
-        let mut output_tokens = 0;
-
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("Failed to read chunk: {}", e))?;
-            let text = String::from_utf8_lossy(&chunk);
-
-            for line in text.lines() {
-                if !line.starts_with("data: ") {
-                    continue;
-                }
-                
-                let data = line[6..].trim();
-                
-                // Skip empty data lines
-                if data.is_empty() {
-                    continue;
-                }
+/// Parser for Claude's server-sent events, fed the response body as it arrives.
+/// A line split across two chunks is parsed once its end has arrived.
+#[derive(Default)]
+struct ClaudeEvents {
+    /// Start of a line whose end has not arrived yet
+    partial: Vec<u8>,
+    input_tokens: u32,
+}
 
-                // Handle ping events - these keep the connection alive
-                if data == "{\"type\": \"ping\"}" {
-                    debug!("Received ping event");
-                    continue;
-                }
+impl ClaudeEvents {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<HeelixResult<Delta>> {
+        self.partial.extend_from_slice(chunk);
+        let mut deltas = Vec::new();
+        while let Some(end) = self.partial.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            if let Some(data) = line.strip_prefix("data: ") {
+                self.parse_event(data.trim(), &mut deltas);
+            }
+        }
+        deltas
+    }
 
-                // Parse the event data
-                let json_data: serde_json::Value = match serde_json::from_str(data) {
-                    Ok(data) => data,
-                    Err(e) => {
-                        error!("Failed to parse event data: {}", e);
-                        continue;
-                    }
+    fn parse_event(&mut self, data: &str, deltas: &mut Vec<HeelixResult<Delta>>) {
+        // Skip empty data lines
+        if data.is_empty() {
+            return;
+        }
+        let event: serde_json::Value = match serde_json::from_str(data) {
+            Ok(event) => event,
+            Err(e) => {
+                error!("Failed to parse event data: {}", e);
+                return;
+            }
+        };
+
+        match event["type"].as_str() {
+            // Ping events keep the connection alive
+            Some("ping") => debug!("Received ping event"),
+            Some("error") => {
+                let error_type = event["error"]["type"].as_str().unwrap_or("unknown");
+                let error_message = event["error"]["message"].as_str().unwrap_or("Unknown error");
+                error!("Received error event: {} - {}", error_type, error_message);
+                let message = match error_type {
+                    "overloaded_error" => "Service is currently overloaded. Please try again later.".to_string(),
+                    _ => format!("Stream error: {}", error_message),
                 };
-
-                // Handle error events
-                if let Some("error") = json_data["type"].as_str() {
-                    if let Some(error) = json_data["error"].as_object() {
-                        let error_type = error["type"].as_str().unwrap_or("unknown");
-                        let error_message = error["message"].as_str().unwrap_or("Unknown error");
-                        
-                        error!("Received error event: {} - {}", error_type, error_message);
-                        
-                        match error_type {
-                            "overloaded_error" => {
-                                return Err("Service is currently overloaded. Please try again later.".to_string());
-                            }
-                            _ => {
-                                return Err(format!("Stream error: {}", error_message));
-                            }
-                        }
-                    }
+                deltas.push(Err(HeelixError::provider("Claude", message)));
+            }
+            Some("message_start") => {
+                let usage = &event["message"]["usage"];
+                self.input_tokens = usage["input_tokens"].as_u64().unwrap_or(0) as u32;
+                info!(
+                    "Claude prompt cache - read: {}, write: {}",
+                    usage["cache_read_input_tokens"].as_u64().unwrap_or(0),
+                    usage["cache_creation_input_tokens"].as_u64().unwrap_or(0)
+                );
+            }
+            Some("content_block_delta") => {
+                if let Some(text) = event["delta"]["text"].as_str() {
+                    deltas.push(Ok(Delta::Text(text.to_string())));
                 }
-
-                // Handle different event types
-                match json_data["type"].as_str() {
-                    Some("message_start") => {
-                        if let Some(usage) = json_data["message"]["usage"].as_object() {
-                            input_tokens = usage["input_tokens"].as_u64().unwrap_or(0) as u32;
-                            output_tokens = usage["output_tokens"].as_u64().unwrap_or(0) as u32;
-                        }
-                    }
-                    Some("content_block_delta") => {
-                        if let Some(delta) = json_data["delta"]["text"].as_str() {
-                            completion.push_str(delta);
-                            
-                            // Emit updates to frontend more frequently
-                            app_handle
-                                .get_window("main")
-                                .expect("Failed to get main window")
-                                .emit("llm_response", completion.clone())
-                                .map_err(|e| format!("Failed to emit response: {}", e))?;
-                        }
-                    }
-                    Some("message_delta") => {
-                        if let Some(usage) = json_data["usage"].as_object() {
-                            output_tokens = usage["output_tokens"].as_u64().unwrap_or(0) as u32;
-                        }
-                    }
-                    Some("message_stop") => {
-                        // Final emission of window titles and completion
-                        app_handle
-                            .get_window("main")
-                            .expect("Failed to get main window")
-                            .emit(
-                                "window_titles",
-                                serde_json::to_string(&window_titles).unwrap(),
-                            )
-                            .map_err(|e| format!("Failed to emit window titles: {}", e))?;
-
-                        app_handle
-                            .get_window("main")
-                            .expect("Failed to get main window")
-                            .emit("output_tokens", output_tokens)
-                            .map_err(|e| format!("Failed to emit output tokens: {}", e))?;
-                    }
-                    _ => {} // Ignore unknown event types
+            }
+            Some("message_delta") => {
+                if let Some(output_tokens) = event["usage"]["output_tokens"].as_u64() {
+                    deltas.push(Ok(Delta::Usage {
+                        input_tokens: self.input_tokens,
+                        output_tokens: output_tokens as u32,
+                    }));
+                }
+                if event["delta"]["stop_reason"].as_str() == Some("max_tokens") {
+                    deltas.push(Ok(Delta::Truncated));
                 }
             }
+            _ => {} // Ignore unknown event types
         }
-
-        debug!(
-            "Claude response complete - Input tokens: {}, Output tokens: {}",
-            input_tokens, output_tokens
-        );
-        Ok(())
-    } else {
-        let error_message = response
-            .text()
-            .await
-            .map_err(|e| format!("Failed to read error message: {}", e))?;
-        error!("Claude API error: {}", error_message);
-        Err(format!("Error from Claude API: {}", error_message))
     }
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_prompt_to_llm(
+    app_handle: tauri::AppHandle,
+    conversation_history: Vec<Message>,
+    is_first_message: bool,
+    combined_activity_text: String,
+    model_id: Option<String>,
+    project_id: Option<i64>, // Project ID for chunk-based retrieval
+    chat_id: Option<i64>, // Tags streamed events so the UI can route them to the right chat
+    options: Option<PromptOptions>,
+) -> HeelixResult<ChatCompletion> {
+    let provider = ClaudeProvider::new(&app_handle)?;
+    let request = ChatRequest {
+        conversation_history,
+        is_first_message,
+        combined_activity_text,
+        model_id,
+        project_ids: project_id.into_iter().collect(),
+        chat_id,
+        options,
+        retrieved_context: None,
+    };
+    run_chat(&app_handle, &provider, request).await
+}
+
 pub async fn name_conversation(
     app_handle: tauri::AppHandle,
     user_input: String,
-) -> Result<String, String> {
-    let setting =
-        app_handle.db(|db| get_setting(db, "api_key_claude").expect("Failed on api_key_claude"));
+) -> HeelixResult<String> {
+    check_naming_input(&user_input)?;
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_claude", ""));
+    if api_key.is_empty() {
+        return Err(HeelixError::MissingApiKey("Claude"));
+    }
+    let model = app_handle
+        .db(|db| get_naming_model(db, NAMING_MODEL_CLAUDE_KEY))?
+        .unwrap_or_else(|| ANTRHOPIC_MODEL_CHEAP.to_string());
+    let max_chars = app_handle.db(get_naming_max_chars)?;
 
     // Use the same client configuration for consistency
     let client = Client::builder()
@@ -381,49 +646,195 @@ pub async fn name_conversation(
         .pool_idle_timeout(Duration::from_secs(90))
         .pool_max_idle_per_host(2)
         .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
+        .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
 
     let system_prompt = format!(
-        "Name the conversation based on the user input. Use a total of 18 characters or less, without quotation marks. Use proper English, don't skip spaces between words. You only need to answer with the name. The following is the user input: \n\n{}\n\n.:",
+        "{} The following is the user input: \n\n{}\n\n.:",
+        naming_instructions(max_chars),
         user_input
     );
     let request_body = ClaudeRequest {
-        model: ANTRHOPIC_MODEL_CHEAP.to_string(),
-        max_tokens: 20,
-        messages: vec![Message {
+        model,
+        max_tokens: naming_max_tokens(max_chars) as usize,
+        messages: vec![ClaudeMessage {
             role: "user".to_string(),
-            content: "Please generate a concise name for the conversation based on the user input."
-                .to_string(),
+            content: ClaudeContent::Text(
+                "Please generate a concise name for the conversation based on the user input."
+                    .to_string(),
+            ),
         }],
-        system: system_prompt,
+        system: vec![ClaudeSystemBlock::text(system_prompt, None)],
         stream: false,
+        thinking: None,
+        temperature: None,
+        top_p: None,
     };
 
+    acquire("claude", request_body.estimated_input_tokens()).await;
     let response = client
         .post(ANTHROPIC_URL)
         .header("Content-Type", "application/json")
-        .header("x-api-key", &setting.setting_value)
+        .header("x-api-key", &api_key)
         .header("anthropic-version", "2023-06-01")
         .header("Connection", "keep-alive")
         .json(&request_body)
         .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
+        .await?;
 
     if response.status().is_success() {
-        let response_body: ClaudeResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
-        let generated_name = response_body.content[0].text.trim().to_string();
+        let response_body: ClaudeResponse = response.json().await.map_err(|e| {
+            HeelixError::provider("Claude", format!("Failed to parse response: {}", e))
+        })?;
+        let generated_name = response_body
+            .content
+            .first()
+            .map(|c| c.text.trim().to_string())
+            .unwrap_or_else(|| "Unnamed Conversation".to_string());
         Ok(generated_name)
     } else {
-        let error_message = response
-            .text()
-            .await
-            .map_err(|e| format!("Failed to read error message: {}", e))?;
-        Err(format!("Error from Claude API: {}", error_message))
+        let error_message = response.text().await?;
+        Err(HeelixError::provider("Claude", error_message))
     }
 }
 
-// Legacy identify_relevant_keywords removed - no longer used with per-project vector search
\ No newline at end of file
+// Legacy identify_relevant_keywords removed - no longer used with per-project vector search
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_breakpoint_on_last_text_block() {
+        let mut message = ClaudeMessage {
+            role: "user".to_string(),
+            content: ClaudeContent::Text("Summarise the notes".to_string()),
+        };
+        add_cache_breakpoint(&mut message);
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(
+            json["content"],
+            serde_json::json!([{
+                "type": "text",
+                "text": "Summarise the notes",
+                "cache_control": {"type": "ephemeral"}
+            }])
+        );
+    }
+
+    fn turn(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    fn request_json(messages: &[Message], system: &SystemPrompt) -> serde_json::Value {
+        let provider = ClaudeProvider {
+            client: Client::new(),
+            api_key: String::new(),
+            configured_max_tokens: 0,
+        };
+        let params = CompletionParams {
+            model: ANTRHOPIC_MAIN_MODEL.to_string(),
+            sampling: Default::default(),
+            reasoning_effort: None,
+            response_format: Default::default(),
+            images: Vec::new(),
+        };
+        serde_json::to_value(provider.request_body(messages, system, &params)).unwrap()
+    }
+
+    fn strip_cache_control(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                map.remove("cache_control");
+                map.values_mut().for_each(strip_cache_control);
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(strip_cache_control),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_cached_prefix_is_sent_unchanged_on_the_next_turn() {
+        let system = SystemPrompt {
+            instructions: "Provide answers in markdown format.".to_string(),
+            context: String::new(),
+        };
+        let mut history = vec![
+            turn("user", "What changed in the budget?"),
+            turn("assistant", "Travel went up."),
+            turn("user", "By how much?"),
+        ];
+        let mut second = request_json(&history, &system);
+        history.push(turn("assistant", "About 10%."));
+        history.push(turn("user", "And hosting?"));
+        let mut third = request_json(&history, &system);
+
+        assert_eq!(second["system"][0]["cache_control"]["type"], "ephemeral");
+        assert_eq!(second["messages"][2]["content"][0]["cache_control"]["type"], "ephemeral");
+        assert_eq!(third["messages"][4]["content"][0]["cache_control"]["type"], "ephemeral");
+
+        // Up to the second turn's breakpoint, the third turn sends the same prompt
+        strip_cache_control(&mut second);
+        strip_cache_control(&mut third);
+        assert_eq!(second["system"], third["system"]);
+        assert_eq!(second["messages"].as_array().unwrap()[..], third["messages"].as_array().unwrap()[..3]);
+    }
+
+    #[test]
+    fn test_retrieved_context_is_not_cached() {
+        let system = SystemPrompt {
+            instructions: "Provide answers in markdown format.".to_string(),
+            context: "Passages from the project: ...".to_string(),
+        };
+        let request = request_json(&[turn("user", "Summarise the notes")], &system);
+        assert_eq!(request["system"][0]["cache_control"]["type"], "ephemeral");
+        assert!(request["system"][1].get("cache_control").is_none());
+        assert!(request["messages"][0]["content"][0].get("cache_control").is_none());
+    }
+
+    #[test]
+    fn test_claude_events_across_chunk_boundaries() {
+        let mut events = ClaudeEvents::default();
+        let first = events.feed(
+            b"event: message_start\ndata: {\"type\": \"message_start\", \"message\": {\"usage\": {\"input_tokens\": 12}}}\n\ndata: {\"type\": \"content_block_delta\", \"delta\": {\"text\": \"Hel",
+        );
+        assert!(first.is_empty());
+
+        let rest = events.feed(
+            b"lo\"}}\ndata: {\"type\": \"ping\"}\ndata: {\"type\": \"message_delta\", \"delta\": {\"stop_reason\": \"max_tokens\"}, \"usage\": {\"output_tokens\": 7}}\n",
+        );
+        let rest: Vec<Delta> = rest.into_iter().map(|delta| delta.unwrap()).collect();
+        assert_eq!(
+            rest,
+            vec![
+                Delta::Text("Hello".to_string()),
+                Delta::Usage { input_tokens: 12, output_tokens: 7 },
+                Delta::Truncated,
+            ]
+        );
+
+        let error = events.feed(b"data: {\"type\": \"error\", \"error\": {\"type\": \"overloaded_error\", \"message\": \"Overloaded\"}}\n");
+        assert!(matches!(error.as_slice(), [Err(HeelixError::Provider { .. })]));
+    }
+
+    #[test]
+    fn test_throttled_text_batches_deltas_between_emits() {
+        let start = Instant::now();
+        let mut text = ThrottledText::default();
+
+        let first = text.push("Hel", start).unwrap();
+        assert_eq!(first, StreamedText { delta: "Hel".to_string(), replace: true });
+        assert_eq!(text.push("lo", start + Duration::from_millis(10)), None);
+        assert_eq!(text.push(" wor", start + Duration::from_millis(20)), None);
+
+        let due = text.push("ld", start + STREAM_EMIT_INTERVAL).unwrap();
+        assert_eq!(due, StreamedText { delta: "lo world".to_string(), replace: false });
+
+        let at = start + STREAM_EMIT_INTERVAL;
+        assert_eq!(text.push(&"x".repeat(STREAM_EMIT_BYTES), at).unwrap().delta.len(), STREAM_EMIT_BYTES);
+        assert_eq!(text.push("!", at), None);
+        assert_eq!(text.flush(at).unwrap().delta, "!");
+        assert_eq!(text.flush(at), None);
+    }
+}
@@ -1,21 +1,33 @@
 use crate::configuration::state::ServiceAccess;
-use crate::engine::similarity_search_engine::DEFAULT_RAG_TOP_K;
-use crate::engine::project_vector_engine::search_project_vectors;
-use crate::repository::settings_repository::get_setting;
-use crate::repository::chunk_repository::{get_chunks_by_ids, get_chunk_sources, ChunkSource};
-use log::{debug, error};
+use crate::error::{HeelixError, HeelixResult};
+use crate::engine::chat_dispatch::{
+    check_naming_input, naming_instructions, naming_max_tokens, PromptOptions, ResponseFormat,
+};
+use crate::engine::chat_engine::{ChatCompletion, Message};
+use crate::engine::chat_provider::{
+    run_chat, single_answer, ChatProvider, ChatRequest, CompletionParams, DeltaStream, SystemPrompt,
+};
+use crate::engine::model_capabilities::max_output_tokens_for;
+use crate::engine::rate_limiter::{acquire, estimate_prompt_tokens};
+use crate::configuration::settings::NAMING_MODEL_GEMINI_KEY;
+use crate::repository::settings_repository::{
+    get_max_output_tokens, get_naming_max_chars, get_naming_model, get_setting_or_default,
+};
+use futures::future::BoxFuture;
+use log::error;
 use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
-use tauri::{AppHandle, Manager};
 
 // Constants for Gemini model versions
-const GEMINI_URL: &str = "https://generativelanguage.googleapis.com/v1/models/gemini-2.0-flash:generateContent";
-
-#[derive(Serialize, Deserialize)]
-pub struct Message {
-    role: String,
-    content: String,
+const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1/models";
+/// The model chats are sent to, also used for capability lookups and for
+/// naming unless `naming_model_gemini` is set
+const GEMINI_MODEL: &str = "gemini-2.0-flash";
+const DEFAULT_MAX_OUTPUT_TOKENS: u32 = 2500;
+
+fn generate_content_url(model: &str) -> String {
+    format!("{}/{}:generateContent", GEMINI_API_BASE, model)
 }
 
 #[derive(Serialize)]
@@ -24,6 +36,17 @@ struct GeminiRequest {
     generation_config: GenerationConfig,
 }
 
+impl GeminiRequest {
+    /// Tokens the request sends, for the rate limiter
+    fn estimated_input_tokens(&self) -> usize {
+        estimate_prompt_tokens(
+            self.contents
+                .iter()
+                .flat_map(|content| content.parts.iter().map(|part| part.text.as_str())),
+        )
+    }
+}
+
 #[derive(Serialize)]
 struct Content {
     role: String,
@@ -38,6 +61,12 @@ struct Part {
 #[derive(Serialize)]
 struct GenerationConfig {
     max_output_tokens: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_mime_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
 }
 
 #[derive(Deserialize)]
@@ -48,6 +77,8 @@ struct GeminiResponse {
 #[derive(Deserialize)]
 struct Candidate {
     content: CandidateContent,
+    #[serde(rename = "finishReason")]
+    finish_reason: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -60,144 +91,152 @@ struct CandidatePart {
     text: String,
 }
 
-#[tauri::command]
-pub async fn send_prompt_to_gemini(
-    app_handle: tauri::AppHandle,
-    conversation_history: Vec<Message>,
-    is_first_message: bool,
-    combined_activity_text: String,
-    model_id: Option<String>,
-    project_id: Option<i64>, // Project ID for chunk-based retrieval
-) -> Result<(), String> {
-    let setting =
-        app_handle.db(|db| get_setting(db, "api_key_gemini").expect("Failed on api_key_gemini"));
-    let setting_openai =
-        app_handle.db(|db| get_setting(db, "api_key_open_ai").expect("Failed on api_key_open_ai"));
-
-    // Configure client with keep-alive and proper timeouts
-    let client = Client::builder()
-        .timeout(Duration::from_secs(180))
-        .tcp_keepalive(Duration::from_secs(60))
-        .pool_idle_timeout(Duration::from_secs(90))
-        .pool_max_idle_per_host(2)
-        .connect_timeout(Duration::from_secs(30))
-        .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
+/// Gemini through `generateContent`, which answers in one piece
+pub struct GeminiProvider {
+    client: Client,
+    api_key: String,
+    /// The `max_output_tokens` setting
+    configured_max_tokens: u32,
+}
 
-    let _model_to_use = match model_id.as_deref() {
-        Some("gemini-3-pro-preview") => "gemini-3-pro-preview",
-        _ => "gemini-2.0-flash", // Default
-    };
-    let rag_top_k: usize = app_handle
-        .db(|db| get_setting(db, "rag_top_k"))
-        .map(|s| s.setting_value.parse().unwrap_or(DEFAULT_RAG_TOP_K))
-        .unwrap_or(DEFAULT_RAG_TOP_K);
-
-    let mut filtered_context = String::new();
-
-    if is_first_message {
-        let user_prompt = conversation_history
-            .last()
-            .map(|msg| msg.content.clone())
-            .unwrap_or_default();
-
-        // Use per-project vector index if project_id is provided
-        if let Some(pid) = project_id {
-            debug!("Using per-project vector search for project {}", pid);
-
-            match search_project_vectors(&app_handle, pid, &user_prompt, rag_top_k, &setting_openai.setting_value).await {
-                Ok(similar_chunk_ids) if !similar_chunk_ids.is_empty() => {
-                    let chunk_ids_to_fetch: Vec<i64> = similar_chunk_ids
-                        .iter()
-                        .map(|(id, _)| *id)
-                        .collect();
-
-                    debug!("Retrieved {} similar chunks from project index", chunk_ids_to_fetch.len());
-
-                    let chunks = app_handle
-                        .db(|conn| get_chunks_by_ids(conn, &chunk_ids_to_fetch))
-                        .map_err(|e| format!("Failed to get chunk content: {}", e))?;
-
-                    // Get source information for citations
-                    let sources: Vec<ChunkSource> = app_handle
-                        .db(|conn| get_chunk_sources(conn, &chunk_ids_to_fetch))
-                        .unwrap_or_else(|e| {
-                            error!("Failed to get chunk sources: {}", e);
-                            vec![]
-                        });
-
-                    // Emit sources to frontend
-                    if !sources.is_empty() {
-                        if let Err(e) = app_handle
-                            .get_window("main")
-                            .expect("Failed to get main window")
-                            .emit("llm_sources", &sources)
-                        {
-                            error!("Failed to emit sources: {}", e);
-                        }
-                    }
-
-                    for (index, chunk) in chunks.iter().enumerate() {
-                        filtered_context.push_str(&format!(
-                            "Chunk {} (from document {}):\n{}\n\n",
-                            index + 1, chunk.document_id, chunk.chunk_text
-                        ));
-                    }
-                }
-                Ok(_) => {
-                    debug!("No vectorized chunks found for project");
-                }
-                Err(e) => {
-                    debug!("Project vector search failed: {}", e);
-                }
-            }
+impl GeminiProvider {
+    pub fn new(app_handle: &tauri::AppHandle) -> HeelixResult<Self> {
+        let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_gemini", ""));
+        if api_key.is_empty() {
+            return Err(HeelixError::MissingApiKey("Gemini"));
         }
+
+        // Configure client with keep-alive and proper timeouts
+        let client = Client::builder()
+            .timeout(Duration::from_secs(180))
+            .tcp_keepalive(Duration::from_secs(60))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(2)
+            .connect_timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
+
+        Ok(GeminiProvider {
+            client,
+            api_key,
+            configured_max_tokens: app_handle.db(get_max_output_tokens)?,
+        })
     }
 
-    // Build system instruction with RAG context if available
-    let system_instruction = if !filtered_context.is_empty() {
-        format!(
-            "You are Heelix chat app powered by Google Gemini. Heelix is developed by Heelix Technologies. Provide answers in markdown format.\n\n\
-            The following document chunks were retrieved from the user's project and may help answer their question. Use them if relevant, otherwise ignore them:\n\n{}",
-            filtered_context
-        )
-    } else {
-        "You are Heelix chat app powered by Google Gemini. Heelix is developed by Heelix Technologies. Provide answers in markdown format.".to_string()
-    };
+    /// Chats always go to `GEMINI_MODEL`, whichever Gemini model was picked
+    pub fn model_for(_model_id: Option<&str>) -> String {
+        GEMINI_MODEL.to_string()
+    }
 
-    // Build contents array using Gemini's native multi-turn format
-    let mut contents: Vec<Content> = vec![];
+    fn request_body(&self, messages: &[Message], system: &SystemPrompt, params: &CompletionParams) -> GeminiRequest {
+        // Build contents array using Gemini's native multi-turn format
+        let mut contents: Vec<Content> = vec![];
 
-    for (i, msg) in conversation_history.iter().enumerate() {
-        let mut content = msg.content.clone();
+        for (i, msg) in messages.iter().enumerate() {
+            // The system instruction goes ahead of the first user message
+            let content = if i == 0 && msg.role == "user" {
+                format!("{}\n\n{}", system.joined(), msg.content)
+            } else {
+                msg.content.clone()
+            };
 
-        // For first user message, prepend system instruction and add activity context if needed
-        if i == 0 && msg.role == "user" {
-            let mut prefix = format!("{}\n\n", system_instruction);
-            if !combined_activity_text.is_empty() && filtered_context.is_empty() {
-                prefix.push_str(&format!("Context from selected documents:\n{}\n\n", combined_activity_text));
-            }
-            content = format!("{}{}", prefix, content);
+            // Gemini uses "model" instead of "assistant"
+            let role = if msg.role == "assistant" { "model" } else { "user" };
+
+            contents.push(Content {
+                role: role.to_string(),
+                parts: vec![Part { text: content }],
+            });
         }
 
-        // Gemini uses "model" instead of "assistant"
-        let role = if msg.role == "assistant" { "model" } else { "user" };
+        let max_output_tokens =
+            max_output_tokens_for(&params.model, self.configured_max_tokens, Some(DEFAULT_MAX_OUTPUT_TOKENS))
+                .unwrap_or(DEFAULT_MAX_OUTPUT_TOKENS);
+        let response_mime_type = if params.response_format == ResponseFormat::JsonObject {
+            Some("application/json")
+        } else {
+            None
+        };
 
-        contents.push(Content {
-            role: role.to_string(),
-            parts: vec![Part { text: content }],
-        });
+        GeminiRequest {
+            contents,
+            generation_config: GenerationConfig {
+                max_output_tokens: max_output_tokens as usize,
+                response_mime_type,
+                temperature: params.sampling.temperature,
+                top_p: params.sampling.top_p,
+            },
+        }
     }
+}
 
-    let api_url = format!("{}?key={}", GEMINI_URL, setting.setting_value);
+impl ChatProvider for GeminiProvider {
+    fn id(&self) -> &'static str {
+        "gemini"
+    }
 
-    let request_body = GeminiRequest {
-        contents,
-        generation_config: GenerationConfig {
-            max_output_tokens: 2500,
-        },
+    fn name(&self) -> &'static str {
+        "Gemini"
+    }
+
+    fn persona_label(&self) -> &'static str {
+        "Google Gemini"
+    }
+
+    fn resolve_model(&self, model_id: Option<&str>) -> String {
+        GeminiProvider::model_for(model_id)
+    }
+
+    fn supports_json(&self) -> bool {
+        true
+    }
+
+    fn stream_completion<'a>(
+        &'a self,
+        messages: &'a [Message],
+        system: &'a SystemPrompt,
+        params: &'a CompletionParams,
+    ) -> BoxFuture<'a, HeelixResult<DeltaStream<'a>>> {
+        Box::pin(async move {
+            let request_body = self.request_body(messages, system, params);
+            let completion = request_gemini_completion(&self.client, &self.api_key, &request_body).await?;
+            Ok(single_answer(completion))
+        })
+    }
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_prompt_to_gemini(
+    app_handle: tauri::AppHandle,
+    conversation_history: Vec<Message>,
+    is_first_message: bool,
+    combined_activity_text: String,
+    model_id: Option<String>,
+    project_id: Option<i64>, // Project ID for chunk-based retrieval
+    chat_id: Option<i64>, // Tags streamed events so the UI can route them to the right chat
+    options: Option<PromptOptions>,
+) -> HeelixResult<ChatCompletion> {
+    let provider = GeminiProvider::new(&app_handle)?;
+    let request = ChatRequest {
+        conversation_history,
+        is_first_message,
+        combined_activity_text,
+        model_id,
+        project_ids: project_id.into_iter().collect(),
+        chat_id,
+        options,
+        retrieved_context: None,
     };
+    run_chat(&app_handle, &provider, request).await
+}
 
+/// Send `request_body` with retries on connection failures and return the answer
+async fn request_gemini_completion(
+    client: &Client,
+    api_key: &str,
+    request_body: &GeminiRequest,
+) -> HeelixResult<ChatCompletion> {
     // Make the request to Gemini API with retries
     let mut attempt = 0;
     let max_retries = 3;
@@ -205,21 +244,21 @@ pub async fn send_prompt_to_gemini(
 
     loop {
         let response = client
-            .post(&api_url)
+            .post(generate_content_url(GEMINI_MODEL))
             .header("Content-Type", "application/json")
-            .json(&request_body)
+            .header("x-goog-api-key", api_key)
+            .json(request_body)
             .send()
             .await;
 
         match response {
             Ok(resp) => {
                 if resp.status().is_success() {
-                    return handle_gemini_response(resp, app_handle).await;
+                    return parse_gemini_response(resp).await;
                 } else {
-                    let error_message = resp.text().await
-                        .map_err(|e| format!("Failed to read error message: {}", e))?;
+                    let error_message = resp.text().await?;
                     error!("Gemini API error: {}", error_message);
-                    return Err(format!("Error from Gemini API: {}", error_message));
+                    return Err(HeelixError::provider("Gemini", error_message));
                 }
             }
             Err(e) => {
@@ -231,73 +270,46 @@ pub async fn send_prompt_to_gemini(
                 } else {
                     let error_message = "Apologies, Gemini API appears to be down right now - please try again later";
                     error!("Request failed after {} attempts: {}", max_retries, e);
-                    app_handle
-                        .get_window("main")
-                        .expect("Failed to get main window")
-                        .emit("llm_response", error_message.to_string())
-                        .map_err(|emit_err| format!("Failed to emit error message: {}", emit_err))?;
-                    return Err(error_message.to_string());
+                    return Err(HeelixError::Network(error_message.to_string()));
                 }
             }
         }
     }
 }
 
-async fn handle_gemini_response(
-    response: Response,
-    app_handle: AppHandle,
-) -> Result<(), String> {
-    let response_body: GeminiResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse Gemini response: {}", e))?;
-
-    let completion = if let Some(candidate) = response_body.candidates.first() {
-        if let Some(part) = candidate.content.parts.first() {
-            part.text.clone()
-        } else {
-            String::new()
-        }
-    } else {
-        String::new()
-    };
+async fn parse_gemini_response(response: Response) -> HeelixResult<ChatCompletion> {
+    let response_body: GeminiResponse = response.json().await.map_err(|e| {
+        HeelixError::provider("Gemini", format!("Failed to parse Gemini response: {}", e))
+    })?;
 
-    // Emit the response
-    app_handle
-        .get_window("main")
-        .expect("Failed to get main window")
-        .emit("llm_response", completion.clone())
-        .map_err(|e| format!("Failed to emit response: {}", e))?;
-
-    // Estimate token usage
-    let word_count = completion.split_whitespace().count();
-    let output_tokens = (word_count as f64 * 0.75) as u32;
-    
-    app_handle
-        .get_window("main")
-        .expect("Failed to get main window")
-        .emit("output_tokens", output_tokens)
-        .map_err(|e| format!("Failed to emit output tokens: {}", e))?;
-
-    debug!("Gemini response complete - output tokens: {}", output_tokens);
-    Ok(())
+    let completion = response_body.candidates.first().map(|candidate| ChatCompletion {
+        text: candidate.content.parts.first().map(|part| part.text.clone()).unwrap_or_default(),
+        truncated: candidate.finish_reason.as_deref() == Some("MAX_TOKENS"),
+    });
+    Ok(completion.unwrap_or_default())
 }
 
-#[tauri::command]
 pub async fn name_conversation_gemini(
     app_handle: tauri::AppHandle,
     user_input: String,
-) -> Result<String, String> {
-    let setting =
-        app_handle.db(|db| get_setting(db, "api_key_gemini").expect("Failed on api_key_gemini"));
+) -> HeelixResult<String> {
+    check_naming_input(&user_input)?;
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_gemini", ""));
+    if api_key.is_empty() {
+        return Err(HeelixError::MissingApiKey("Gemini"));
+    }
+    let model = app_handle
+        .db(|db| get_naming_model(db, NAMING_MODEL_GEMINI_KEY))?
+        .unwrap_or_else(|| GEMINI_MODEL.to_string());
+    let max_chars = app_handle.db(get_naming_max_chars)?;
 
     let client = Client::builder()
         .timeout(Duration::from_secs(180))
         .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
+        .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
+
+    let system_prompt = naming_instructions(max_chars);
 
-    let system_prompt = "Name the conversation based on the user input. Use a total of 18 characters or less, without quotation marks. You only need to answer with the name.";
-    
     let contents = vec![
         Content {
             role: "user".to_string(),
@@ -305,28 +317,29 @@ pub async fn name_conversation_gemini(
         },
     ];
 
-    let api_url = format!("{}?key={}", GEMINI_URL, setting.setting_value);
-
     let request_body = GeminiRequest {
         contents,
         generation_config: GenerationConfig {
-            max_output_tokens: 20,
+            max_output_tokens: naming_max_tokens(max_chars) as usize,
+            response_mime_type: None,
+            temperature: None,
+            top_p: None,
         },
     };
 
+    acquire("gemini", request_body.estimated_input_tokens()).await;
     let response = client
-        .post(&api_url)
+        .post(generate_content_url(&model))
         .header("Content-Type", "application/json")
+        .header("x-goog-api-key", &api_key)
         .json(&request_body)
         .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
+        .await?;
 
     if response.status().is_success() {
-        let response_body: GeminiResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
+        let response_body: GeminiResponse = response.json().await.map_err(|e| {
+            HeelixError::provider("Gemini", format!("Failed to parse response: {}", e))
+        })?;
         
         let generated_name = if let Some(candidate) = response_body.candidates.first() {
             if let Some(part) = candidate.content.parts.first() {
@@ -340,8 +353,7 @@ pub async fn name_conversation_gemini(
         
         Ok(generated_name)
     } else {
-        let error_message = response.text().await
-            .map_err(|e| format!("Failed to read error message: {}", e))?;
-        Err(format!("Error from Gemini API: {}", error_message))
+        let error_message = response.text().await?;
+        Err(HeelixError::provider("Gemini", error_message))
     }
 }
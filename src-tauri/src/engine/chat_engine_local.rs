@@ -1,29 +1,53 @@
 use crate::configuration::state::ServiceAccess;
-use crate::engine::similarity_search_engine::DEFAULT_RAG_TOP_K;
-use crate::engine::project_vector_engine::search_project_vectors;
-use crate::repository::settings_repository::get_setting;
-use crate::repository::chunk_repository::{get_chunks_by_ids, get_chunk_sources, ChunkSource};
+use crate::error::{HeelixError, HeelixResult};
+use crate::engine::chat_dispatch::{check_naming_input, naming_instructions, PromptOptions};
+use crate::engine::chat_engine::{ChatCompletion, Message};
+use crate::engine::chat_provider::{
+    run_chat, single_answer, ChatProvider, ChatRequest, CompletionParams, DeltaStream, SystemPrompt,
+};
+use crate::engine::model_capabilities::Sampling;
+use crate::engine::provider_status::record_health_check;
+use crate::configuration::settings::NAMING_MODEL_LOCAL_KEY;
+use crate::repository::settings_repository::{get_local_model_url, get_naming_max_chars, get_naming_model, get_setting};
+use futures::future::BoxFuture;
 use log::{debug, error};
 use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
-use tauri::{AppHandle, Manager};
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
 
 // Default model for local Ollama
 const DEFAULT_MODEL: &str = "llama3.3:70b";
 
-#[derive(Serialize, Deserialize)]
-pub struct Message {
-    role: String,
-    content: String,
-}
-
 // Ollama API structures
 #[derive(Serialize)]
 struct OllamaRequest {
     model: String,
     messages: Vec<OllamaMessage>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OllamaOptions>,
+}
+
+/// Model parameters Ollama takes per request
+#[derive(Serialize)]
+struct OllamaOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+}
+
+impl OllamaOptions {
+    fn from_sampling(sampling: Sampling) -> Option<Self> {
+        if sampling == Sampling::default() {
+            return None;
+        }
+        Some(OllamaOptions {
+            temperature: sampling.temperature,
+            top_p: sampling.top_p,
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -35,251 +59,288 @@ struct OllamaMessage {
 #[derive(Deserialize)]
 struct OllamaResponse {
     message: OllamaMessage,
+    /// "length" when generation stopped at the output limit
+    #[serde(default)]
+    done_reason: Option<String>,
+}
+
+/// Outcome of a successful `test_local_endpoint` round-trip
+#[derive(Serialize)]
+pub struct LocalEndpointCheck {
+    pub model: String,
+    pub latency_ms: u64,
+    pub reply: String,
 }
 
+/// Explain a non-success answer from the local server, telling a missing model
+/// apart from other server errors
+fn local_endpoint_error(status: u16, body: &str, model: &str) -> HeelixError {
+    if status == 404 && body.to_lowercase().contains("not found") {
+        return HeelixError::provider(
+            "Ollama",
+            format!("Model '{}' was not found on the server. Download it with `ollama pull {}`.", model, model),
+        );
+    }
+    HeelixError::provider("Ollama", format!("Server answered with status {}: {}", status, body.trim()))
+}
+
+/// Send a trivial chat to `url` with `model` (the default local model when
+/// empty) so a local setup can be checked from Settings before it is used.
+/// The outcome is shown as the local provider's health in `get_providers_status`.
 #[tauri::command]
-pub async fn send_prompt_to_local(
-    app_handle: tauri::AppHandle,
-    conversation_history: Vec<Message>,
-    is_first_message: bool,
-    combined_activity_text: String,
-    model_id: Option<String>,
-    project_id: Option<i64>, // Project ID for chunk-based retrieval
-) -> Result<(), String> {
-    // Get local model URL from settings (defaults to localhost:11434 for Ollama)
-    let setting = app_handle.db(|db| get_setting(db, "local_model_url").expect("Failed on local_model_url"));
-    let base_url = if setting.setting_value.is_empty() {
-        "http://localhost:11434".to_string()
-    } else {
-        setting.setting_value
-    };
+pub async fn test_local_endpoint(url: String, model: Option<String>) -> HeelixResult<LocalEndpointCheck> {
+    let check = check_local_endpoint(url, model).await;
+    record_health_check("local", check.is_ok());
+    check
+}
+
+async fn check_local_endpoint(url: String, model: Option<String>) -> HeelixResult<LocalEndpointCheck> {
+    let base_url = url.trim().trim_end_matches('/');
+    if base_url.is_empty() {
+        return Err(HeelixError::InvalidInput("Enter the local model URL to test.".to_string()));
+    }
+    let model = model
+        .map(|model| model.trim().to_string())
+        .filter(|model| !model.is_empty())
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string());
 
-    // Configure client with longer timeouts for local models
     let client = Client::builder()
-        .timeout(Duration::from_secs(300))  // Longer timeout for local inference
-        .tcp_keepalive(Duration::from_secs(60))
-        .pool_idle_timeout(Duration::from_secs(90))
-        .pool_max_idle_per_host(2)
-        .connect_timeout(Duration::from_secs(30))
+        .timeout(Duration::from_secs(120))
+        .connect_timeout(Duration::from_secs(5))
         .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
+        .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
 
-    let model_to_use = match model_id.as_deref() {
-        Some(model) => model.to_string(),
-        _ => DEFAULT_MODEL.to_string(),
+    let request_body = OllamaRequest {
+        model: model.clone(),
+        messages: vec![OllamaMessage {
+            role: "user".to_string(),
+            content: "Reply with OK.".to_string(),
+        }],
+        stream: false,
+        options: None,
     };
-    let rag_top_k: usize = app_handle
-        .db(|db| get_setting(db, "rag_top_k"))
-        .map(|s| s.setting_value.parse().unwrap_or(DEFAULT_RAG_TOP_K))
-        .unwrap_or(DEFAULT_RAG_TOP_K);
-
-    let mut filtered_context = String::new();
-
-    if is_first_message {
-        let user_prompt = conversation_history
-            .last()
-            .map(|msg| msg.content.clone())
-            .unwrap_or_default();
-
-        let setting_openai = app_handle.db(|db| {
-            get_setting(db, "api_key_open_ai").expect("Failed on api_key_open_ai")
-        });
-
-        // Use per-project vector index if project_id is provided
-        if let Some(pid) = project_id {
-            debug!("Using per-project vector search for project {}", pid);
-
-            match search_project_vectors(&app_handle, pid, &user_prompt, rag_top_k, &setting_openai.setting_value).await {
-                Ok(similar_chunk_ids) if !similar_chunk_ids.is_empty() => {
-                    let chunk_ids_to_fetch: Vec<i64> = similar_chunk_ids
-                        .iter()
-                        .map(|(id, _)| *id)
-                        .collect();
-
-                    debug!("Retrieved {} similar chunks from project index", chunk_ids_to_fetch.len());
-
-                    let chunks = app_handle
-                        .db(|conn| get_chunks_by_ids(conn, &chunk_ids_to_fetch))
-                        .map_err(|e| format!("Failed to get chunk content: {}", e))?;
-
-                    // Get source information for citations
-                    let sources: Vec<ChunkSource> = app_handle
-                        .db(|conn| get_chunk_sources(conn, &chunk_ids_to_fetch))
-                        .unwrap_or_else(|e| {
-                            error!("Failed to get chunk sources: {}", e);
-                            vec![]
-                        });
-
-                    // Emit sources to frontend
-                    if !sources.is_empty() {
-                        if let Err(e) = app_handle
-                            .get_window("main")
-                            .expect("Failed to get main window")
-                            .emit("llm_sources", &sources)
-                        {
-                            error!("Failed to emit sources: {}", e);
-                        }
-                    }
 
-                    for (index, chunk) in chunks.iter().enumerate() {
-                        filtered_context.push_str(&format!(
-                            "Chunk {} (from document {}):\n{}\n\n",
-                            index + 1, chunk.document_id, chunk.chunk_text
-                        ));
-                    }
-                }
-                Ok(_) => {
-                    debug!("No vectorized chunks found for project");
-                }
-                Err(e) => {
-                    debug!("Project vector search failed: {}", e);
-                }
-            }
-        }
+    let started = Instant::now();
+    let response = client
+        .post(format!("{}/api/chat", base_url))
+        .header("Content-Type", "application/json")
+        .json(&request_body)
+        .send()
+        .await
+        .map_err(|e| {
+            HeelixError::Network(format!(
+                "Could not connect to {}: {}. Make sure the server is running.",
+                base_url,
+                e.without_url()
+            ))
+        })?;
+
+    let status = response.status();
+    let body = response.text().await?;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    if !status.is_success() {
+        error!("Local endpoint test failed with {}: {}", status, body);
+        return Err(local_endpoint_error(status.as_u16(), &body, &model));
     }
 
-    // Build system prompt - include RAG context only on first message
-    let system_prompt = if !filtered_context.is_empty() {
-        format!(
-            "You are Heelix, a helpful AI assistant running locally via Ollama. Provide answers in markdown format.\n\n\
-            The following document chunks were retrieved from the user's project and may help answer their question. Use them if relevant, otherwise ignore them:\n\n{}",
-            filtered_context
+    let parsed: OllamaResponse = serde_json::from_str(&body).map_err(|e| {
+        HeelixError::provider(
+            "Ollama",
+            format!("Unexpected response format ({}). Is this an Ollama server?", e),
         )
-    } else {
-        "You are Heelix, a helpful AI assistant running locally via Ollama. Provide answers in markdown format.".to_string()
-    };
+    })?;
+    debug!("Local endpoint answered in {} ms", latency_ms);
+
+    Ok(LocalEndpointCheck {
+        model,
+        latency_ms,
+        reply: parsed.message.content.trim().to_string(),
+    })
+}
 
-    // Build Ollama messages using native multi-turn format
-    let mut messages: Vec<OllamaMessage> = vec![
-        OllamaMessage {
-            role: "system".to_string(),
-            content: system_prompt,
-        },
-    ];
+/// A model served by Ollama at the `local_model_url` setting
+pub struct LocalProvider {
+    client: Client,
+    base_url: String,
+}
 
-    for (i, msg) in conversation_history.iter().enumerate() {
-        let mut content = msg.content.clone();
+impl LocalProvider {
+    pub fn new(app_handle: &AppHandle) -> HeelixResult<Self> {
+        // Get local model URL from settings (defaults to localhost:11434 for Ollama)
+        let base_url = app_handle.db(get_local_model_url)?;
+
+        // Configure client with longer timeouts for local models
+        let client = Client::builder()
+            .timeout(Duration::from_secs(300))  // Longer timeout for local inference
+            .tcp_keepalive(Duration::from_secs(60))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(2)
+            .connect_timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
+
+        Ok(LocalProvider { client, base_url })
+    }
 
-        // Add combined_activity_text to first user message if no RAG context
-        if i == 0 && msg.role == "user" && !combined_activity_text.is_empty() && filtered_context.is_empty() {
-            content = format!(
-                "{}\n\nContext from selected documents:\n{}",
-                content, combined_activity_text
-            );
-        }
+    /// Any model Ollama serves can be named; `DEFAULT_MODEL` otherwise
+    pub fn model_for(model_id: Option<&str>) -> String {
+        model_id.unwrap_or(DEFAULT_MODEL).to_string()
+    }
 
-        messages.push(OllamaMessage {
+    fn request_body(&self, messages: &[Message], system: &SystemPrompt, params: &CompletionParams) -> OllamaRequest {
+        // Build Ollama messages using native multi-turn format
+        let mut ollama_messages = vec![OllamaMessage {
+            role: "system".to_string(),
+            content: system.joined(),
+        }];
+        ollama_messages.extend(messages.iter().map(|msg| OllamaMessage {
             role: msg.role.clone(),
-            content,
-        });
+            content: msg.content.clone(),
+        }));
+
+        OllamaRequest {
+            model: params.model.clone(),
+            messages: ollama_messages,
+            stream: false,
+            options: OllamaOptions::from_sampling(params.sampling),
+        }
     }
+}
 
-    let api_url = format!("{}/api/chat", base_url);
+impl ChatProvider for LocalProvider {
+    fn id(&self) -> &'static str {
+        "local"
+    }
 
-    let request_body = OllamaRequest {
-        model: model_to_use,
-        messages,
-        stream: false,
-    };
+    fn name(&self) -> &'static str {
+        "Ollama"
+    }
 
-    // Make the request to Ollama
-    let mut attempt = 0;
-    let max_retries = 3;
-    let mut delay = Duration::from_secs(2);
-
-    loop {
-        let response = client
-            .post(&api_url)
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await;
-
-        match response {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    return handle_ollama_response(resp, app_handle).await;
-                } else {
-                    let error_message = resp.text().await
-                        .map_err(|e| format!("Failed to read error message: {}", e))?;
-                    error!("Ollama error: {}", error_message);
-                    return Err(format!("Error from Ollama: {}. Make sure Ollama is running and the model is downloaded.", error_message));
-                }
-            }
-            Err(e) => {
-                if attempt < max_retries {
-                    attempt += 1;
-                    error!("Request to Ollama failed: {}. Retrying... (Attempt {}/{})", e, attempt, max_retries);
-                    tokio::time::sleep(delay).await;
-                    delay *= 2;
-                } else {
-                    let error_message = "Could not connect to Ollama. Make sure Ollama is running (ollama serve) and try again.";
-                    error!("Request failed after {} attempts: {}", max_retries, e);
-                    app_handle
-                        .get_window("main")
-                        .expect("Failed to get main window")
-                        .emit("llm_response", error_message.to_string())
-                        .map_err(|emit_err| format!("Failed to emit error message: {}", emit_err))?;
-                    return Err(error_message.to_string());
-                }
-            }
-        }
+    fn persona_label(&self) -> &'static str {
+        "a local model via Ollama"
     }
-}
 
-async fn handle_ollama_response(
-    response: Response,
-    app_handle: AppHandle,
-) -> Result<(), String> {
-    let response_body: OllamaResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse Ollama response: {}", e))?;
+    fn resolve_model(&self, model_id: Option<&str>) -> String {
+        LocalProvider::model_for(model_id)
+    }
 
-    let completion = response_body.message.content;
+    /// Documents stay on the user's machine, so they are sent unredacted
+    fn is_remote(&self) -> bool {
+        false
+    }
 
-    // Emit the response
-    app_handle
-        .get_window("main")
-        .expect("Failed to get main window")
-        .emit("llm_response", completion.clone())
-        .map_err(|e| format!("Failed to emit response: {}", e))?;
+    fn stream_completion<'a>(
+        &'a self,
+        messages: &'a [Message],
+        system: &'a SystemPrompt,
+        params: &'a CompletionParams,
+    ) -> BoxFuture<'a, HeelixResult<DeltaStream<'a>>> {
+        Box::pin(async move {
+            let request_body = self.request_body(messages, system, params);
+            let api_url = format!("{}/api/chat", self.base_url);
+
+            // Make the request to Ollama
+            let mut attempt = 0;
+            let max_retries = 3;
+            let mut delay = Duration::from_secs(2);
+
+            loop {
+                let response = self
+                    .client
+                    .post(&api_url)
+                    .header("Content-Type", "application/json")
+                    .json(&request_body)
+                    .send()
+                    .await;
+
+                match response {
+                    Ok(resp) => {
+                        if resp.status().is_success() {
+                            return Ok(single_answer(parse_ollama_response(resp).await?));
+                        } else {
+                            let error_message = resp.text().await?;
+                            error!("Ollama error: {}", error_message);
+                            return Err(HeelixError::provider(
+                                "Ollama",
+                                format!("{}. Make sure Ollama is running and the model is downloaded.", error_message),
+                            ));
+                        }
+                    }
+                    Err(e) => {
+                        if attempt < max_retries {
+                            attempt += 1;
+                            error!("Request to Ollama failed: {}. Retrying... (Attempt {}/{})", e, attempt, max_retries);
+                            tokio::time::sleep(delay).await;
+                            delay *= 2;
+                        } else {
+                            let error_message = "Could not connect to Ollama. Make sure Ollama is running (ollama serve) and try again.";
+                            error!("Request failed after {} attempts: {}", max_retries, e);
+                            return Err(HeelixError::Network(error_message.to_string()));
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
 
-    // Estimate token usage
-    let word_count = completion.split_whitespace().count();
-    let output_tokens = (word_count as f64 * 0.75) as u32;
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_prompt_to_local(
+    app_handle: tauri::AppHandle,
+    conversation_history: Vec<Message>,
+    is_first_message: bool,
+    combined_activity_text: String,
+    model_id: Option<String>,
+    project_id: Option<i64>, // Project ID for chunk-based retrieval
+    chat_id: Option<i64>, // Tags streamed events so the UI can route them to the right chat
+    options: Option<PromptOptions>,
+) -> HeelixResult<ChatCompletion> {
+    let provider = LocalProvider::new(&app_handle)?;
+    let request = ChatRequest {
+        conversation_history,
+        is_first_message,
+        combined_activity_text,
+        model_id,
+        project_ids: project_id.into_iter().collect(),
+        chat_id,
+        options,
+        retrieved_context: None,
+    };
+    run_chat(&app_handle, &provider, request).await
+}
 
-    app_handle
-        .get_window("main")
-        .expect("Failed to get main window")
-        .emit("output_tokens", output_tokens)
-        .map_err(|e| format!("Failed to emit output tokens: {}", e))?;
+async fn parse_ollama_response(response: Response) -> HeelixResult<ChatCompletion> {
+    let response_body: OllamaResponse = response.json().await.map_err(|e| {
+        HeelixError::provider("Ollama", format!("Failed to parse Ollama response: {}", e))
+    })?;
 
-    debug!("Ollama response complete - output tokens: {}", output_tokens);
-    Ok(())
+    Ok(ChatCompletion {
+        text: response_body.message.content,
+        truncated: response_body.done_reason.as_deref() == Some("length"),
+    })
 }
 
-#[tauri::command]
 pub async fn name_conversation_local(
     app_handle: tauri::AppHandle,
     user_input: String,
-) -> Result<String, String> {
-    let setting = app_handle.db(|db| get_setting(db, "local_model_url").expect("Failed on local_model_url"));
-    let base_url = if setting.setting_value.is_empty() {
-        "http://localhost:11434".to_string()
-    } else {
-        setting.setting_value
-    };
+) -> HeelixResult<String> {
+    check_naming_input(&user_input)?;
+    let base_url = app_handle.db(get_local_model_url)?;
+    let model = app_handle
+        .db(|db| get_naming_model(db, NAMING_MODEL_LOCAL_KEY))?
+        .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+    let max_chars = app_handle.db(get_naming_max_chars)?;
 
     let client = Client::builder()
         .timeout(Duration::from_secs(60))
         .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
+        .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
 
     let messages = vec![
         OllamaMessage {
             role: "system".to_string(),
-            content: "Name the conversation based on the user input. Use a total of 18 characters or less, without quotation marks. You only need to answer with the name.".to_string(),
+            content: naming_instructions(max_chars),
         },
         OllamaMessage {
             role: "user".to_string(),
@@ -290,9 +351,10 @@ pub async fn name_conversation_local(
     let api_url = format!("{}/api/chat", base_url);
 
     let request_body = OllamaRequest {
-        model: DEFAULT_MODEL.to_string(),
+        model,
         messages,
         stream: false,
+        options: None,
     };
 
     let response = client
@@ -300,14 +362,12 @@ pub async fn name_conversation_local(
         .header("Content-Type", "application/json")
         .json(&request_body)
         .send()
-        .await
-        .map_err(|e| format!("Request failed: {}", e))?;
+        .await?;
 
     if response.status().is_success() {
-        let response_body: OllamaResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse response: {}", e))?;
+        let response_body: OllamaResponse = response.json().await.map_err(|e| {
+            HeelixError::provider("Ollama", format!("Failed to parse response: {}", e))
+        })?;
 
         let generated_name = response_body.message.content.trim().to_string();
         if generated_name.is_empty() {
@@ -316,8 +376,25 @@ pub async fn name_conversation_local(
             Ok(generated_name)
         }
     } else {
-        let error_message = response.text().await
-            .map_err(|e| format!("Failed to read error message: {}", e))?;
-        Err(format!("Error from Ollama: {}", error_message))
+        let error_message = response.text().await?;
+        Err(HeelixError::provider("Ollama", error_message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_endpoint_error_names_missing_model() {
+        let error = local_endpoint_error(
+            404,
+            r#"{"error":"model \"llama9\" not found, try pulling it first"}"#,
+            "llama9",
+        );
+        assert!(error.to_string().contains("Model 'llama9' was not found"));
+
+        let error = local_endpoint_error(500, "out of memory", "llama9");
+        assert!(error.to_string().contains("status 500: out of memory"));
     }
 }
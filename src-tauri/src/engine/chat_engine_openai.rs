@@ -1,238 +1,355 @@
+use crate::configuration::settings::{
+    NAMING_MODEL_OPENAI_KEY, OPENAI_BASE_URL_KEY, OPENAI_ORG_ID_KEY, OPENAI_PROJECT_ID_KEY,
+};
 use crate::configuration::state::ServiceAccess;
-use crate::engine::similarity_search_engine::DEFAULT_RAG_TOP_K;
-use crate::engine::project_vector_engine::search_project_vectors;
-use crate::repository::settings_repository::get_setting;
-use crate::repository::chunk_repository::{get_chunks_by_ids, get_chunk_sources, ChunkSource};
+use crate::error::{HeelixError, HeelixResult};
+use crate::engine::chat_engine::{ChatCompletion, Message};
+use crate::engine::chat_dispatch::{
+    check_naming_input, naming_instructions, naming_max_tokens, PromptOptions, ReasoningEffort, ResponseFormat,
+};
+use crate::engine::chat_provider::{
+    delta_stream, run_chat, ChatProvider, ChatRequest, CompletionParams, Delta, DeltaStream, SystemPrompt,
+};
+use crate::engine::model_capabilities::{capabilities_for, max_output_tokens_for};
+use crate::engine::rate_limiter::{acquire, estimate_prompt_tokens};
+use crate::repository::settings_repository::{
+    get_max_output_tokens, get_naming_max_chars, get_naming_model, get_setting_or_default,
+};
 use async_openai::{
-    config::OpenAIConfig,
+    config::{Config, OpenAIConfig},
     types::{
         ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
         ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage,
-        CreateChatCompletionRequestArgs,
+        ChatCompletionRequestMessageContentPart, ChatCompletionRequestMessageContentPartImageArgs,
+        ChatCompletionRequestMessageContentPartTextArgs, CreateChatCompletionRequestArgs,
+        ChatCompletionResponseFormat, ChatCompletionResponseFormatType,
+        CreateChatCompletionRequest, CreateChatCompletionStreamResponse, FinishReason, ImageUrlArgs,
     },
     Client as OpenAIClient,
 };
-use futures::StreamExt;
+use futures::future::BoxFuture;
 use log::{debug, error};
-use serde::{Deserialize, Serialize};
-use tauri::Manager;
 
 // Only GPT-5 is available for OpenAI
 const DEFAULT_MODEL: &str = "gpt-5";
-
-#[derive(Serialize, Deserialize)]
-pub struct Message {
-    role: String,
-    content: String,
+/// Small, fast model for conversation titles unless `naming_model_openai` is set
+const NAMING_MODEL: &str = "gpt-4o-mini";
+
+/// OpenAI chat completions, or the compatible server set as `openai_base_url`
+pub struct OpenAIProvider {
+    client: OpenAIClient<OpenAIConfig>,
+    /// Posts chat requests; async-openai cannot send `reasoning_effort`
+    http: reqwest::Client,
+    /// The `max_output_tokens` setting
+    configured_max_tokens: u32,
 }
 
-#[tauri::command]
-pub async fn send_prompt_to_openai(
-    app_handle: tauri::AppHandle,
-    conversation_history: Vec<Message>,
-    is_first_message: bool,
-    combined_activity_text: String,
-    model_id: Option<String>,
-    project_id: Option<i64>, // Project ID for chunk-based retrieval
-) -> Result<(), String> {
-    let setting =
-        app_handle.db(|db| get_setting(db, "api_key_open_ai").expect("Failed on api_key_open_ai"));
-
-    let mut filtered_context = String::new();
-    let model_to_use = match model_id.as_deref() {
-        Some("gpt-5") => "gpt-5",
-        _ => "gpt-5", // Default to GPT-5
-    };
-    let rag_top_k: usize = app_handle
-        .db(|db| get_setting(db, "rag_top_k"))
-        .map(|s| s.setting_value.parse().unwrap_or(DEFAULT_RAG_TOP_K))
-        .unwrap_or(DEFAULT_RAG_TOP_K);
-
-    if is_first_message {
-        let user_prompt = conversation_history
-            .last()
-            .map(|msg| msg.content.clone())
-            .unwrap_or_default();
-
-        // Use per-project vector index if project_id is provided
-        if let Some(pid) = project_id {
-            debug!("Using per-project vector search for project {}", pid);
-
-            match search_project_vectors(&app_handle, pid, &user_prompt, rag_top_k, &setting.setting_value).await {
-                Ok(similar_chunk_ids) if !similar_chunk_ids.is_empty() => {
-                    let chunk_ids_to_fetch: Vec<i64> = similar_chunk_ids
-                        .iter()
-                        .map(|(id, _)| *id)
-                        .collect();
-
-                    debug!("Retrieved {} similar chunks from project index", chunk_ids_to_fetch.len());
-
-                    let chunks = app_handle
-                        .db(|conn| get_chunks_by_ids(conn, &chunk_ids_to_fetch))
-                        .map_err(|e| format!("Failed to get chunk content: {}", e))?;
-
-                    // Get source information for citations
-                    let sources: Vec<ChunkSource> = app_handle
-                        .db(|conn| get_chunk_sources(conn, &chunk_ids_to_fetch))
-                        .unwrap_or_else(|e| {
-                            error!("Failed to get chunk sources: {}", e);
-                            vec![]
-                        });
-
-                    // Emit sources to frontend
-                    if !sources.is_empty() {
-                        if let Err(e) = app_handle
-                            .get_window("main")
-                            .expect("Failed to get main window")
-                            .emit("llm_sources", &sources)
-                        {
-                            error!("Failed to emit sources: {}", e);
-                        }
-                    }
-
-                    for (index, chunk) in chunks.iter().enumerate() {
-                        filtered_context.push_str(&format!(
-                            "Chunk {} (from document {}):\n{}\n\n",
-                            index + 1, chunk.document_id, chunk.chunk_text
-                        ));
-                    }
-                }
-                Ok(_) => {
-                    debug!("No vectorized chunks found for project");
-                }
-                Err(e) => {
-                    debug!("Project vector search failed: {}", e);
-                }
-            }
+impl OpenAIProvider {
+    pub fn new(app_handle: &tauri::AppHandle) -> HeelixResult<Self> {
+        let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
+        if api_key.is_empty() {
+            return Err(HeelixError::MissingApiKey("OpenAI"));
         }
+        Ok(OpenAIProvider {
+            client: OpenAIClient::with_config(openai_config(app_handle, &api_key)),
+            http: reqwest::Client::new(),
+            configured_max_tokens: app_handle.db(get_max_output_tokens)?,
+        })
     }
 
-    // Build system prompt - include RAG context only on first message
-    let system_prompt = if !filtered_context.is_empty() {
-        format!(
-            "You are Heelix chat app that is powered by OpenAI LLM. Heelix chat is developed by Heelix Technologies. Only identify yourself as such. Provide answers in markdown format.\n\n\
-            The following document chunks were retrieved from the user's project and may help answer their question. Use them if relevant, otherwise ignore them:\n\n{}",
-            filtered_context
-        )
-    } else {
-        "You are Heelix chat app that is powered by OpenAI LLM. Heelix chat is developed by Heelix Technologies. Only identify yourself as such. Provide answers in markdown format.".to_string()
-    };
+    /// Model that answers a request for `model_id`; always GPT-5 for now
+    pub fn model_for(model_id: Option<&str>) -> String {
+        match model_id {
+            Some("gpt-5") => "gpt-5",
+            _ => DEFAULT_MODEL,
+        }
+        .to_string()
+    }
 
-    // Build messages array using OpenAI's native multi-turn format
-    let mut messages: Vec<ChatCompletionRequestMessage> = vec![
-        ChatCompletionRequestSystemMessageArgs::default()
-            .content(system_prompt)
-            .build()
-            .unwrap()
-            .into(),
-    ];
-
-    // Add conversation history
-    for (i, msg) in conversation_history.iter().enumerate() {
-        let mut content = msg.content.clone();
-
-        // Add combined_activity_text to first user message if no RAG context
-        if i == 0 && msg.role == "user" && !combined_activity_text.is_empty() && filtered_context.is_empty() {
-            content = format!(
-                "{}\n\nContext from selected documents:\n{}",
-                content, combined_activity_text
-            );
+    fn request(
+        &self,
+        messages: &[Message],
+        system: &SystemPrompt,
+        params: &CompletionParams,
+    ) -> HeelixResult<CreateChatCompletionRequest> {
+        let model = params.model.as_str();
+
+        // Build messages array using OpenAI's native multi-turn format
+        let mut request_messages: Vec<ChatCompletionRequestMessage> = vec![
+            ChatCompletionRequestSystemMessageArgs::default()
+                .content(system.joined())
+                .build()
+                .map_err(HeelixError::from_openai)?
+                .into(),
+        ];
+
+        let last_user_index = messages.iter().rposition(|m| m.role == "user");
+        for (i, msg) in messages.iter().enumerate() {
+            let content = msg.content.clone();
+            if Some(i) == last_user_index && !params.images.is_empty() {
+                let mut parts: Vec<ChatCompletionRequestMessageContentPart> = vec![
+                    ChatCompletionRequestMessageContentPartTextArgs::default()
+                        .text(content)
+                        .build()
+                        .map_err(HeelixError::from_openai)?
+                        .into(),
+                ];
+                for image in &params.images {
+                    parts.push(
+                        ChatCompletionRequestMessageContentPartImageArgs::default()
+                            .image_url(
+                                ImageUrlArgs::default()
+                                    .url(image.data_url())
+                                    .build()
+                                    .map_err(HeelixError::from_openai)?,
+                            )
+                            .build()
+                            .map_err(HeelixError::from_openai)?
+                            .into(),
+                    );
+                }
+                request_messages.push(
+                    ChatCompletionRequestUserMessageArgs::default()
+                        .content(parts)
+                        .build()
+                        .map_err(HeelixError::from_openai)?
+                        .into(),
+                );
+            } else if msg.role == "user" {
+                request_messages.push(
+                    ChatCompletionRequestUserMessageArgs::default()
+                        .content(content)
+                        .build()
+                        .map_err(HeelixError::from_openai)?
+                        .into(),
+                );
+            } else {
+                request_messages.push(
+                    ChatCompletionRequestAssistantMessageArgs::default()
+                        .content(content)
+                        .build()
+                        .map_err(HeelixError::from_openai)?
+                        .into(),
+                );
+            }
         }
 
-        if msg.role == "user" {
-            messages.push(
-                ChatCompletionRequestUserMessageArgs::default()
-                    .content(content)
-                    .build()
-                    .unwrap()
-                    .into(),
-            );
-        } else {
-            messages.push(
-                ChatCompletionRequestAssistantMessageArgs::default()
-                    .content(content)
-                    .build()
-                    .unwrap()
-                    .into(),
-            );
+        let mut request_args = CreateChatCompletionRequestArgs::default();
+        request_args.model(model).messages(request_messages);
+        if params.response_format == ResponseFormat::JsonObject {
+            request_args.response_format(ChatCompletionResponseFormat {
+                r#type: ChatCompletionResponseFormatType::JsonObject,
+            });
         }
+        if let Some(temperature) = params.sampling.temperature {
+            request_args.temperature(temperature);
+        }
+        if let Some(top_p) = params.sampling.top_p {
+            request_args.top_p(top_p);
+        }
+
+        // Reasoning models only take `max_completion_tokens`, which async-openai 0.23
+        // does not have, so the cap is applied to the other models only
+        if let Some(max_tokens) = max_output_tokens_for(model, self.configured_max_tokens, None) {
+            if capabilities_for(model).reasoning {
+                debug!("max_output_tokens={} requested for {}, using the model default", max_tokens, model);
+            } else {
+                request_args.max_tokens(max_tokens);
+            }
+        }
+        request_args.build().map_err(HeelixError::from_openai)
     }
 
-    let request = CreateChatCompletionRequestArgs::default()
-        .model(model_to_use)
-        .messages(messages)
-        .build()
-        .map_err(|e| format!("Failed to build request: {}", e))?;
+    /// JSON body of the streamed request, with `reasoning_effort` for reasoning models
+    fn request_body(
+        &self,
+        messages: &[Message],
+        system: &SystemPrompt,
+        params: &CompletionParams,
+    ) -> HeelixResult<serde_json::Value> {
+        let mut request = self.request(messages, system, params)?;
+        request.stream = Some(true);
+        let reasoning_effort = params
+            .reasoning_effort
+            .filter(|_| capabilities_for(&params.model).reasoning);
+        with_reasoning_effort(request, reasoning_effort)
+    }
+}
 
-    let response_client =
-        OpenAIClient::with_config(OpenAIConfig::new().with_api_key(&setting.setting_value));
-    let mut stream = response_client
-        .chat()
-        .create_stream(request)
-        .await
-        .map_err(|e| format!("Failed to create chat completion stream: {}", e))?;
+/// `request` as JSON with `reasoning_effort` added when set. async-openai 0.23
+/// has no field for it, so the request is posted as JSON instead of through
+/// the client.
+fn with_reasoning_effort(
+    request: CreateChatCompletionRequest,
+    reasoning_effort: Option<ReasoningEffort>,
+) -> HeelixResult<serde_json::Value> {
+    let mut body = serde_json::to_value(request)
+        .map_err(|e| HeelixError::Internal(format!("Failed to serialize OpenAI request: {}", e)))?;
+    if let Some(effort) = reasoning_effort {
+        body["reasoning_effort"] = effort.as_str().into();
+    }
+    Ok(body)
+}
 
-    let mut completion = String::new();
+impl ChatProvider for OpenAIProvider {
+    fn id(&self) -> &'static str {
+        "openai"
+    }
 
-    while let Some(result) = stream.next().await {
-        match result {
-            Ok(response) => {
-                if let Some(choice) = response.choices.first() {
-                    if let Some(content) = &choice.delta.content {
-                        completion.push_str(content);
-                    }
-                }
-            }
-            Err(e) => {
-                return Err(format!("Error while streaming response: {}", e));
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn persona_label(&self) -> &'static str {
+        "OpenAI LLM"
+    }
+
+    fn resolve_model(&self, model_id: Option<&str>) -> String {
+        OpenAIProvider::model_for(model_id)
+    }
+
+    fn supports_images(&self) -> bool {
+        true
+    }
+
+    fn supports_json(&self) -> bool {
+        true
+    }
+
+    fn stream_completion<'a>(
+        &'a self,
+        messages: &'a [Message],
+        system: &'a SystemPrompt,
+        params: &'a CompletionParams,
+    ) -> BoxFuture<'a, HeelixResult<DeltaStream<'a>>> {
+        Box::pin(async move {
+            let body = self.request_body(messages, system, params)?;
+            let config = self.client.config();
+            let response = self
+                .http
+                .post(config.url("/chat/completions"))
+                .query(&config.query())
+                .headers(config.headers())
+                .json(&body)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                let error_message = response.text().await?;
+                error!("OpenAI API error: {}", error_message);
+                return Err(HeelixError::provider("OpenAI", error_message));
             }
-        }
 
-        app_handle
-            .get_window("main")
-            .expect("Failed to get main window")
-            .emit("llm_response", completion.clone())
-            .map_err(|e| format!("Failed to emit response: {}", e))?;
+            let mut events = OpenAIEvents::default();
+            Ok(delta_stream(response.bytes_stream(), "OpenAI", move |chunk| match chunk {
+                Ok(chunk) => events.feed(&chunk),
+                Err(e) => vec![Err(HeelixError::Network(format!("Failed to read chunk: {}", e)))],
+            }))
+        })
     }
+}
 
-    // Estimate token usage based on word count
-    let word_count = completion.split_whitespace().count();
-    let output_tokens = (word_count as f64 * 0.75) as i64;
+/// Parser for OpenAI's server-sent events, fed the response body as it arrives.
+/// A line split across two chunks is parsed once its end has arrived.
+#[derive(Default)]
+struct OpenAIEvents {
+    /// Start of a line whose end has not arrived yet
+    partial: Vec<u8>,
+}
 
-    // Emit the estimated token usage to the frontend
-    app_handle
-        .get_window("main")
-        .expect("Failed to get main window")
-        .emit("output_tokens", output_tokens)
-        .map_err(|e| format!("Failed to emit estimated tokens: {}", e))?;
+impl OpenAIEvents {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<HeelixResult<Delta>> {
+        self.partial.extend_from_slice(chunk);
+        let mut deltas = Vec::new();
+        while let Some(end) = self.partial.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            match line.strip_prefix("data:").map(str::trim) {
+                None | Some("") | Some("[DONE]") => {}
+                Some(data) => parse_event(data, &mut deltas),
+            }
+        }
+        deltas
+    }
+}
 
-    debug!("OpenAI response complete - estimated tokens: {}", output_tokens);
-    Ok(())
+fn parse_event(data: &str, deltas: &mut Vec<HeelixResult<Delta>>) {
+    // Anything but a completion chunk is an error event, e.g. {"error": {...}}
+    let response: CreateChatCompletionStreamResponse = match serde_json::from_str(data) {
+        Ok(response) => response,
+        Err(_) => {
+            error!("Received error event: {}", data);
+            deltas.push(Err(HeelixError::provider("OpenAI", data)));
+            return;
+        }
+    };
+    if let Some(choice) = response.choices.first() {
+        if let Some(content) = &choice.delta.content {
+            deltas.push(Ok(Delta::Text(content.clone())));
+        }
+        if matches!(choice.finish_reason, Some(FinishReason::Length)) {
+            deltas.push(Ok(Delta::Truncated));
+        }
+    }
 }
 
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_prompt_to_openai(
+    app_handle: tauri::AppHandle,
+    conversation_history: Vec<Message>,
+    is_first_message: bool,
+    combined_activity_text: String,
+    model_id: Option<String>,
+    project_id: Option<i64>, // Project ID for chunk-based retrieval
+    chat_id: Option<i64>, // Tags streamed events so the UI can route them to the right chat
+    options: Option<PromptOptions>,
+) -> HeelixResult<ChatCompletion> {
+    let provider = OpenAIProvider::new(&app_handle)?;
+    let request = ChatRequest {
+        conversation_history,
+        is_first_message,
+        combined_activity_text,
+        model_id,
+        project_ids: project_id.into_iter().collect(),
+        chat_id,
+        options,
+        retrieved_context: None,
+    };
+    run_chat(&app_handle, &provider, request).await
+}
+
 pub async fn generate_conversation_name(
     app_handle: tauri::AppHandle,
-    user_input: &str,
-) -> Result<String, String> {
+    user_input: String,
+) -> HeelixResult<String> {
+    check_naming_input(&user_input)?;
     // Fetch the OpenAI API key from your settings
-    let setting =
-        app_handle.db(|db| get_setting(db, "api_key_open_ai").expect("Failed on api_key_open_ai"));
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
+    if api_key.is_empty() {
+        return Err(HeelixError::MissingApiKey("OpenAI"));
+    }
+    let model = app_handle
+        .db(|db| get_naming_model(db, NAMING_MODEL_OPENAI_KEY))?
+        .unwrap_or_else(|| NAMING_MODEL.to_string());
+    let max_chars = app_handle.db(get_naming_max_chars)?;
 
     // Initialize the OpenAI client with the API key
-    let config = OpenAIConfig::new().with_api_key(&setting.setting_value);
+    let config = openai_config(&app_handle, &api_key);
     let client = OpenAIClient::with_config(config);
 
     // Define the system prompt to guide the model
     let system_prompt = format!(
-        "Name the conversation based on the user input. Use a total of 18 characters or less, without quotation marks. Use proper English, don't skip spaces between words. You only need to answer with the name. The following is the user input: \n\n{}\n\n.:",
+        "{} The following is the user input: \n\n{}\n\n.:",
+        naming_instructions(max_chars),
         user_input
     );
 
+    let input_tokens = estimate_prompt_tokens([system_prompt.as_str()]);
+
     // Create a chat completion request with the system message and user input
     let request = CreateChatCompletionRequestArgs::default()
-        .model(DEFAULT_MODEL) // Specify the model, you can use "gpt-4" if needed
-        .max_tokens(20u32) // Limit the response to 20 tokens
+        .model(model)
+        .max_tokens(naming_max_tokens(max_chars))
         .messages(vec![
             // Use the correct message type for the system message
             ChatCompletionRequestSystemMessageArgs::default()
@@ -250,22 +367,106 @@ pub async fn generate_conversation_name(
                 .into(), // Convert to correct type
         ])
         .build()
-        .map_err(|e| format!("generate_conversation_name request_error: {}", e))?; // Handle request building error
+        .map_err(HeelixError::from_openai)?; // Handle request building error
 
-    // Send the request to OpenAI and await the response, converting any OpenAIError to a String
+    // Send the request to OpenAI and await the response, converting any OpenAIError to a HeelixError
+    acquire("openai", input_tokens).await;
     let response = client
         .chat()
         .create(request)
         .await
-        .map_err(|e| format!("generate_conversation_name OpenAI API request failed: {}", e))?;
+        .map_err(HeelixError::from_openai)?;
 
     // Extract the first message content safely from the response
-    let generated_name = response.choices[0]
-        .message
-        .content
-        .as_ref() // Convert Option<String> to Option<&String>
+    let generated_name = response
+        .choices
+        .first()
+        .and_then(|choice| choice.message.content.as_ref()) // Convert Option<String> to Option<&String>
         .map(|s| s.trim().to_string()) // Trim and convert to String if Some
         .unwrap_or_else(|| "Unnamed Conversation".to_string()); // Provide fallback if None
 
     Ok(generated_name)
 }
+
+/// OpenAI client config for `api_key`, pointed at `openai_base_url` when one is set.
+/// The base URL includes the version path, e.g. `https://my-gateway.example.com/v1`.
+pub fn openai_config(app_handle: &tauri::AppHandle, api_key: &str) -> OpenAIConfig {
+    let base_url = app_handle.db(|db| get_setting_or_default(db, OPENAI_BASE_URL_KEY, ""));
+    let config = openai_account_config(app_handle, api_key);
+    if base_url.is_empty() {
+        config
+    } else {
+        config.with_api_base(base_url.trim_end_matches('/'))
+    }
+}
+
+/// OpenAI client config for `api_key` with the `OpenAI-Organization` and
+/// `OpenAI-Project` headers from `openai_org_id` and `openai_project_id` when
+/// they are set. Embeddings use it as is, since they always go to OpenAI.
+pub fn openai_account_config(app_handle: &tauri::AppHandle, api_key: &str) -> OpenAIConfig {
+    let (org_id, project_id) = app_handle.db(|db| {
+        (
+            get_setting_or_default(db, OPENAI_ORG_ID_KEY, ""),
+            get_setting_or_default(db, OPENAI_PROJECT_ID_KEY, ""),
+        )
+    });
+    let mut config = OpenAIConfig::new().with_api_key(api_key);
+    if !org_id.trim().is_empty() {
+        config = config.with_org_id(org_id.trim());
+    }
+    if !project_id.trim().is_empty() {
+        config = config.with_project_id(project_id.trim());
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> CreateChatCompletionRequest {
+        CreateChatCompletionRequestArgs::default()
+            .model("gpt-5")
+            .messages(vec![ChatCompletionRequestUserMessageArgs::default()
+                .content("Why is the sky blue?")
+                .build()
+                .unwrap()
+                .into()])
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reasoning_effort_is_added_to_the_request_body() {
+        let body = with_reasoning_effort(request(), Some(ReasoningEffort::High)).unwrap();
+        assert_eq!(body["reasoning_effort"], "high");
+        assert_eq!(body["model"], "gpt-5");
+        assert_eq!(body["messages"][0]["content"], "Why is the sky blue?");
+
+        let body = with_reasoning_effort(request(), None).unwrap();
+        assert!(body.get("reasoning_effort").is_none());
+    }
+
+    #[test]
+    fn test_openai_events_across_chunk_boundaries() {
+        let mut events = OpenAIEvents::default();
+        let chunk = |content: &str, finish: &str| {
+            format!(
+                r#"data: {{"id":"1","object":"chat.completion.chunk","created":1,"model":"gpt-5","choices":[{{"index":0,"delta":{{"content":"{}"}},"finish_reason":{}}}]}}"#,
+                content, finish
+            )
+        };
+        let body = format!("{}\n\n{}\n\ndata: [DONE]\n\n", chunk("Hel", "null"), chunk("lo", "\"length\""));
+        let (first, rest) = body.split_at(40);
+
+        assert!(events.feed(first.as_bytes()).is_empty());
+        let deltas: Vec<_> = events.feed(rest.as_bytes()).into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            deltas,
+            vec![Delta::Text("Hel".to_string()), Delta::Text("lo".to_string()), Delta::Truncated]
+        );
+
+        let error = events.feed(b"data: {\"error\": {\"message\": \"Rate limit reached\"}}\n");
+        assert!(matches!(&error[..], [Err(e)] if e.is_retryable()));
+    }
+}
@@ -0,0 +1,397 @@
+//! The parts of a chat request every provider shares: project retrieval,
+//! context redaction, the system prompt, where the selected documents go, rate
+//! limiting, streaming to the UI, the JSON repair turn and usage reporting.
+//! Each engine only implements `ChatProvider`, turning the prepared prompt into
+//! its own wire format and the answer back into `Delta`s.
+
+use std::collections::VecDeque;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
+use log::{debug, info, warn};
+use tauri::AppHandle;
+
+use crate::configuration::state::ServiceAccess;
+use crate::engine::chat_dispatch::{
+    chat_system_prompt, invalid_json_reason, json_correction_prompt, PromptOptions, ReasoningEffort, ResponseFormat,
+};
+use crate::engine::chat_engine::{emit_chat_event, next_or_stall, ChatCompletion, Message, ResponseStream};
+use crate::engine::image_attachment::{images_for_model, ImageAttachment};
+use crate::engine::model_capabilities::Sampling;
+use crate::engine::rag_engine::retrieve_project_context;
+use crate::engine::rate_limiter::{acquire, estimate_prompt_tokens};
+use crate::engine::redaction::redact_context;
+use crate::error::{HeelixError, HeelixResult};
+use crate::repository::settings_repository::{get_rag_on_every_turn, get_setting_or_default};
+
+/// Introduces the retrieved chunks in the system prompt
+const RETRIEVED_CONTEXT_INTRO: &str = "The following document chunks were retrieved from the user's project and may help answer their question. Use them if relevant, otherwise ignore them:";
+
+/// One piece of a streamed answer
+#[derive(Debug, Clone, PartialEq)]
+pub enum Delta {
+    Text(String),
+    /// The provider stopped at the output token limit
+    Truncated,
+    /// Token counts reported by the provider. Without one, output tokens are
+    /// estimated from the answer.
+    Usage { input_tokens: u32, output_tokens: u32 },
+}
+
+pub type DeltaStream<'a> = BoxStream<'a, HeelixResult<Delta>>;
+
+/// System prompt of a request, kept in parts so a provider can cache the fixed
+/// instructions separately from the chunks retrieved for this conversation
+#[derive(Debug, Clone, Default)]
+pub struct SystemPrompt {
+    pub instructions: String,
+    /// Retrieved project chunks with their introduction; empty when nothing was retrieved
+    pub context: String,
+}
+
+impl SystemPrompt {
+    fn new(instructions: String, retrieved: &str) -> Self {
+        let context = if retrieved.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n\n{}", RETRIEVED_CONTEXT_INTRO, retrieved)
+        };
+        SystemPrompt { instructions, context }
+    }
+
+    /// Instructions and context as one prompt, for APIs with a single system message
+    pub fn joined(&self) -> String {
+        if self.context.is_empty() {
+            self.instructions.clone()
+        } else {
+            format!("{}\n\n{}", self.instructions, self.context)
+        }
+    }
+}
+
+/// Everything about a request besides the prompt, resolved before the provider is called
+pub struct CompletionParams {
+    pub model: String,
+    pub sampling: Sampling,
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Always `Text` for providers without JSON support
+    pub response_format: ResponseFormat,
+    /// Images for the last user message; only loaded for providers that take them
+    pub images: Vec<ImageAttachment>,
+}
+
+/// A chat backend. Implementations translate the prepared prompt into their
+/// API's request and stream the answer back; everything else is `run_chat`.
+pub trait ChatProvider: Send + Sync {
+    /// Key used for settings, sampling ranges and rate limits, e.g. "claude"
+    fn id(&self) -> &'static str;
+    /// Name used in errors and logs, e.g. "Claude"
+    fn name(&self) -> &'static str;
+    /// How the assistant persona refers to the model, e.g. "Anthropic LLM"
+    fn persona_label(&self) -> &'static str;
+    /// Model that answers a request for `model_id`
+    fn resolve_model(&self, model_id: Option<&str>) -> String;
+    /// Whether requests can carry image attachments
+    fn supports_images(&self) -> bool {
+        false
+    }
+    /// Whether `ResponseFormat::JsonObject` is honoured
+    fn supports_json(&self) -> bool {
+        false
+    }
+    /// Whether document text leaves the machine, so the redaction setting applies
+    fn is_remote(&self) -> bool {
+        true
+    }
+    /// Send one completion request and stream its answer. The selected
+    /// documents are already part of `messages`.
+    fn stream_completion<'a>(
+        &'a self,
+        messages: &'a [Message],
+        system: &'a SystemPrompt,
+        params: &'a CompletionParams,
+    ) -> BoxFuture<'a, HeelixResult<DeltaStream<'a>>>;
+}
+
+/// A chat prompt as the engines receive it from `send_prompt`
+#[derive(Clone)]
+pub struct ChatRequest {
+    pub conversation_history: Vec<Message>,
+    pub is_first_message: bool,
+    pub combined_activity_text: String,
+    pub model_id: Option<String>,
+    /// Projects to retrieve chunks from on the first message, and on every
+    /// message with `rag_on_every_turn`; empty skips retrieval
+    pub project_ids: Vec<i64>,
+    /// Tags streamed events so the UI can route them to the right chat
+    pub chat_id: Option<i64>,
+    pub options: Option<PromptOptions>,
+    /// Chunks already retrieved for this prompt, so a fallback provider does
+    /// not search again; None retrieves them in `run_chat`
+    pub retrieved_context: Option<String>,
+}
+
+/// Retrieve the project chunks for `request`, emitting their sources once.
+/// Follow-up messages search again only with `rag_on_every_turn`, and like
+/// the first message only while the project index is in use.
+pub async fn retrieve_context(app_handle: &AppHandle, request: &ChatRequest) -> HeelixResult<String> {
+    let retrieve = request.is_first_message
+        || (!request.project_ids.is_empty()
+            && app_handle.db(get_rag_on_every_turn)?
+            && app_handle.db(|db| get_setting_or_default(db, "vectorization_enabled", "false")) == "true");
+    if !retrieve {
+        return Ok(String::new());
+    }
+    let user_prompt = request.conversation_history.last().map_or("", |msg| msg.content.as_str());
+    retrieve_project_context(app_handle, &request.project_ids, request.chat_id, user_prompt).await
+}
+
+/// Answer `request` with `provider`, streaming the text as `llm_response` and
+/// emitting `output_tokens` once the answer is complete
+pub async fn run_chat(
+    app_handle: &AppHandle,
+    provider: &dyn ChatProvider,
+    request: ChatRequest,
+) -> HeelixResult<ChatCompletion> {
+    let mut retrieved = match request.retrieved_context.clone() {
+        Some(retrieved) => retrieved,
+        None => retrieve_context(app_handle, &request).await?,
+    };
+    let ChatRequest {
+        conversation_history,
+        mut combined_activity_text,
+        model_id,
+        chat_id,
+        options,
+        ..
+    } = request;
+
+    let model = provider.resolve_model(model_id.as_deref());
+    let response_format = options
+        .as_ref()
+        .map(|o| o.response_format)
+        .filter(|_| provider.supports_json())
+        .unwrap_or_default();
+    let images = if provider.supports_images() {
+        images_for_model(options.as_ref(), &model)?
+    } else {
+        Vec::new()
+    };
+    let params = CompletionParams {
+        sampling: options
+            .as_ref()
+            .map_or_else(Sampling::default, |o| o.sampling(provider.id(), &model)),
+        reasoning_effort: options.as_ref().and_then(|o| o.reasoning_effort),
+        response_format,
+        images,
+        model,
+    };
+
+    if provider.is_remote() {
+        // Document text leaves the machine here; the typed prompt is left as is
+        redact_context(app_handle, chat_id, &mut [&mut retrieved, &mut combined_activity_text])?;
+    }
+
+    let instructions = chat_system_prompt(app_handle, provider.persona_label(), params.response_format)?;
+    let system = SystemPrompt::new(instructions, &retrieved);
+    // Retrieved chunks already cover the project, so the selected documents only go in without them
+    let documents = if retrieved.is_empty() { combined_activity_text.as_str() } else { "" };
+    let mut messages = with_selected_documents(conversation_history, documents);
+
+    let (mut completion, mut reported_tokens) =
+        stream_answer(app_handle, provider, &messages, &system, &params, chat_id).await?;
+
+    // Give the model one chance to repair output that does not parse
+    if params.response_format == ResponseFormat::JsonObject {
+        if let Some(reason) = invalid_json_reason(&completion.text) {
+            warn!("{} returned invalid JSON ({}), asking for a correction", provider.name(), reason);
+            messages.push(Message {
+                role: "assistant".to_string(),
+                content: completion.text,
+            });
+            messages.push(Message {
+                role: "user".to_string(),
+                content: json_correction_prompt(&reason),
+            });
+            (completion, reported_tokens) =
+                stream_answer(app_handle, provider, &messages, &system, &params, chat_id).await?;
+            if let Some(reason) = invalid_json_reason(&completion.text) {
+                return Err(HeelixError::provider(
+                    provider.name(),
+                    format!("Response was not valid JSON: {}", reason),
+                ));
+            }
+        }
+    }
+
+    let output_tokens = reported_tokens.unwrap_or_else(|| estimate_output_tokens(&completion.text));
+    emit_chat_event(app_handle, "output_tokens", chat_id, output_tokens)
+        .map_err(|e| HeelixError::Internal(format!("Failed to emit output tokens: {}", e)))?;
+    debug!("{} response complete - output tokens: {}", provider.name(), output_tokens);
+    Ok(completion)
+}
+
+/// Send one request once the rate limiter allows it and stream the answer to
+/// the UI. Returns the answer and the output tokens the provider reported.
+async fn stream_answer(
+    app_handle: &AppHandle,
+    provider: &dyn ChatProvider,
+    messages: &[Message],
+    system: &SystemPrompt,
+    params: &CompletionParams,
+    chat_id: Option<i64>,
+) -> HeelixResult<(ChatCompletion, Option<u32>)> {
+    let input_tokens = estimate_prompt_tokens(
+        [system.instructions.as_str(), system.context.as_str()]
+            .into_iter()
+            .chain(messages.iter().map(|msg| msg.content.as_str())),
+    );
+    acquire(provider.id(), input_tokens).await;
+
+    let mut deltas = provider.stream_completion(messages, system, params).await?;
+    let mut response = ResponseStream::new(app_handle, chat_id);
+    let mut truncated = false;
+    let mut reported_tokens = None;
+    while let Some(delta) = deltas.next().await {
+        match delta? {
+            Delta::Text(text) => response.push(&text)?,
+            Delta::Truncated => truncated = true,
+            Delta::Usage {
+                input_tokens,
+                output_tokens,
+            } => {
+                info!(
+                    "{} usage - input tokens: {}, output tokens: {}",
+                    provider.name(),
+                    input_tokens,
+                    output_tokens
+                );
+                reported_tokens = Some(output_tokens);
+            }
+        }
+    }
+    let completion = ChatCompletion {
+        text: response.finish()?,
+        truncated,
+    };
+    Ok((completion, reported_tokens))
+}
+
+/// `history` with the selected documents appended to its first user message
+fn with_selected_documents(mut history: Vec<Message>, documents: &str) -> Vec<Message> {
+    if documents.is_empty() {
+        return history;
+    }
+    if let Some(first_user_msg) = history.iter_mut().find(|msg| msg.role == "user") {
+        first_user_msg.content = format!(
+            "{}\n\nContext from selected documents:\n{}",
+            first_user_msg.content, documents
+        );
+    }
+    history
+}
+
+/// Output tokens of `text` for providers that report none
+fn estimate_output_tokens(text: &str) -> u32 {
+    (text.split_whitespace().count() as f64 * 0.75) as u32
+}
+
+/// Deltas parsed from a provider's raw event stream. `parse` turns each event
+/// into any number of deltas; the stream fails with a "stream stalled" error
+/// when no event arrives for `STREAM_IDLE_TIMEOUT`, so keep-alive events that
+/// produce no delta still count as activity.
+pub fn delta_stream<'a, S, F>(events: S, provider: &'static str, parse: F) -> DeltaStream<'a>
+where
+    S: Stream + Unpin + Send + 'a,
+    S::Item: Send,
+    F: FnMut(S::Item) -> Vec<HeelixResult<Delta>> + Send + 'a,
+{
+    let pending: VecDeque<HeelixResult<Delta>> = VecDeque::new();
+    futures::stream::unfold(
+        (events, parse, pending, false),
+        move |(mut events, mut parse, mut pending, failed)| async move {
+            if failed {
+                return None;
+            }
+            loop {
+                if let Some(delta) = pending.pop_front() {
+                    // Nothing after an error is read
+                    let failed = delta.is_err();
+                    return Some((delta, (events, parse, pending, failed)));
+                }
+                match next_or_stall(&mut events, provider).await {
+                    Ok(Some(event)) => pending.extend(parse(event)),
+                    Ok(None) => return None,
+                    Err(e) => pending.push_back(Err(e)),
+                }
+            }
+        },
+    )
+    .boxed()
+}
+
+/// Deltas of an answer that arrived in one piece
+pub fn single_answer(completion: ChatCompletion) -> DeltaStream<'static> {
+    let mut deltas = vec![Ok(Delta::Text(completion.text))];
+    if completion.truncated {
+        deltas.push(Ok(Delta::Truncated));
+    }
+    futures::stream::iter(deltas).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_selected_documents_go_into_the_first_user_message() {
+        let history = vec![
+            message("assistant", "Hi, how can I help?"),
+            message("user", "Summarise the notes"),
+            message("user", "Shorter please"),
+        ];
+        let messages = with_selected_documents(history.clone(), "Meeting notes");
+        assert_eq!(messages[0].content, "Hi, how can I help?");
+        assert_eq!(messages[1].content, "Summarise the notes\n\nContext from selected documents:\nMeeting notes");
+        assert_eq!(messages[2].content, "Shorter please");
+
+        let unchanged = with_selected_documents(history, "");
+        assert_eq!(unchanged[1].content, "Summarise the notes");
+    }
+
+    #[test]
+    fn test_system_prompt_only_introduces_retrieved_chunks() {
+        let bare = SystemPrompt::new("Be brief.".to_string(), "");
+        assert!(bare.context.is_empty());
+        assert_eq!(bare.joined(), "Be brief.");
+
+        let with_chunks = SystemPrompt::new("Be brief.".to_string(), "[1] Budget: 40k");
+        assert_eq!(with_chunks.context, format!("{}\n\n[1] Budget: 40k", RETRIEVED_CONTEXT_INTRO));
+        assert_eq!(with_chunks.joined(), format!("Be brief.\n\n{}", with_chunks.context));
+    }
+
+    #[tokio::test]
+    async fn test_delta_stream_flattens_events_and_stops_after_an_error() {
+        let events = futures::stream::iter(vec![Ok("Hel"), Ok(""), Ok("lo"), Err("overloaded"), Ok("ignored")]);
+        let deltas: Vec<HeelixResult<Delta>> = delta_stream(events, "Test", |event: Result<&str, &str>| match event {
+            Ok("") => vec![],
+            Ok(text) => vec![Ok(Delta::Text(text.to_string()))],
+            Err(e) => vec![Err(HeelixError::provider("Test", e))],
+        })
+        .collect()
+        .await;
+
+        assert_eq!(deltas.len(), 3);
+        assert_eq!(deltas[0].as_ref().ok(), Some(&Delta::Text("Hel".to_string())));
+        assert_eq!(deltas[1].as_ref().ok(), Some(&Delta::Text("lo".to_string())));
+        assert!(deltas[2].is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashSet;
+
+use serde::Serialize;
+
+use crate::engine::chat_engine::Message;
+use crate::engine::model_capabilities::{capabilities_for, max_output_tokens_for};
+
+/// Emitted once per send with the selected documents that made it into the
+/// prompt and the ones left out to stay inside the model's context window
+pub const CONTEXT_DOCUMENTS_EVENT: &str = "context_documents";
+
+/// Context window assumed for models we know nothing about (mostly local
+/// models, which Ollama runs with a small window unless configured otherwise)
+pub const FALLBACK_CONTEXT_WINDOW: u32 = 8_192;
+/// Output room kept free when the engine leaves the response length to the provider
+const DEFAULT_OUTPUT_RESERVE: u32 = 4_096;
+/// Room for the system prompt and retrieved chunks
+const SYSTEM_PROMPT_RESERVE: usize = 2_000;
+
+/// Rough token count: about four characters per token for English prose
+pub fn estimate_tokens(text: &str) -> usize {
+    (text.chars().count() + 3) / 4
+}
+
+/// Tokens left for selected documents once the answer, the system prompt and
+/// the conversation so far are accounted for
+pub fn document_budget(model_id: &str, max_output_tokens: u32, history: &[Message]) -> usize {
+    let context_window = match capabilities_for(model_id).context_window {
+        0 => FALLBACK_CONTEXT_WINDOW,
+        window => window,
+    };
+    let output_reserve = max_output_tokens_for(model_id, max_output_tokens, Some(DEFAULT_OUTPUT_RESERVE))
+        .unwrap_or(DEFAULT_OUTPUT_RESERVE);
+    let history_tokens: usize = history.iter().map(|message| estimate_tokens(&message.content)).sum();
+    (context_window as usize)
+        .saturating_sub(output_reserve as usize)
+        .saturating_sub(SYSTEM_PROMPT_RESERVE)
+        .saturating_sub(history_tokens)
+}
+
+/// A document the user selected as chat context
+pub struct ContextDocument {
+    pub document_id: i64,
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct BudgetedDocument {
+    pub document_id: i64,
+    pub name: String,
+    pub tokens: usize,
+    /// Only the beginning of the document fit
+    pub truncated: bool,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct ContextSelection {
+    pub included: Vec<BudgetedDocument>,
+    pub dropped: Vec<BudgetedDocument>,
+    /// Included documents formatted for the first user message
+    #[serde(skip)]
+    pub text: String,
+}
+
+fn words(text: &str) -> HashSet<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// How many distinct words of `prompt` appear in `text`
+fn relevance(prompt_words: &HashSet<String>, text: &str) -> usize {
+    words(text).intersection(prompt_words).count()
+}
+
+/// Fit `documents` into `budget` tokens, most relevant to `prompt` first. A
+/// document that does not fit is dropped, except that the most relevant one is
+/// cut down to the budget rather than leaving the prompt without context.
+pub fn select_documents(documents: Vec<ContextDocument>, prompt: &str, budget: usize) -> ContextSelection {
+    let prompt_words = words(prompt);
+    let mut ranked: Vec<(usize, ContextDocument)> = documents
+        .into_iter()
+        .map(|document| (relevance(&prompt_words, &document.text), document))
+        .collect();
+    // Stable sort keeps the selection order between equally relevant documents
+    ranked.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let mut selection = ContextSelection::default();
+    let mut parts = Vec::new();
+    let mut remaining = budget;
+    for (_, document) in ranked {
+        let tokens = estimate_tokens(&document.text);
+        let (text, tokens, truncated) = if tokens <= remaining {
+            (document.text, tokens, false)
+        } else if selection.included.is_empty() && remaining > 0 {
+            let text: String = document.text.chars().take(remaining * 4).collect();
+            (text, remaining, true)
+        } else {
+            selection.dropped.push(BudgetedDocument {
+                document_id: document.document_id,
+                name: document.name,
+                tokens,
+                truncated: false,
+            });
+            continue;
+        };
+        remaining -= tokens;
+        parts.push(format!("--- Document: {} ---\n{}", document.name, text));
+        selection.included.push(BudgetedDocument {
+            document_id: document.document_id,
+            name: document.name,
+            tokens,
+            truncated,
+        });
+    }
+    selection.text = parts.join("\n\n");
+    selection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(document_id: i64, name: &str, text: &str) -> ContextDocument {
+        ContextDocument {
+            document_id,
+            name: name.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_drops_least_relevant_when_over_budget() {
+        let documents = vec![
+            document(1, "Recipes", &"flour sugar butter ".repeat(20)),
+            document(2, "Budget", &"quarterly revenue forecast ".repeat(20)),
+        ];
+        let selection = select_documents(documents, "What is the revenue forecast?", 150);
+        assert_eq!(selection.included.len(), 1);
+        assert_eq!(selection.included[0].document_id, 2);
+        assert_eq!(selection.dropped[0].document_id, 1);
+        assert!(selection.text.starts_with("--- Document: Budget ---"));
+    }
+
+    #[test]
+    fn test_truncates_a_single_oversized_document() {
+        let selection = select_documents(vec![document(7, "Long", &"a".repeat(4_000))], "", 100);
+        assert_eq!(selection.included[0].tokens, 100);
+        assert!(selection.included[0].truncated);
+        assert!(selection.dropped.is_empty());
+    }
+
+    #[test]
+    fn test_unknown_models_use_fallback_window() {
+        let budget = document_budget("llama3.3:70b", 0, &[]);
+        assert_eq!(budget, (FALLBACK_CONTEXT_WINDOW - DEFAULT_OUTPUT_RESERVE) as usize - SYSTEM_PROMPT_RESERVE);
+        assert!(document_budget("claude-sonnet-4-5", 0, &[]) > budget);
+    }
+}
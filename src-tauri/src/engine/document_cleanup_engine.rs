@@ -1,7 +1,10 @@
 use crate::configuration::state::ServiceAccess;
-use crate::repository::settings_repository::get_setting;
+use crate::engine::chat_dispatch::assistant_persona;
+use crate::engine::chat_engine_openai::openai_config;
+use crate::engine::rate_limiter::{acquire, estimate_prompt_tokens};
+use crate::error::{HeelixError, HeelixResult};
+use crate::repository::settings_repository::{get_local_model_url, get_setting_or_default};
 use async_openai::{
-    config::OpenAIConfig,
     types::{
         ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestUserMessageArgs,
         ChatCompletionRequestMessage, CreateChatCompletionRequestArgs,
@@ -13,7 +16,7 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-const CLEANUP_SYSTEM_PROMPT: &str = r#"You are a document cleanup assistant. Take the following raw text and produce a clean, well-formatted markdown document. Your job is to make the content presentable and professional:
+const CLEANUP_INSTRUCTIONS: &str = r#"Your task is to clean up a document. Take the following raw text and produce a clean, well-formatted markdown document. Your job is to make the content presentable and professional:
 
 - Fix grammar, spelling, and punctuation errors
 - Add proper structure with markdown headings (#, ##, ###) where appropriate
@@ -27,6 +30,11 @@ const CLEANUP_SYSTEM_PROMPT: &str = r#"You are a document cleanup assistant. Tak
 
 Return ONLY the cleaned markdown. No explanations, no preamble, no wrapping in code fences."#;
 
+/// The assistant persona followed by the cleanup instructions
+fn cleanup_system_prompt(app_handle: &tauri::AppHandle, provider_label: &str) -> HeelixResult<String> {
+    Ok(format!("{}\n\n{}", assistant_persona(app_handle, provider_label)?, CLEANUP_INSTRUCTIONS))
+}
+
 // Claude types
 #[derive(Serialize)]
 struct ClaudeRequest {
@@ -124,11 +132,11 @@ pub async fn clean_up_document_with_llm(
     plain_text: String,
     provider: String,
     model_id: Option<String>,
-) -> Result<String, String> {
+) -> HeelixResult<String> {
     info!("Cleaning up document with provider: {}, model: {:?}", provider, model_id);
 
     if plain_text.trim().is_empty() {
-        return Err("Document is empty, nothing to clean up.".to_string());
+        return Err(HeelixError::InvalidInput("Document is empty, nothing to clean up.".to_string()));
     }
 
     match provider.as_str() {
@@ -136,7 +144,7 @@ pub async fn clean_up_document_with_llm(
         "openai" => clean_up_with_openai(&app_handle, &plain_text, model_id).await,
         "gemini" => clean_up_with_gemini(&app_handle, &plain_text, model_id).await,
         "local" => clean_up_with_local(&app_handle, &plain_text, model_id).await,
-        _ => Err(format!("Unknown provider: {}", provider)),
+        _ => Err(HeelixError::InvalidInput(format!("Unknown provider: {}", provider))),
     }
 }
 
@@ -144,17 +152,17 @@ async fn clean_up_with_claude(
     app_handle: &tauri::AppHandle,
     plain_text: &str,
     model_id: Option<String>,
-) -> Result<String, String> {
-    let setting = app_handle.db(|db| get_setting(db, "api_key_claude").expect("Failed on api_key_claude"));
+) -> HeelixResult<String> {
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_claude", ""));
 
-    if setting.setting_value.is_empty() {
-        return Err("Claude API key is not configured. Please set it in Settings.".to_string());
+    if api_key.is_empty() {
+        return Err(HeelixError::MissingApiKey("Claude"));
     }
 
     let client = Client::builder()
         .timeout(Duration::from_secs(180))
         .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
+        .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
 
     let model_to_use = match model_id.as_deref() {
         Some("claude-haiku-4-5") => "claude-haiku-4-5",
@@ -169,35 +177,33 @@ async fn clean_up_with_claude(
             role: "user".to_string(),
             content: plain_text.to_string(),
         }],
-        system: CLEANUP_SYSTEM_PROMPT.to_string(),
+        system: cleanup_system_prompt(app_handle, "Anthropic LLM")?,
         stream: false,
     };
 
+    acquire("claude", estimate_prompt_tokens([request_body.system.as_str(), plain_text])).await;
     let response = client
         .post(ANTHROPIC_URL)
         .header("Content-Type", "application/json")
-        .header("x-api-key", &setting.setting_value)
+        .header("x-api-key", &api_key)
         .header("anthropic-version", "2023-06-01")
         .json(&request_body)
         .send()
-        .await
-        .map_err(|e| format!("Request to Claude API failed: {}", e))?;
+        .await?;
 
     if response.status().is_success() {
-        let response_body: ClaudeResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse Claude response: {}", e))?;
+        let response_body: ClaudeResponse = response.json().await.map_err(|e| {
+            HeelixError::provider("Claude", format!("Failed to parse Claude response: {}", e))
+        })?;
         let cleaned = response_body.content.first()
             .map(|c| c.text.trim().to_string())
             .unwrap_or_default();
         debug!("Claude cleanup complete, {} chars", cleaned.len());
         Ok(cleaned)
     } else {
-        let error_message = response.text().await
-            .map_err(|e| format!("Failed to read error: {}", e))?;
+        let error_message = response.text().await?;
         error!("Claude API error: {}", error_message);
-        Err(format!("Claude API error: {}", error_message))
+        Err(HeelixError::provider("Claude", error_message))
     }
 }
 
@@ -205,11 +211,11 @@ async fn clean_up_with_openai(
     app_handle: &tauri::AppHandle,
     plain_text: &str,
     model_id: Option<String>,
-) -> Result<String, String> {
-    let setting = app_handle.db(|db| get_setting(db, "api_key_open_ai").expect("Failed on api_key_open_ai"));
+) -> HeelixResult<String> {
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
 
-    if setting.setting_value.is_empty() {
-        return Err("OpenAI API key is not configured. Please set it in Settings.".to_string());
+    if api_key.is_empty() {
+        return Err(HeelixError::MissingApiKey("OpenAI"));
     }
 
     let model_to_use = match model_id.as_deref() {
@@ -217,9 +223,11 @@ async fn clean_up_with_openai(
         _ => "gpt-5",
     };
 
+    let system_prompt = cleanup_system_prompt(app_handle, "OpenAI LLM")?;
+    let input_tokens = estimate_prompt_tokens([system_prompt.as_str(), plain_text]);
     let messages: Vec<ChatCompletionRequestMessage> = vec![
         ChatCompletionRequestSystemMessageArgs::default()
-            .content(CLEANUP_SYSTEM_PROMPT)
+            .content(system_prompt)
             .build()
             .unwrap()
             .into(),
@@ -234,14 +242,15 @@ async fn clean_up_with_openai(
         .model(model_to_use)
         .messages(messages)
         .build()
-        .map_err(|e| format!("Failed to build request: {}", e))?;
+        .map_err(HeelixError::from_openai)?;
 
-    let client = OpenAIClient::with_config(OpenAIConfig::new().with_api_key(&setting.setting_value));
+    let client = OpenAIClient::with_config(openai_config(app_handle, &api_key));
+    acquire("openai", input_tokens).await;
     let response = client
         .chat()
         .create(request)
         .await
-        .map_err(|e| format!("OpenAI API request failed: {}", e))?;
+        .map_err(HeelixError::from_openai)?;
 
     let cleaned = response.choices.first()
         .and_then(|c| c.message.content.as_ref())
@@ -256,27 +265,25 @@ async fn clean_up_with_gemini(
     app_handle: &tauri::AppHandle,
     plain_text: &str,
     model_id: Option<String>,
-) -> Result<String, String> {
-    let setting = app_handle.db(|db| get_setting(db, "api_key_gemini").expect("Failed on api_key_gemini"));
+) -> HeelixResult<String> {
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_gemini", ""));
 
-    if setting.setting_value.is_empty() {
-        return Err("Gemini API key is not configured. Please set it in Settings.".to_string());
+    if api_key.is_empty() {
+        return Err(HeelixError::MissingApiKey("Gemini"));
     }
 
     let client = Client::builder()
         .timeout(Duration::from_secs(180))
         .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
+        .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
 
     let contents = vec![GeminiContent {
         role: "user".to_string(),
         parts: vec![GeminiPart {
-            text: format!("{}\n\n{}", CLEANUP_SYSTEM_PROMPT, plain_text),
+            text: format!("{}\n\n{}", cleanup_system_prompt(app_handle, "Google Gemini")?, plain_text),
         }],
     }];
 
-    let api_url = format!("{}?key={}", GEMINI_URL, setting.setting_value);
-
     let request_body = GeminiRequest {
         contents,
         generation_config: GeminiGenerationConfig {
@@ -284,19 +291,22 @@ async fn clean_up_with_gemini(
         },
     };
 
+    let input_tokens = estimate_prompt_tokens(request_body.contents.iter().flat_map(|content| {
+        content.parts.iter().map(|part| part.text.as_str())
+    }));
+    acquire("gemini", input_tokens).await;
     let response = client
-        .post(&api_url)
+        .post(GEMINI_URL)
         .header("Content-Type", "application/json")
+        .header("x-goog-api-key", &api_key)
         .json(&request_body)
         .send()
-        .await
-        .map_err(|e| format!("Request to Gemini API failed: {}", e))?;
+        .await?;
 
     if response.status().is_success() {
-        let response_body: GeminiResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse Gemini response: {}", e))?;
+        let response_body: GeminiResponse = response.json().await.map_err(|e| {
+            HeelixError::provider("Gemini", format!("Failed to parse Gemini response: {}", e))
+        })?;
 
         let cleaned = response_body.candidates.first()
             .and_then(|c| c.content.parts.first())
@@ -306,10 +316,9 @@ async fn clean_up_with_gemini(
         debug!("Gemini cleanup complete, {} chars", cleaned.len());
         Ok(cleaned)
     } else {
-        let error_message = response.text().await
-            .map_err(|e| format!("Failed to read error: {}", e))?;
+        let error_message = response.text().await?;
         error!("Gemini API error: {}", error_message);
-        Err(format!("Gemini API error: {}", error_message))
+        Err(HeelixError::provider("Gemini", error_message))
     }
 }
 
@@ -317,25 +326,20 @@ async fn clean_up_with_local(
     app_handle: &tauri::AppHandle,
     plain_text: &str,
     model_id: Option<String>,
-) -> Result<String, String> {
-    let setting = app_handle.db(|db| get_setting(db, "local_model_url").expect("Failed on local_model_url"));
-    let base_url = if setting.setting_value.is_empty() {
-        "http://localhost:11434".to_string()
-    } else {
-        setting.setting_value
-    };
+) -> HeelixResult<String> {
+    let base_url = app_handle.db(get_local_model_url)?;
 
     let client = Client::builder()
         .timeout(Duration::from_secs(300))
         .build()
-        .map_err(|e| format!("Failed to create client: {}", e))?;
+        .map_err(|e| HeelixError::Internal(format!("Failed to create client: {}", e)))?;
 
     let model_to_use = model_id.unwrap_or_else(|| "llama3.3:70b".to_string());
 
     let messages = vec![
         OllamaMessage {
             role: "system".to_string(),
-            content: CLEANUP_SYSTEM_PROMPT.to_string(),
+            content: cleanup_system_prompt(app_handle, "a local model via Ollama")?,
         },
         OllamaMessage {
             role: "user".to_string(),
@@ -357,21 +361,22 @@ async fn clean_up_with_local(
         .json(&request_body)
         .send()
         .await
-        .map_err(|e| format!("Request to Ollama failed: {}. Make sure Ollama is running.", e))?;
+        .map_err(|e| HeelixError::Network(format!("Request to Ollama failed: {}. Make sure Ollama is running.", e)))?;
 
     if response.status().is_success() {
-        let response_body: OllamaResponse = response
-            .json()
-            .await
-            .map_err(|e| format!("Failed to parse Ollama response: {}", e))?;
+        let response_body: OllamaResponse = response.json().await.map_err(|e| {
+            HeelixError::provider("Ollama", format!("Failed to parse Ollama response: {}", e))
+        })?;
 
         let cleaned = response_body.message.content.trim().to_string();
         debug!("Ollama cleanup complete, {} chars", cleaned.len());
         Ok(cleaned)
     } else {
-        let error_message = response.text().await
-            .map_err(|e| format!("Failed to read error: {}", e))?;
+        let error_message = response.text().await?;
         error!("Ollama error: {}", error_message);
-        Err(format!("Ollama error: {}. Make sure Ollama is running and the model is downloaded.", error_message))
+        Err(HeelixError::provider(
+            "Ollama",
+            format!("{}. Make sure Ollama is running and the model is downloaded.", error_message),
+        ))
     }
 }
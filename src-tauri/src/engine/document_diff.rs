@@ -0,0 +1,154 @@
+use std::ops::Range;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use similar::{ChangeTag, TextDiff};
+
+/// Past this the diff settles for a coarser but still correct result
+const DIFF_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffGranularity {
+    Word,
+    Line,
+}
+
+impl Default for DiffGranularity {
+    fn default() -> Self {
+        DiffGranularity::Word
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A run of text that is unchanged, added or removed. Ranges are JavaScript
+/// string indices (UTF-16 code units) into the original and cleaned text; a
+/// side the text does not appear on has no range.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffSpan {
+    pub kind: DiffKind,
+    pub text: String,
+    pub original: Option<Range<usize>>,
+    pub cleaned: Option<Range<usize>>,
+}
+
+/// Compare a document with its cleaned-up version, word by word or line by
+/// line. Adjacent tokens of the same kind are merged into one span, so
+/// removing several words in a row comes back as a single deletion.
+pub fn diff_documents(original: &str, cleaned: &str, granularity: DiffGranularity) -> Vec<DiffSpan> {
+    let mut config = TextDiff::configure();
+    config.timeout(DIFF_TIMEOUT);
+    let diff = match granularity {
+        DiffGranularity::Word => config.diff_words(original, cleaned),
+        DiffGranularity::Line => config.diff_lines(original, cleaned),
+    };
+
+    let mut spans: Vec<DiffSpan> = Vec::new();
+    let (mut original_at, mut cleaned_at) = (0, 0);
+    for change in diff.iter_all_changes() {
+        let text = change.value();
+        let len = text.encode_utf16().count();
+        let kind = match change.tag() {
+            ChangeTag::Equal => DiffKind::Equal,
+            ChangeTag::Insert => DiffKind::Insert,
+            ChangeTag::Delete => DiffKind::Delete,
+        };
+        let original_range = (kind != DiffKind::Insert).then(|| original_at..original_at + len);
+        let cleaned_range = (kind != DiffKind::Delete).then(|| cleaned_at..cleaned_at + len);
+        if let Some(range) = &original_range {
+            original_at = range.end;
+        }
+        if let Some(range) = &cleaned_range {
+            cleaned_at = range.end;
+        }
+
+        match spans.last_mut() {
+            Some(last) if last.kind == kind => {
+                last.text.push_str(text);
+                extend(&mut last.original, original_range);
+                extend(&mut last.cleaned, cleaned_range);
+            }
+            _ => spans.push(DiffSpan {
+                kind,
+                text: text.to_string(),
+                original: original_range,
+                cleaned: cleaned_range,
+            }),
+        }
+    }
+    spans
+}
+
+fn extend(range: &mut Option<Range<usize>>, next: Option<Range<usize>>) {
+    if let (Some(range), Some(next)) = (range.as_mut(), next) {
+        range.end = next.end;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_text(spans: &[DiffSpan]) -> Vec<(DiffKind, &str)> {
+        spans.iter().map(|span| (span.kind, span.text.as_str())).collect()
+    }
+
+    #[test]
+    fn test_word_diff_merges_runs_and_tracks_both_sides() {
+        let original = "the very quick brown fox";
+        let cleaned = "the slow fox";
+        let spans = diff_documents(original, cleaned, DiffGranularity::Word);
+        assert_eq!(
+            kinds_and_text(&spans),
+            vec![
+                (DiffKind::Equal, "the "),
+                (DiffKind::Delete, "very quick brown"),
+                (DiffKind::Insert, "slow"),
+                (DiffKind::Equal, " fox"),
+            ]
+        );
+        for span in &spans {
+            if let Some(range) = &span.original {
+                assert_eq!(&original[range.clone()], span.text);
+            }
+            if let Some(range) = &span.cleaned {
+                assert_eq!(&cleaned[range.clone()], span.text);
+            }
+        }
+        assert_eq!(spans[1].cleaned, None);
+        assert_eq!(spans[2].original, None);
+    }
+
+    #[test]
+    fn test_ranges_count_utf16_code_units() {
+        let spans = diff_documents("caf\u{e9} \u{1f600} ok", "caf\u{e9} \u{1f600} fine", DiffGranularity::Word);
+        let last = spans.last().unwrap();
+        assert_eq!(last.kind, DiffKind::Insert);
+        // "café " is 5 units and the emoji a surrogate pair
+        assert_eq!(last.cleaned, Some(8..12));
+    }
+
+    #[test]
+    fn test_line_diff_and_identical_text() {
+        let spans = diff_documents("one\ntwo\nthree\n", "one\n2\nthree\n", DiffGranularity::Line);
+        assert_eq!(
+            kinds_and_text(&spans),
+            vec![
+                (DiffKind::Equal, "one\n"),
+                (DiffKind::Delete, "two\n"),
+                (DiffKind::Insert, "2\n"),
+                (DiffKind::Equal, "three\n"),
+            ]
+        );
+        let same = diff_documents("unchanged text", "unchanged text", DiffGranularity::Word);
+        assert_eq!(kinds_and_text(&same), vec![(DiffKind::Equal, "unchanged text")]);
+        assert!(diff_documents("", "", DiffGranularity::Word).is_empty());
+    }
+}
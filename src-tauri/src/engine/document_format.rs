@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{self, Read};
+
+/// How many leading bytes of a file are read to tell its format
+const SNIFF_BYTES: usize = 8192;
+/// Parts every Word package has; other zip-based files such as XLSX, EPUB or
+/// plain archives lack at least one
+const DOCX_PARTS: [&str; 2] = ["[Content_Types].xml", "word/document.xml"];
+
+/// A file format `extract_document_text` knows how to read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentFormat {
+    Pdf,
+    Docx,
+    Email,
+    Text,
+}
+
+/// Whether a file starts like a zip archive
+fn is_zip(header: &[u8]) -> bool {
+    header.starts_with(b"PK\x03\x04")
+}
+
+/// Identify a document from its magic bytes rather than its file extension.
+/// None when the bytes do not settle it.
+fn sniff_format(header: &[u8]) -> Option<DocumentFormat> {
+    if header.starts_with(b"%PDF-") {
+        Some(DocumentFormat::Pdf)
+    } else if header.starts_with(b"{\\rtf") {
+        Some(DocumentFormat::Text)
+    } else {
+        None
+    }
+}
+
+fn format_for_extension(extension: &str) -> Option<DocumentFormat> {
+    match extension {
+        "pdf" => Some(DocumentFormat::Pdf),
+        "docx" => Some(DocumentFormat::Docx),
+        "eml" => Some(DocumentFormat::Email),
+        "txt" | "md" | "rtf" => Some(DocumentFormat::Text),
+        _ => None,
+    }
+}
+
+/// Whether `header` reads as text: valid UTF-8, allowing a character cut off
+/// at the end, with no NUL bytes
+fn looks_like_text(header: &[u8]) -> bool {
+    if header.is_empty() || header.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(header) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// The format of a document given its leading bytes and lowercased
+/// extension. Magic bytes win over the extension, so a PDF renamed `.txt` is
+/// still read as a PDF; the extension is used when they are inconclusive, and
+/// a file with an unknown or missing extension is read as text if it looks
+/// like text. A zip archive is DOCX when `is_docx_archive` says so and
+/// unsupported otherwise, whatever its extension.
+pub fn detect_document_format(
+    header: &[u8],
+    extension: &str,
+    is_docx_archive: impl FnOnce() -> bool,
+) -> Option<DocumentFormat> {
+    if is_zip(header) {
+        return if is_docx_archive() { Some(DocumentFormat::Docx) } else { None };
+    }
+    sniff_format(header)
+        .or_else(|| format_for_extension(extension))
+        .or_else(|| looks_like_text(header).then(|| DocumentFormat::Text))
+}
+
+/// Whether the zip archive at `file_path` holds the parts of a Word document
+pub fn is_docx_file(file_path: &str) -> bool {
+    match File::open(file_path).map_err(zip::result::ZipError::Io).and_then(zip::ZipArchive::new) {
+        Ok(mut archive) => DOCX_PARTS.iter().all(|part| archive.by_name(part).is_ok()),
+        Err(_) => false,
+    }
+}
+
+/// Read the leading bytes of `file_path` for `detect_document_format`
+pub fn read_header(file_path: &str) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(SNIFF_BYTES);
+    File::open(file_path)?.take(SNIFF_BYTES as u64).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Read at most `max_bytes` of a UTF-8 text file, and whether the file was
+/// longer. A character cut in half by the limit is dropped.
+pub fn read_text_prefix(file_path: &str, max_bytes: u64) -> io::Result<(String, bool)> {
+    let mut bytes = Vec::new();
+    File::open(file_path)?.take(max_bytes.saturating_add(1)).read_to_end(&mut bytes)?;
+    let truncated = bytes.len() as u64 > max_bytes;
+    bytes.truncate(max_bytes as usize);
+    match String::from_utf8(bytes) {
+        Ok(text) => Ok((text, truncated)),
+        Err(e) if truncated && e.utf8_error().error_len().is_none() => {
+            let valid = e.utf8_error().valid_up_to();
+            let mut bytes = e.into_bytes();
+            bytes.truncate(valid);
+            let text = String::from_utf8(bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            Ok((text, true))
+        }
+        Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magic_bytes_override_the_extension() {
+        let no_zip = || unreachable!();
+        assert_eq!(detect_document_format(b"%PDF-1.7\n%\xE2\xE3", "txt", no_zip), Some(DocumentFormat::Pdf));
+        assert_eq!(detect_document_format(b"PK\x03\x04\x14\0\x06\0", "md", || true), Some(DocumentFormat::Docx));
+        assert_eq!(detect_document_format(b"{\\rtf1\\ansi", "", no_zip), Some(DocumentFormat::Text));
+        assert_eq!(detect_document_format(b"%PDF-1.4", "", no_zip), Some(DocumentFormat::Pdf));
+    }
+
+    #[test]
+    fn test_only_zips_with_word_parts_are_docx() {
+        let dir = tempfile::tempdir().unwrap();
+        let write_zip = |name: &str, parts: &[&str]| {
+            let path = dir.path().join(name);
+            let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+            for part in parts {
+                zip.start_file(*part, zip::write::FileOptions::default()).unwrap();
+                std::io::Write::write_all(&mut zip, b"<xml/>").unwrap();
+            }
+            zip.finish().unwrap();
+            path.to_string_lossy().into_owned()
+        };
+        let docx = write_zip("report.docx", &["[Content_Types].xml", "word/document.xml"]);
+        let xlsx = write_zip("sheet.docx", &["[Content_Types].xml", "xl/workbook.xml"]);
+        let archive = write_zip("notes.zip", &["notes.txt"]);
+
+        assert!(is_docx_file(&docx));
+        assert!(!is_docx_file(&xlsx));
+        assert!(!is_docx_file(&archive));
+        assert!(!is_docx_file(dir.path().join("missing.docx").to_str().unwrap()));
+
+        let header = read_header(&xlsx).unwrap();
+        assert_eq!(detect_document_format(&header, "docx", || is_docx_file(&xlsx)), None);
+        let header = read_header(&docx).unwrap();
+        assert_eq!(detect_document_format(&header, "zip", || is_docx_file(&docx)), Some(DocumentFormat::Docx));
+    }
+
+    #[test]
+    fn test_read_text_prefix_truncates_on_a_character_boundary() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        std::fs::write(&path, "ab\u{e9}cd").unwrap();
+        let path = path.to_str().unwrap();
+
+        assert_eq!(read_text_prefix(path, 100).unwrap(), ("ab\u{e9}cd".to_string(), false));
+        assert_eq!(read_text_prefix(path, 6).unwrap(), ("ab\u{e9}cd".to_string(), false));
+        assert_eq!(read_text_prefix(path, 4).unwrap(), ("ab\u{e9}".to_string(), true));
+        // The limit falls inside the two bytes of é
+        assert_eq!(read_text_prefix(path, 3).unwrap(), ("ab".to_string(), true));
+    }
+
+    #[test]
+    fn test_inconclusive_bytes_fall_back_to_the_extension_then_to_text() {
+        let detect = |header: &[u8], extension: &str| detect_document_format(header, extension, || unreachable!());
+        assert_eq!(detect(b"# Notes", "md"), Some(DocumentFormat::Text));
+        assert_eq!(detect(b"From: a@example.com", "eml"), Some(DocumentFormat::Email));
+        assert_eq!(detect(b"\0\x01binary", "docx"), Some(DocumentFormat::Docx));
+        assert_eq!(detect("Caf\u{e9} notes".as_bytes(), ""), Some(DocumentFormat::Text));
+        // A multi-byte character cut off by the sniff window is still text
+        assert_eq!(detect(&"\u{e9}".as_bytes()[..1], "log"), Some(DocumentFormat::Text));
+        assert_eq!(detect(b"\x89PNG\r\n\x1a\n\0\0", "png"), None);
+        assert_eq!(detect(b"", ""), None);
+    }
+}
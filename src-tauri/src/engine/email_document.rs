@@ -0,0 +1,96 @@
+use mail_parser::{MessageParser, MimeHeaders, PartType};
+
+use heelix::html_to_markdown;
+
+/// The text of a saved email (`.eml`): the subject as a heading, the sender
+/// and date, the body and the names of its attachments. The plain-text body
+/// is preferred; an HTML-only message is converted to markdown.
+pub fn email_to_text(raw: &[u8]) -> Result<String, String> {
+    let message = MessageParser::default()
+        .parse(raw)
+        .ok_or_else(|| "Failed to parse the email".to_string())?;
+
+    let mut text = String::new();
+    let subject = message.subject().map(str::trim).filter(|subject| !subject.is_empty());
+    text.push_str(&format!("# {}\n\n", subject.unwrap_or("(no subject)")));
+
+    let sender = message.from().and_then(|from| from.first()).map(|sender| {
+        match (sender.name(), sender.address()) {
+            (Some(name), Some(address)) => format!("{} <{}>", name, address),
+            (Some(name), None) => name.to_string(),
+            (None, Some(address)) => address.to_string(),
+            (None, None) => String::new(),
+        }
+    });
+    if let Some(sender) = sender.filter(|sender| !sender.is_empty()) {
+        text.push_str(&format!("From: {}\n", sender));
+    }
+    if let Some(date) = message.date() {
+        text.push_str(&format!("Date: {}\n", date.to_rfc822()));
+    }
+
+    let body = match message.text_part(0).map(|part| &part.body) {
+        Some(PartType::Text(plain)) => plain.trim().to_string(),
+        Some(PartType::Html(html)) => html_to_markdown(html).trim().to_string(),
+        _ => String::new(),
+    };
+    if !body.is_empty() {
+        text.push_str(&format!("\n{}\n", body));
+    }
+
+    let attachments: Vec<&str> = message.attachments().filter_map(|part| part.attachment_name()).collect();
+    if !attachments.is_empty() {
+        text.push_str("\nAttachments:\n");
+        for name in attachments {
+            text.push_str(&format!("- {}\n", name));
+        }
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_email_with_attachment() {
+        let raw = concat!(
+            "From: Jane Doe <jane@example.com>\r\n",
+            "To: me@example.com\r\n",
+            "Subject: Quarterly report\r\n",
+            "Date: Tue, 1 Apr 2025 09:30:00 +0000\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: multipart/mixed; boundary=\"b1\"\r\n",
+            "\r\n",
+            "--b1\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "\r\n",
+            "Numbers are up.\r\n",
+            "--b1\r\n",
+            "Content-Type: application/pdf; name=\"report.pdf\"\r\n",
+            "Content-Disposition: attachment; filename=\"report.pdf\"\r\n",
+            "Content-Transfer-Encoding: base64\r\n",
+            "\r\n",
+            "JVBERi0xLjQK\r\n",
+            "--b1--\r\n",
+        );
+        let text = email_to_text(raw.as_bytes()).unwrap();
+        assert!(text.starts_with("# Quarterly report\n\nFrom: Jane Doe <jane@example.com>\nDate: "));
+        assert!(text.contains("\nNumbers are up.\n"));
+        assert!(text.ends_with("\nAttachments:\n- report.pdf\n"));
+    }
+
+    #[test]
+    fn test_html_only_email_becomes_markdown() {
+        let raw = concat!(
+            "From: news@example.com\r\n",
+            "Content-Type: text/html; charset=utf-8\r\n",
+            "\r\n",
+            "<h2>Update</h2><p>Read <strong>this</strong>.</p>\r\n",
+        );
+        let text = email_to_text(raw.as_bytes()).unwrap();
+        assert!(text.starts_with("# (no subject)\n\nFrom: news@example.com\n"));
+        assert!(text.contains("**this**"));
+        assert!(!text.contains("<p>"));
+    }
+}
@@ -0,0 +1,108 @@
+//! Images attached to a chat prompt, read from disk and base64-encoded for
+//! the Claude and OpenAI vision message formats.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+use crate::engine::chat_dispatch::PromptOptions;
+use crate::engine::model_capabilities::capabilities_for;
+use crate::error::{HeelixError, HeelixResult};
+
+/// Claude rejects images over 5 MB; OpenAI allows more, so use the lower limit for both
+pub const MAX_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+
+pub struct ImageAttachment {
+    pub media_type: &'static str,
+    /// Base64 of the file contents, without a data URL prefix
+    pub data: String,
+}
+
+impl ImageAttachment {
+    /// `data:` URL form used by OpenAI's `image_url` content part
+    pub fn data_url(&self) -> String {
+        format!("data:{};base64,{}", self.media_type, self.data)
+    }
+}
+
+/// Load the images requested in `options` for `model_id`, failing if there
+/// are images and the model cannot take them
+pub fn images_for_model(
+    options: Option<&PromptOptions>,
+    model_id: &str,
+) -> HeelixResult<Vec<ImageAttachment>> {
+    let paths = options.map(|o| o.image_paths.as_slice()).unwrap_or_default();
+    if paths.is_empty() {
+        return Ok(vec![]);
+    }
+    if !capabilities_for(model_id).vision {
+        return Err(HeelixError::InvalidInput(format!(
+            "{} does not accept image attachments",
+            model_id
+        )));
+    }
+    load_image_attachments(paths)
+}
+
+/// Read and encode `paths`, rejecting files that are too large or are not
+/// PNG, JPEG, GIF or WebP images
+fn load_image_attachments(paths: &[String]) -> HeelixResult<Vec<ImageAttachment>> {
+    paths.iter().map(|path| load_image_attachment(path)).collect()
+}
+
+fn load_image_attachment(path: &str) -> HeelixResult<ImageAttachment> {
+    let size = std::fs::metadata(path)
+        .map_err(|e| HeelixError::InvalidInput(format!("Cannot read image {}: {}", path, e)))?
+        .len();
+    if size > MAX_IMAGE_BYTES {
+        return Err(HeelixError::InvalidInput(format!(
+            "Image {} is {:.1} MB, the limit is {} MB",
+            path,
+            size as f64 / (1024.0 * 1024.0),
+            MAX_IMAGE_BYTES / (1024 * 1024)
+        )));
+    }
+
+    let bytes = std::fs::read(path)
+        .map_err(|e| HeelixError::InvalidInput(format!("Cannot read image {}: {}", path, e)))?;
+    let media_type = detect_media_type(&bytes).ok_or_else(|| {
+        HeelixError::InvalidInput(format!(
+            "{} is not a supported image (PNG, JPEG, GIF or WebP)",
+            path
+        ))
+    })?;
+
+    Ok(ImageAttachment {
+        media_type,
+        data: STANDARD.encode(&bytes),
+    })
+}
+
+/// Identify the image format from its magic bytes rather than the file extension
+fn detect_media_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_media_type() {
+        assert_eq!(detect_media_type(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(detect_media_type(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(detect_media_type(b"GIF89a..."), Some("image/gif"));
+        assert_eq!(detect_media_type(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(detect_media_type(b"%PDF-1.7"), None);
+        assert_eq!(detect_media_type(b""), None);
+    }
+}
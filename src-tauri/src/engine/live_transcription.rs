@@ -0,0 +1,104 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::engine::audio_engine::{end_live_samples, take_live_samples, IS_RECORDING};
+use crate::engine::transcription_engine::{transcribe_samples, TranscriptionEndpoint};
+
+/// Emitted with each window of a live recording once Whisper has transcribed it
+pub const TRANSCRIPTION_PARTIAL_EVENT: &str = "transcription_partial";
+/// Seconds of audio per Whisper request. Whisper transcribes whole uploads
+/// rather than streams, so a partial arrives this long after the speech it
+/// starts with, plus the request time. Shorter windows lower that latency but
+/// cost more requests and cut more words in half at window edges.
+pub const LIVE_WINDOW_SECONDS: u32 = 8;
+/// A tail shorter than this at the end of a recording is not worth a request
+const MIN_TAIL_MILLIS: u32 = 500;
+/// How often the buffer is checked for a full window
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Time for the recording thread to write its last samples after a stop
+const STOP_GRACE: Duration = Duration::from_millis(600);
+
+#[derive(Serialize, Clone, Debug)]
+pub struct TranscriptionPartial {
+    pub file_path: String,
+    /// Text of this window
+    pub text: String,
+    /// Text of every window so far
+    pub transcript: String,
+    /// Position of this window in the recording, in seconds
+    pub start: f64,
+    pub end: f64,
+    /// Set on the last partial, sent once the recording has stopped
+    pub is_final: bool,
+}
+
+/// Transcribe a live recording window by window until it stops, emitting
+/// `transcription_partial` after each. A failed window is logged and skipped
+/// so the dictation keeps going; the WAV file still has the whole recording.
+pub async fn run_live_transcription(app_handle: AppHandle, file_path: String, endpoint: TranscriptionEndpoint) {
+    let mut transcript = String::new();
+    let mut covered = 0.0;
+    let mut stopped = false;
+    loop {
+        if !stopped && !IS_RECORDING.load(Ordering::SeqCst) {
+            stopped = true;
+            tokio::time::sleep(STOP_GRACE).await;
+        }
+        // Full windows while recording, then whatever is left once it stops
+        let min_millis = if stopped { MIN_TAIL_MILLIS } else { LIVE_WINDOW_SECONDS * 1000 };
+        let window = take_live_samples(min_millis, LIVE_WINDOW_SECONDS * 1000);
+        let (sample_rate, samples) = match window {
+            Some(window) => window,
+            None if stopped => break,
+            None => {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
+            }
+        };
+
+        let start = covered;
+        covered += samples.len() as f64 / f64::from(sample_rate);
+        let text = match transcribe_samples(&samples, sample_rate, &endpoint).await {
+            Ok(text) => text,
+            Err(e) => {
+                warn!("Live transcription of {:.0}s-{:.0}s failed: {}", start, covered, e);
+                String::new()
+            }
+        };
+        if !text.is_empty() {
+            if !transcript.is_empty() {
+                transcript.push(' ');
+            }
+            transcript.push_str(&text);
+        }
+        emit_partial(&app_handle, TranscriptionPartial {
+            file_path: file_path.clone(),
+            text,
+            transcript: transcript.clone(),
+            start,
+            end: covered,
+            is_final: false,
+        });
+    }
+
+    end_live_samples();
+    info!("Live transcription of {} finished after {:.0}s", file_path, covered);
+    emit_partial(&app_handle, TranscriptionPartial {
+        file_path,
+        text: String::new(),
+        transcript,
+        start: covered,
+        end: covered,
+        is_final: true,
+    });
+}
+
+fn emit_partial(app_handle: &AppHandle, partial: TranscriptionPartial) {
+    if let Err(e) = app_handle.emit_all(TRANSCRIPTION_PARTIAL_EVENT, partial) {
+        warn!("Failed to emit live transcription: {}", e);
+    }
+}
@@ -8,3 +8,18 @@ pub mod audio_engine;
 pub mod transcription_engine;
 pub mod project_vector_engine;
 pub mod document_cleanup_engine;
+pub mod document_diff;
+pub mod model_capabilities;
+pub mod chat_dispatch;
+pub mod chat_provider;
+pub mod rag_engine;
+pub mod image_attachment;
+pub mod context_budget;
+pub mod redaction;
+pub mod ocr_engine;
+pub mod document_format;
+pub mod email_document;
+pub mod provider_status;
+pub mod source_attribution;
+pub mod live_transcription;
+pub mod rate_limiter;
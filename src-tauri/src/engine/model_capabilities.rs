@@ -0,0 +1,135 @@
+/// Optional features a model accepts. Request parameters for features a model
+/// lacks are dropped rather than sent and rejected by the provider.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ModelCapabilities {
+    /// OpenAI `reasoning_effort` / Claude extended thinking
+    pub reasoning: bool,
+    /// Image content blocks in user messages
+    pub vision: bool,
+    /// Largest output the provider accepts for the model, 0 when unknown
+    pub max_output_tokens: u32,
+    /// Input plus output tokens the model can attend to, 0 when unknown
+    pub context_window: u32,
+}
+
+const fn caps(reasoning: bool, vision: bool, max_output_tokens: u32, context_window: u32) -> ModelCapabilities {
+    ModelCapabilities { reasoning, vision, max_output_tokens, context_window }
+}
+
+/// Model id prefixes and their capabilities. The first matching prefix wins,
+/// unknown models get `ModelCapabilities::default()`.
+const MODEL_CAPABILITIES: &[(&str, ModelCapabilities)] = &[
+    ("claude-sonnet-4", caps(true, true, 64_000, 200_000)),
+    ("claude-haiku-4", caps(true, true, 64_000, 200_000)),
+    ("claude-opus-4", caps(true, true, 32_000, 200_000)),
+    ("claude-3-7-sonnet", caps(true, true, 64_000, 200_000)),
+    ("claude-3-5-sonnet", caps(false, true, 8_192, 200_000)),
+    ("gpt-5", caps(true, true, 128_000, 400_000)),
+    ("gpt-4o", caps(false, true, 16_384, 128_000)),
+    ("gpt-4.1", caps(false, true, 32_768, 1_047_576)),
+    ("o1", caps(true, true, 100_000, 200_000)),
+    ("o3", caps(true, true, 100_000, 200_000)),
+    ("o4", caps(true, true, 100_000, 200_000)),
+    ("gemini-3-pro", caps(false, true, 65_536, 1_048_576)),
+    ("gemini-2.0-flash", caps(false, true, 8_192, 1_048_576)),
+];
+
+pub fn capabilities_for(model_id: &str) -> ModelCapabilities {
+    MODEL_CAPABILITIES
+        .iter()
+        .find(|(prefix, _)| model_id.starts_with(prefix))
+        .map(|(_, capabilities)| *capabilities)
+        .unwrap_or_default()
+}
+
+/// Output token cap for a request: `configured` (the `max_output_tokens`
+/// setting) when non-zero, otherwise `engine_default`, clamped to the model's
+/// maximum when known. `None` leaves the provider's own default in place.
+pub fn max_output_tokens_for(
+    model_id: &str,
+    configured: u32,
+    engine_default: Option<u32>,
+) -> Option<u32> {
+    let requested = if configured > 0 { Some(configured) } else { engine_default }?;
+    match capabilities_for(model_id).max_output_tokens {
+        0 => Some(requested),
+        model_max => Some(requested.min(model_max)),
+    }
+}
+
+/// Sampling parameters for one request; `None` leaves the provider default
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sampling {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+}
+
+/// Requested `temperature` and `top_p` clamped to what `provider` accepts:
+/// temperature 0-1 for Claude and 0-2 elsewhere, top_p 0-1. OpenAI reasoning
+/// models reject both, so they get neither. Newer Claude models reject the two
+/// together, so Claude keeps only the temperature when both are set.
+pub fn sampling_for(provider: &str, model_id: &str, temperature: Option<f32>, top_p: Option<f32>) -> Sampling {
+    if provider == "openai" && capabilities_for(model_id).reasoning {
+        return Sampling::default();
+    }
+    let max_temperature = if provider == "claude" { 1.0 } else { 2.0 };
+    let temperature = temperature
+        .filter(|value| value.is_finite())
+        .map(|value| value.clamp(0.0, max_temperature));
+    let top_p = top_p
+        .filter(|value| value.is_finite())
+        .map(|value| value.clamp(0.0, 1.0))
+        .filter(|_| provider != "claude" || temperature.is_none());
+    Sampling { temperature, top_p }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reasoning_models() {
+        assert!(capabilities_for("claude-sonnet-4-5").reasoning);
+        assert!(capabilities_for("o3-mini").reasoning);
+        assert!(!capabilities_for("claude-3-5-sonnet-20241022").reasoning);
+        assert!(!capabilities_for("llama3.3:70b").reasoning);
+    }
+
+    #[test]
+    fn test_vision_models() {
+        assert!(capabilities_for("claude-3-5-sonnet-20241022").vision);
+        assert!(capabilities_for("gpt-4o-mini").vision);
+        assert!(!capabilities_for("llama3.3:70b").vision);
+    }
+
+    #[test]
+    fn test_max_output_tokens() {
+        assert_eq!(max_output_tokens_for("claude-sonnet-4-5", 0, Some(4096)), Some(4096));
+        assert_eq!(max_output_tokens_for("claude-sonnet-4-5", 20_000, Some(4096)), Some(20_000));
+        assert_eq!(max_output_tokens_for("claude-3-5-sonnet-20241022", 20_000, Some(4096)), Some(8_192));
+        assert_eq!(max_output_tokens_for("gpt-5", 0, None), None);
+        assert_eq!(max_output_tokens_for("llama3.3:70b", 50_000, None), Some(50_000));
+    }
+
+    #[test]
+    fn test_sampling_is_clamped_per_provider() {
+        let claude = sampling_for("claude", "claude-sonnet-4-5", Some(1.7), Some(0.9));
+        assert_eq!(claude, Sampling { temperature: Some(1.0), top_p: None });
+        let gemini = sampling_for("gemini", "gemini-2.0-flash", Some(1.7), Some(1.5));
+        assert_eq!(gemini, Sampling { temperature: Some(1.7), top_p: Some(1.0) });
+        assert_eq!(sampling_for("local", "llama3.3:70b", Some(-1.0), None).temperature, Some(0.0));
+        assert_eq!(sampling_for("local", "llama3.3:70b", None, None), Sampling::default());
+    }
+
+    #[test]
+    fn test_reasoning_openai_models_skip_sampling() {
+        assert_eq!(sampling_for("openai", "o3-mini", Some(0.2), Some(0.5)), Sampling::default());
+        assert_eq!(sampling_for("openai", "gpt-4o", Some(0.2), None).temperature, Some(0.2));
+    }
+
+    #[test]
+    fn test_context_window() {
+        assert_eq!(capabilities_for("claude-sonnet-4-5").context_window, 200_000);
+        assert_eq!(capabilities_for("llama3.3:70b").context_window, 0);
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::ErrorKind;
+use std::process::Command;
+
+use anyhow::{anyhow, bail, Result};
+use log::{info, warn};
+
+/// Below this many non-whitespace characters per page a PDF is treated as
+/// scanned: text extraction only found page numbers, headers or nothing
+pub const MIN_CHARS_PER_PAGE: usize = 25;
+/// Rendering resolution for OCR; tesseract is most accurate around 300 DPI
+const OCR_DPI: &str = "300";
+
+/// Whether extracted PDF text is too sparse for its page count to be the
+/// document's real content
+pub fn needs_ocr(text: &str, total_pages: u32) -> bool {
+    let characters = text.chars().filter(|c| !c.is_whitespace()).count();
+    characters < MIN_CHARS_PER_PAGE * total_pages.max(1) as usize
+}
+
+/// The command-line tools OCR runs, with the flag each prints its version with
+const OCR_TOOLS: [(&str, &str); 2] = [("pdftoppm", "-v"), ("tesseract", "--version")];
+
+/// The OCR tools that cannot be started from PATH
+pub fn missing_ocr_tools() -> Vec<&'static str> {
+    OCR_TOOLS
+        .iter()
+        .filter(|(program, version_flag)| {
+            matches!(
+                Command::new(program).arg(version_flag).output(),
+                Err(e) if e.kind() == ErrorKind::NotFound
+            )
+        })
+        .map(|(program, _)| *program)
+        .collect()
+}
+
+/// How to install the OCR tools on this platform
+pub fn ocr_install_hint() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "Install them with `brew install tesseract poppler`"
+    } else if cfg!(target_os = "windows") {
+        "Install Tesseract (github.com/UB-Mannheim/tesseract/wiki) and Poppler for Windows, then add both to PATH"
+    } else {
+        "Install them with your package manager, e.g. `apt install tesseract-ocr poppler-utils`"
+    }
+}
+
+/// Run an external tool, turning a missing binary into an install hint
+fn run_tool(program: &str, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new(program).args(args).output().map_err(|e| {
+        if e.kind() == ErrorKind::NotFound {
+            anyhow!(
+                "OCR needs the `{}` command. Install tesseract and poppler (pdftoppm) and try again.",
+                program
+            )
+        } else {
+            anyhow!("Failed to run {}: {}", program, e)
+        }
+    })?;
+    if !output.status.success() {
+        bail!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(output.stdout)
+}
+
+/// OCR every page of a PDF by rendering it with `pdftoppm` and reading it
+/// with `tesseract`. `on_page` is called after each page with the page number;
+/// `is_cancelled` is checked before each page. Returns the text and whether
+/// it stopped early.
+pub fn ocr_pdf(
+    file_path: &str,
+    total_pages: u32,
+    mut on_page: impl FnMut(u32),
+    is_cancelled: impl Fn() -> bool,
+) -> Result<(String, bool)> {
+    let work_dir = tempfile::tempdir()?;
+    let mut text = String::new();
+    for page in 1..=total_pages {
+        if is_cancelled() {
+            info!("OCR cancelled after {} of {} pages", page - 1, total_pages);
+            return Ok((text, true));
+        }
+        let page_number = page.to_string();
+        let prefix = work_dir.path().join(format!("page-{}", page));
+        let prefix = prefix.to_string_lossy();
+        run_tool(
+            "pdftoppm",
+            &["-r", OCR_DPI, "-gray", "-png", "-singlefile", "-f", &page_number, "-l", &page_number, file_path, &prefix],
+        )?;
+        let image_path = format!("{}.png", prefix);
+        match run_tool("tesseract", &[&image_path, "stdout"]) {
+            Ok(page_text) => {
+                text.push_str(String::from_utf8_lossy(&page_text).trim_end());
+                text.push_str("\n\n");
+            }
+            // One unreadable page should not cost the rest of the document
+            Err(e) if page > 1 => warn!("OCR failed on page {}: {}", page, e),
+            Err(e) => return Err(e),
+        }
+        let _ = std::fs::remove_file(&image_path);
+        on_page(page);
+    }
+    info!("OCR extracted {} characters from {} pages", text.len(), total_pages);
+    Ok((text, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_needs_ocr_when_text_is_sparse_for_page_count() {
+        assert!(needs_ocr("", 3));
+        assert!(needs_ocr("1\n\n2\n\n3", 3));
+        assert!(!needs_ocr(&"A page of real text. ".repeat(10), 3));
+        assert!(needs_ocr(&"A page of real text. ".repeat(10), 40));
+    }
+}
@@ -1,86 +1,340 @@
 //! Per-project vector index management
 //! 
 //! Each project gets its own HNSW index stored at:
-//! `{app_data}/vectors/project_{id}/chunks.hnsw.*`
+//! `{data_directory}/vectors/project_{id}/chunks.hnsw.*`
 //! 
-//! This ensures search results are always scoped to the project.
+//! This ensures search results are always scoped to the project; a search
+//! over several projects queries each index and merges the hits. An
+//! `index.json` next to it records the embedding model and vector dimension,
+//! so an index is never searched with vectors from a different model.
 
-use std::collections::HashMap;
-use std::fs::create_dir_all;
-use std::path::PathBuf;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::fs::{self, create_dir_all};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::Result;
-use log::info;
+use anyhow::{anyhow, Result};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
 use tauri::AppHandle;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 
-use crate::engine::similarity_search_engine::SimilaritySearch;
+use crate::configuration::data_directory::data_directory;
+use crate::configuration::state::ServiceAccess;
+use crate::engine::chat_engine_openai::openai_account_config;
+use crate::engine::similarity_search_engine::{BatchOutcome, SimilaritySearch};
+use crate::configuration::settings::DEFAULT_EMBEDDING_MODEL;
+use crate::repository::settings_repository::{get_embedding_model, get_vector_cache_size};
 
-/// Cache of open project vector indices
+/// Open project indices kept in memory when the setting is unset
+pub const DEFAULT_VECTOR_CACHE_SIZE: usize = 5;
+/// Per-project record of how the index's vectors were made
+const INDEX_METADATA_FILE: &str = "index.json";
+/// Dimension of the default model, which built every index from before
+/// `index.json` was written
+const LEGACY_EMBEDDING_DIMENSION: usize = 1536;
+
+/// Cache of open project vector indices, bounded by the `vector_cache_size` setting
 /// Key: project_id, Value: SimilaritySearch instance
-type ProjectVectorCache = Arc<Mutex<HashMap<i64, Arc<Mutex<SimilaritySearch>>>>>;
+type ProjectVectorCache = Arc<Mutex<LruCache<Arc<Mutex<SimilaritySearch>>>>>;
+
+/// Least-recently-used map from project id to an open index
+struct LruCache<V> {
+    entries: HashMap<i64, V>,
+    /// Project ids from least to most recently used
+    recency: VecDeque<i64>,
+}
+
+impl<V: Clone> LruCache<V> {
+    fn new() -> Self {
+        LruCache {
+            entries: HashMap::new(),
+            recency: VecDeque::new(),
+        }
+    }
+
+    fn get(&mut self, project_id: i64) -> Option<V> {
+        let value = self.entries.get(&project_id)?.clone();
+        self.touch(project_id);
+        Some(value)
+    }
+
+    /// Insert as most recently used and return the entries pushed out by `capacity`
+    fn insert(&mut self, project_id: i64, value: V, capacity: usize) -> Vec<(i64, V)> {
+        self.entries.insert(project_id, value);
+        self.touch(project_id);
+
+        let mut evicted = Vec::new();
+        while self.entries.len() > capacity.max(1) {
+            let oldest = match self.recency.pop_front() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            if let Some(value) = self.entries.remove(&oldest) {
+                evicted.push((oldest, value));
+            }
+        }
+        evicted
+    }
+
+    fn remove(&mut self, project_id: i64) -> Option<V> {
+        self.recency.retain(|id| *id != project_id);
+        self.entries.remove(&project_id)
+    }
+
+    fn touch(&mut self, project_id: i64) {
+        self.recency.retain(|id| *id != project_id);
+        self.recency.push_back(project_id);
+    }
+}
+
+/// One lock per project that serializes whole vectorization runs against that
+/// project's index. Different projects get different locks and run in parallel.
+type ProjectLocks = Arc<Mutex<HashMap<i64, Arc<Mutex<()>>>>>;
 
 lazy_static::lazy_static! {
-    static ref PROJECT_VECTORS: ProjectVectorCache = Arc::new(Mutex::new(HashMap::new()));
+    static ref PROJECT_VECTORS: ProjectVectorCache = Arc::new(Mutex::new(LruCache::new()));
+    static ref PROJECT_VECTOR_LOCKS: ProjectLocks = Arc::new(Mutex::new(HashMap::new()));
+    /// Projects whose running vectorization should stop after the current batch
+    static ref CANCELLED_VECTORIZATIONS: std::sync::Mutex<HashSet<i64>> = std::sync::Mutex::new(HashSet::new());
+}
+
+/// Ask the vectorization running in `project_id` to stop after its current batch
+pub fn cancel_project_vectorization(project_id: i64) {
+    CANCELLED_VECTORIZATIONS.lock().unwrap().insert(project_id);
+}
+
+/// Forget a cancel request once the run it stopped, or outlived, has ended
+pub fn clear_vectorization_cancel(project_id: i64) {
+    CANCELLED_VECTORIZATIONS.lock().unwrap().remove(&project_id);
+}
+
+/// Whether `cancel_project_vectorization` was called since the last run ended
+pub fn is_vectorization_cancelled(project_id: i64) -> bool {
+    CANCELLED_VECTORIZATIONS.lock().unwrap().contains(&project_id)
+}
+
+/// Wait for exclusive write access to a project's vector index.
+/// Hold the guard across a full read-unvectorized / add / mark / sync sequence
+/// so two saves in the same project cannot interleave.
+pub async fn lock_project_vectors(project_id: i64) -> OwnedMutexGuard<()> {
+    let lock = {
+        let mut locks = PROJECT_VECTOR_LOCKS.lock().await;
+        locks.entry(project_id).or_default().clone()
+    };
+    lock.lock_owned().await
 }
 
 /// Get the directory path for a project's vector index
-fn get_project_vector_path(app_handle: &AppHandle, project_id: i64) -> PathBuf {
-    let app_dir = app_handle
-        .path_resolver()
-        .app_data_dir()
-        .expect("The app data directory should exist.");
+fn get_project_vector_path(app_handle: &AppHandle, project_id: i64) -> Result<PathBuf> {
+    let data_dir = data_directory(app_handle)
+        .ok_or_else(|| anyhow!("The app data directory could not be resolved"))?;
     
-    app_dir.join("vectors").join(format!("project_{}", project_id))
+    Ok(data_dir.join("vectors").join(format!("project_{}", project_id)))
+}
+
+/// Embedding model and vector dimension a project index was built with
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexMetadata {
+    pub embedding_model: String,
+    /// None until the first vectors are added
+    pub dimension: Option<usize>,
+}
+
+/// A project index was built with a different embedding model than the one
+/// now configured, so its vectors cannot be compared with new query vectors
+#[derive(Debug)]
+pub struct EmbeddingModelMismatch {
+    pub project_id: i64,
+    pub indexed_model: String,
+    pub configured_model: String,
 }
 
-/// Get or create a vector index for a specific project
+impl fmt::Display for EmbeddingModelMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Project {} was indexed with {} but the embedding model is now {}. Rebuild the project index to search it.",
+            self.project_id, self.indexed_model, self.configured_model
+        )
+    }
+}
+
+impl std::error::Error for EmbeddingModelMismatch {}
+
+/// Metadata recorded for the index in `vector_path`. An index with vectors but
+/// no record predates it and was built with the default model.
+fn read_index_metadata(vector_path: &Path) -> Option<IndexMetadata> {
+    match fs::read_to_string(vector_path.join(INDEX_METADATA_FILE)) {
+        Ok(json) => match serde_json::from_str(&json) {
+            Ok(metadata) => Some(metadata),
+            Err(e) => {
+                warn!("Ignoring unreadable {}: {}", vector_path.join(INDEX_METADATA_FILE).display(), e);
+                None
+            }
+        },
+        Err(_) if vector_path.join("chunks.hnsw.data").exists() => Some(IndexMetadata {
+            embedding_model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            dimension: Some(LEGACY_EMBEDDING_DIMENSION),
+        }),
+        Err(_) => None,
+    }
+}
+
+fn write_index_metadata(vector_path: &Path, metadata: &IndexMetadata) -> Result<()> {
+    fs::write(vector_path.join(INDEX_METADATA_FILE), serde_json::to_string(metadata)?)?;
+    Ok(())
+}
+
+/// Record `embedding_model` for a new index, or fail when an existing one was
+/// built with another model
+fn check_index_metadata(project_id: i64, vector_path: &Path, embedding_model: &str) -> Result<()> {
+    match read_index_metadata(vector_path) {
+        Some(metadata) if metadata.embedding_model != embedding_model => Err(EmbeddingModelMismatch {
+            project_id,
+            indexed_model: metadata.embedding_model,
+            configured_model: embedding_model.to_string(),
+        }
+        .into()),
+        Some(_) => Ok(()),
+        None => write_index_metadata(
+            vector_path,
+            &IndexMetadata {
+                embedding_model: embedding_model.to_string(),
+                dimension: None,
+            },
+        ),
+    }
+}
+
+/// Get or create a vector index for a specific project. Opening one more index
+/// than `vector_cache_size` allows syncs and drops the least recently used one.
 pub async fn get_project_vector_db(
     app_handle: &AppHandle,
     project_id: i64,
 ) -> Result<Arc<Mutex<SimilaritySearch>>> {
+    let capacity = app_handle
+        .db(get_vector_cache_size)
+        .unwrap_or(DEFAULT_VECTOR_CACHE_SIZE);
+    let embedding_model = app_handle.db(get_embedding_model)?;
+    let vector_path = get_project_vector_path(app_handle, project_id)?;
+
+    // Checked on every access, since the setting can change while the index is
+    // cached. Done under the cache lock so two first accesses cannot both find
+    // no metadata and the later write drop a dimension the earlier one recorded.
     let mut cache = PROJECT_VECTORS.lock().await;
-    
+    create_dir_all(&vector_path)?;
+    check_index_metadata(project_id, &vector_path, &embedding_model)?;
+
     // Check if already cached
-    if let Some(db) = cache.get(&project_id) {
+    if let Some(db) = cache.get(project_id) {
         info!("Using cached vector index for project {}", project_id);
-        return Ok(db.clone());
+        return Ok(db);
     }
     
     // Create new index
     info!("Initializing vector index for project {}", project_id);
-    let vector_path = get_project_vector_path(app_handle, project_id);
-    
-    // Ensure directory exists
-    create_dir_all(&vector_path)?;
-    
+
     let collection_name = "chunks";
-    let db = SimilaritySearch::open(vector_path.to_str().unwrap(), collection_name)?;
+    let vector_dir = vector_path
+        .to_str()
+        .ok_or_else(|| anyhow!("Vector index path is not valid UTF-8: {}", vector_path.display()))?;
+    let db = SimilaritySearch::open(vector_dir, collection_name, &embedding_model)?;
     let db_arc = Arc::new(Mutex::new(db));
     
-    cache.insert(project_id, db_arc.clone());
+    // Evicted indices are synced while the cache is still locked, so a reopen
+    // of the same project cannot read the files before they are written
+    for (evicted_id, evicted) in cache.insert(project_id, db_arc.clone(), capacity) {
+        if let Err(e) = evicted.lock().await.sync().await {
+            error!("Failed to sync evicted vector index for project {}: {}", evicted_id, e);
+        }
+        info!("Evicted vector index for project {} from cache", evicted_id);
+    }
     
     Ok(db_arc)
 }
 
-/// Add a chunk to a project's vector index
-pub async fn add_chunk_to_project_vectors(
+/// Add several chunks to a project's vector index, normally with one
+/// embeddings request. Chunks that could not be embedded are returned as failed.
+pub async fn add_chunks_to_project_vectors(
     app_handle: &AppHandle,
     project_id: i64,
-    chunk_id: i64,
-    chunk_text: &str,
+    chunks: &[(i64, &str)],
     api_key: &str,
+    retries: u32,
+) -> Result<BatchOutcome> {
+    let db_arc = get_project_vector_db(app_handle, project_id).await?;
+    let db = db_arc.lock().await;
+
+    let outcome = db.add_batch(chunks, &openai_account_config(app_handle, api_key), retries).await?;
+    if let Some(dimension) = outcome.dimension {
+        record_index_dimension(app_handle, project_id, dimension)?;
+    }
+
+    info!(
+        "Added {} chunks to project {} vector index, {} failed",
+        outcome.added.len(),
+        project_id,
+        outcome.failed.len()
+    );
+    Ok(outcome)
+}
+
+/// Store the vector dimension the first time vectors are added, and refuse
+/// vectors that do not match it
+fn record_index_dimension(app_handle: &AppHandle, project_id: i64, dimension: usize) -> Result<()> {
+    let vector_path = get_project_vector_path(app_handle, project_id)?;
+    let mut metadata = match read_index_metadata(&vector_path) {
+        Some(metadata) => metadata,
+        None => return Ok(()),
+    };
+    match metadata.dimension {
+        Some(recorded) if recorded != dimension => Err(anyhow!(
+            "{} returned {}-dimensional vectors but project {} holds {}-dimensional ones. Rebuild the project index.",
+            metadata.embedding_model,
+            dimension,
+            project_id,
+            recorded
+        )),
+        Some(_) => Ok(()),
+        None => {
+            metadata.dimension = Some(dimension);
+            write_index_metadata(&vector_path, &metadata)
+        }
+    }
+}
+
+/// Remove chunks from a project's vector index, e.g. when their document is
+/// deleted or moved to another project
+pub async fn remove_chunks_from_project_vectors(
+    app_handle: &AppHandle,
+    project_id: i64,
+    chunk_ids: &[i64],
 ) -> Result<()> {
+    if chunk_ids.is_empty() {
+        return Ok(());
+    }
     let db_arc = get_project_vector_db(app_handle, project_id).await?;
     let db = db_arc.lock().await;
-    
-    db.add(chunk_id, chunk_text, api_key).await?;
-    
-    info!("Added chunk {} to project {} vector index", chunk_id, project_id);
+
+    for chunk_id in chunk_ids {
+        db.remove(*chunk_id).await?;
+    }
+    db.sync().await?;
+
+    info!("Removed {} chunks from project {} vector index", chunk_ids.len(), project_id);
     Ok(())
 }
 
+/// Number of chunks searchable in a project's vector index
+pub async fn project_vector_count(app_handle: &AppHandle, project_id: i64) -> Result<usize> {
+    let db_arc = get_project_vector_db(app_handle, project_id).await?;
+    let db = db_arc.lock().await;
+    db.count().await
+}
+
 /// Search for similar chunks within a project's vector index
 pub async fn search_project_vectors(
     app_handle: &AppHandle,
@@ -92,7 +346,7 @@ pub async fn search_project_vectors(
     let db_arc = get_project_vector_db(app_handle, project_id).await?;
     let db = db_arc.lock().await;
     
-    let results = db.top_k(query, top_k, api_key).await?;
+    let results = db.top_k(query, top_k, &openai_account_config(app_handle, api_key)).await?;
     
     // Convert usize IDs to i64
     let results: Vec<(i64, f32)> = results
@@ -104,6 +358,55 @@ pub async fn search_project_vectors(
     Ok(results)
 }
 
+/// Search several projects' indexes for `query` and merge the hits into one
+/// ranking of at most `top_k` chunks. A project whose search fails is skipped
+/// with a warning; the last error is returned only when every search failed.
+pub async fn search_multi_project_vectors(
+    app_handle: &AppHandle,
+    project_ids: &[i64],
+    query: &str,
+    top_k: usize,
+    api_key: &str,
+) -> Result<Vec<(i64, f32)>> {
+    let mut searched: Vec<i64> = Vec::new();
+    let mut per_project = Vec::new();
+    let mut last_error = None;
+    for &project_id in project_ids {
+        if searched.contains(&project_id) {
+            continue;
+        }
+        searched.push(project_id);
+        match search_project_vectors(app_handle, project_id, query, top_k, api_key).await {
+            Ok(hits) => per_project.push(hits),
+            Err(e) => {
+                warn!("Skipping project {} in multi-project search: {}", project_id, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    if let (true, Some(e)) = (per_project.is_empty(), last_error) {
+        return Err(e);
+    }
+    Ok(merge_project_hits(per_project, top_k))
+}
+
+/// Merge hits from several indexes: a chunk found in more than one project
+/// keeps its closest distance, then the `top_k` closest remain, ties broken by
+/// chunk id. Projects sharing a document index the same chunk ids.
+fn merge_project_hits(per_project: Vec<Vec<(i64, f32)>>, top_k: usize) -> Vec<(i64, f32)> {
+    let mut closest: HashMap<i64, f32> = HashMap::new();
+    for (chunk_id, distance) in per_project.into_iter().flatten() {
+        closest
+            .entry(chunk_id)
+            .and_modify(|best| *best = best.min(distance))
+            .or_insert(distance);
+    }
+    let mut hits: Vec<(i64, f32)> = closest.into_iter().collect();
+    hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal).then(a.0.cmp(&b.0)));
+    hits.truncate(top_k);
+    hits
+}
+
 /// Sync a project's vector index to disk
 pub async fn sync_project_vectors(
     app_handle: &AppHandle,
@@ -123,13 +426,32 @@ pub async fn close_project_vectors(project_id: i64) -> Result<()> {
     let mut cache = PROJECT_VECTORS.lock().await;
     
     // Simply remove from cache - the index will be synced when dropped
-    if cache.remove(&project_id).is_some() {
+    if cache.remove(project_id).is_some() {
         info!("Removed project {} vector index from cache", project_id);
     }
     
     Ok(())
 }
 
+/// Save every open index to disk and empty the cache, so later access reopens
+/// the indexes from the current data directory
+pub async fn close_all_project_vectors() -> Result<()> {
+    let project_ids: Vec<i64> = PROJECT_VECTORS.lock().await.recency.iter().copied().collect();
+    for project_id in project_ids {
+        // Waits out a vectorization run that is still writing to the index
+        let _guard = lock_project_vectors(project_id).await;
+        let removed = PROJECT_VECTORS.lock().await.remove(project_id);
+        if let Some(db_arc) = removed {
+            let db = db_arc.lock().await;
+            db.sync().await?;
+            // Answered after the save, so the files are complete once this returns
+            db.count().await?;
+            info!("Closed vector index for project {}", project_id);
+        }
+    }
+    Ok(())
+}
+
 /// Delete a project's vector index entirely
 pub async fn delete_project_vectors(
     app_handle: &AppHandle,
@@ -139,7 +461,7 @@ pub async fn delete_project_vectors(
     close_project_vectors(project_id).await?;
     
     // Delete the directory
-    let vector_path = get_project_vector_path(app_handle, project_id);
+    let vector_path = get_project_vector_path(app_handle, project_id)?;
     if vector_path.exists() {
         std::fs::remove_dir_all(&vector_path)?;
         info!("Deleted vector index directory for project {}", project_id);
@@ -147,3 +469,102 @@ pub async fn delete_project_vectors(
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use super::*;
+
+    async fn run_concurrent_writers(project_ids: Vec<i64>) -> usize {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let tasks: Vec<_> = project_ids
+            .into_iter()
+            .map(|project_id| {
+                let in_flight = in_flight.clone();
+                let max_in_flight = max_in_flight.clone();
+                tokio::spawn(async move {
+                    let _guard = lock_project_vectors(project_id).await;
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        max_in_flight.load(Ordering::SeqCst)
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_same_project_writers_are_serialized() {
+        assert_eq!(run_concurrent_writers(vec![9001; 32]).await, 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_different_projects_run_in_parallel() {
+        assert!(run_concurrent_writers((9101..9109).collect()).await > 1);
+    }
+
+    #[test]
+    fn test_multi_project_hits_are_deduplicated_and_reranked() {
+        let first = vec![(10, 0.30), (11, 0.10), (12, 0.50)];
+        let second = vec![(20, 0.20), (12, 0.05), (21, 0.10)];
+        assert_eq!(
+            merge_project_hits(vec![first, second], 4),
+            vec![(12, 0.05), (11, 0.10), (21, 0.10), (20, 0.20)]
+        );
+        assert!(merge_project_hits(vec![vec![], vec![]], 4).is_empty());
+    }
+
+    #[test]
+    fn test_cancel_flags_are_per_project() {
+        cancel_project_vectorization(901);
+        assert!(is_vectorization_cancelled(901));
+        assert!(!is_vectorization_cancelled(902));
+        clear_vectorization_cancel(901);
+        assert!(!is_vectorization_cancelled(901));
+    }
+
+    #[test]
+    fn test_lru_cache_evicts_least_recently_used() {
+        let mut cache = LruCache::new();
+        assert!(cache.insert(1, "one", 2).is_empty());
+        assert!(cache.insert(2, "two", 2).is_empty());
+        // Using project 1 makes project 2 the eviction candidate
+        assert_eq!(cache.get(1), Some("one"));
+        assert_eq!(cache.insert(3, "three", 2), vec![(2, "two")]);
+        assert_eq!(cache.get(2), None);
+
+        assert_eq!(cache.remove(1), Some("one"));
+        assert!(cache.insert(4, "four", 2).is_empty());
+    }
+
+    #[test]
+    fn test_index_metadata_detects_model_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        // A new index takes the configured model
+        check_index_metadata(1, dir.path(), "text-embedding-3-large").unwrap();
+        assert_eq!(
+            read_index_metadata(dir.path()),
+            Some(IndexMetadata { embedding_model: "text-embedding-3-large".to_string(), dimension: None })
+        );
+        check_index_metadata(1, dir.path(), "text-embedding-3-large").unwrap();
+        let error = check_index_metadata(1, dir.path(), "text-embedding-3-small").unwrap_err();
+        assert!(error.is::<EmbeddingModelMismatch>());
+    }
+
+    #[test]
+    fn test_unrecorded_index_with_vectors_is_the_default_model() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("chunks.hnsw.data"), b"vectors").unwrap();
+        let metadata = read_index_metadata(dir.path()).unwrap();
+        assert_eq!(metadata.embedding_model, DEFAULT_EMBEDDING_MODEL);
+        assert_eq!(metadata.dimension, Some(LEGACY_EMBEDDING_DIMENSION));
+        assert!(check_index_metadata(1, dir.path(), "text-embedding-3-large").is_err());
+    }
+}
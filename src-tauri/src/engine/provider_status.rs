@@ -0,0 +1,81 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::configuration::settings::API_CHOICES;
+use crate::configuration::state::ServiceAccess;
+use crate::engine::chat_dispatch::model_for;
+use crate::error::{HeelixError, HeelixResult};
+use crate::repository::settings_repository::is_provider_configured;
+
+lazy_static! {
+    /// Whether each provider passed its latest health check since launch
+    static ref LAST_HEALTH_CHECKS: Mutex<HashMap<String, bool>> = Mutex::new(HashMap::new());
+}
+
+/// Remember whether `provider` answered its latest health check
+pub fn record_health_check(provider: &str, passed: bool) {
+    LAST_HEALTH_CHECKS.lock().unwrap().insert(provider.to_string(), passed);
+}
+
+/// Count a chat request as a health check: an answer passes, transport and
+/// provider errors fail, and anything else (bad input, a missing key) says
+/// nothing about the provider
+pub fn record_request_outcome<T>(provider: &str, result: &HeelixResult<T>) {
+    match result {
+        Ok(_) => record_health_check(provider, true),
+        Err(HeelixError::Network(_)) | Err(HeelixError::Provider { .. }) => record_health_check(provider, false),
+        Err(_) => {}
+    }
+}
+
+fn last_health_check(provider: &str) -> Option<bool> {
+    LAST_HEALTH_CHECKS.lock().unwrap().get(provider).copied()
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ProviderStatus {
+    pub provider: String,
+    /// An API key is set; local models only need the server URL, which has a default
+    pub configured: bool,
+    /// Outcome of the latest endpoint test or chat request since launch, None
+    /// when the provider has not been used yet
+    pub last_check_passed: Option<bool>,
+    /// Model used when a request does not name one
+    pub model: String,
+}
+
+/// Readiness of every provider in one call, for the badges in Settings
+#[tauri::command]
+pub fn get_providers_status(app_handle: AppHandle) -> HeelixResult<Vec<ProviderStatus>> {
+    API_CHOICES
+        .iter()
+        .map(|provider| -> HeelixResult<ProviderStatus> {
+            Ok(ProviderStatus {
+                provider: provider.to_string(),
+                configured: app_handle.db(|db| is_provider_configured(db, provider))?,
+                last_check_passed: last_health_check(provider),
+                model: model_for(provider, None)?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_provider_failures_count_as_failed_checks() {
+        assert_eq!(last_health_check("status-test"), None);
+        record_request_outcome::<()>("status-test", &Err(HeelixError::InvalidInput("empty".to_string())));
+        assert_eq!(last_health_check("status-test"), None);
+        record_request_outcome::<()>("status-test", &Err(HeelixError::provider("Claude", "overloaded")));
+        assert_eq!(last_health_check("status-test"), Some(false));
+        record_request_outcome("status-test", &Ok(()));
+        assert_eq!(last_health_check("status-test"), Some(true));
+    }
+}
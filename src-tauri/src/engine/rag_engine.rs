@@ -0,0 +1,320 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use log::{debug, error, warn};
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::configuration::state::ServiceAccess;
+use crate::engine::chat_engine::emit_chat_event;
+use crate::engine::project_vector_engine::{search_multi_project_vectors, search_project_vectors, EmbeddingModelMismatch};
+use crate::engine::source_attribution::remember_retrieved_chunks;
+use crate::error::{HeelixError, HeelixResult};
+use crate::repository::chunk_repository::{
+    get_chunk_neighbors, get_chunk_sources, get_chunks_by_ids, group_chunk_sources, stitch_chunks, ChunkSource,
+    DocumentChunk,
+};
+use crate::repository::settings_repository::{
+    clamp_rag_top_k, get_attribute_sources, get_expand_chunk_context, get_max_context_documents, get_rag_top_k,
+    get_setting_or_default,
+};
+
+/// Emitted when a first message goes out without any retrieved project context,
+/// so the UI can mark the answer as not grounded in the user's documents.
+pub const RAG_CONTEXT_EMPTY_EVENT: &str = "rag_context_empty";
+/// With `expand_chunk_context` on, how many of the best-ranked chunks are
+/// sent with their neighbours
+const EXPANDED_CHUNKS: usize = 3;
+/// Neighbouring chunks added on each side of an expanded chunk
+const EXPANSION_WINDOW: i32 = 1;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RagEmptyReason {
+    NoProject,
+    NoMatches,
+    SearchFailed,
+    /// The project index was built with another embedding model and needs a rebuild
+    IndexOutdated,
+}
+
+#[derive(Serialize, Clone)]
+struct RagContextEmptyPayload {
+    chat_id: Option<i64>,
+    reason: RagEmptyReason,
+}
+
+/// Retrieve the chunks of `project_ids` most similar to `user_prompt`, ranked
+/// across all the projects, keeping only those from the `max_context_documents`
+/// best-ranked documents, emit their sources as `llm_sources` for `chat_id`,
+/// and return them formatted for the system prompt.
+/// Returns an empty string (and emits `rag_context_empty`) when nothing was retrieved.
+pub async fn retrieve_project_context(
+    app_handle: &AppHandle,
+    project_ids: &[i64],
+    chat_id: Option<i64>,
+    user_prompt: &str,
+) -> HeelixResult<String> {
+    if project_ids.is_empty() {
+        debug!("No project selected, skipping RAG retrieval");
+        emit_context_empty(app_handle, chat_id, RagEmptyReason::NoProject);
+        return Ok(String::new());
+    }
+    debug!("Using per-project vector search for projects {:?}", project_ids);
+
+    let rag_top_k = app_handle.db(get_rag_top_k)?;
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
+
+    let search = search_multi_project_vectors(app_handle, project_ids, user_prompt, rag_top_k, &api_key).await;
+    let similar_chunk_ids = match search {
+        Ok(ids) if !ids.is_empty() => ids,
+        Ok(_) => {
+            debug!("No vectorized chunks found for project");
+            emit_context_empty(app_handle, chat_id, RagEmptyReason::NoMatches);
+            return Ok(String::new());
+        }
+        Err(e) if e.is::<EmbeddingModelMismatch>() => {
+            warn!("{}", e);
+            emit_context_empty(app_handle, chat_id, RagEmptyReason::IndexOutdated);
+            return Ok(String::new());
+        }
+        Err(e) => {
+            debug!("Project vector search failed: {}", e);
+            emit_context_empty(app_handle, chat_id, RagEmptyReason::SearchFailed);
+            return Ok(String::new());
+        }
+    };
+
+    let chunk_ids_to_fetch: Vec<i64> = rank_hits(similar_chunk_ids)
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    debug!("Retrieved {} similar chunks from project index", chunk_ids_to_fetch.len());
+
+    // The repository returns rows in document order; put them back in rank order
+    let rank_of = |chunk_id: i64| chunk_ids_to_fetch.iter().position(|id| *id == chunk_id);
+    let mut chunks = app_handle.db(|conn| get_chunks_by_ids(conn, &chunk_ids_to_fetch))?;
+    chunks.sort_by_key(|chunk| rank_of(chunk.id));
+    let max_documents = app_handle.db(get_max_context_documents)?;
+    let mut chunks = limit_documents(chunks, max_documents);
+    if app_handle.db(get_attribute_sources).unwrap_or(false) {
+        remember_retrieved_chunks(chat_id, &chunks);
+    }
+    let kept_chunk_ids: Vec<i64> = chunks.iter().map(|chunk| chunk.id).collect();
+    if app_handle.db(get_expand_chunk_context).unwrap_or(false) {
+        app_handle.db(|conn| {
+            expand_top_chunks(&mut chunks, |chunk_id| get_chunk_neighbors(conn, chunk_id, EXPANSION_WINDOW))
+        })?;
+    }
+
+    // Get source information for citations
+    let mut sources: Vec<ChunkSource> = app_handle
+        .db(|conn| get_chunk_sources(conn, &kept_chunk_ids))
+        .unwrap_or_else(|e| {
+            error!("Failed to get chunk sources: {}", e);
+            vec![]
+        });
+    sources.sort_by_key(|source| rank_of(source.chunk_id));
+
+    // Emit sources to frontend
+    if !sources.is_empty() {
+        if let Err(e) = emit_chat_event(app_handle, "llm_sources", chat_id, &sources) {
+            error!("Failed to emit sources: {}", e);
+        }
+        // The same citations with one entry per document, for a deduplicated list
+        if let Err(e) = emit_chat_event(app_handle, "llm_document_sources", chat_id, group_chunk_sources(&sources)) {
+            error!("Failed to emit document sources: {}", e);
+        }
+    }
+
+    let mut context = String::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        context.push_str(&format!(
+            "Chunk {} (from document {}):\n{}\n\n",
+            index + 1, chunk.document_id, chunk.chunk_text
+        ));
+    }
+
+    if context.is_empty() {
+        emit_context_empty(app_handle, chat_id, RagEmptyReason::NoMatches);
+    }
+    Ok(context)
+}
+
+/// A chunk as retrieval ranked it, for inspecting RAG without generating an answer
+#[derive(Serialize)]
+pub struct RetrievedChunk {
+    pub rank: usize,
+    /// Vector distance to the query; lower is more similar
+    pub distance: f32,
+    #[serde(flatten)]
+    pub source: ChunkSource,
+}
+
+/// Dry run of project retrieval: returns the chunks a chat prompt would receive
+/// for `query`, in rank order, without calling any chat model.
+/// `top_k` defaults to the `rag_top_k` setting and is held to the same range.
+#[tauri::command]
+pub async fn preview_retrieval(
+    app_handle: AppHandle,
+    project_id: i64,
+    query: String,
+    top_k: Option<usize>,
+) -> HeelixResult<Vec<RetrievedChunk>> {
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
+    if api_key.is_empty() {
+        return Err(HeelixError::MissingApiKey("OpenAI"));
+    }
+    let top_k = match top_k {
+        Some(top_k) => clamp_rag_top_k(top_k),
+        None => app_handle.db(get_rag_top_k)?,
+    };
+
+    let ranked = search_project_vectors(&app_handle, project_id, &query, top_k, &api_key)
+        .await
+        .map_err(|e| match e.downcast::<EmbeddingModelMismatch>() {
+            Ok(mismatch) => HeelixError::InvalidInput(mismatch.to_string()),
+            Err(e) => HeelixError::Internal(format!("Vector search failed: {}", e)),
+        })?;
+    let chunk_ids: Vec<i64> = ranked.iter().map(|(id, _)| *id).collect();
+    let sources = app_handle.db(|conn| get_chunk_sources(conn, &chunk_ids))?;
+
+    // get_chunk_sources orders by document; restore the ranking order
+    Ok(rank_hits(ranked)
+        .into_iter()
+        .filter_map(|(chunk_id, distance)| {
+            sources
+                .iter()
+                .find(|source| source.chunk_id == chunk_id)
+                .cloned()
+                .map(|source| (distance, source))
+        })
+        .enumerate()
+        .map(|(index, (distance, source))| RetrievedChunk {
+            rank: index + 1,
+            distance,
+            source,
+        })
+        .collect())
+}
+
+/// Order search hits by distance, breaking ties by chunk id, so the same hits
+/// always produce the same prompt
+fn rank_hits(mut hits: Vec<(i64, f32)>) -> Vec<(i64, f32)> {
+    hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal).then(a.0.cmp(&b.0)));
+    hits
+}
+
+/// Keep the ranked chunks that belong to the first `max_documents` distinct
+/// documents, in rank order
+fn limit_documents(ranked_chunks: Vec<DocumentChunk>, max_documents: usize) -> Vec<DocumentChunk> {
+    let mut documents: Vec<i64> = Vec::new();
+    ranked_chunks
+        .into_iter()
+        .filter(|chunk| {
+            if documents.contains(&chunk.document_id) {
+                true
+            } else if documents.len() < max_documents {
+                documents.push(chunk.document_id);
+                true
+            } else {
+                false
+            }
+        })
+        .collect()
+}
+
+/// Replace the text of the first `EXPANDED_CHUNKS` chunks with the chunk and
+/// its neighbours, so the model sees the passage around a match. Neighbours
+/// already in the context, retrieved or added for an earlier chunk, are left
+/// out so no chunk is sent twice, and the text chunks share is sent once.
+fn expand_top_chunks(
+    chunks: &mut [DocumentChunk],
+    mut neighbors_of: impl FnMut(i64) -> Result<Vec<DocumentChunk>, rusqlite::Error>,
+) -> Result<(), rusqlite::Error> {
+    let mut included: HashSet<i64> = chunks.iter().map(|chunk| chunk.id).collect();
+    for chunk in chunks.iter_mut().take(EXPANDED_CHUNKS) {
+        let neighbors = neighbors_of(chunk.id)?;
+        if neighbors.is_empty() {
+            continue;
+        }
+        chunk.chunk_text = stitch_chunks(
+            neighbors
+                .iter()
+                .filter(|neighbor| neighbor.id == chunk.id || included.insert(neighbor.id)),
+        );
+    }
+    Ok(())
+}
+
+fn emit_context_empty(app_handle: &AppHandle, chat_id: Option<i64>, reason: RagEmptyReason) {
+    if let Err(e) = app_handle
+        .get_window("main")
+        .expect("Failed to get main window")
+        .emit(RAG_CONTEXT_EMPTY_EVENT, RagContextEmptyPayload { chat_id, reason })
+    {
+        error!("Failed to emit {}: {}", RAG_CONTEXT_EMPTY_EVENT, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::chunk_repository::split_into_chunks;
+
+    #[test]
+    fn test_rank_hits_orders_by_distance_then_id() {
+        let hits = vec![(7, 0.3), (4, 0.1), (9, 0.1), (2, 0.3)];
+        assert_eq!(rank_hits(hits), vec![(4, 0.1), (9, 0.1), (2, 0.3), (7, 0.3)]);
+    }
+
+    fn chunk(id: i64, document_id: i64) -> DocumentChunk {
+        DocumentChunk {
+            id,
+            document_id,
+            project_id: 1,
+            chunk_index: id as i32,
+            chunk_text: format!("chunk {}", id),
+            is_vectorized: true,
+        }
+    }
+
+    #[test]
+    fn test_limit_documents_keeps_best_ranked_documents() {
+        let ranked = vec![chunk(1, 10), chunk(2, 20), chunk(3, 10), chunk(4, 30), chunk(5, 20)];
+        let kept: Vec<i64> = limit_documents(ranked, 2).iter().map(|chunk| chunk.id).collect();
+        assert_eq!(kept, vec![1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn test_expand_top_chunks_adds_neighbours_once() {
+        // Chunks 2 and 3 were both retrieved; 1 through 5 are one document
+        let mut chunks = vec![chunk(2, 10), chunk(3, 10)];
+        expand_top_chunks(&mut chunks, |chunk_id| {
+            Ok((chunk_id - 1..=chunk_id + 1).map(|id| chunk(id, 10)).collect())
+        })
+        .unwrap();
+        assert_eq!(chunks[0].chunk_text, "chunk 1\nchunk 2");
+        assert_eq!(chunks[1].chunk_text, "chunk 3\nchunk 4");
+    }
+
+    #[test]
+    fn test_expanded_chunks_do_not_repeat_the_overlap() {
+        let text: String = (0..300)
+            .map(|n| format!("Sentence {} of the quarterly report covers revenue. ", n))
+            .collect();
+        let document: Vec<DocumentChunk> = split_into_chunks(&text)
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk_text)| DocumentChunk { chunk_text, ..chunk(index as i64 + 1, 10) })
+            .collect();
+        assert!(document.len() >= 3);
+
+        let mut chunks = vec![document[1].clone()];
+        expand_top_chunks(&mut chunks, |_| Ok(document[..3].to_vec())).unwrap();
+        let expected = stitch_chunks(&document[..3]);
+        assert_eq!(chunks[0].chunk_text, expected);
+        // The report has no repeated text, so any doubled overlap breaks the prefix
+        assert!(text.starts_with(&expected));
+    }
+}
@@ -0,0 +1,204 @@
+//! Per-provider request and token budgets shared by every chat and embeddings
+//! call, so bulk work such as vectorizing a large project waits for capacity
+//! instead of running into the provider's rate limits.
+//!
+//! Each provider has its own buckets, so a busy OpenAI budget never delays a
+//! Claude or local request. Local models have no limits.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::debug;
+
+use crate::configuration::settings::Settings;
+use crate::engine::context_budget::estimate_tokens;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct RateLimits {
+    /// 0 leaves requests unlimited
+    pub requests_per_minute: u32,
+    /// Estimated input tokens; 0 leaves tokens unlimited
+    pub tokens_per_minute: u32,
+}
+
+/// Budget that refills continuously up to one minute's allowance
+#[derive(Debug)]
+struct Bucket {
+    per_minute: f64,
+    available: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    /// None for an unlimited budget
+    fn new(per_minute: u32, now: Instant) -> Option<Self> {
+        (per_minute > 0).then(|| Bucket {
+            per_minute: per_minute as f64,
+            available: per_minute as f64,
+            updated: now,
+        })
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.available = (self.available + elapsed * self.per_minute / 60.0).min(self.per_minute);
+        self.updated = now;
+    }
+
+    /// A request larger than a whole minute's allowance waits for a full bucket
+    /// rather than forever
+    fn cost(&self, amount: f64) -> f64 {
+        amount.min(self.per_minute)
+    }
+
+    fn wait_for(&self, amount: f64) -> Duration {
+        let missing = self.cost(amount) - self.available;
+        if missing <= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(missing * 60.0 / self.per_minute)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= self.cost(amount);
+    }
+}
+
+#[derive(Debug)]
+struct ProviderLimiter {
+    limits: RateLimits,
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>,
+}
+
+impl ProviderLimiter {
+    fn new(limits: RateLimits, now: Instant) -> Self {
+        ProviderLimiter {
+            limits,
+            requests: Bucket::new(limits.requests_per_minute, now),
+            tokens: Bucket::new(limits.tokens_per_minute, now),
+        }
+    }
+
+    /// Count one request of `tokens` when both budgets allow it now, otherwise
+    /// return how long to wait before trying again
+    fn try_acquire(&mut self, tokens: usize, now: Instant) -> Result<(), Duration> {
+        let mut needs = [(self.requests.as_mut(), 1.0), (self.tokens.as_mut(), tokens as f64)];
+        let mut wait = Duration::ZERO;
+        for (bucket, amount) in needs.iter_mut() {
+            if let Some(bucket) = bucket {
+                bucket.refill(now);
+                wait = wait.max(bucket.wait_for(*amount));
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        for (bucket, amount) in needs {
+            if let Some(bucket) = bucket {
+                bucket.take(amount);
+            }
+        }
+        Ok(())
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref LIMITERS: Mutex<HashMap<String, ProviderLimiter>> = Mutex::new(HashMap::new());
+}
+
+/// Replace `provider`'s limits. Unchanged limits keep the current budgets, so
+/// saving settings does not hand out a fresh minute's allowance.
+pub fn set_limits(provider: &str, limits: RateLimits) {
+    let mut limiters = LIMITERS.lock().unwrap();
+    if limiters.get(provider).map_or(false, |limiter| limiter.limits == limits) {
+        return;
+    }
+    limiters.insert(provider.to_string(), ProviderLimiter::new(limits, Instant::now()));
+}
+
+/// Apply the `rate_limit_*` settings of every cloud provider
+pub fn configure_rate_limits(settings: &Settings) {
+    set_limits(
+        "claude",
+        RateLimits {
+            requests_per_minute: settings.rate_limit_rpm_claude,
+            tokens_per_minute: settings.rate_limit_tpm_claude,
+        },
+    );
+    set_limits(
+        "openai",
+        RateLimits {
+            requests_per_minute: settings.rate_limit_rpm_openai,
+            tokens_per_minute: settings.rate_limit_tpm_openai,
+        },
+    );
+    set_limits(
+        "gemini",
+        RateLimits {
+            requests_per_minute: settings.rate_limit_rpm_gemini,
+            tokens_per_minute: settings.rate_limit_tpm_gemini,
+        },
+    );
+}
+
+/// Estimated input tokens of a request made of `texts`
+pub fn estimate_prompt_tokens<'a>(texts: impl IntoIterator<Item = &'a str>) -> usize {
+    texts.into_iter().map(estimate_tokens).sum()
+}
+
+/// Wait until `provider` may be sent one more request of about `tokens` input
+/// tokens, and count it. Returns at once for providers without limits.
+pub async fn acquire(provider: &str, tokens: usize) {
+    loop {
+        let outcome = match LIMITERS.lock().unwrap().get_mut(provider) {
+            Some(limiter) => limiter.try_acquire(tokens, Instant::now()),
+            None => return,
+        };
+        match outcome {
+            Ok(()) => return,
+            Err(wait) => {
+                debug!("Waiting {:?} for {} rate limit capacity", wait, provider);
+                tokio::time::sleep(wait).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_requests_wait_for_the_bucket_to_refill() {
+        let start = Instant::now();
+        let mut limiter = ProviderLimiter::new(RateLimits { requests_per_minute: 2, tokens_per_minute: 0 }, start);
+        assert!(limiter.try_acquire(100, start).is_ok());
+        assert!(limiter.try_acquire(100, start).is_ok());
+        assert_eq!(limiter.try_acquire(100, start), Err(Duration::from_secs(30)));
+        assert!(limiter.try_acquire(100, start + Duration::from_secs(30)).is_ok());
+    }
+
+    #[test]
+    fn test_tokens_are_limited_and_oversized_requests_wait_for_a_full_bucket() {
+        let start = Instant::now();
+        let mut limiter = ProviderLimiter::new(RateLimits { requests_per_minute: 0, tokens_per_minute: 600 }, start);
+        assert!(limiter.try_acquire(500, start).is_ok());
+        // Refused requests take nothing from either budget
+        assert_eq!(limiter.try_acquire(200, start), Err(Duration::from_secs(10)));
+        assert!(limiter.try_acquire(100, start).is_ok());
+        assert_eq!(limiter.try_acquire(5000, start), Err(Duration::from_secs(60)));
+        assert!(limiter.try_acquire(5000, start + Duration::from_secs(60)).is_ok());
+    }
+
+    #[test]
+    fn test_unlimited_providers_never_wait() {
+        let start = Instant::now();
+        let mut limiter = ProviderLimiter::new(RateLimits::default(), start);
+        for _ in 0..1000 {
+            assert!(limiter.try_acquire(1_000_000, start).is_ok());
+        }
+    }
+}
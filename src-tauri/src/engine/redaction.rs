@@ -0,0 +1,145 @@
+use lazy_static::lazy_static;
+use log::info;
+use regex::{Captures, Regex};
+use tauri::AppHandle;
+
+use crate::configuration::state::ServiceAccess;
+use crate::engine::chat_engine::emit_chat_event;
+use crate::error::HeelixResult;
+use crate::repository::settings_repository::{get_redact_pii_before_send, get_redaction_denylist};
+
+/// Emitted with the number of masked values when document context was
+/// redacted before going to a cloud provider
+pub const CONTEXT_REDACTED_EVENT: &str = "context_redacted";
+
+lazy_static! {
+    // 13-19 digits, optionally grouped with spaces or dashes; confirmed with a Luhn check
+    static ref CARD_NUMBER: Regex = Regex::new(r"\b(?:\d[ \-]?){12,18}\d\b").unwrap();
+    static ref EMAIL: Regex = Regex::new(r"(?i)\b[A-Z0-9._%+\-]+@[A-Z0-9.\-]+\.[A-Z]{2,}\b").unwrap();
+    // A parenthesised area code as in (020) 7946 0958, a country code as in
+    // +44 20 7946 0958 or +15551234567, or ten digits grouped as 555-123-4567.
+    // Other digit groups are too often IP addresses or amounts.
+    static ref PHONE_NUMBER: Regex = Regex::new(concat!(
+        r"(?:\+\d{1,3}[\s.\-]?)?\(\d{2,4}\)[\s.\-]?\d{3,4}[\s.\-]?\d{3,4}\b",
+        r"|\+\d{1,3}[\s.\-]?\d{2,4}[\s.\-]?\d{3,4}[\s.\-]?\d{3,4}\b",
+        r"|\b\d{3}[\s.\-]\d{3}[\s.\-]\d{4}\b"
+    ))
+    .unwrap();
+}
+
+fn passes_luhn(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, digit)| match (index % 2, digit * 2) {
+            (1, doubled) if doubled > 9 => doubled - 9,
+            (1, doubled) => doubled,
+            _ => *digit,
+        })
+        .sum();
+    sum % 10 == 0
+}
+
+/// Replace the matches of `pattern` accepted by `accept` with `placeholder`,
+/// adding the number replaced to `count`
+fn mask(text: &str, pattern: &Regex, placeholder: &str, accept: impl Fn(&str) -> bool, count: &mut usize) -> String {
+    pattern
+        .replace_all(text, |captures: &Captures| {
+            let found = &captures[0];
+            if accept(found) {
+                *count += 1;
+                placeholder.to_string()
+            } else {
+                found.to_string()
+            }
+        })
+        .into_owned()
+}
+
+/// Mask card numbers, emails, phone numbers and the `denylist` terms (whole
+/// words, case-insensitive) in `text`. Returns the redacted text and the number
+/// of values masked.
+pub fn redact_pii(text: &str, denylist: &[String]) -> (String, usize) {
+    let mut count = 0;
+    let redacted = mask(text, &CARD_NUMBER, "[CARD]", passes_luhn, &mut count);
+    let redacted = mask(&redacted, &EMAIL, "[EMAIL]", |_| true, &mut count);
+    let mut redacted = mask(&redacted, &PHONE_NUMBER, "[PHONE]", |_| true, &mut count);
+
+    let terms: Vec<String> = denylist
+        .iter()
+        .map(|term| term.trim())
+        .filter(|term| !term.is_empty())
+        .map(regex::escape)
+        .collect();
+    if !terms.is_empty() {
+        if let Ok(pattern) = Regex::new(&format!(r"(?i)\b(?:{})\b", terms.join("|"))) {
+            redacted = mask(&redacted, &pattern, "[REDACTED]", |_| true, &mut count);
+        }
+    }
+    (redacted, count)
+}
+
+/// Redact document context in place when `redact_pii_before_send` is on, and
+/// emit the total count for `chat_id` if anything was masked. Cloud engines
+/// call this on selected documents and retrieved chunks; the user's own
+/// messages are sent as typed.
+pub fn redact_context(app_handle: &AppHandle, chat_id: Option<i64>, texts: &mut [&mut String]) -> HeelixResult<()> {
+    if !app_handle.db(get_redact_pii_before_send)? {
+        return Ok(());
+    }
+    let denylist = app_handle.db(get_redaction_denylist)?;
+    let mut total = 0;
+    for text in texts.iter_mut() {
+        let (redacted, count) = redact_pii(text, &denylist);
+        **text = redacted;
+        total += count;
+    }
+    if total > 0 {
+        info!("Redacted {} values from context before sending", total);
+        emit_chat_event(app_handle, CONTEXT_REDACTED_EVENT, chat_id, total)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_masks_emails_phones_and_cards() {
+        let text = "Mail jane.doe@example.com or call 555-123-4567. Card 4111 1111 1111 1111 expires soon.";
+        let (redacted, count) = redact_pii(text, &[]);
+        assert_eq!(redacted, "Mail [EMAIL] or call [PHONE]. Card [CARD] expires soon.");
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_masks_international_and_area_code_phones() {
+        let text = "Call (020) 7946 0958, +44 20 7946 0958 or +15551234567.";
+        let (redacted, count) = redact_pii(text, &[]);
+        assert_eq!(redacted, "Call [PHONE], [PHONE] or [PHONE].");
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn test_leaves_dates_and_non_luhn_numbers() {
+        let text = "Meeting on 2024-01-15, order 1234567890123 shipped, revenue 1,250,000.";
+        assert_eq!(redact_pii(text, &[]), (text.to_string(), 0));
+    }
+
+    #[test]
+    fn test_leaves_ip_addresses_and_space_grouped_amounts() {
+        let text = "The server at 192.168.100.200 and 10.0.0.1 logged 10 000 000 requests, 1 250 000 of them failed.";
+        assert_eq!(redact_pii(text, &[]), (text.to_string(), 0));
+    }
+
+    #[test]
+    fn test_masks_denylisted_terms_as_whole_words() {
+        let denylist = vec!["Project Falcon".to_string(), "acme".to_string(), " ".to_string()];
+        let (redacted, count) = redact_pii("ACME funds project falcon, not Acmeville.", &denylist);
+        assert_eq!(redacted, "[REDACTED] funds [REDACTED], not Acmeville.");
+        assert_eq!(count, 2);
+    }
+}
@@ -1,13 +1,18 @@
+use std::collections::{HashSet, VecDeque};
 use std::fs::create_dir_all;
+use std::future::Future;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, bail, Error, Result};
+use async_openai::config::OpenAIConfig;
 use hnsw_rs::prelude::*;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use tokio::sync::mpsc::{Receiver, Sender};
 use tokio::sync::Mutex;
 
-use crate::repository::vector_db_repository::compute_vector_embedding;
+use crate::repository::vector_db_repository::{compute_vector_embedding, compute_vector_embeddings};
 
 pub const DEFAULT_RAG_TOP_K: usize = 20; // Default top K chunks for RAG retrieval
 pub const MAX_NB_CONNECTION: usize = 50; // Keep high for HNSW graph quality
@@ -16,6 +21,16 @@ pub const MAX_LAYERS: usize = 24;
 pub const EF_CONSTRUCTION: usize = 400;
 
 pub const MAX_INFLIGHT_COMMANDS: usize = 100;
+/// Texts per embeddings request; oversized chunks are sub-split so this stays well under the token limit
+pub const EMBEDDING_BATCH_SIZE: usize = 64;
+/// Input limit of text-embedding-3-small. BPE tokens cover at least one byte,
+/// so a text no longer than this many bytes cannot exceed it.
+pub const EMBEDDING_MAX_TOKENS: usize = 8191;
+/// Pause before retrying a failed embeddings request
+const EMBEDDING_RETRY_DELAY: Duration = Duration::from_secs(2);
+/// Search queries whose embeddings are kept, so a repeated question, or one
+/// searched in several projects, is only embedded once
+const QUERY_EMBEDDING_CACHE_SIZE: usize = 64;
 
 fn get_db<'a>() -> Hnsw<'a, f32, DistCosine> {
     Hnsw::new(
@@ -31,9 +46,40 @@ enum HnswCommand {
     Save,
     Add(Vec<f32>, usize),
     Lookup(Vec<f32>, usize, Sender<Result<Vec<(usize, f32)>, Error>>),
+    Remove(usize),
+    Count(Sender<usize>),
     Shutdown,
 }
 
+/// hnsw_rs cannot delete points, so removed ids are kept in a tombstone set,
+/// filtered out of lookups and persisted next to the graph on save.
+fn tombstone_file_name(collection_name: &str) -> String {
+    format!("{}.hnsw.tombstones", collection_name)
+}
+
+fn load_tombstones(db_path: &str, collection_name: &str) -> HashSet<usize> {
+    let path = Path::new(db_path).join(tombstone_file_name(collection_name));
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Drop tombstoned ids and repeated ids (a re-added id keeps its old point),
+/// then cut to top_k
+fn filter_candidates(
+    candidates: Vec<(usize, f32)>,
+    tombstones: &HashSet<usize>,
+    top_k: usize,
+) -> Vec<(usize, f32)> {
+    let mut seen = HashSet::new();
+    candidates
+        .into_iter()
+        .filter(|(id, _)| !tombstones.contains(id) && seen.insert(*id))
+        .take(top_k)
+        .collect()
+}
+
 async fn hnsw_thread_worker(
     db_path: &str,
     collection_name: &str,
@@ -45,6 +91,13 @@ async fn hnsw_thread_worker(
         Ok(db) => db,
         Err(_) => get_db(),
     };
+    let mut tombstones = load_tombstones(db_path, collection_name);
+    let mut live_ids: HashSet<usize> = db
+        .get_point_indexation()
+        .into_iter()
+        .map(|point| point.get_origin_id())
+        .filter(|id| !tombstones.contains(id))
+        .collect();
 
     loop {
         let command = command_reader.recv().await.ok_or(anyhow!(
@@ -77,18 +130,35 @@ async fn hnsw_thread_worker(
 
                 std::fs::rename(&save_data_path, &new_data_path)?;
                 std::fs::rename(&save_graph_path, &new_graph_path)?;
+
+                let new_tombstone_path = std::path::Path::new(db_path)
+                    .join(tombstone_file_name(&format!("{}_new", collection_name)));
+                std::fs::write(&new_tombstone_path, serde_json::to_string(&tombstones)?)?;
             }
             HnswCommand::Add(vector, id) => {
                 // trace!("Adding vector to HNSW index.");
                 db.insert((&vector, id));
+                tombstones.remove(&id);
+                live_ids.insert(id);
             }
             HnswCommand::Lookup(vector, top_k, sender) => {
-                let results = db.search(&vector, top_k, MAX_NB_CONNECTION);
+                // Over-fetch so tombstoned points do not shrink the result set
+                let results = db.search(&vector, top_k + tombstones.len(), MAX_NB_CONNECTION);
                 let candidates = results
                     .iter()
                     .map(|result| (result.d_id, result.distance))
                     .collect::<Vec<_>>();
-                sender.send(Ok(candidates)).await?;
+                sender
+                    .send(Ok(filter_candidates(candidates, &tombstones, top_k)))
+                    .await?;
+            }
+            HnswCommand::Remove(id) => {
+                if live_ids.remove(&id) {
+                    tombstones.insert(id);
+                }
+            }
+            HnswCommand::Count(sender) => {
+                sender.send(live_ids.len()).await?;
             }
             HnswCommand::Shutdown => {
                 info!("Shutting down HNSW thread worker");
@@ -99,9 +169,12 @@ async fn hnsw_thread_worker(
     Ok(())
 }
 
+/// An HNSW index plus the embedding model its vectors come from; every text
+/// added or searched is embedded with that model
 pub struct SimilaritySearch(
     Option<tokio::task::JoinHandle<()>>,
     Option<Sender<HnswCommand>>,
+    String,
 );
 
 unsafe impl Send for SimilaritySearch {}
@@ -141,24 +214,191 @@ const IS_TEST: bool = cfg!(test);
 
 const MAX_CHARS: usize = 7900;
 
-async fn get_embedding(text: &str, api_key: &str) -> Result<Vec<f32>> {
+/// Cut text to at most MAX_CHARS bytes without splitting a UTF-8 character
+fn truncate_for_embedding(text: &str) -> &str {
+    if text.len() <= MAX_CHARS {
+        return text;
+    }
+    let mut end = MAX_CHARS;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+async fn get_embedding(text: &str, openai: &OpenAIConfig, model: &str) -> Result<Vec<f32>> {
     if IS_TEST {
         return Ok(vec![0.0; 512]);
     }
 
-    let truncated_text = if text.len() > MAX_CHARS {
-        &text[..MAX_CHARS]
-    } else {
-        text
-    };
-
-    compute_vector_embedding(truncated_text, api_key)
+    compute_vector_embedding(truncate_for_embedding(text), openai, model)
         .await
         .map_err(|e| anyhow!("{}", e))
 }
 
+/// Most recently embedded search queries with their embedding model
+struct QueryEmbeddingCache {
+    /// (model, query, vector) from least to most recently used
+    entries: VecDeque<(String, String, Vec<f32>)>,
+    capacity: usize,
+}
+
+impl QueryEmbeddingCache {
+    fn new(capacity: usize) -> Self {
+        QueryEmbeddingCache {
+            entries: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    fn get(&mut self, model: &str, query: &str) -> Option<Vec<f32>> {
+        let index = self.entries.iter().position(|(m, q, _)| m == model && q == query)?;
+        let entry = self.entries.remove(index)?;
+        let vector = entry.2.clone();
+        self.entries.push_back(entry);
+        Some(vector)
+    }
+
+    fn insert(&mut self, model: &str, query: &str, vector: Vec<f32>) {
+        self.entries.retain(|(m, q, _)| !(m == model && q == query));
+        self.entries.push_back((model.to_string(), query.to_string(), vector));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref QUERY_EMBEDDINGS: std::sync::Mutex<QueryEmbeddingCache> =
+        std::sync::Mutex::new(QueryEmbeddingCache::new(QUERY_EMBEDDING_CACHE_SIZE));
+}
+
+/// Embedding of a search query, reused when the same query was embedded
+/// with the same model recently
+async fn get_query_embedding(query: &str, openai: &OpenAIConfig, model: &str) -> Result<Vec<f32>> {
+    if let Some(vector) = QUERY_EMBEDDINGS.lock().unwrap().get(model, query) {
+        debug!("Reusing cached embedding for query");
+        return Ok(vector);
+    }
+    let vector = get_embedding(query, openai, model).await?;
+    QUERY_EMBEDDINGS.lock().unwrap().insert(model, query, vector.clone());
+    Ok(vector)
+}
+
+/// Split text that could exceed the embedding token limit into pieces that
+/// cannot, breaking at whitespace where possible
+fn split_for_embedding(text: &str) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while rest.len() > EMBEDDING_MAX_TOKENS {
+        let mut end = EMBEDDING_MAX_TOKENS;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if let Some(space) = rest[..end].rfind(char::is_whitespace) {
+            if space > end / 2 {
+                end = space;
+            }
+        }
+        pieces.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+    if !rest.is_empty() || pieces.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
+
+/// Element-wise mean, so a sub-split chunk still gets a single vector
+fn mean_vector(vectors: Vec<Vec<f32>>) -> Vec<f32> {
+    let count = vectors.len() as f32;
+    let mut vectors = vectors.into_iter();
+    let mut sum = vectors.next().unwrap_or_default();
+    for vector in vectors {
+        for (total, value) in sum.iter_mut().zip(vector) {
+            *total += value;
+        }
+    }
+    if count > 1.0 {
+        sum.iter_mut().for_each(|total| *total /= count);
+    }
+    sum
+}
+
+/// One vector per text. Texts over the token limit are embedded in pieces
+/// and their vectors averaged.
+async fn get_embeddings(texts: &[&str], openai: &OpenAIConfig, model: &str) -> Result<Vec<Vec<f32>>> {
+    if IS_TEST {
+        return Ok(vec![vec![0.0; 512]; texts.len()]);
+    }
+
+    let split_texts: Vec<Vec<&str>> = texts.iter().map(|text| split_for_embedding(text)).collect();
+    let pieces: Vec<&str> = split_texts.iter().flatten().copied().collect();
+
+    let mut vectors = compute_vector_embeddings(&pieces, openai, model)
+        .await
+        .map_err(|e| anyhow!("{}", e))?
+        .into_iter();
+    Ok(split_texts
+        .iter()
+        .map(|text_pieces| mean_vector(vectors.by_ref().take(text_pieces.len()).collect()))
+        .collect())
+}
+
+/// Embed `items` with `embed`, retrying a failing request `retries` times, then
+/// halving the batch until the items that cannot be embedded are isolated.
+/// Returns the embedded ids with their vectors and the ids that failed.
+async fn embed_isolating_failures<'a, F, Fut>(
+    items: &'a [(i64, &'a str)],
+    retries: u32,
+    mut embed: F,
+) -> (Vec<(i64, Vec<f32>)>, Vec<i64>)
+where
+    F: FnMut(Vec<&'a str>) -> Fut,
+    Fut: Future<Output = Result<Vec<Vec<f32>>>>,
+{
+    let mut embedded = Vec::new();
+    let mut failed = Vec::new();
+    let mut pending: Vec<&[(i64, &str)]> = vec![items];
+    let mut retries_left = retries;
+    while let Some(slice) = pending.pop() {
+        let texts: Vec<&str> = slice.iter().map(|(_, text)| *text).collect();
+        match embed(texts).await {
+            Ok(vectors) => embedded.extend(slice.iter().map(|(id, _)| *id).zip(vectors)),
+            Err(e) if retries_left > 0 => {
+                retries_left -= 1;
+                warn!("Embeddings request for {} texts failed, retrying: {}", slice.len(), e);
+                tokio::time::sleep(EMBEDDING_RETRY_DELAY).await;
+                pending.push(slice);
+            }
+            Err(e) if slice.len() > 1 => {
+                warn!("Embeddings request for {} texts failed, splitting the batch: {}", slice.len(), e);
+                let (left, right) = slice.split_at(slice.len() / 2);
+                pending.push(right);
+                pending.push(left);
+            }
+            // Only this item is left out; the rest of the queue is still embedded
+            Err(e) => {
+                error!("Failed to compute vector embedding for item {}: {}", slice[0].0, e);
+                failed.push(slice[0].0);
+            }
+        }
+    }
+    (embedded, failed)
+}
+
+/// Ids from one `add_batch` call: the ones now in the index and the ones
+/// whose text could not be embedded
+#[derive(Debug, Default, PartialEq)]
+pub struct BatchOutcome {
+    pub added: Vec<i64>,
+    pub failed: Vec<i64>,
+    /// Length of the added vectors, None when nothing was embedded
+    pub dimension: Option<usize>,
+}
+
 impl SimilaritySearch {
-    pub fn open(db_path: &str, collection_name: &str) -> Result<Self> {
+    pub fn open(db_path: &str, collection_name: &str, embedding_model: &str) -> Result<Self> {
         info!(
             "Opening HNSW instance: {}, collection: {}",
             db_path, collection_name
@@ -190,6 +430,12 @@ impl SimilaritySearch {
             std::fs::rename(&new_graph_path, &graph_path)?;
         }
 
+        let new_tombstone_path =
+            dir_path.join(tombstone_file_name(&format!("{}_new", collection_name)));
+        if new_tombstone_path.exists() {
+            std::fs::rename(&new_tombstone_path, dir_path.join(tombstone_file_name(collection_name)))?;
+        }
+
         let (command_sender, command_receiver) = tokio::sync::mpsc::channel(MAX_INFLIGHT_COMMANDS);
         async fn worker(
             db_path: String,
@@ -208,7 +454,7 @@ impl SimilaritySearch {
             command_receiver,
         ));
 
-        Ok(SimilaritySearch(Some(db), Some(command_sender)))
+        Ok(SimilaritySearch(Some(db), Some(command_sender), embedding_model.to_string()))
     }
 
     pub async fn sync(&self) -> Result<()> {
@@ -218,8 +464,8 @@ impl SimilaritySearch {
         Ok(())
     }
 
-    pub async fn add(&self, id: i64, text: &str, api_key: &str) -> Result<()> {
-        let vector_res = get_embedding(text, api_key).await;
+    pub async fn add(&self, id: i64, text: &str, openai: &OpenAIConfig) -> Result<()> {
+        let vector_res = get_embedding(text, openai, &self.2).await;
         let vector = match vector_res {
             Ok(v) => v,
             Err(e) => {
@@ -243,17 +489,68 @@ impl SimilaritySearch {
         }
     }
 
+    /// Embed and add several items, normally with a single embeddings request.
+    /// Callers should keep batches at or below EMBEDDING_BATCH_SIZE.
+    ///
+    /// A failing request is retried `retries` times, then the batch is halved
+    /// until the items that cannot be embedded are isolated; the rest are
+    /// still added and the failures reported in the outcome.
+    pub async fn add_batch(&self, items: &[(i64, &str)], openai: &OpenAIConfig, retries: u32) -> Result<BatchOutcome> {
+        let mut outcome = BatchOutcome::default();
+        if items.is_empty() {
+            return Ok(outcome);
+        }
+        let sender = self.1.as_ref().ok_or_else(|| anyhow!("Command sender is None"))?;
+
+        let model = self.2.as_str();
+        let (embedded, failed) = embed_isolating_failures(items, retries, |texts| async move {
+            get_embeddings(&texts, openai, model).await
+        })
+        .await;
+        outcome.failed = failed;
+        for (id, vector) in embedded {
+            outcome.dimension = Some(vector.len());
+            if let Err(e) = sender.send(HnswCommand::Add(vector, id as usize)).await {
+                error!("Failed to send HnswCommand::Add: {}", e);
+                return Err(anyhow!("Failed to send HnswCommand::Add: {}", e));
+            }
+            outcome.added.push(id);
+        }
+        Ok(outcome)
+    }
+
+    /// Remove an item from search results. The point stays in the graph
+    /// as a tombstone until the id is added again.
+    pub async fn remove(&self, id: i64) -> Result<()> {
+        let sender = self.1.as_ref().ok_or_else(|| anyhow!("Command sender is None"))?;
+        if let Err(e) = sender.send(HnswCommand::Remove(id as usize)).await {
+            error!("Failed to send HnswCommand::Remove: {}", e);
+            return Err(anyhow!("Failed to send HnswCommand::Remove: {}", e));
+        }
+        Ok(())
+    }
+
+    /// Number of distinct ids that can be returned by a search
+    pub async fn count(&self) -> Result<usize> {
+        let sender = self.1.as_ref().ok_or_else(|| anyhow!("Command sender is None"))?;
+        let (count_sender, mut receiver) = tokio::sync::mpsc::channel(1);
+        sender.send(HnswCommand::Count(count_sender)).await?;
+        receiver.recv().await.ok_or(anyhow!(
+            "Failed to receive count, probably the remote peer is no longer available"
+        ))
+    }
+
     pub async fn top_k(
         &self,
         query_text: &str,
         top_k: usize,
-        api_key: &str,
+        openai: &OpenAIConfig,
     ) -> Result<Vec<(usize, f32)>> {
         info!(
             "Performing similarity search in HNSW Index: Query={}",
             query_text
         );
-        let query_vector_res = get_embedding(query_text, api_key).await;
+        let query_vector_res = get_query_embedding(query_text, openai, &self.2).await;
         let query_vector = match query_vector_res {
             Ok(v) => v,
             Err(e) => {
@@ -294,7 +591,7 @@ impl SimilaritySearch {
         &self,
         query_text: &str,
         top_k: usize,
-        api_key: &str,
+        openai: &OpenAIConfig,
         allowed_ids: &[i64],
     ) -> Result<Vec<(usize, f32)>> {
         if allowed_ids.is_empty() {
@@ -310,7 +607,7 @@ impl SimilaritySearch {
             query_text, search_k, allowed_ids.len()
         );
 
-        let all_results = self.top_k(query_text, search_k, api_key).await?;
+        let all_results = self.top_k(query_text, search_k, openai).await?;
         
         // Filter to only allowed IDs
         let allowed_set: std::collections::HashSet<i64> = allowed_ids.iter().copied().collect();
@@ -331,23 +628,94 @@ impl SimilaritySearch {
 
 #[cfg(test)]
 mod tests {
-    use anyhow::Result;
+    use std::collections::HashSet;
+
+    use anyhow::{anyhow, Result};
+    use async_openai::config::OpenAIConfig;
 
-    use super::SimilaritySearch;
+    use super::{
+        embed_isolating_failures, filter_candidates, mean_vector, split_for_embedding, QueryEmbeddingCache,
+        SimilaritySearch, EMBEDDING_MAX_TOKENS,
+    };
+
+    #[tokio::test]
+    async fn test_split_batch_only_fails_the_bad_item() {
+        let items = vec![(1, "fine"), (2, "bad"), (3, "fine"), (4, "fine"), (5, "fine")];
+        let (embedded, failed) = embed_isolating_failures(&items, 0, |texts| async move {
+            if texts.contains(&"bad") {
+                Err(anyhow!("invalid input"))
+            } else {
+                Ok(vec![vec![1.0]; texts.len()])
+            }
+        })
+        .await;
+
+        let mut added: Vec<i64> = embedded.iter().map(|(id, _)| *id).collect();
+        added.sort();
+        assert_eq!(added, vec![1, 3, 4, 5]);
+        assert_eq!(failed, vec![2]);
+    }
+
+    #[test]
+    fn test_filter_candidates_skips_tombstones_and_repeats() {
+        let tombstones: HashSet<usize> = [2].into_iter().collect();
+        let candidates = vec![(1, 0.1), (2, 0.2), (1, 0.3), (3, 0.4), (4, 0.5)];
+        assert_eq!(
+            filter_candidates(candidates, &tombstones, 2),
+            vec![(1, 0.1), (3, 0.4)]
+        );
+    }
+
+    #[test]
+    fn test_split_for_embedding_keeps_pieces_under_limit() {
+        assert_eq!(split_for_embedding("short chunk"), vec!["short chunk"]);
+
+        let text = "word ".repeat(EMBEDDING_MAX_TOKENS / 4);
+        let pieces = split_for_embedding(&text);
+        assert_eq!(pieces.len(), 2);
+        assert!(pieces.iter().all(|piece| piece.len() <= EMBEDDING_MAX_TOKENS));
+        assert!(pieces[0].ends_with("word"));
+        assert!(pieces[1].starts_with("word"));
+
+        let wide = "é".repeat(EMBEDDING_MAX_TOKENS);
+        assert!(split_for_embedding(&wide).iter().all(|piece| piece.len() <= EMBEDDING_MAX_TOKENS));
+    }
+
+    #[test]
+    fn test_mean_vector_averages_pieces() {
+        assert_eq!(mean_vector(vec![vec![1.0, 2.0]]), vec![1.0, 2.0]);
+        assert_eq!(mean_vector(vec![vec![1.0, 2.0], vec![3.0, 4.0]]), vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_query_embedding_cache_keys_on_model_and_evicts_oldest() {
+        let mut cache = QueryEmbeddingCache::new(2);
+        cache.insert("small", "budget", vec![1.0]);
+        cache.insert("large", "budget", vec![2.0]);
+        assert_eq!(cache.get("small", "budget"), Some(vec![1.0]));
+        assert_eq!(cache.get("small", "timeline"), None);
+
+        // "large" was used least recently, so it goes first
+        cache.insert("small", "timeline", vec![3.0]);
+        assert_eq!(cache.get("large", "budget"), None);
+        assert_eq!(cache.get("small", "budget"), Some(vec![1.0]));
+        assert_eq!(cache.get("small", "timeline"), Some(vec![3.0]));
+    }
 
     #[tokio::test]
     async fn test_similarity_search() -> Result<()> {
         let temp_dir = tempfile::tempdir()?;
         let db_path = temp_dir.path().join("test.db");
         let collection_name = "test_collection";
-        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
-        index.add(1, "hello world", "").await?;
-        let candidates = index.top_k("hello world", 1, "").await?;
+        let mut index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name, "test-model")?;
+        let openai = OpenAIConfig::new();
+        index.add(1, "hello world", &openai).await?;
+        let candidates = index.top_k("hello world", 1, &openai).await?;
         assert_eq!(candidates, vec![(1, 0.0)]);
         index.close().await?;
         drop(index);
-        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name)?;
-        let candidates = index.top_k("hello world", 1, "").await?;
+        let index = SimilaritySearch::open(db_path.to_str().unwrap(), collection_name, "test-model")?;
+        let candidates = index.top_k("hello world", 1, &openai).await?;
         assert_eq!(candidates, vec![(1, 0.0)]);
         Ok(())
     }
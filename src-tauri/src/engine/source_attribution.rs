@@ -0,0 +1,211 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use log::{debug, info};
+use serde::Serialize;
+use tauri::AppHandle;
+
+use crate::configuration::state::ServiceAccess;
+use crate::engine::chat_engine::emit_chat_event;
+use crate::engine::chat_engine_openai::openai_account_config;
+use crate::error::{HeelixError, HeelixResult};
+use crate::repository::chunk_repository::DocumentChunk;
+use crate::repository::settings_repository::{get_attribute_sources, get_embedding_model, get_setting_or_default};
+use crate::repository::vector_db_repository::compute_vector_embeddings;
+
+/// Emitted after an answer with the sentences that closely match a retrieved chunk
+pub const LLM_SOURCE_SPANS_EVENT: &str = "llm_source_spans";
+/// Cosine similarity a sentence needs to its closest chunk to count as drawn from it
+const MIN_SIMILARITY: f32 = 0.5;
+/// Shorter sentences are headings or filler and are not attributed
+const MIN_SENTENCE_CHARS: usize = 20;
+/// Sentences attributed per answer, which bounds the embeddings request
+const MAX_SENTENCES: usize = 200;
+
+lazy_static! {
+    /// Chunk ids and texts retrieved for each chat's latest prompt, held until
+    /// the answer has been attributed
+    static ref RETRIEVED_CHUNKS: Mutex<HashMap<i64, Vec<(i64, String)>>> = Mutex::new(HashMap::new());
+}
+
+/// Keep the chunks retrieved for `chat_id` so its answer can be attributed to them
+pub fn remember_retrieved_chunks(chat_id: Option<i64>, chunks: &[DocumentChunk]) {
+    if let Some(chat_id) = chat_id {
+        let retrieved = chunks.iter().map(|chunk| (chunk.id, chunk.chunk_text.clone())).collect();
+        RETRIEVED_CHUNKS.lock().unwrap().insert(chat_id, retrieved);
+    }
+}
+
+fn take_retrieved_chunks(chat_id: i64) -> Vec<(i64, String)> {
+    RETRIEVED_CHUNKS.lock().unwrap().remove(&chat_id).unwrap_or_default()
+}
+
+/// An answer sentence and the chunk it most likely came from. Offsets are
+/// UTF-16 code units into the answer text, as JavaScript string indices are.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct SourceSpan {
+    pub start: usize,
+    pub end: usize,
+    pub chunk_id: i64,
+    pub similarity: f32,
+}
+
+/// A sentence of the answer with its UTF-16 range
+#[derive(Debug, PartialEq)]
+struct Sentence<'a> {
+    start: usize,
+    end: usize,
+    text: &'a str,
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Split `text` into trimmed sentences at sentence-ending punctuation followed
+/// by whitespace, and at line breaks
+fn split_sentences(text: &str) -> Vec<Sentence<'_>> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        let next = chars.peek().map(|(_, next)| *next);
+        let is_end = c == '\n'
+            || matches!(c, '。' | '！' | '？')
+            || (matches!(c, '.' | '!' | '?') && next.map_or(true, char::is_whitespace));
+        if !is_end && next.is_some() {
+            continue;
+        }
+        let end = index + c.len_utf8();
+        let raw = &text[start..end];
+        let trimmed = raw.trim();
+        if !trimmed.is_empty() {
+            let trimmed_start = start + (raw.len() - raw.trim_start().len());
+            let utf16_start = utf16_len(&text[..trimmed_start]);
+            sentences.push(Sentence {
+                start: utf16_start,
+                end: utf16_start + utf16_len(trimmed),
+                text: trimmed,
+            });
+        }
+        start = end;
+    }
+    sentences
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+/// Pair each sentence with its most similar chunk, keeping pairs at or above `MIN_SIMILARITY`
+fn attribute(
+    sentences: &[Sentence],
+    sentence_vectors: &[Vec<f32>],
+    chunk_ids: &[i64],
+    chunk_vectors: &[Vec<f32>],
+) -> Vec<SourceSpan> {
+    sentences
+        .iter()
+        .zip(sentence_vectors)
+        .filter_map(|(sentence, sentence_vector)| {
+            let (chunk_id, similarity) = chunk_ids
+                .iter()
+                .zip(chunk_vectors)
+                .map(|(chunk_id, chunk_vector)| (*chunk_id, cosine_similarity(sentence_vector, chunk_vector)))
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))?;
+            if similarity < MIN_SIMILARITY {
+                return None;
+            }
+            Some(SourceSpan {
+                start: sentence.start,
+                end: sentence.end,
+                chunk_id,
+                similarity,
+            })
+        })
+        .collect()
+}
+
+/// When `attribute_sources` is on, embed the sentences of `answer` and the
+/// chunks retrieved for `chat_id` in one request and emit `llm_source_spans`
+/// with the sentences that match a chunk
+pub async fn attribute_sources(app_handle: &AppHandle, chat_id: Option<i64>, answer: &str) -> HeelixResult<()> {
+    let chat_id = match chat_id {
+        Some(chat_id) => chat_id,
+        None => return Ok(()),
+    };
+    let chunks = take_retrieved_chunks(chat_id);
+    if chunks.is_empty() || !app_handle.db(get_attribute_sources)? {
+        return Ok(());
+    }
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
+    if api_key.is_empty() {
+        return Ok(());
+    }
+
+    let sentences: Vec<Sentence> = split_sentences(answer)
+        .into_iter()
+        .filter(|sentence| sentence.text.chars().count() >= MIN_SENTENCE_CHARS)
+        .take(MAX_SENTENCES)
+        .collect();
+    if sentences.is_empty() {
+        return Ok(());
+    }
+
+    let texts: Vec<&str> = sentences
+        .iter()
+        .map(|sentence| sentence.text)
+        .chain(chunks.iter().map(|(_, text)| text.as_str()))
+        .collect();
+    let model = app_handle.db(get_embedding_model)?;
+    let mut vectors = compute_vector_embeddings(&texts, &openai_account_config(app_handle, &api_key), &model)
+        .await
+        .map_err(|e| HeelixError::Network(format!("Embedding the answer for attribution failed: {}", e)))?;
+    let chunk_vectors = vectors.split_off(sentences.len());
+    let chunk_ids: Vec<i64> = chunks.iter().map(|(chunk_id, _)| *chunk_id).collect();
+
+    let spans = attribute(&sentences, &vectors, &chunk_ids, &chunk_vectors);
+    debug!("Attributed {} of {} sentences in chat {}", spans.len(), sentences.len(), chat_id);
+    if !spans.is_empty() {
+        info!("Emitting {} source spans for chat {}", spans.len(), chat_id);
+        emit_chat_event(app_handle, LLM_SOURCE_SPANS_EVENT, Some(chat_id), spans)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_splits_sentences_with_utf16_offsets() {
+        let text = "Revenue grew 12%. Costs fell!\n\n- Café prices rose 3.5 percent";
+        let sentences = split_sentences(text);
+        let texts: Vec<&str> = sentences.iter().map(|s| s.text).collect();
+        assert_eq!(texts, vec!["Revenue grew 12%.", "Costs fell!", "- Café prices rose 3.5 percent"]);
+        let last = &sentences[2];
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        assert_eq!(String::from_utf16(&utf16[last.start..last.end]).unwrap(), last.text);
+    }
+
+    #[test]
+    fn test_attributes_sentences_to_closest_chunk_above_threshold() {
+        let sentences = vec![
+            Sentence { start: 0, end: 10, text: "about revenue" },
+            Sentence { start: 11, end: 20, text: "unrelated" },
+        ];
+        let sentence_vectors = vec![vec![1.0, 0.1], vec![0.0, 0.0]];
+        let chunk_vectors = vec![vec![0.0, 1.0], vec![1.0, 0.0]];
+        let spans = attribute(&sentences, &sentence_vectors, &[7, 8], &chunk_vectors);
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].start, spans[0].end, spans[0].chunk_id), (0, 10, 8));
+    }
+}
@@ -1,12 +1,316 @@
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Mutex;
 use reqwest::{self, multipart, StatusCode};
 use anyhow::{Result, anyhow};
 use log::{info, warn, error};
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
-/// Transcribe audio using OpenAI's Whisper API
-pub async fn transcribe_with_openai(file_path: &str, api_key: &str) -> Result<String> {
-    info!("Transcribing with OpenAI Whisper API: {}", file_path);
+use heelix::escape_html;
+
+/// Where recordings are transcribed: an OpenAI-compatible server, the model
+/// to ask it for and the bearer token to send
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptionEndpoint {
+    /// Base URL including the version path, without a trailing slash
+    pub base_url: String,
+    pub model: String,
+    /// Empty for servers that take no key, such as a local Whisper server
+    pub api_key: String,
+}
+
+impl TranscriptionEndpoint {
+    fn transcriptions_url(&self) -> String {
+        format!("{}/audio/transcriptions", self.base_url)
+    }
+}
+
+/// A stretch of speech with its position in the recording, in seconds
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TranscriptSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// Transcript with segment timestamps, from Whisper's `verbose_json` format
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct TimedTranscript {
+    pub text: String,
+    /// Length of the transcribed audio in seconds
+    pub duration: f64,
+    pub segments: Vec<TranscriptSegment>,
+}
+
+impl TimedTranscript {
+    /// Append the transcript of the next piece of the same recording, shifting
+    /// its timestamps by the audio already covered so they stay continuous
+    pub fn append(&mut self, next: TimedTranscript) {
+        let offset = self.duration;
+        self.segments.extend(next.segments.into_iter().map(|segment| TranscriptSegment {
+            start: segment.start + offset,
+            end: segment.end + offset,
+            text: segment.text,
+        }));
+        if !self.text.is_empty() && !next.text.is_empty() {
+            self.text.push(' ');
+        }
+        self.text.push_str(next.text.trim());
+        self.duration += next.duration;
+    }
+
+    /// Document HTML: one paragraph per segment, each prefixed with its
+    /// `[m:ss]` start time, or the escaped plain text when there are no segments
+    pub fn to_html(&self) -> String {
+        if self.segments.is_empty() {
+            return escape_html(&self.text);
+        }
+        self.segments
+            .iter()
+            .map(|segment| {
+                format!(
+                    "<p>[{}] {}</p>",
+                    format_timestamp(segment.start),
+                    escape_html(segment.text.trim())
+                )
+            })
+            .collect()
+    }
+}
+
+/// `m:ss`, or `h:mm:ss` from one hour on
+fn format_timestamp(seconds: f64) -> String {
+    let total = seconds.max(0.0) as u64;
+    let (h, m, s) = (total / 3600, (total % 3600) / 60, total % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+/// Whisper's upload limit. Every size rule below derives from this one value.
+pub const WHISPER_MAX_UPLOAD_BYTES: u64 = 25 * 1024 * 1024;
+/// Headroom under the limit for the multipart envelope and WAV headers
+const UPLOAD_SAFETY_MARGIN_BYTES: u64 = 1024 * 1024;
+/// Recordings larger than this are split into pieces of at most this size
+pub const MAX_PIECE_BYTES: u64 = WHISPER_MAX_UPLOAD_BYTES - UPLOAD_SAFETY_MARGIN_BYTES;
+/// Canonical WAV header size written by hound
+const WAV_HEADER_BYTES: u64 = 44;
+
+/// Check that a recording can be transcribed before anything is uploaded:
+/// the header parses, it holds at least one sample, and the sample format is
+/// one Whisper and the splitter both handle.
+pub fn validate_wav(file_path: &str) -> Result<()> {
+    let corrupt = |reason: String| anyhow!("The recording appears empty or corrupt ({})", reason);
+    let reader = hound::WavReader::open(file_path).map_err(|e| corrupt(e.to_string()))?;
+    let spec = reader.spec();
+    let supported = match spec.sample_format {
+        hound::SampleFormat::Int => matches!(spec.bits_per_sample, 8 | 16 | 24 | 32),
+        hound::SampleFormat::Float => spec.bits_per_sample == 32,
+    };
+    if !supported || spec.channels == 0 || spec.sample_rate == 0 {
+        return Err(corrupt(format!(
+            "unsupported format: {} channel(s), {} Hz, {}-bit {:?}",
+            spec.channels, spec.sample_rate, spec.bits_per_sample, spec.sample_format
+        )));
+    }
+    if reader.duration() == 0 {
+        return Err(corrupt("no audio samples".to_string()));
+    }
+    Ok(())
+}
+
+/// Whether a recording of `file_size` bytes has to be uploaded in pieces
+pub fn needs_splitting(file_size: u64) -> bool {
+    file_size > MAX_PIECE_BYTES
+}
+
+lazy_static::lazy_static! {
+    /// Recordings being transcribed, with whether their run was asked to stop
+    static ref RUNNING_TRANSCRIPTIONS: Mutex<HashMap<String, bool>> = Mutex::new(HashMap::new());
+}
+
+/// Ask the transcription of `file_path` to stop before its next piece. Does
+/// nothing when no transcription of it is running, so a late cancel cannot
+/// stop the next run.
+pub fn cancel_transcription(file_path: &str) {
+    if let Some(cancelled) = RUNNING_TRANSCRIPTIONS.lock().unwrap().get_mut(file_path) {
+        *cancelled = true;
+    }
+}
+
+/// Register a run for `file_path` that has not been cancelled
+fn start_transcription_run(file_path: &str) {
+    RUNNING_TRANSCRIPTIONS.lock().unwrap().insert(file_path.to_string(), false);
+}
+
+/// Forget the run for `file_path` and any cancel request it got
+fn end_transcription_run(file_path: &str) {
+    RUNNING_TRANSCRIPTIONS.lock().unwrap().remove(file_path);
+}
+
+fn is_transcription_cancelled(file_path: &str) -> bool {
+    RUNNING_TRANSCRIPTIONS.lock().unwrap().get(file_path).copied().unwrap_or(false)
+}
+
+/// Upload the pieces of `file_path` in order, handing each response body to
+/// `on_piece`. A `cancel_transcription` stops before the next piece; the
+/// pieces already transcribed are kept and the temporary files removed.
+async fn transcribe_pieces(
+    file_path: &str,
+    endpoint: &TranscriptionEndpoint,
+    response_format: &str,
+    mut on_piece: impl FnMut(String) -> Result<()>,
+) -> Result<()> {
+    start_transcription_run(file_path);
+    let result = async {
+        let pieces = split_for_upload(file_path)?;
+        let paths = pieces.paths();
+        for (index, piece) in paths.iter().enumerate() {
+            if is_transcription_cancelled(file_path) {
+                info!("Transcription of {} cancelled after {} of {} pieces", file_path, index, paths.len());
+                break;
+            }
+            on_piece(request_transcription(piece, endpoint, response_format).await?)?;
+        }
+        Ok(())
+    }
+    .await;
+    end_transcription_run(file_path);
+    result
+}
+
+/// Transcribe audio with Whisper at `endpoint`. Returns the text of the
+/// pieces transcribed so far when cancelled.
+pub async fn transcribe_with_openai(file_path: &str, endpoint: &TranscriptionEndpoint) -> Result<String> {
+    let mut texts = Vec::new();
+    transcribe_pieces(file_path, endpoint, "text", |body| {
+        texts.push(body.trim().to_string());
+        Ok(())
+    })
+    .await?;
+    Ok(texts.join(" "))
+}
+
+/// Transcribe audio with per-segment timestamps. Returns the pieces
+/// transcribed so far when cancelled.
+pub async fn transcribe_with_timestamps(file_path: &str, endpoint: &TranscriptionEndpoint) -> Result<TimedTranscript> {
+    let mut transcript = TimedTranscript::default();
+    transcribe_pieces(file_path, endpoint, "verbose_json", |body| {
+        let next: TimedTranscript = serde_json::from_str(&body)
+            .map_err(|e| anyhow!("Unexpected transcription response: {}", e))?;
+        transcript.append(next);
+        Ok(())
+    })
+    .await?;
+    Ok(transcript)
+}
+
+/// Transcribe 16-bit mono samples, such as a window of a live recording.
+/// Whisper only takes files, so they are written to a temporary WAV first.
+pub async fn transcribe_samples(samples: &[i16], sample_rate: u32, endpoint: &TranscriptionEndpoint) -> Result<String> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("window.wav");
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(&path, spec)?;
+    for &sample in samples {
+        writer.write_sample(sample)?;
+    }
+    writer.finalize()?;
+    let text = request_transcription(&path.to_string_lossy(), endpoint, "text").await?;
+    Ok(text.trim().to_string())
+}
+
+/// The file itself, or its pieces in a temporary directory that is removed on drop
+enum UploadPieces {
+    Whole(String),
+    Split(tempfile::TempDir, Vec<String>),
+}
+
+impl UploadPieces {
+    fn paths(&self) -> Vec<&str> {
+        match self {
+            UploadPieces::Whole(path) => vec![path.as_str()],
+            UploadPieces::Split(_, paths) => paths.iter().map(String::as_str).collect(),
+        }
+    }
+}
+
+fn split_for_upload(file_path: &str) -> Result<UploadPieces> {
+    let file_size = std::fs::metadata(file_path)?.len();
+    if !needs_splitting(file_size) {
+        return Ok(UploadPieces::Whole(file_path.to_string()));
+    }
+    let dir = tempfile::tempdir()?;
+    let paths = split_wav_file(file_path, dir.path(), MAX_PIECE_BYTES)?;
+    info!("Split {} byte recording into {} pieces", file_size, paths.len());
+    Ok(UploadPieces::Split(dir, paths))
+}
+
+/// Frames that fit in a WAV piece of at most `max_bytes`, header included
+fn frames_per_piece(spec: &hound::WavSpec, max_bytes: u64) -> u64 {
+    let bytes_per_frame = u64::from(spec.channels) * ((u64::from(spec.bits_per_sample) + 7) / 8);
+    (max_bytes.saturating_sub(WAV_HEADER_BYTES) / bytes_per_frame.max(1)).max(1)
+}
+
+/// Split a WAV file into consecutive pieces of at most `max_bytes` each,
+/// written to `out_dir`. Returns the piece paths in playback order.
+fn split_wav_file(file_path: &str, out_dir: &Path, max_bytes: u64) -> Result<Vec<String>> {
+    let reader = hound::WavReader::open(file_path)?;
+    let samples_per_piece = frames_per_piece(&reader.spec(), max_bytes) * u64::from(reader.spec().channels);
+    match reader.spec().sample_format {
+        hound::SampleFormat::Int => copy_into_pieces::<i32>(reader, out_dir, samples_per_piece),
+        hound::SampleFormat::Float => copy_into_pieces::<f32>(reader, out_dir, samples_per_piece),
+    }
+}
+
+fn copy_into_pieces<S: hound::Sample>(
+    mut reader: hound::WavReader<std::io::BufReader<std::fs::File>>,
+    out_dir: &Path,
+    samples_per_piece: u64,
+) -> Result<Vec<String>> {
+    let spec = reader.spec();
+    let mut paths = Vec::new();
+    let mut writer: Option<hound::WavWriter<std::io::BufWriter<std::fs::File>>> = None;
+    let mut written = 0u64;
+
+    for sample in reader.samples::<S>() {
+        let sample = sample?;
+        let current = match writer.as_mut() {
+            Some(current) if written < samples_per_piece => current,
+            _ => {
+                if let Some(full) = writer.take() {
+                    full.finalize()?;
+                }
+                let path = out_dir.join(format!("piece_{:03}.wav", paths.len()));
+                paths.push(path.to_string_lossy().into_owned());
+                written = 0;
+                writer.insert(hound::WavWriter::create(path, spec)?)
+            }
+        };
+        current.write_sample(sample)?;
+        written += 1;
+    }
+    if let Some(last) = writer {
+        last.finalize()?;
+    }
+    Ok(paths)
+}
+
+/// Upload `file_path` to Whisper and return the response body in `response_format`
+async fn request_transcription(
+    file_path: &str,
+    endpoint: &TranscriptionEndpoint,
+    response_format: &str,
+) -> Result<String> {
+    info!("Transcribing {} with {} at {}", file_path, endpoint.model, endpoint.base_url);
     
     // Prepare file for upload
     let file_name = Path::new(file_path).file_name()
@@ -17,9 +321,9 @@ pub async fn transcribe_with_openai(file_path: &str, api_key: &str) -> Result<St
     let file_bytes = std::fs::read(file_path)?;
     info!("Audio file size: {} bytes", file_bytes.len());
     
-    // Check file size - Whisper has a 25MB limit
-    if file_bytes.len() > 24 * 1024 * 1024 {
-        return Err(anyhow!("Audio file exceeds size limit (24 MB). File size: {} MB", 
+    // Pieces are split below the limit, so this only guards against misuse
+    if file_bytes.len() as u64 > WHISPER_MAX_UPLOAD_BYTES {
+        return Err(anyhow!("Audio file exceeds the 25 MB upload limit. File size: {} MB",
             file_bytes.len() / (1024 * 1024)));
     }
     
@@ -39,11 +343,14 @@ pub async fn transcribe_with_openai(file_path: &str, api_key: &str) -> Result<St
             .part("file", multipart::Part::bytes(file_bytes.to_vec())
                 .file_name(file_name.to_string())
                 .mime_str("audio/wav")?)
-            .text("model", "whisper-1")
-            .text("response_format", "text");
+            .text("model", endpoint.model.clone())
+            .text("response_format", response_format.to_string());
         
-        let response_result = client.post("https://api.openai.com/v1/audio/transcriptions")
-            .header("Authorization", format!("Bearer {}", api_key))
+        let mut request = client.post(endpoint.transcriptions_url());
+        if !endpoint.api_key.is_empty() {
+            request = request.header("Authorization", format!("Bearer {}", endpoint.api_key));
+        }
+        let response_result = request
             .multipart(form)
             .send()
             .await;
@@ -69,7 +376,7 @@ pub async fn transcribe_with_openai(file_path: &str, api_key: &str) -> Result<St
                     }
                     
                     // For other errors, return immediately
-                    return Err(anyhow!("OpenAI API error {}: {}", status, error_text));
+                    return Err(anyhow!("Transcription API error {}: {}", status, error_text));
                 }
             },
             Err(err) => {
@@ -84,3 +391,144 @@ pub async fn transcribe_with_openai(file_path: &str, api_key: &str) -> Result<St
     
     Err(anyhow!("Failed to transcribe audio after multiple attempts"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcript(text: &str, duration: f64, segments: &[(f64, f64, &str)]) -> TimedTranscript {
+        TimedTranscript {
+            text: text.to_string(),
+            duration,
+            segments: segments
+                .iter()
+                .map(|(start, end, text)| TranscriptSegment { start: *start, end: *end, text: text.to_string() })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_transcriptions_url_is_under_the_base_url() {
+        let endpoint = TranscriptionEndpoint {
+            base_url: "https://api.groq.com/openai/v1".to_string(),
+            model: "whisper-large-v3".to_string(),
+            api_key: "gsk-test".to_string(),
+        };
+        assert_eq!(endpoint.transcriptions_url(), "https://api.groq.com/openai/v1/audio/transcriptions");
+    }
+
+    #[test]
+    fn test_cancel_flags_are_per_recording() {
+        start_transcription_run("/tmp/first.wav");
+        start_transcription_run("/tmp/second.wav");
+        cancel_transcription("/tmp/first.wav");
+        assert!(is_transcription_cancelled("/tmp/first.wav"));
+        assert!(!is_transcription_cancelled("/tmp/second.wav"));
+        end_transcription_run("/tmp/first.wav");
+        end_transcription_run("/tmp/second.wav");
+        assert!(!is_transcription_cancelled("/tmp/first.wav"));
+    }
+
+    #[test]
+    fn test_each_run_starts_uncancelled() {
+        start_transcription_run("/tmp/rerun.wav");
+        cancel_transcription("/tmp/rerun.wav");
+        end_transcription_run("/tmp/rerun.wav");
+        // Arrives after the run ended and must not carry over
+        cancel_transcription("/tmp/rerun.wav");
+
+        start_transcription_run("/tmp/rerun.wav");
+        assert!(!is_transcription_cancelled("/tmp/rerun.wav"));
+        end_transcription_run("/tmp/rerun.wav");
+    }
+
+    #[test]
+    fn test_append_offsets_later_pieces() {
+        let mut whole = TimedTranscript::default();
+        whole.append(transcript("Hello there.", 30.0, &[(0.0, 2.5, "Hello there.")]));
+        whole.append(transcript(" Second part.", 20.0, &[(1.0, 4.0, "Second part.")]));
+
+        assert_eq!(whole.text, "Hello there. Second part.");
+        assert_eq!(whole.duration, 50.0);
+        assert_eq!(whole.segments[1].start, 31.0);
+        assert_eq!(whole.segments[1].end, 34.0);
+    }
+
+    #[test]
+    fn test_parses_verbose_json() {
+        let body = r#"{"task":"transcribe","language":"english","duration":3.2,"text":"Hi.",
+            "segments":[{"id":0,"seek":0,"start":0.0,"end":3.2,"text":" Hi.","tokens":[1],"temperature":0.0}]}"#;
+        let parsed: TimedTranscript = serde_json::from_str(body).unwrap();
+        assert_eq!(parsed.segments.len(), 1);
+        assert_eq!(parsed.segments[0].end, 3.2);
+    }
+
+    #[test]
+    fn test_to_html_prefixes_segment_times() {
+        let timed = transcript("a b", 4000.0, &[(5.0, 6.0, " a < b"), (3725.0, 3726.0, "later")]);
+        assert_eq!(timed.to_html(), "<p>[0:05] a &lt; b</p><p>[1:02:05] later</p>");
+        assert_eq!(transcript("x & y", 1.0, &[]).to_html(), "x &amp; y");
+    }
+
+    #[test]
+    fn test_split_threshold_sits_under_the_upload_limit() {
+        const MB: u64 = 1024 * 1024;
+        assert!(!needs_splitting(20 * MB));
+        assert!(!needs_splitting(24 * MB));
+        assert!(needs_splitting(24 * MB + 1));
+        assert!(needs_splitting(25 * MB));
+    }
+
+    #[test]
+    fn test_split_wav_pieces_fit_and_keep_every_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("long.wav");
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&source, spec).unwrap();
+        for i in 0..10_000 {
+            writer.write_sample((i % 1000) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let max_bytes = 4_044; // 2000 frames per piece
+        let out = tempfile::tempdir().unwrap();
+        let pieces = split_wav_file(source.to_str().unwrap(), out.path(), max_bytes).unwrap();
+        assert_eq!(pieces.len(), 5);
+        let mut total = 0;
+        for piece in &pieces {
+            assert!(std::fs::metadata(piece).unwrap().len() <= max_bytes);
+            total += hound::WavReader::open(piece).unwrap().len();
+        }
+        assert_eq!(total, 10_000);
+    }
+
+    #[test]
+    fn test_validate_wav_rejects_empty_and_garbage_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 16000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+
+        let empty = dir.path().join("empty.wav");
+        hound::WavWriter::create(&empty, spec).unwrap().finalize().unwrap();
+        assert!(validate_wav(empty.to_str().unwrap()).is_err());
+
+        let garbage = dir.path().join("garbage.wav");
+        std::fs::write(&garbage, b"not a wav file").unwrap();
+        assert!(validate_wav(garbage.to_str().unwrap()).is_err());
+
+        let valid = dir.path().join("valid.wav");
+        let mut writer = hound::WavWriter::create(&valid, spec).unwrap();
+        writer.write_sample(1i16).unwrap();
+        writer.finalize().unwrap();
+        assert!(validate_wav(valid.to_str().unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,12 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// One captured activity in the history list
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ActivityHistoryItem {
+    pub id: i64,
+    /// Title of the window the activity was captured from
+    pub title: String,
+    pub timestamp: String,
+    /// Start of the activity's plain text, cut to `ACTIVITY_PREVIEW_CHARS`
+    pub preview: String,
+}
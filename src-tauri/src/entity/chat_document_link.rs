@@ -0,0 +1,20 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// A document saved from a message of a chat
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkedDocument {
+    pub document_id: i64,
+    pub document_name: String,
+    pub project_id: i64,
+    pub message_id: i64,
+    pub created_at: String,
+}
+
+/// A chat a document was saved from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LinkedChat {
+    pub chat_id: i64,
+    pub chat_name: String,
+    pub message_id: i64,
+    pub created_at: String,
+}
@@ -0,0 +1,10 @@
+use serde_derive::{Deserialize, Serialize};
+
+/// A document's `full_document_text` as it was before an edit replaced it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DocumentVersion {
+    pub id: i64,
+    pub document_id: i64,
+    pub full_document_text: String,
+    pub created_at: String,
+}
@@ -1,4 +1,7 @@
+pub mod activity;
 pub mod chat_item;
+pub mod chat_document_link;
+pub mod document_version;
 pub mod permission;
 pub mod setting;
 pub mod project;
@@ -8,4 +8,10 @@ pub struct Project {
     pub activity_ids: Vec<Option<i64>>,
     pub activity_names: Vec<String>,
     pub created_at: String,
+    /// Filled in by `fetch_all_projects`; ignored when the frontend sends a project
+    #[serde(default)]
+    pub document_count: i64,
+    /// Documents whose chunks are all in the project index
+    #[serde(default)]
+    pub vectorized_document_count: i64,
 }
\ No newline at end of file
@@ -0,0 +1,172 @@
+use async_openai::error::OpenAIError;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// Error returned from Tauri commands.
+///
+/// Serialized as `{ "code": "...", "message": "..." }` so the frontend can branch
+/// on the stable `code` (e.g. open Settings on `missing_api_key`) while still
+/// having a human-readable `message` to display. `invalid_settings` errors also
+/// carry a `fields` list so the settings form can flag each rejected input.
+#[derive(Debug, thiserror::Error)]
+pub enum HeelixError {
+    #[error("{0} API key is not configured. Please set it in Settings.")]
+    MissingApiKey(&'static str),
+    #[error("{0}")]
+    Network(String),
+    #[error("{provider} API error: {message}")]
+    Provider {
+        provider: &'static str,
+        message: String,
+    },
+    #[error("Database error: {0}")]
+    Database(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("Invalid settings: {}", .0.join(", "))]
+    InvalidSettings(Vec<String>),
+    #[error("{0}")]
+    Internal(String),
+}
+
+/// Fragments of provider error bodies that signal a temporary, server-side failure
+const RETRYABLE_PROVIDER_MARKERS: [&str; 8] = [
+    "overloaded",
+    "rate limit",
+    "rate_limit",
+    "resource_exhausted",
+    "unavailable",
+    "internal server error",
+    "api_error",
+    "bad gateway",
+];
+
+impl HeelixError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            HeelixError::MissingApiKey(_) => "missing_api_key",
+            HeelixError::Network(_) => "network",
+            HeelixError::Provider { .. } => "provider",
+            HeelixError::Database(_) => "database",
+            HeelixError::NotFound(_) => "not_found",
+            HeelixError::InvalidInput(_) => "invalid_input",
+            HeelixError::InvalidSettings(_) => "invalid_settings",
+            HeelixError::Internal(_) => "internal",
+        }
+    }
+
+    pub fn provider(provider: &'static str, message: impl Into<String>) -> Self {
+        HeelixError::Provider {
+            provider,
+            message: message.into(),
+        }
+    }
+
+    /// Whether another provider might succeed where this one failed: transport
+    /// failures and provider-side overload, rate limiting or outages. Stalled
+    /// streams are excluded because the partial answer has been kept.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            HeelixError::Network(_) => true,
+            HeelixError::Provider { message, .. } => {
+                let message = message.to_lowercase();
+                RETRYABLE_PROVIDER_MARKERS.iter().any(|marker| message.contains(marker))
+            }
+            _ => false,
+        }
+    }
+
+    /// Map an `async-openai` error, keeping transport failures distinct from API errors
+    pub fn from_openai(error: OpenAIError) -> Self {
+        match error {
+            OpenAIError::Reqwest(e) => HeelixError::from(e),
+            other => HeelixError::provider("OpenAI", other.to_string()),
+        }
+    }
+}
+
+impl Serialize for HeelixError {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut state = serializer.serialize_struct("HeelixError", 3)?;
+        state.serialize_field("code", self.code())?;
+        state.serialize_field("message", &self.to_string())?;
+        match self {
+            HeelixError::InvalidSettings(fields) => state.serialize_field("fields", fields)?,
+            _ => state.skip_field("fields")?,
+        }
+        state.end()
+    }
+}
+
+impl From<rusqlite::Error> for HeelixError {
+    fn from(error: rusqlite::Error) -> Self {
+        match error {
+            rusqlite::Error::QueryReturnedNoRows => {
+                HeelixError::NotFound("The requested item was not found".to_string())
+            }
+            other => HeelixError::Database(other.to_string()),
+        }
+    }
+}
+
+impl From<reqwest::Error> for HeelixError {
+    fn from(error: reqwest::Error) -> Self {
+        // Drop the URL so query-string credentials never reach the frontend
+        HeelixError::Network(format!("Request failed: {}", error.without_url()))
+    }
+}
+
+impl From<tauri::Error> for HeelixError {
+    fn from(error: tauri::Error) -> Self {
+        HeelixError::Internal(error.to_string())
+    }
+}
+
+pub type HeelixResult<T> = Result<T, HeelixError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serializes_code_and_message() {
+        let error = HeelixError::MissingApiKey("Claude");
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["code"], "missing_api_key");
+        assert_eq!(
+            json["message"],
+            "Claude API key is not configured. Please set it in Settings."
+        );
+    }
+
+    #[test]
+    fn test_invalid_settings_lists_fields() {
+        let error = HeelixError::InvalidSettings(vec!["interval".to_string()]);
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!(json["code"], "invalid_settings");
+        assert_eq!(json["fields"], serde_json::json!(["interval"]));
+    }
+
+    #[test]
+    fn test_retryable_errors() {
+        assert!(HeelixError::Network("Request failed".to_string()).is_retryable());
+        assert!(HeelixError::provider(
+            "Claude",
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#
+        )
+        .is_retryable());
+        assert!(HeelixError::provider("OpenAI", "Rate limit reached for gpt-5").is_retryable());
+        assert!(!HeelixError::provider("Claude", "prompt is too long").is_retryable());
+        assert!(!HeelixError::MissingApiKey("Gemini").is_retryable());
+    }
+
+    #[test]
+    fn test_no_rows_maps_to_not_found() {
+        let error = HeelixError::from(rusqlite::Error::QueryReturnedNoRows);
+        assert_eq!(error.code(), "not_found");
+    }
+}
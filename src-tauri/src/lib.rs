@@ -1,10 +1,340 @@
 pub mod entity;
 
+use pulldown_cmark::{html, Options, Parser};
 use scraper::{Html, Selector};
 
-/// Utility function to convert HTML to plain text using industrial-strength parser
+/// Render LLM markdown output as HTML for storage in `full_document_text`
+pub fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut output = String::new();
+    html::push_html(&mut output, Parser::new_ext(markdown, options));
+    output
+}
+
+/// Escape text for inclusion in document HTML
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Join `(name, html)` documents into one, each under an `<h2>` with its name
+/// and separated by a rule
+pub fn merge_html_documents(documents: &[(String, String)]) -> String {
+    documents
+        .iter()
+        .map(|(name, html)| format!("<h2>{}</h2>\n{}", escape_html(name), html))
+        .collect::<Vec<_>>()
+        .join("\n<hr>\n")
+}
+
+/// A title for markdown text: its first non-blank line without heading,
+/// quote or emphasis markers, cut to `max_chars` characters. None when the
+/// text has no such line.
+pub fn markdown_title(markdown: &str, max_chars: usize) -> Option<String> {
+    let line = markdown
+        .lines()
+        .map(|line| {
+            line.trim()
+                .trim_start_matches(|c| c == '#' || c == '>')
+                .trim()
+                .trim_matches(|c| c == '*' || c == '_' || c == '`')
+                .trim()
+        })
+        .find(|line| !line.is_empty())?;
+    let title: String = line.chars().take(max_chars).collect();
+    Some(title.trim_end().to_string())
+}
+
+/// Convert document HTML to markdown for chunking and embedding, keeping the
+/// headings, lists, task boxes, links, emphasis, quotes, code and data tables that
+/// `html_to_plain_text` flattens. Text without markup is returned as is.
+pub fn html_to_markdown(html: &str) -> String {
+    if !html.contains('<') {
+        return html.trim().to_string();
+    }
+    let document = Html::parse_document(html);
+    let body = Selector::parse("body").unwrap();
+    let root = document.select(&body).next().unwrap_or_else(|| document.root_element());
+
+    let mut output = String::new();
+    write_markdown_children(root, &mut output, MarkdownContext::default(), 0);
+    clean_markdown(&output)
+}
+
+/// Element nesting `html_to_markdown` follows before flattening to plain text
+const MAX_MARKDOWN_DEPTH: usize = 50;
+
+#[derive(Clone, Copy, Default)]
+struct MarkdownContext {
+    list_depth: usize,
+    in_pre: bool,
+}
+
+/// End the current block with a blank line
+fn push_block_break(output: &mut String) {
+    let kept = output.trim_end_matches(' ').len();
+    output.truncate(kept);
+    if output.is_empty() {
+        return;
+    }
+    while !output.ends_with("\n\n") {
+        output.push('\n');
+    }
+}
+
+fn push_line_break(output: &mut String) {
+    let kept = output.trim_end_matches(' ').len();
+    output.truncate(kept);
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+/// Markdown of `element`'s children with line breaks folded into spaces, for
+/// headings, links and table cells
+fn inline_markdown(element: scraper::ElementRef, context: MarkdownContext, depth: usize) -> String {
+    let mut inner = String::new();
+    write_markdown_children(element, &mut inner, context, depth);
+    inner.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Markdown of `element`'s children as standalone blocks, keeping one blank
+/// line between them
+fn block_markdown(element: scraper::ElementRef, context: MarkdownContext, depth: usize) -> String {
+    let mut inner = String::new();
+    write_markdown_children(element, &mut inner, context, depth);
+    clean_markdown(&inner)
+}
+
+/// `block_markdown` without the blank lines, for tight list items
+fn tight_block_markdown(element: scraper::ElementRef, context: MarkdownContext, depth: usize) -> String {
+    block_markdown(element, context, depth)
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn write_markdown_children(element: scraper::ElementRef, output: &mut String, context: MarkdownContext, depth: usize) {
+    use scraper::Node;
+
+    // Bound the recursion on deeply nested markup; what lies deeper is kept as plain text
+    if depth > MAX_MARKDOWN_DEPTH {
+        let text = element.text().collect::<String>();
+        let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !words.is_empty() {
+            if !output.is_empty() && !output.ends_with([' ', '\n']) {
+                output.push(' ');
+            }
+            output.push_str(&words);
+        }
+        return;
+    }
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) if context.in_pre => output.push_str(text),
+            Node::Text(text) => {
+                let words = text.split_whitespace().collect::<Vec<_>>().join(" ");
+                let starts_with_space = text.starts_with(char::is_whitespace);
+                if (starts_with_space || words.is_empty()) && !output.is_empty() && !output.ends_with([' ', '\n']) {
+                    output.push(' ');
+                }
+                output.push_str(&words);
+                if !words.is_empty() && text.ends_with(char::is_whitespace) {
+                    output.push(' ');
+                }
+            }
+            Node::Element(_) => {
+                if let Some(child_element) = scraper::ElementRef::wrap(child) {
+                    write_markdown_element(child_element, output, context, depth + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn write_markdown_element(element: scraper::ElementRef, output: &mut String, context: MarkdownContext, depth: usize) {
+    let tag = element.value().name();
+    // Code is kept verbatim; markup inside it only styles the text
+    if context.in_pre {
+        match tag {
+            "br" => output.push('\n'),
+            _ => output.extend(element.text()),
+        }
+        return;
+    }
+    match tag {
+        "script" | "style" | "head" | "title" | "img" => {}
+        "br" => push_line_break(output),
+        "hr" => {
+            push_block_break(output);
+            output.push_str("---");
+            push_block_break(output);
+        }
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let text = inline_markdown(element, context, depth);
+            if !text.is_empty() {
+                push_block_break(output);
+                output.push_str(&"#".repeat(tag[1..].parse().unwrap_or(1)));
+                output.push(' ');
+                output.push_str(&text);
+                push_block_break(output);
+            }
+        }
+        "strong" | "b" => wrap_inline(element, output, context, depth, "**"),
+        "em" | "i" => wrap_inline(element, output, context, depth, "*"),
+        "del" | "s" | "strike" => wrap_inline(element, output, context, depth, "~~"),
+        "input" if element.value().attr("type") == Some("checkbox") => {
+            output.push_str(if element.value().attr("checked").is_some() { "[x] " } else { "[ ] " });
+        }
+        "code" if !context.in_pre => wrap_inline(element, output, context, depth, "`"),
+        "a" => {
+            let text = inline_markdown(element, context, depth);
+            let href = element.value().attr("href").unwrap_or("").trim();
+            if href.is_empty() || href.starts_with('#') || href.starts_with("javascript:") {
+                output.push_str(&text);
+            } else if text.is_empty() || text == href {
+                output.push_str(&format!("<{}>", href));
+            } else {
+                output.push_str(&format!("[{}]({})", text, href));
+            }
+        }
+        "pre" => {
+            push_block_break(output);
+            output.push_str("```\n");
+            write_markdown_children(element, output, MarkdownContext { in_pre: true, ..context }, depth);
+            push_line_break(output);
+            output.push_str("```");
+            push_block_break(output);
+        }
+        "blockquote" => {
+            let quoted = block_markdown(element, context, depth);
+            if !quoted.is_empty() {
+                push_block_break(output);
+                for line in quoted.lines() {
+                    output.push_str(if line.is_empty() { ">" } else { "> " });
+                    output.push_str(line);
+                    output.push('\n');
+                }
+                push_block_break(output);
+            }
+        }
+        "ul" | "ol" => {
+            if context.list_depth == 0 {
+                push_block_break(output);
+            } else {
+                push_line_break(output);
+            }
+            let items = element
+                .children()
+                .filter_map(scraper::ElementRef::wrap)
+                .filter(|child| child.value().name() == "li");
+            for (index, item) in items.enumerate() {
+                let marker = if tag == "ol" { format!("{}. ", index + 1) } else { "- ".to_string() };
+                write_list_item(item, output, context, depth, &marker);
+            }
+            if context.list_depth == 0 {
+                push_block_break(output);
+            }
+        }
+        "li" => write_list_item(element, output, context, depth, "- "),
+        "table" => write_table(element, output, context, depth),
+        "p" | "div" | "section" | "article" | "header" | "footer" | "main" | "center" | "tr" | "td" | "th" => {
+            push_block_break(output);
+            write_markdown_children(element, output, context, depth);
+            push_block_break(output);
+        }
+        _ => write_markdown_children(element, output, context, depth),
+    }
+}
+
+fn wrap_inline(element: scraper::ElementRef, output: &mut String, context: MarkdownContext, depth: usize, marker: &str) {
+    let text = inline_markdown(element, context, depth);
+    if text.is_empty() {
+        return;
+    }
+    output.push_str(marker);
+    output.push_str(&text);
+    output.push_str(marker);
+}
+
+/// One list item; nested lists and extra paragraphs are indented by the
+/// marker's width, which markdown needs to keep them inside the item
+fn write_list_item(item: scraper::ElementRef, output: &mut String, context: MarkdownContext, depth: usize, marker: &str) {
+    let nested = MarkdownContext { list_depth: context.list_depth + 1, ..context };
+    let content = tight_block_markdown(item, nested, depth);
+    let indent = " ".repeat(marker.len());
+    push_line_break(output);
+    for (index, line) in content.lines().enumerate() {
+        if index == 0 {
+            output.push_str(marker);
+            output.push_str(line.trim_start());
+        } else {
+            output.push_str(&indent);
+            output.push_str(line);
+        }
+        output.push('\n');
+    }
+}
+
+/// Data tables become markdown tables. Email layouts nest tables or use
+/// single-cell rows for positioning, so those are read as plain blocks.
+fn write_table(table: scraper::ElementRef, output: &mut String, context: MarkdownContext, depth: usize) {
+    let row_selector = Selector::parse("tr").unwrap();
+    let nested_selector = Selector::parse("table").unwrap();
+    let rows: Vec<Vec<String>> = table
+        .select(&row_selector)
+        .map(|row| {
+            row.children()
+                .filter_map(scraper::ElementRef::wrap)
+                .filter(|cell| matches!(cell.value().name(), "td" | "th"))
+                .map(|cell| inline_markdown(cell, context, depth).replace('|', "\\|"))
+                .collect()
+        })
+        .collect();
+    let is_layout = table.select(&nested_selector).next().is_some() || rows.iter().all(|cells| cells.len() <= 1);
+    if is_layout {
+        push_block_break(output);
+        write_markdown_children(table, output, context, depth);
+        push_block_break(output);
+        return;
+    }
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    push_block_break(output);
+    for (index, cells) in rows.iter().filter(|cells| !cells.is_empty()).enumerate() {
+        let padded: Vec<&str> = (0..columns).map(|column| cells.get(column).map_or("", String::as_str)).collect();
+        output.push_str(&format!("| {} |\n", padded.join(" | ")));
+        if index == 0 {
+            output.push_str(&format!("|{}\n", " --- |".repeat(columns)));
+        }
+    }
+    push_block_break(output);
+}
+
+/// Trim trailing spaces and keep at most one blank line between blocks
+fn clean_markdown(markdown: &str) -> String {
+    let mut cleaned = String::new();
+    let mut blank_lines = 0;
+    for line in markdown.lines() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            blank_lines += 1;
+            continue;
+        }
+        if !cleaned.is_empty() {
+            cleaned.push_str(if blank_lines > 0 { "\n\n" } else { "\n" });
+        }
+        cleaned.push_str(line);
+        blank_lines = 0;
+    }
+    cleaned
+}
+
+/// Convert document HTML to plain text, one line per block. Never panics:
+/// html5ever recovers from any malformed markup, and the tree is walked
+/// without recursion so arbitrarily deep nesting keeps all of its text.
 pub fn html_to_plain_text(html: &str) -> String {
-    // Safety check for empty or invalid input
     if html.is_empty() {
         return String::new();
     }
@@ -24,22 +354,20 @@ fn extract_text_with_structure(document: &Html) -> String {
     let root_element = document.select(&main_selector).next()
         .unwrap_or_else(|| document.root_element());
     
-    extract_element_text(root_element, &mut result, 0);
+    extract_element_text(root_element, &mut result);
     
     // Clean up the result
     clean_extracted_text(&result)
 }
 
-fn extract_element_text(element: scraper::ElementRef, result: &mut String, depth: usize) {
+fn extract_element_text(root: scraper::ElementRef, result: &mut String) {
     use scraper::Node;
-    
-    // Prevent infinite recursion in malformed HTML
-    if depth > 50 {
-        return;
-    }
-    
-    for child in element.children() {
-        match child.value() {
+
+    // Nodes left to visit, last first; `true` marks leaving an element once
+    // its children are done
+    let mut stack: Vec<_> = root.children().rev().map(|child| (child, false)).collect();
+    while let Some((node, leaving)) = stack.pop() {
+        match node.value() {
             Node::Text(text) => {
                 let text_content = text.trim();
                 if !text_content.is_empty() {
@@ -51,47 +379,43 @@ fn extract_element_text(element: scraper::ElementRef, result: &mut String, depth
             }
             Node::Element(element_data) => {
                 let tag_name = element_data.name();
-                
+
+                if leaving {
+                    // Add spacing after certain elements
+                    match tag_name {
+                        "br" => result.push('\n'),
+                        "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "li" => {
+                            if !result.is_empty() && !result.ends_with('\n') {
+                                result.push('\n');
+                            }
+                        }
+                        "td" | "th" => {
+                            if !result.is_empty() && !result.ends_with(' ') && !result.ends_with('\n') {
+                                result.push(' ');
+                            }
+                        }
+                        _ => {}
+                    }
+                    continue;
+                }
+
                 // Skip script and style elements
                 if matches!(tag_name, "script" | "style") {
                     continue;
                 }
-                
+
                 // Add line breaks for block elements
-                let is_block_element = matches!(tag_name, 
-                    "div" | "p" | "br" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | 
+                let is_block_element = matches!(tag_name,
+                    "div" | "p" | "br" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" |
                     "li" | "ul" | "ol" | "blockquote" | "pre" | "hr" | "table" | "tr"
                 );
-                
+
                 if is_block_element && !result.is_empty() && !result.ends_with('\n') {
                     result.push('\n');
                 }
-                
-                // Recursively process child elements
-                if let Some(child_element) = scraper::ElementRef::wrap(child) {
-                    extract_element_text(child_element, result, depth + 1);
-                }
-                
-                // Add spacing after certain elements
-                match tag_name {
-                    "br" => result.push('\n'),
-                    "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
-                        if !result.is_empty() && !result.ends_with('\n') {
-                            result.push('\n');
-                        }
-                    }
-                    "li" => {
-                        if !result.is_empty() && !result.ends_with('\n') {
-                            result.push('\n');
-                        }
-                    }
-                    "td" | "th" => {
-                        if !result.is_empty() && !result.ends_with(' ') && !result.ends_with('\n') {
-                            result.push(' ');
-                        }
-                    }
-                    _ => {}
-                }
+
+                stack.push((node, true));
+                stack.extend(node.children().rev().map(|child| (child, false)));
             }
             _ => {}
         }
@@ -123,4 +447,172 @@ fn clean_extracted_text(text: &str) -> String {
     lines.join("\n")
         .trim()
         .to_string()
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EMAIL: &str = r#"<html><head><style>p { color: red; }</style></head><body>
+        <table width="100%"><tr><td>
+          <table><tr><td>
+            <h2>Quarterly   update</h2>
+            <p>Hi team,<br>revenue grew <strong>12%</strong> this quarter. See the
+               <a href="https://example.com/report">full report</a> or
+               <a href="https://example.com/faq">https://example.com/faq</a>.</p>
+            <ul>
+              <li>Hiring <em>paused</em></li>
+              <li>Budget approved
+                <ol><li>Marketing</li><li>Research</li></ol>
+              </li>
+            </ul>
+            <blockquote><p>Great work everyone.</p></blockquote>
+            <table>
+              <tr><th>Region</th><th>Revenue</th></tr>
+              <tr><td>EMEA</td><td>1.2M</td></tr>
+            </table>
+            <img src="https://tracker.example.com/pixel.gif">
+          </td></tr></table>
+        </td></tr></table>
+    </body></html>"#;
+
+    #[test]
+    fn test_html_email_keeps_links_and_structure() {
+        let markdown = html_to_markdown(EMAIL);
+        assert_eq!(
+            markdown,
+            "## Quarterly update\n\n\
+             Hi team,\nrevenue grew **12%** this quarter. See the [full report](https://example.com/report) or <https://example.com/faq>.\n\n\
+             - Hiring *paused*\n\
+             - Budget approved\n  \
+             1. Marketing\n  \
+             2. Research\n\n\
+             > Great work everyone.\n\n\
+             | Region | Revenue |\n\
+             | --- | --- |\n\
+             | EMEA | 1.2M |"
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested_html_keeps_its_text() {
+        let depth = 1_000;
+        let html = format!("{}deep text{}", "<div><span>".repeat(depth), "</span></div>".repeat(depth));
+        assert_eq!(html_to_plain_text(&html), "deep text");
+        assert_eq!(html_to_markdown(&html), "deep text");
+
+        // Unclosed, so every tag nests inside the one before
+        let html = format!("<p>top</p>{}bottom", "<blockquote><ul><li>".repeat(depth));
+        assert_eq!(html_to_plain_text(&html), "top\nbottom");
+        assert!(html_to_markdown(&html).ends_with("bottom"));
+    }
+
+    #[test]
+    fn test_malformed_html_does_not_panic() {
+        const PIECES: &[&str] = &[
+            "<div>", "</div>", "<p>", "</p>", "<table><tr><td>", "</td>", "<li>", "<ol>", "</ul>", "<b><i>",
+            "</b>", "<pre>", "<a href=\"#x\">", "<br/>", "<script>", "</script>", "<style>", "<!--", "-->",
+            "<", ">", "</", "<!DOCTYPE", "&amp", "&#x110000;", "&#0;", "\0", "<h7>", "<td>", "<tr>", "\u{e9}t\u{e9}",
+            "word ", "\n", "<html><body>", "</body></html>", "<![CDATA[", "]]>", "<svg><foreignObject>", "'\"",
+        ];
+        // A fixed xorshift sequence, so a failure is reproducible
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        for _ in 0..300 {
+            let length = (next() % 80) as usize;
+            let html: String = (0..length).map(|_| PIECES[(next() % PIECES.len() as u64) as usize]).collect();
+            let plain = html_to_plain_text(&html);
+            assert!(!plain.contains('\0'), "NUL kept for {:?}", html);
+            html_to_markdown(&html);
+        }
+    }
+
+    #[test]
+    fn test_text_without_markup_is_unchanged() {
+        assert_eq!(html_to_markdown("Line one\n\nLine two "), "Line one\n\nLine two");
+        assert_eq!(html_to_markdown(""), "");
+    }
+
+    #[test]
+    fn test_markdown_survives_a_round_trip_through_html() {
+        const DOCUMENTS: &[&str] = &[
+            "# Title\n\nParagraph with **bold**, *italic*, ~~struck~~ and `code`.",
+            "###### Six\n\n## Two",
+            "- one\n- two\n  - nested\n    - deeper\n- three",
+            "1. first\n2. second\n   1. inner\n   2. inner too\n3. third",
+            "- [ ] todo\n- [x] done",
+            "> quote line\n>\n> second paragraph",
+            "> - quoted item\n>\n> after the list",
+            "```\nfn main() {\n    println!(\"hi\");\n}\n```",
+            "Text with [link](https://a.example/x?y=1) and <https://b.example>.",
+            "| A | B |\n| --- | --- |\n| 1 | 2 |",
+            "before\n\n---\n\nafter",
+        ];
+        for markdown in DOCUMENTS {
+            assert_eq!(html_to_markdown(&markdown_to_html(markdown)), *markdown);
+        }
+    }
+
+    #[test]
+    fn test_links_keep_only_real_targets() {
+        assert_eq!(html_to_markdown("<a href=\"#top\">Top</a>"), "Top");
+        assert_eq!(html_to_markdown("<a href=\"javascript:void(0)\">Menu</a>"), "Menu");
+        assert_eq!(html_to_markdown("<a>No target</a>"), "No target");
+        assert_eq!(html_to_markdown("<a href=\" https://x.example \"></a>"), "<https://x.example>");
+        assert_eq!(
+            html_to_markdown("<h1><a href=\"https://x.example\">Linked</a>\n  title</h1>"),
+            "# [Linked](https://x.example) title"
+        );
+    }
+
+    #[test]
+    fn test_tables_escape_pipes_and_pad_short_rows() {
+        assert_eq!(
+            html_to_markdown("<table><tr><td>a|b</td><td>c</td></tr><tr><td>d</td></tr></table>"),
+            "| a\\|b | c |\n| --- | --- |\n| d |  |"
+        );
+        // Single-cell rows lay out a page rather than hold data
+        assert_eq!(html_to_markdown("<table><tr><td>only</td></tr><tr><td>cells</td></tr></table>"), "only\n\ncells");
+    }
+
+    #[test]
+    fn test_code_blocks_keep_whitespace_and_markup_is_decoded() {
+        assert_eq!(
+            html_to_markdown("<pre><code>a &lt; b\n  <b>indented</b></code></pre>"),
+            "```\na < b\n  indented\n```"
+        );
+        assert_eq!(html_to_markdown("<p>x &amp; y &lt;z&gt;</p>"), "x & y <z>");
+    }
+
+    #[test]
+    fn test_inline_markup_keeps_word_spacing() {
+        assert_eq!(html_to_markdown("<span>in</span><span>line</span>"), "inline");
+        assert_eq!(html_to_markdown("<p>one <b>two</b> <i>three</i></p>"), "one **two** *three*");
+        assert_eq!(html_to_markdown("<p><b>  </b>empty emphasis</p>"), "empty emphasis");
+        assert_eq!(html_to_markdown("<p>line<br>break</p><div>block</div><div>block</div>"), "line\nbreak\n\nblock\n\nblock");
+        assert_eq!(html_to_markdown("<script>alert(1)</script><style>p {}</style><p>kept</p>"), "kept");
+    }
+
+    #[test]
+    fn test_list_items_hold_their_paragraphs_and_stray_items_become_bullets() {
+        assert_eq!(
+            html_to_markdown("<ol><li><p>para one</p><p>para two</p></li><li>next</li></ol>"),
+            "1. para one\n   para two\n2. next"
+        );
+        assert_eq!(html_to_markdown("<li>stray</li><li>items</li>"), "- stray\n- items");
+    }
+
+    #[test]
+    fn test_markdown_title_takes_the_first_line_without_markers() {
+        assert_eq!(markdown_title("\n## **Launch plan**\n\nDetails", 80).as_deref(), Some("Launch plan"));
+        assert_eq!(markdown_title("> Quoted advice\nmore", 80).as_deref(), Some("Quoted advice"));
+        assert_eq!(markdown_title("Caf\u{e9} opening hours", 4).as_deref(), Some("Caf\u{e9}"));
+        assert_eq!(markdown_title("Two words", 4).as_deref(), Some("Two"));
+        assert_eq!(markdown_title(" \n#\n", 80), None);
+    }
+}
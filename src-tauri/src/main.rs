@@ -1,13 +1,16 @@
 // Prevents additional console window on Windows in release!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::collections::HashMap;
 use std::env;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use lazy_static::lazy_static;
-use log::info;
+use log::{error, info};
 use rusqlite::Connection;
-use serde_derive::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use tauri::api::dialog::{MessageDialogBuilder, MessageDialogKind};
 use tauri::utils::config::AppUrl;
 use tauri::SystemTray;
 use tauri::{AppHandle, Manager, State, SystemTrayEvent, WindowUrl};
@@ -15,37 +18,70 @@ use tauri::{CustomMenuItem, SystemTrayMenu};
 use tauri_plugin_log::LogTarget;
 use tokio::sync::Mutex;
 
-use configuration::settings::Settings;
+use configuration::settings::{Settings, DEFAULT_TRANSCRIPTION_BASE_URL};
 
 use crate::bootstrap::{fix_path_env, prerequisites, setup_directories};
+use crate::configuration::backup::{export_all, import_all};
+use crate::configuration::data_directory::{get_data_directory, set_data_directory};
 use crate::configuration::database;
 use crate::configuration::database::drop_database_handle;
+use crate::configuration::logging::redact_secrets;
+use crate::configuration::maintenance::{get_database_size, vacuum_database};
 use crate::configuration::state::{AppState, ServiceAccess};
-use crate::engine::chat_engine::{name_conversation, send_prompt_to_llm};
-use crate::engine::chat_engine_openai::{generate_conversation_name, send_prompt_to_openai};
-use crate::engine::chat_engine_gemini::{name_conversation_gemini, send_prompt_to_gemini};
-use crate::engine::chat_engine_local::{name_conversation_local, send_prompt_to_local};
+use crate::engine::chat_dispatch::{continue_response, name_conversation_unified, name_with_provider, resolve_model, send_prompt};
+use crate::engine::chat_engine::send_prompt_to_llm;
+use crate::engine::chat_engine_openai::send_prompt_to_openai;
+use crate::engine::chat_engine_gemini::send_prompt_to_gemini;
+use crate::engine::chat_engine_local::{send_prompt_to_local, test_local_endpoint};
+use crate::engine::rag_engine::preview_retrieval;
+use crate::engine::rate_limiter::configure_rate_limits;
 use crate::engine::clean_up_engine::clean_up;
 use crate::engine::document_cleanup_engine::clean_up_document_with_llm;
-use crate::engine::similarity_search_engine::SyncSimilaritySearch;
+use crate::engine::document_diff::{DiffGranularity, DiffSpan};
+use crate::engine::ocr_engine::{missing_ocr_tools, needs_ocr, ocr_install_hint, ocr_pdf};
+use crate::engine::document_format::{detect_document_format, is_docx_file, read_header, read_text_prefix, DocumentFormat};
+use crate::engine::email_document::email_to_text;
+use crate::engine::provider_status::get_providers_status;
+use crate::engine::similarity_search_engine::{SyncSimilaritySearch, EMBEDDING_BATCH_SIZE};
+use crate::engine::transcription_engine::TranscriptionEndpoint;
+use crate::entity::activity::ActivityHistoryItem;
+use crate::entity::chat_document_link::{LinkedChat, LinkedDocument};
 use crate::entity::chat_item::{Chat, StoredMessage};
+use crate::entity::document_version::DocumentVersion;
 use crate::entity::permission::Permission;
 use crate::entity::project::Project;
 use crate::entity::setting::Setting;
+use crate::error::{HeelixError, HeelixResult};
 use crate::permissions::permission_engine::init_permissions;
 use crate::repository::chat_db_repository;
-use crate::repository::chunk_repository::{save_chunks_for_document, get_chunk_full_text};
+use crate::repository::chat_document_link_repository::{self, link_message_to_document};
+use crate::repository::activity_repository;
+use crate::repository::document_text::compress_stored_documents;
+use crate::repository::document_version_repository::{self, get_document_version};
+use crate::repository::chunk_repository::{
+    save_chunks_for_document, is_excluded_from_rag, get_chunk_full_text, get_vectorized_chunk_ids_for_document,
+    get_vectorization_status_for_project, get_projects_with_unvectorized_chunks, get_unvectorized_chunks,
+    mark_chunk_as_vectorized, mark_project_chunks_unvectorized, get_chunk_count_for_document, get_chunks_by_ids, get_chunks_for_document, replace_chunk_text, ChunkWithContext, DocumentChunk, DocumentSource, DocumentVectorizationStatus,
+    UnvectorizedDocument,
+};
+use crate::engine::project_vector_engine::{
+    add_chunks_to_project_vectors, cancel_project_vectorization, clear_vectorization_cancel, delete_project_vectors,
+    get_project_vector_db, is_vectorization_cancelled, lock_project_vectors, remove_chunks_from_project_vectors,
+    sync_project_vectors,
+};
 use crate::repository::permissions_repository::{get_permissions, update_permission};
 use crate::repository::project_repository::{
-    delete_project, fetch_all_projects, add_blank_document, save_project, update_project, get_activity_text_from_project, get_activity_plain_text, get_project_id_for_document, update_activity_text, update_activity_name, delete_project_document, ensure_unassigned_project, move_document_to_project, get_all_documents,
+    self, delete_project, fetch_all_projects, add_blank_document, save_project, update_project, get_activity_text_from_project, get_activity_plain_text, get_project_id_for_document, update_activity_text, update_activity_name, backup_activity_text, take_activity_backup, delete_project_document, ensure_unassigned_project, move_document_to_project, get_all_documents, get_activity_full_text, find_document_by_name, add_document, content_fingerprint, find_duplicate_document, fetch_activities_by_project_id, set_exclude_from_rag,
+    touch_activity_text, get_dirty_document_ids, rechunk_dirty_document, document_search_pattern, DocumentMatch,
 };
-use crate::repository::settings_repository::{get_setting, get_settings, insert_or_update_setting};
+use crate::repository::settings_repository::{get_api_choice, get_close_to_tray, get_compress_documents, get_embedding_retries, get_max_import_bytes, get_ocr_scanned_pdfs, get_recording_sample_rate, get_reindex_delay_secs, get_setting_or_default, get_settings, get_transcription_endpoint, update_settings_batch};
 use tauri_plugin_autostart::MacosLauncher;
 
 mod bootstrap;
 mod configuration;
 mod engine;
 mod entity;
+mod error;
 pub mod permissions;
 mod repository;
 
@@ -61,6 +97,10 @@ const USE_LOCALHOST_SERVER: bool = true;
 
 lazy_static! {
     static ref HNSW: SyncSimilaritySearch = Arc::new(Mutex::new(None));
+    /// When each document waiting for its delayed re-index was last touched
+    static ref LAST_TOUCHED: std::sync::Mutex<HashMap<i64, Instant>> = Default::default();
+    /// Files being extracted, with whether their run was asked to stop
+    static ref RUNNING_EXTRACTIONS: std::sync::Mutex<HashMap<String, bool>> = Default::default();
 }
 
 //#[cfg(any(target_os = "macos"))]
@@ -69,7 +109,16 @@ lazy_static! {
 #[tokio::main]
 async fn main() {
     let port = 5173;
-    let mut builder = tauri::Builder::default().plugin(tauri_plugin_oauth::init());
+    let mut builder = tauri::Builder::default()
+        // Must be registered first: a second launch hands off to the running
+        // instance and exits before the rest of the app starts
+        .plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
+            info!("Another launch was started with {:?}, focusing the running instance", args);
+            if !args.contains(&"--minimized".to_string()) {
+                show_main_window(app);
+            }
+        }))
+        .plugin(tauri_plugin_oauth::init());
 
     fix_path_env::fix_all_vars().expect("Failed to load env");
     let tray = build_system_tray();
@@ -89,6 +138,15 @@ async fn main() {
         .plugin(
             tauri_plugin_log::Builder::default()
                 .targets([LogTarget::Stdout, LogTarget::Webview])
+                .format(|out, message, record| {
+                    out.finish(format_args!(
+                        "{}[{}][{}] {}",
+                        chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
+                        record.target(),
+                        record.level(),
+                        redact_secrets(&message.to_string())
+                    ))
+                })
                 .level_for("hnsw_rs", log::LevelFilter::Warn)  // Suppress noisy HNSW debug logs
                 .build(),
         )
@@ -106,8 +164,7 @@ async fn main() {
                 if window.is_visible().unwrap() {
                     window.hide().unwrap();
                 } else {
-                    window.show().unwrap();
-                    window.set_focus().unwrap();
+                    show_main_window(app);
                 }
             }
             SystemTrayEvent::MenuItemClick { id, .. } => match id.as_str() {
@@ -129,14 +186,23 @@ async fn main() {
         .invoke_handler(tauri::generate_handler![
             update_settings,
             get_latest_settings,
+            get_all_settings_typed,
+            get_data_directory,
+            set_data_directory,
+            get_database_size,
+            vacuum_database,
+            export_all,
+            import_all,
+            send_prompt,
+            continue_response,
             send_prompt_to_llm,
             send_prompt_to_openai,
             send_prompt_to_gemini,
             send_prompt_to_local,
-            generate_conversation_name,
-            name_conversation_gemini,
-            name_conversation_local,
-            name_conversation,
+            name_conversation_unified,
+            resolve_model,
+            test_local_endpoint,
+            get_providers_status,
             create_chat,
             get_all_chats,
             create_message,
@@ -149,32 +215,71 @@ async fn main() {
             update_app_project,
             delete_app_project,
             delete_chat,
+            save_message_as_document,
+            get_documents_for_chat,
+            get_chats_for_document,
             get_chunk_text,
+            get_chunk_with_context,
+            get_chunk_neighbors,
+            get_document_sources,
+            preview_retrieval,
             prompt_for_accessibility_permissions,
             get_app_project_activity_text,
             update_project_activity_text,
             vectorize_document_chunks,
+            vectorize_project,
+            rebuild_project_vectors,
+            cancel_vectorization,
+            warm_up_project_vectors,
+            get_project_vectorization_status,
+            get_unvectorized_documents,
+            retry_vectorization,
+            get_document_chunks,
+            update_chunk_text,
             add_project_blank_activity,
             update_project_activity_name,
+            set_document_exclude_from_rag,
             delete_project_activity,
+            merge_documents,
+            import_document,
             ensure_unassigned_activity,
             update_project_activity_content,
             get_app_project_activity_plain_text,
             get_all_project_documents,
             start_audio_recording,
+            start_live_transcription,
             stop_audio_recording,
             read_audio_file,
             transcribe_audio,
+            transcribe_audio_with_timestamps,
+            transcribe_to_document,
+            cancel_transcription,
             extract_document_text,
+            cancel_document_extraction,
             clean_up_document_with_llm,
+            clean_up_and_save_document,
+            restore_document_backup,
+            diff_documents,
+            get_document_versions,
+            restore_document_version,
+            get_activity_history,
+            touch_document,
+            search_within_document,
         ])
-        .manage(AppState {
-            db: Default::default(),
-        })
+        .manage(AppState::default())
         .on_window_event(|event| match event.event() {
             tauri::WindowEvent::CloseRequested { api, .. } => {
-                api.prevent_close();
-                event.window().hide().unwrap(); // Hide window on close
+                // Unless turned off, closing hides to the tray. Otherwise the close
+                // goes ahead and the app exits through the normal shutdown below.
+                let close_to_tray = event
+                    .window()
+                    .app_handle()
+                    .db(get_close_to_tray)
+                    .unwrap_or(true);
+                if close_to_tray {
+                    api.prevent_close();
+                    event.window().hide().unwrap(); // Hide window on close
+                }
             }
             _ => {}
         })
@@ -208,8 +313,24 @@ async fn main() {
                     .unwrap(),
             );
             clean_up(app_handle.path_resolver().app_data_dir().unwrap());
-            setup_keypress_listener(&app_handle);
-            init_app_permissions(app_handle);
+            if let Err(e) = setup_keypress_listener(&app_handle) {
+                // Data from a newer version, or a database that cannot be opened:
+                // say why and quit rather than run without storage
+                error!("Database initialization failed: {}", e);
+                MessageDialogBuilder::new("Heelix cannot open its data", e.to_string())
+                    .kind(MessageDialogKind::Error)
+                    .show(|_| std::process::exit(1));
+                return Ok(());
+            }
+            init_app_permissions(app_handle.clone());
+            tauri::async_runtime::spawn(async move {
+                if let Err(e) = reindex_dirty_documents(&app_handle).await {
+                    error!("Failed to re-index edited documents: {}", e);
+                }
+                if let Err(e) = resume_vectorization(app_handle).await {
+                    error!("Failed to resume vectorization: {}", e);
+                }
+            });
             Ok(())
         })
         .run(context)
@@ -217,6 +338,15 @@ async fn main() {
     drop_database_handle().await;
 }
 
+/// Bring the main window to the front, unhiding it from the tray if needed
+fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_window("main") {
+        if let Err(e) = window.unminimize().and_then(|_| window.show()).and_then(|_| window.set_focus()) {
+            error!("Failed to show the main window: {}", e);
+        }
+    }
+}
+
 fn build_system_tray() -> SystemTray {
     let quit = CustomMenuItem::new("quit".to_string(), "Quit");
     let tray_menu = SystemTrayMenu::new()
@@ -224,12 +354,13 @@ fn build_system_tray() -> SystemTray {
     SystemTray::new().with_menu(tray_menu)
 }
 
-fn setup_keypress_listener(app_handle: &AppHandle) {
+fn setup_keypress_listener(app_handle: &AppHandle) -> Result<(), Box<dyn std::error::Error>> {
     let app_state: State<AppState> = app_handle.state();
 
-    let db: Connection =
-        database::initialize_database(&app_handle).expect("Database initialization failed!");
+    let db: Connection = database::initialize_database(&app_handle)?;
+    configure_rate_limits(&Settings::from_setting_rows(&get_settings(&db)?));
     *app_state.db.lock().unwrap() = Some(db);
+    Ok(())
 }
 
 #[tauri::command]
@@ -238,91 +369,38 @@ fn get_latest_settings(app_handle: AppHandle) -> Result<Vec<Setting>, ()> {
     return Ok(settings);
 }
 
+/// Settings as one typed object, with defaults filled in for missing keys.
+/// `get_latest_settings` still returns the raw rows.
 #[tauri::command]
-async fn update_settings(app_handle: AppHandle, settings: Settings) {
-    info!("update_settings: {:?}", settings);
-    app_handle.db(|db| {
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("interval"),
-                setting_value: format!("{}", settings.interval),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("is_dev_mode"),
-                setting_value: format!("{}", settings.is_dev_mode),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("auto_start"),
-                setting_value: format!("{}", settings.auto_start),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("api_choice"),
-                setting_value: format!("{}", settings.api_choice),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("api_key_claude"),
-                setting_value: format!("{}", settings.api_key_claude),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("api_key_open_ai"),
-                setting_value: format!("{}", settings.api_key_open_ai),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("api_key_gemini"),
-                setting_value: format!("{}", settings.api_key_gemini),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("local_model_url"),
-                setting_value: format!("{}", settings.local_model_url),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("vectorization_enabled"),
-                setting_value: format!("{}", settings.vectorization_enabled),
-            },
-        )
-        .unwrap();
-        insert_or_update_setting(
-            db,
-            Setting {
-                setting_key: String::from("rag_top_k"),
-                setting_value: format!("{}", settings.rag_top_k),
-            },
-        )
-        .unwrap();
-    });
+fn get_all_settings_typed(app_handle: AppHandle) -> HeelixResult<Settings> {
+    let rows = app_handle.db(get_settings)?;
+    Ok(Settings::from_setting_rows(&rows))
+}
+
+#[tauri::command]
+async fn update_settings(app_handle: AppHandle, settings: Settings) -> HeelixResult<Settings> {
+    let rejected = settings.invalid_fields();
+    if !rejected.is_empty() {
+        return Err(HeelixError::InvalidSettings(rejected));
+    }
+    info!(
+        "update_settings: api_choice={}, vectorization_enabled={}, rag_top_k={}",
+        settings.api_choice, settings.vectorization_enabled, settings.rag_top_k
+    );
+    let was_compressing = app_handle.db(get_compress_documents)?;
+    let written = app_handle.db_mut(|db| update_settings_batch(db, settings.to_setting_rows()))?;
+    info!("update_settings: {} setting(s) changed", written);
+    if settings.compress_documents && !was_compressing {
+        // Documents saved while compression was off
+        app_handle.db_mut(|db| {
+            let tx = db.transaction()?;
+            compress_stored_documents(&tx)?;
+            tx.commit()
+        })?;
+    }
+    let saved = get_all_settings_typed(app_handle)?;
+    configure_rate_limits(&saved);
+    Ok(saved)
 }
 
 #[tauri::command]
@@ -377,17 +455,17 @@ fn delete_app_project(app_handle: AppHandle, project_id: i64) -> Result<i64, ()>
 }
 
 #[tauri::command]
-fn create_chat(app_handle: AppHandle, name: &str) -> Result<i64, String> {
+fn create_chat(app_handle: AppHandle, name: &str) -> HeelixResult<i64> {
     app_handle
         .db(|db| chat_db_repository::create_chat(db, name))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
 }
 
 #[tauri::command]
-fn get_all_chats(app_handle: AppHandle) -> Result<Vec<Chat>, String> {
+fn get_all_chats(app_handle: AppHandle) -> HeelixResult<Vec<Chat>> {
     app_handle
         .db(|db| chat_db_repository::get_all_chats(db))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
 }
 
 #[tauri::command]
@@ -397,41 +475,138 @@ fn create_message(
     role: &str,
     content: &str,
     sources: Option<String>,
-) -> Result<i64, String> {
+) -> HeelixResult<i64> {
     app_handle
         .db(|db| chat_db_repository::create_message(db, chat_id, role, content, sources.as_deref()))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
 }
 
 #[tauri::command]
 fn get_messages_by_chat_id(
     app_handle: AppHandle,
     chat_id: i64,
-) -> Result<Vec<StoredMessage>, String> {
+) -> HeelixResult<Vec<StoredMessage>> {
     app_handle
         .db(|db| chat_db_repository::get_messages_by_chat_id(db, chat_id))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
 }
 
 #[tauri::command]
-fn update_chat_name(app_handle: AppHandle, chat_id: i64, name: &str) -> Result<bool, String> {
+fn update_chat_name(app_handle: AppHandle, chat_id: i64, name: &str) -> HeelixResult<bool> {
     app_handle
         .db(|db| chat_db_repository::update_chat(db, chat_id, name))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
 }
 
 #[tauri::command]
-fn delete_chat(app_handle: AppHandle, chat_id: i64) -> Result<bool, String> {
+fn delete_chat(app_handle: AppHandle, chat_id: i64) -> HeelixResult<bool> {
     app_handle
         .db(|db| chat_db_repository::delete_chat(db, chat_id))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
+}
+
+/// Longest name given to a document saved from a chat message
+const SAVED_MESSAGE_NAME_CHARS: usize = 80;
+
+/// Save an assistant message as a document of `project_id`, or of the
+/// Unassigned project when none is given, and link it to the message's chat.
+/// The document is named after the message's first line and indexed like an
+/// import. Returns the new document's id.
+#[tauri::command]
+async fn save_message_as_document(
+    app_handle: AppHandle,
+    message_id: i64,
+    project_id: Option<i64>,
+) -> HeelixResult<i64> {
+    let message = app_handle
+        .db(|db| chat_db_repository::get_message(db, message_id))?
+        .ok_or_else(|| HeelixError::NotFound(format!("Message {} not found", message_id)))?;
+    if message.role != "assistant" {
+        return Err(HeelixError::InvalidInput("Only assistant messages can be saved as documents".to_string()));
+    }
+    if message.content.trim().is_empty() {
+        return Err(HeelixError::InvalidInput("The message is empty".to_string()));
+    }
+
+    let full_document_text = heelix::markdown_to_html(&message.content);
+    let (project_id, document_id) = app_handle.db_mut(|db| {
+        let name = match heelix::markdown_title(&message.content, SAVED_MESSAGE_NAME_CHARS) {
+            Some(title) => title,
+            None => chat_db_repository::get_chat_name(db, message.chat_id)?,
+        };
+        // A document is only kept together with its link to the message
+        let tx = db.transaction()?;
+        let project_id = match project_id {
+            Some(id) => id,
+            None => ensure_unassigned_project(&tx)?,
+        };
+        let document_id = add_document(&tx, project_id, &name, &full_document_text)?;
+        link_message_to_document(&tx, message.chat_id, message.id, document_id)?;
+        tx.commit()?;
+        Ok::<_, rusqlite::Error>((project_id, document_id))
+    })?;
+    replace_document_text(&app_handle, document_id, &full_document_text)?;
+    info!("Saved message {} of chat {} as document {} in project {}", message.id, message.chat_id, document_id, project_id);
+    Ok(document_id)
 }
 
+/// Documents saved from a chat's messages
 #[tauri::command]
-fn get_chunk_text(app_handle: AppHandle, chunk_id: i64) -> Result<Option<String>, String> {
+fn get_documents_for_chat(app_handle: AppHandle, chat_id: i64) -> HeelixResult<Vec<LinkedDocument>> {
+    app_handle
+        .db(|db| chat_document_link_repository::get_documents_for_chat(db, chat_id))
+        .map_err(HeelixError::from)
+}
+
+/// Chats a document was saved from
+#[tauri::command]
+fn get_chats_for_document(app_handle: AppHandle, document_id: i64) -> HeelixResult<Vec<LinkedChat>> {
+    app_handle
+        .db(|db| chat_document_link_repository::get_chats_for_document(db, document_id))
+        .map_err(HeelixError::from)
+}
+
+#[tauri::command]
+fn get_chunk_text(app_handle: AppHandle, chunk_id: i64) -> HeelixResult<Option<String>> {
     app_handle
         .db(|db| get_chunk_full_text(db, chunk_id))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
+}
+
+/// One citation per document for the given chunks, e.g. to regroup the
+/// per-chunk sources saved with older messages
+#[tauri::command]
+fn get_document_sources(app_handle: AppHandle, chunk_ids: Vec<i64>) -> HeelixResult<Vec<DocumentSource>> {
+    app_handle
+        .db(|db| crate::repository::chunk_repository::get_document_sources(db, &chunk_ids))
+        .map_err(HeelixError::from)
+}
+
+/// Full text of a chunk plus its neighbouring chunks, for expanding a citation.
+/// `radius` is the number of chunks on each side and defaults to 1.
+#[tauri::command]
+fn get_chunk_with_context(
+    app_handle: AppHandle,
+    chunk_id: i64,
+    radius: Option<i32>,
+) -> HeelixResult<ChunkWithContext> {
+    let radius = radius.unwrap_or(1).max(0);
+    app_handle
+        .db(|db| crate::repository::chunk_repository::get_chunk_with_context(db, chunk_id, radius))?
+        .ok_or_else(|| HeelixError::NotFound(format!("Chunk {} not found", chunk_id)))
+}
+
+/// A chunk plus `window` chunks before and after it in its document, in document
+/// order, for widening a retrieved passage. `window` defaults to 1.
+#[tauri::command]
+fn get_chunk_neighbors(app_handle: AppHandle, chunk_id: i64, window: Option<i32>) -> HeelixResult<Vec<DocumentChunk>> {
+    let window = window.unwrap_or(1).max(0);
+    let chunks = app_handle
+        .db(|db| crate::repository::chunk_repository::get_chunk_neighbors(db, chunk_id, window))?;
+    if chunks.is_empty() {
+        return Err(HeelixError::NotFound(format!("Chunk {} not found", chunk_id)));
+    }
+    Ok(chunks)
 }
 
 #[tauri::command]
@@ -455,34 +630,60 @@ fn get_app_project_activity_text(
 fn get_app_project_activity_plain_text(
     app_handle: AppHandle,
     activity_id: i64,
-) -> Result<(String, String), String> {
+) -> HeelixResult<(String, String)> {
     app_handle
         .db(|database| get_activity_plain_text(database, activity_id))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
+}
+
+/// Find `query` in a document's plain text, ignoring case. `whole_word` only
+/// matches whole words and `regex` reads the query as a regular expression.
+#[tauri::command]
+fn search_within_document(
+    app_handle: AppHandle,
+    activity_id: i64,
+    query: String,
+    whole_word: Option<bool>,
+    regex: Option<bool>,
+) -> HeelixResult<Vec<DocumentMatch>> {
+    if query.is_empty() {
+        return Err(HeelixError::InvalidInput("The search is empty".to_string()));
+    }
+    let pattern = document_search_pattern(&query, whole_word.unwrap_or(false), regex.unwrap_or(false))
+        .map_err(|e| HeelixError::InvalidInput(format!("Invalid search pattern: {}", e)))?;
+    app_handle
+        .db(|db| project_repository::search_within_document(db, activity_id, &pattern))
+        .map_err(HeelixError::from)
 }
 
 /// Get all documents across all projects for the "Add content to Heelix" modal
 #[tauri::command]
 fn get_all_project_documents(
     app_handle: AppHandle,
-) -> Result<Vec<(i64, String, String, String)>, String> {
+) -> HeelixResult<Vec<(i64, String, String, String)>> {
     app_handle
         .db(|database| get_all_documents(database))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
 }
 
 #[tauri::command]
-fn update_project_activity_content(
+async fn update_project_activity_content(
     app_handle: AppHandle,
     document_id: i64,
     target_project_id: i64,
-) -> Result<(), String> {
-    app_handle
-        .db(|database| {
-            move_document_to_project(database, document_id, target_project_id)
-                .map_err(|e| e.to_string())
-        })
-        .map_err(|e| e.to_string())
+) -> HeelixResult<()> {
+    let (source_project_id, chunk_ids) = app_handle.db(|db| {
+        let project_id = get_project_id_for_document(db, document_id)?;
+        let chunk_ids = get_vectorized_chunk_ids_for_document(db, document_id)?;
+        Ok::<(i64, Vec<i64>), rusqlite::Error>((project_id, chunk_ids))
+    })?;
+    app_handle.db(|database| move_document_to_project(database, document_id, target_project_id))?;
+
+    // The chunks are re-vectorized into the target project on its next run
+    if source_project_id != target_project_id {
+        remove_document_vectors(&app_handle, source_project_id, &chunk_ids).await;
+    }
+    Ok(())
 }
 
 #[tauri::command]
@@ -490,30 +691,238 @@ fn update_project_activity_text(
     app_handle: AppHandle,
     activity_id: i64,
     text: &str,
-) -> Result<(), String> {
-    // Update the document text (this also generates plain_text)
-    app_handle
-        .db(|db| update_activity_text(db, activity_id, text))
-        .map_err(|e| e.to_string())?;
-    
-    // Get project_id and plain_text for chunking
-    let (project_id, plain_text) = app_handle
-        .db(|db| {
-            let project_id = get_project_id_for_document(db, activity_id)?;
-            let (_, plain_text) = get_activity_plain_text(db, activity_id)?;
-            Ok::<(i64, String), rusqlite::Error>((project_id, plain_text))
-        })
-        .map_err(|e| e.to_string())?;
-    
-    // Create chunks for the document (for RAG)
-    app_handle
-        .db(|db| save_chunks_for_document(db, activity_id, project_id, &plain_text))
-        .map_err(|e| e.to_string())?;
+) -> HeelixResult<()> {
+    save_document_text(&app_handle, activity_id, text)
+}
+
+/// Save a document's text while it is being edited and return at once. The
+/// chunks are left as they are and the document is marked dirty; it is
+/// re-chunked and re-vectorized once it has gone `reindex_delay_secs` without
+/// another touch, or at the next startup if the app closes first.
+#[tauri::command]
+fn touch_document(app_handle: AppHandle, activity_id: i64, text: &str) -> HeelixResult<()> {
+    let delay = app_handle.db_mut(|db| {
+        // The version snapshot and the new text are stored together
+        let tx = db.transaction()?;
+        touch_activity_text(&tx, activity_id, text)?;
+        tx.commit()?;
+        get_reindex_delay_secs(db)
+    })?;
+    let touched_at = Instant::now();
+    LAST_TOUCHED.lock().unwrap().insert(activity_id, touched_at);
+
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(Duration::from_secs(delay as u64)).await;
+        {
+            let mut last_touched = LAST_TOUCHED.lock().unwrap();
+            // A later touch restarted the wait
+            if last_touched.get(&activity_id) != Some(&touched_at) {
+                return;
+            }
+            last_touched.remove(&activity_id);
+        }
+        reindex_touched_document(handle, activity_id).await;
+    });
+    Ok(())
+}
+
+/// Re-chunk a touched document from its saved text and re-vectorize it. The
+/// vectors of the chunks it replaces are dropped first. Does nothing when the
+/// document is no longer dirty or was deleted.
+async fn reindex_touched_document(app_handle: AppHandle, activity_id: i64) {
+    let rechunked = app_handle.db_mut(|db| {
+        // The new chunks replace the old ones and clear the dirty flag together, or not at all
+        let tx = db.transaction()?;
+        let stale_chunk_ids = get_vectorized_chunk_ids_for_document(&tx, activity_id)?;
+        let project_id = rechunk_dirty_document(&tx, activity_id)?;
+        tx.commit()?;
+        Ok::<_, rusqlite::Error>(project_id.map(|project_id| (project_id, stale_chunk_ids)))
+    });
+    let (project_id, stale_chunk_ids) = match rechunked {
+        Ok(Some(rechunked)) => rechunked,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Re-chunking document {} failed: {}", activity_id, e);
+            return;
+        }
+    };
+    info!("Document {} re-chunked after editing", activity_id);
+    remove_document_vectors(&app_handle, project_id, &stale_chunk_ids).await;
+    if let Err(e) = vectorize_document_chunks(app_handle, activity_id).await {
+        error!("Re-vectorization of document {} failed: {}", activity_id, e);
+    }
+}
+
+/// Re-index the documents touched right before the app last closed
+async fn reindex_dirty_documents(app_handle: &AppHandle) -> HeelixResult<()> {
+    for activity_id in app_handle.db(get_dirty_document_ids)? {
+        reindex_touched_document(app_handle.clone(), activity_id).await;
+    }
+    Ok(())
+}
+
+/// Store a document's text and rebuild its chunks for RAG
+fn save_document_text(app_handle: &AppHandle, activity_id: i64, text: &str) -> HeelixResult<()> {
+    // The text is saved and re-chunked together, since saving clears the
+    // dirty flag that would otherwise have the chunks rebuilt later
+    app_handle.db_mut(|db| {
+        let tx = db.transaction()?;
+        // Update the document text (this also generates plain_text)
+        update_activity_text(&tx, activity_id, text)?;
+
+        // Get project_id and plain_text for chunking
+        let project_id = get_project_id_for_document(&tx, activity_id)?;
+        let (_, plain_text) = get_activity_plain_text(&tx, activity_id)?;
+
+        // Create chunks for the document (for RAG)
+        save_chunks_for_document(&tx, activity_id, project_id, &plain_text)?;
+        tx.commit()
+    })?;
     
     info!("Document {} updated and chunked", activity_id);
     Ok(())
 }
 
+#[derive(Serialize)]
+struct SavedDocumentText {
+    full_document_text: String,
+    plain_text: String,
+}
+
+/// Clean a document up with `provider` and save the result in place: the text
+/// is rewritten, re-chunked and queued for re-vectorization. The previous text
+/// is kept as a backup for `restore_document_backup`.
+#[tauri::command]
+async fn clean_up_and_save_document(
+    app_handle: AppHandle,
+    activity_id: i64,
+    provider: String,
+    model_id: Option<String>,
+) -> HeelixResult<SavedDocumentText> {
+    let (_, plain_text) = app_handle.db(|db| get_activity_plain_text(db, activity_id))?;
+    let cleaned_markdown =
+        clean_up_document_with_llm(app_handle.clone(), plain_text, provider, model_id).await?;
+    if cleaned_markdown.trim().is_empty() {
+        return Err(HeelixError::Internal("Cleanup returned an empty document".to_string()));
+    }
+
+    app_handle.db(|db| backup_activity_text(db, activity_id))?;
+    let full_document_text = heelix::markdown_to_html(&cleaned_markdown);
+    let saved = replace_document_text(&app_handle, activity_id, &full_document_text)?;
+    info!("Document {} cleaned up and saved", activity_id);
+    Ok(saved)
+}
+
+/// Compare a document's text with a cleaned-up version before it is saved,
+/// so the changes can be reviewed. Word by word unless `granularity` is "line".
+#[tauri::command]
+fn diff_documents(original: String, cleaned: String, granularity: Option<DiffGranularity>) -> Vec<DiffSpan> {
+    crate::engine::document_diff::diff_documents(&original, &cleaned, granularity.unwrap_or_default())
+}
+
+/// Put back the text a document had before its last cleanup. The backup is
+/// consumed, so a second call fails with NotFound.
+#[tauri::command]
+async fn restore_document_backup(
+    app_handle: AppHandle,
+    activity_id: i64,
+) -> HeelixResult<SavedDocumentText> {
+    let backup = app_handle
+        .db_mut(|db| take_activity_backup(db, activity_id))?
+        .ok_or_else(|| HeelixError::NotFound(format!("No backup for document {}", activity_id)))?;
+    let restored = replace_document_text(&app_handle, activity_id, &backup)?;
+    info!("Document {} restored from backup", activity_id);
+    Ok(restored)
+}
+
+/// Page of captured activities, newest first, each with a short text preview
+#[tauri::command]
+fn get_activity_history(app_handle: AppHandle, offset: i64, limit: i64) -> HeelixResult<Vec<ActivityHistoryItem>> {
+    app_handle
+        .db(|db| activity_repository::get_activity_history(db, offset, limit))
+        .map_err(HeelixError::from)
+}
+
+/// Earlier texts of a document, newest first
+#[tauri::command]
+fn get_document_versions(app_handle: AppHandle, activity_id: i64) -> HeelixResult<Vec<DocumentVersion>> {
+    app_handle
+        .db(|db| document_version_repository::get_document_versions(db, activity_id))
+        .map_err(HeelixError::from)
+}
+
+/// Bring back a saved version of a document. The text being replaced is
+/// itself kept as a version, so a restore can be undone the same way.
+#[tauri::command]
+async fn restore_document_version(
+    app_handle: AppHandle,
+    version_id: i64,
+) -> HeelixResult<SavedDocumentText> {
+    let version = app_handle
+        .db(|db| get_document_version(db, version_id))?
+        .ok_or_else(|| HeelixError::NotFound(format!("Document version {}", version_id)))?;
+    let restored = replace_document_text(&app_handle, version.document_id, &version.full_document_text)?;
+    info!("Document {} restored to version {}", version.document_id, version_id);
+    Ok(restored)
+}
+
+/// Save new text for a document, then re-vectorize it in the background. The
+/// old chunks' vectors are dropped first since their chunk rows are replaced.
+fn replace_document_text(
+    app_handle: &AppHandle,
+    activity_id: i64,
+    full_document_text: &str,
+) -> HeelixResult<SavedDocumentText> {
+    let (project_id, stale_chunk_ids) = app_handle.db(|db| {
+        let project_id = get_project_id_for_document(db, activity_id)?;
+        let chunk_ids = get_vectorized_chunk_ids_for_document(db, activity_id)?;
+        Ok::<(i64, Vec<i64>), rusqlite::Error>((project_id, chunk_ids))
+    })?;
+    save_document_text(app_handle, activity_id, full_document_text)?;
+    let (_, plain_text) = app_handle.db(|db| get_activity_plain_text(db, activity_id))?;
+
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        remove_document_vectors(&handle, project_id, &stale_chunk_ids).await;
+        if let Err(e) = vectorize_document_chunks(handle, activity_id).await {
+            error!("Re-vectorization of document {} failed: {}", activity_id, e);
+        }
+    });
+
+    Ok(SavedDocumentText {
+        full_document_text: full_document_text.to_string(),
+        plain_text,
+    })
+}
+
+/// Create a document and its chunks in one transaction, so a failure leaves
+/// no empty document behind, then vectorize it in the background. Returns the
+/// new document's id.
+fn create_document(
+    app_handle: &AppHandle,
+    project_id: i64,
+    name: &str,
+    full_document_text: &str,
+) -> HeelixResult<i64> {
+    let document_id = app_handle.db_mut(|db| {
+        let tx = db.transaction()?;
+        let document_id = add_document(&tx, project_id, name, full_document_text)?;
+        let (_, plain_text) = get_activity_plain_text(&tx, document_id)?;
+        save_chunks_for_document(&tx, document_id, project_id, &plain_text)?;
+        tx.commit()?;
+        Ok::<_, rusqlite::Error>(document_id)
+    })?;
+
+    let handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = vectorize_document_chunks(handle, document_id).await {
+            error!("Vectorization of document {} failed: {}", document_id, e);
+        }
+    });
+    Ok(document_id)
+}
+
 /// Vectorize all unvectorized chunks for a document
 /// Called after document is saved when vectorization is enabled
 /// Uses per-project vector indices for proper scoping
@@ -521,16 +930,12 @@ fn update_project_activity_text(
 async fn vectorize_document_chunks(
     app_handle: AppHandle,
     document_id: i64,
-) -> Result<i32, String> {
-    use crate::repository::chunk_repository::mark_chunk_as_vectorized;
-    use crate::engine::project_vector_engine::{add_chunk_to_project_vectors, sync_project_vectors};
-    use log::{info, error};
+) -> HeelixResult<i32> {
+    use log::info;
     
     // Check if vectorization is enabled
-    let vectorization_enabled = app_handle
-        .db(|db| get_setting(db, "vectorization_enabled"))
-        .map(|s| s.setting_value == "true")
-        .unwrap_or(false);
+    let vectorization_enabled =
+        app_handle.db(|db| get_setting_or_default(db, "vectorization_enabled", "false")) == "true";
     
     if !vectorization_enabled {
         info!("Vectorization disabled, skipping for document {}", document_id);
@@ -538,20 +943,23 @@ async fn vectorize_document_chunks(
     }
     
     // Get OpenAI API key
-    let api_key = app_handle
-        .db(|db| get_setting(db, "api_key_open_ai"))
-        .map(|s| s.setting_value)
-        .unwrap_or_default();
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
     
     if api_key.is_empty() {
         info!("No OpenAI API key, skipping vectorization for document {}", document_id);
         return Ok(0);
     }
     
+    if app_handle.db(|db| is_excluded_from_rag(db, document_id))? {
+        info!("Document {} is excluded from RAG, skipping vectorization", document_id);
+        return Ok(0);
+    }
+
     // Get project_id for the document
-    let project_id = app_handle
-        .db(|db| get_project_id_for_document(db, document_id))
-        .map_err(|e| e.to_string())?;
+    let project_id = app_handle.db(|db| get_project_id_for_document(db, document_id))?;
+    
+    // Serialize vectorization per project until the index has been synced
+    let _project_lock = lock_project_vectors(project_id).await;
     
     // Get unvectorized chunks for this document
     let chunks = app_handle
@@ -577,8 +985,7 @@ async fn vectorize_document_chunks(
             )?.collect::<Result<Vec<_>, _>>()?;
             
             Ok::<Vec<crate::repository::chunk_repository::DocumentChunk>, rusqlite::Error>(chunks)
-        })
-        .map_err(|e| e.to_string())?;
+        })?;
     
     if chunks.is_empty() {
         info!("No chunks to vectorize for document {}", document_id);
@@ -586,60 +993,409 @@ async fn vectorize_document_chunks(
     }
     
     info!("Vectorizing {} chunks for document {} in project {}", chunks.len(), document_id, project_id);
-    
+    let vectorized_count = vectorize_chunks(&app_handle, project_id, &chunks, &api_key, |_| {}).await?;
+    info!("Vectorized {} chunks for document {} in project {}", vectorized_count, document_id, project_id);
+    Ok(vectorized_count)
+}
+
+/// Embed `chunks` in batches into `project_id`'s vector index, mark the ones
+/// that made it as vectorized and sync the index to disk. `on_batch` gets the
+/// running count after each batch. Chunks that fail stay unvectorized for the
+/// next run. `cancel_vectorization` stops the run after the batch being
+/// embedded and emits `vectorization_cancelled`; the rest stay queued too.
+/// The caller holds the project's vector lock.
+async fn vectorize_chunks(
+    app_handle: &AppHandle,
+    project_id: i64,
+    chunks: &[DocumentChunk],
+    api_key: &str,
+    mut on_batch: impl FnMut(i32),
+) -> HeelixResult<i32> {
+    let retries = app_handle.db(get_embedding_retries)?;
     let mut vectorized_count = 0;
-    
-    for chunk in chunks {
-        // Add to project-specific vector index
-        if let Err(e) = add_chunk_to_project_vectors(
-            &app_handle,
-            project_id,
-            chunk.id,
-            &chunk.chunk_text,
-            &api_key
-        ).await {
-            error!("Failed to vectorize chunk {}: {}", chunk.id, e);
-            continue;
+
+    for (batch_index, batch) in chunks.chunks(EMBEDDING_BATCH_SIZE).enumerate() {
+        if is_vectorization_cancelled(project_id) {
+            let remaining = chunks.len() - batch_index * EMBEDDING_BATCH_SIZE;
+            info!("Vectorization of project {} cancelled with {} chunks left", project_id, remaining);
+            let cancelled = VectorizationCancelled { project_id, vectorized: vectorized_count, remaining };
+            if let Err(e) = app_handle.emit_all(VECTORIZATION_CANCELLED_EVENT, cancelled) {
+                error!("Failed to emit vectorization cancelled: {}", e);
+            }
+            break;
         }
-        
-        // Mark as vectorized in DB
-        if let Err(e) = app_handle.db(|db| mark_chunk_as_vectorized(db, chunk.id)) {
-            error!("Failed to mark chunk {} as vectorized: {}", chunk.id, e);
-            continue;
+        // Embed the whole batch in one request and add it to the project-specific vector index
+        let items: Vec<(i64, &str)> = batch
+            .iter()
+            .map(|chunk| (chunk.id, chunk.chunk_text.as_str()))
+            .collect();
+        let outcome = match add_chunks_to_project_vectors(app_handle, project_id, &items, api_key, retries).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                error!("Failed to vectorize batch of {} chunks: {}", batch.len(), e);
+                continue;
+            }
+        };
+        if !outcome.failed.is_empty() {
+            // Left unvectorized so the next run picks them up again
+            error!("Could not embed chunks {:?} in project {}", outcome.failed, project_id);
         }
-        
-        vectorized_count += 1;
+
+        // Mark only the embedded chunks as vectorized in DB
+        for chunk_id in outcome.added {
+            if let Err(e) = app_handle.db(|db| mark_chunk_as_vectorized(db, chunk_id)) {
+                error!("Failed to mark chunk {} as vectorized: {}", chunk_id, e);
+                continue;
+            }
+            vectorized_count += 1;
+        }
+        on_batch(vectorized_count);
     }
-    
+    // A cancel sent while waiting for the lock stops this run above; one sent
+    // after the last batch must not stop the next run instead
+    clear_vectorization_cancel(project_id);
+
     // Sync project's vector index to disk
-    if let Err(e) = sync_project_vectors(&app_handle, project_id).await {
+    if let Err(e) = sync_project_vectors(app_handle, project_id).await {
         error!("Failed to sync project {} vector index: {}", project_id, e);
     }
-    
-    info!("Vectorized {} chunks for document {} in project {}", vectorized_count, document_id, project_id);
     Ok(vectorized_count)
 }
 
+/// Emitted while interrupted indexing is resumed at startup and while a
+/// project is re-indexed with `vectorize_project` or `rebuild_project_vectors`
+const VECTORIZATION_PROGRESS_EVENT: &str = "vectorization_progress";
+
+#[derive(Serialize, Clone)]
+struct VectorizationProgress {
+    project_id: i64,
+    vectorized: i32,
+    total: i64,
+}
+
+/// Emitted when a run stops early because of `cancel_vectorization`
+const VECTORIZATION_CANCELLED_EVENT: &str = "vectorization_cancelled";
+
+#[derive(Serialize, Clone)]
+struct VectorizationCancelled {
+    project_id: i64,
+    /// Chunks indexed before the run stopped
+    vectorized: i32,
+    /// Chunks left queued for the next run
+    remaining: usize,
+}
+
+/// Stop the vectorization running in `project_id` once the batch being
+/// embedded finishes. Indexed chunks stay indexed; the rest stay queued and
+/// are picked up at the next startup or re-index.
+#[tauri::command]
+fn cancel_vectorization(project_id: i64) {
+    info!("Cancelling vectorization of project {}", project_id);
+    cancel_project_vectorization(project_id);
+}
+
+/// Pick up chunks left unvectorized when the app closed mid-indexing, project
+/// by project, emitting `vectorization_progress` as batches complete. Does
+/// nothing when indexing is disabled or there is no OpenAI key.
+async fn resume_vectorization(app_handle: AppHandle) -> HeelixResult<()> {
+    let vectorization_enabled =
+        app_handle.db(|db| get_setting_or_default(db, "vectorization_enabled", "false")) == "true";
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
+    if !vectorization_enabled || api_key.is_empty() {
+        return Ok(());
+    }
+
+    let pending = app_handle.db(get_projects_with_unvectorized_chunks)?;
+    for (project_id, total) in pending {
+        let _project_lock = lock_project_vectors(project_id).await;
+        // Re-read under the lock: a save may have indexed some in the meantime
+        let chunks = app_handle.db(|db| get_unvectorized_chunks(db, project_id, -1))?;
+        if chunks.is_empty() {
+            continue;
+        }
+        info!("Resuming vectorization of {} chunks in project {}", chunks.len(), project_id);
+        let vectorized = vectorize_chunks(&app_handle, project_id, &chunks, &api_key, |vectorized| {
+            let progress = VectorizationProgress { project_id, vectorized, total };
+            if let Err(e) = app_handle.emit_all(VECTORIZATION_PROGRESS_EVENT, progress) {
+                error!("Failed to emit vectorization progress: {}", e);
+            }
+        })
+        .await?;
+        info!("Resumed vectorization of project {}: {} of {} chunks", project_id, vectorized, chunks.len());
+    }
+    Ok(())
+}
+
+/// Re-chunk every document in a project and embed all of its chunks into a
+/// fresh index, e.g. after a bulk import or a chunking change.
+/// Chunks that cannot be embedded stay unvectorized and are retried at the
+/// next startup. Emits `vectorization_progress` and returns the number of
+/// chunks indexed.
+#[tauri::command]
+async fn vectorize_project(app_handle: AppHandle, project_id: i64) -> HeelixResult<i32> {
+    reindex_project(&app_handle, project_id, true).await
+}
+
+/// Embed a project's existing chunks again into a fresh index with the current
+/// `embedding_model`, for a project whose index was built with another model.
+/// Emits `vectorization_progress` and returns the number of chunks indexed.
+#[tauri::command]
+async fn rebuild_project_vectors(app_handle: AppHandle, project_id: i64) -> HeelixResult<i32> {
+    reindex_project(&app_handle, project_id, false).await
+}
+
+/// Clear a project's index and embed all of its chunks, re-chunking the
+/// documents first when `rechunk` is set
+async fn reindex_project(app_handle: &AppHandle, project_id: i64, rechunk: bool) -> HeelixResult<i32> {
+    let vectorization_enabled =
+        app_handle.db(|db| get_setting_or_default(db, "vectorization_enabled", "false")) == "true";
+    if !vectorization_enabled {
+        return Err(HeelixError::InvalidInput(
+            "Local indexing is turned off. Enable it in Settings to index this project.".to_string(),
+        ));
+    }
+    let api_key = app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", ""));
+    if api_key.is_empty() {
+        return Err(HeelixError::MissingApiKey("OpenAI"));
+    }
+
+    let _project_lock = lock_project_vectors(project_id).await;
+    // From here on a failure leaves chunks queued for the startup resume
+    // rather than marked as indexed in an index that no longer has them
+    app_handle.db(|db| mark_project_chunks_unvectorized(db, project_id))?;
+    delete_project_vectors(app_handle, project_id)
+        .await
+        .map_err(|e| HeelixError::Internal(format!("Failed to clear the vector index: {}", e)))?;
+
+    if rechunk {
+        let (document_ids, _, _) = app_handle.db(|db| fetch_activities_by_project_id(db, project_id))?;
+        app_handle.db_mut(|db| {
+            let tx = db.transaction()?;
+            for &document_id in &document_ids {
+                let (_, plain_text) = get_activity_plain_text(&tx, document_id)?;
+                save_chunks_for_document(&tx, document_id, project_id, &plain_text)?;
+            }
+            tx.commit()
+        })?;
+    }
+
+    let chunks = app_handle.db(|db| get_unvectorized_chunks(db, project_id, -1))?;
+    let total = chunks.len() as i64;
+    info!("Indexing {} chunks in project {}", total, project_id);
+    let vectorized = vectorize_chunks(app_handle, project_id, &chunks, &api_key, |vectorized| {
+        let progress = VectorizationProgress { project_id, vectorized, total };
+        if let Err(e) = app_handle.emit_all(VECTORIZATION_PROGRESS_EVENT, progress) {
+            error!("Failed to emit vectorization progress: {}", e);
+        }
+    })
+    .await?;
+    info!("Indexed {} of {} chunks in project {}", vectorized, total, project_id);
+    Ok(vectorized)
+}
+
+/// Per-document indexing state for a project, for "indexed" / "pending" badges
+#[tauri::command]
+fn get_project_vectorization_status(
+    app_handle: AppHandle,
+    project_id: i64,
+) -> HeelixResult<Vec<DocumentVectorizationStatus>> {
+    app_handle
+        .db(|db| get_vectorization_status_for_project(db, project_id))
+        .map_err(HeelixError::from)
+}
+
+/// Documents that should be searchable but are missing from their project's
+/// index, e.g. after an embedding call failed while saving. Empty when
+/// indexing is turned off.
+#[tauri::command]
+fn get_unvectorized_documents(app_handle: AppHandle) -> HeelixResult<Vec<UnvectorizedDocument>> {
+    let vectorization_enabled =
+        app_handle.db(|db| get_setting_or_default(db, "vectorization_enabled", "false")) == "true";
+    if !vectorization_enabled {
+        return Ok(vec![]);
+    }
+    app_handle
+        .db(crate::repository::chunk_repository::get_unvectorized_documents)
+        .map_err(HeelixError::from)
+}
+
+/// Try again to index one document from `get_unvectorized_documents`. A
+/// document that was never chunked is chunked first. Returns the number of
+/// chunks vectorized.
+#[tauri::command]
+async fn retry_vectorization(app_handle: AppHandle, document_id: i64) -> HeelixResult<i32> {
+    let vectorization_enabled =
+        app_handle.db(|db| get_setting_or_default(db, "vectorization_enabled", "false")) == "true";
+    if !vectorization_enabled {
+        return Err(HeelixError::InvalidInput(
+            "Local indexing is turned off. Enable it in Settings to index this document.".to_string(),
+        ));
+    }
+    if app_handle.db(|db| get_setting_or_default(db, "api_key_open_ai", "")).is_empty() {
+        return Err(HeelixError::MissingApiKey("OpenAI"));
+    }
+
+    app_handle.db_mut(|db| {
+        let tx = db.transaction()?;
+        if get_chunk_count_for_document(&tx, document_id)? == 0 {
+            let project_id = get_project_id_for_document(&tx, document_id)?;
+            let (_, plain_text) = get_activity_plain_text(&tx, document_id)?;
+            save_chunks_for_document(&tx, document_id, project_id, &plain_text)?;
+        }
+        tx.commit()
+    })?;
+    let vectorized = vectorize_document_chunks(app_handle, document_id).await?;
+    info!("Retried vectorization of document {}: {} chunks indexed", document_id, vectorized);
+    Ok(vectorized)
+}
+
+/// A document's chunks in order with whether each is indexed, for seeing how
+/// the document was split when retrieval misses it
+#[tauri::command]
+fn get_document_chunks(app_handle: AppHandle, document_id: i64) -> HeelixResult<Vec<DocumentChunk>> {
+    app_handle
+        .db(|db| get_chunks_for_document(db, document_id))
+        .map_err(HeelixError::from)
+}
+
+/// Fix the text of one badly split chunk by hand and re-embed only that
+/// chunk. The document text is untouched, so the next edit of the document
+/// re-chunks it as usual. Returns the chunk, which has a new id.
+#[tauri::command]
+async fn update_chunk_text(app_handle: AppHandle, chunk_id: i64, text: String) -> HeelixResult<DocumentChunk> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(HeelixError::InvalidInput("A chunk cannot be empty".to_string()));
+    }
+    let (old, new) = app_handle
+        .db_mut(|db| replace_chunk_text(db, chunk_id, text))?
+        .ok_or_else(|| HeelixError::NotFound(format!("Chunk {} not found", chunk_id)))?;
+    if old.is_vectorized {
+        remove_document_vectors(&app_handle, old.project_id, &[old.id]).await;
+    }
+
+    vectorize_document_chunks(app_handle.clone(), new.document_id).await?;
+    let chunk = app_handle
+        .db(|db| get_chunks_by_ids(db, &[new.id]))?
+        .pop()
+        .unwrap_or(new);
+    info!("Chunk {} of document {} replaced by chunk {}", old.id, chunk.document_id, chunk.id);
+    Ok(chunk)
+}
+
+/// Open a project's vector index ahead of the first prompt so retrieval does
+/// not pay the cost of loading it from disk. A no-op when indexing is disabled.
+#[tauri::command]
+async fn warm_up_project_vectors(app_handle: AppHandle, project_id: i64) -> HeelixResult<()> {
+    let vectorization_enabled =
+        app_handle.db(|db| get_setting_or_default(db, "vectorization_enabled", "false")) == "true";
+    if !vectorization_enabled {
+        return Ok(());
+    }
+
+    get_project_vector_db(&app_handle, project_id)
+        .await
+        .map_err(|e| HeelixError::Internal(format!("Failed to load vector index: {}", e)))?;
+    info!("Warmed up vector index for project {}", project_id);
+    Ok(())
+}
+
 #[tauri::command]
 fn add_project_blank_activity(
     app_handle: AppHandle,
     project_id: i64,
-) -> Result<i64, String> {
+) -> HeelixResult<i64> {
     app_handle
         .db(|db| add_blank_document(db, project_id))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
 }
 
 #[tauri::command]
-fn ensure_unassigned_activity(app_handle: AppHandle) -> Result<i64, String> {
-  app_handle
-    .db(|db| {
-      // First ensure unassigned project exists
-      let unassigned_project_id = ensure_unassigned_project(db)?;
-      // Then add blank document to it
-      add_blank_document(db, unassigned_project_id)
-    })
-    .map_err(|e| e.to_string())
+fn ensure_unassigned_activity(app_handle: AppHandle) -> HeelixResult<i64> {
+    app_handle
+        .db(|db| add_quick_capture_document(db, None))
+        .map_err(HeelixError::from)
+}
+
+/// Add a blank document to `project_id`, or to the Unassigned project when none
+/// is given. Returns the new document's id.
+fn add_quick_capture_document(
+    db: &Connection,
+    project_id: Option<i64>,
+) -> Result<i64, rusqlite::Error> {
+    let project_id = match project_id {
+        Some(id) => id,
+        None => ensure_unassigned_project(db)?,
+    };
+    add_blank_document(db, project_id)
+}
+
+/// What `import_document` does when the project already has a document with
+/// the same text
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum DuplicatePolicy {
+    /// Keep the existing document and import nothing
+    Skip,
+    /// Overwrite the existing document's name and text
+    Update,
+    /// Import a second copy anyway
+    Create,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum ImportAction {
+    Created,
+    Updated,
+    Skipped,
+}
+
+#[derive(Serialize)]
+struct ImportedDocument {
+    document_id: i64,
+    action: ImportAction,
+}
+
+/// Save `text` as a document named `name` in `project_id`, or in the Unassigned
+/// project when none is given. A document in that project with the same text,
+/// ignoring case and whitespace, is handled by `duplicate_policy`, which
+/// defaults to `create`. Returns the document the text ended up in and the
+/// action taken; new and updated documents are chunked and vectorized.
+#[tauri::command]
+async fn import_document(
+    app_handle: AppHandle,
+    project_id: Option<i64>,
+    name: String,
+    text: String,
+    duplicate_policy: Option<DuplicatePolicy>,
+) -> HeelixResult<ImportedDocument> {
+    let policy = duplicate_policy.unwrap_or(DuplicatePolicy::Create);
+    let (project_id, duplicate) = app_handle.db(|db| {
+        let project_id = match project_id {
+            Some(id) => id,
+            None => ensure_unassigned_project(db)?,
+        };
+        let duplicate = match content_fingerprint(&heelix::html_to_plain_text(&text)) {
+            Some(fingerprint) if policy != DuplicatePolicy::Create => {
+                find_duplicate_document(db, project_id, fingerprint)?
+            }
+            _ => None,
+        };
+        Ok::<_, rusqlite::Error>((project_id, duplicate))
+    })?;
+
+    let (document_id, action) = match duplicate {
+        Some(document_id) if policy == DuplicatePolicy::Skip => (document_id, ImportAction::Skipped),
+        Some(document_id) => {
+            app_handle.db(|db| update_activity_name(db, document_id, &name))?;
+            replace_document_text(&app_handle, document_id, &text)?;
+            (document_id, ImportAction::Updated)
+        }
+        None => (create_document(&app_handle, project_id, &name, &text)?, ImportAction::Created),
+    };
+    info!("Imported \"{}\" into project {}: {:?} document {}", name, project_id, action, document_id);
+    Ok(ImportedDocument { document_id, action })
 }
 
 #[tauri::command]
@@ -647,20 +1403,130 @@ fn update_project_activity_name(
     app_handle: AppHandle,
     activity_id: i64,
     name: &str,
-) -> Result<(), String> {
+) -> HeelixResult<()> {
     app_handle
         .db(|db| update_activity_name(db, activity_id, name))
-        .map_err(|e| e.to_string())
+        .map_err(HeelixError::from)
 }
 
+/// Keep a document out of assistant answers, or let it back in. Excluding
+/// drops its chunks and their vectors; including re-chunks it and vectorizes
+/// it in the background.
 #[tauri::command]
-fn delete_project_activity(
+async fn set_document_exclude_from_rag(
     app_handle: AppHandle,
     activity_id: i64,
-) -> Result<(), String> {
-    app_handle
-        .db(|db| delete_project_document(db, activity_id))
-        .map_err(|e| e.to_string())
+    exclude: bool,
+) -> HeelixResult<()> {
+    // The flag and the chunks change together, so a failure cannot leave an
+    // excluded document with chunks or an included one without them
+    let (project_id, chunk_ids) = app_handle.db_mut(|db| {
+        let tx = db.transaction()?;
+        let project_id = get_project_id_for_document(&tx, activity_id)?;
+        let chunk_ids = get_vectorized_chunk_ids_for_document(&tx, activity_id)?;
+        set_exclude_from_rag(&tx, activity_id, exclude)?;
+        // Deletes the chunks of an excluded document and rebuilds an included one's
+        let (_, plain_text) = get_activity_plain_text(&tx, activity_id)?;
+        save_chunks_for_document(&tx, activity_id, project_id, &plain_text)?;
+        tx.commit()?;
+        Ok::<(i64, Vec<i64>), rusqlite::Error>((project_id, chunk_ids))
+    })?;
+
+    remove_document_vectors(&app_handle, project_id, &chunk_ids).await;
+    if !exclude {
+        let handle = app_handle.clone();
+        tauri::async_runtime::spawn(async move {
+            if let Err(e) = vectorize_document_chunks(handle, activity_id).await {
+                error!("Vectorization of document {} failed: {}", activity_id, e);
+            }
+        });
+    }
+    info!("Document {} {} RAG", activity_id, if exclude { "excluded from" } else { "included in" });
+    Ok(())
+}
+
+#[tauri::command]
+async fn delete_project_activity(
+    app_handle: AppHandle,
+    activity_id: i64,
+) -> HeelixResult<()> {
+    delete_document(&app_handle, activity_id).await
+}
+
+/// Delete a document along with its chunks and their vectors
+async fn delete_document(app_handle: &AppHandle, activity_id: i64) -> HeelixResult<()> {
+    let (project_id, chunk_ids) = app_handle.db(|db| {
+        let project_id = get_project_id_for_document(db, activity_id)?;
+        let chunk_ids = get_vectorized_chunk_ids_for_document(db, activity_id)?;
+        Ok::<(i64, Vec<i64>), rusqlite::Error>((project_id, chunk_ids))
+    })?;
+    app_handle.db(|db| delete_project_document(db, activity_id))?;
+
+    remove_document_vectors(app_handle, project_id, &chunk_ids).await;
+    Ok(())
+}
+
+/// Combine documents into one named `target_name`, in the order of `ids`, each
+/// under a heading with its name. The result goes into the first document's
+/// project: a document there already called `target_name` is overwritten (its
+/// old text is kept as a version), otherwise a new one is created. With
+/// `delete_sources` the other documents are deleted along with their vectors.
+/// Returns the merged document's id.
+#[tauri::command]
+async fn merge_documents(
+    app_handle: AppHandle,
+    ids: Vec<i64>,
+    target_name: String,
+    delete_sources: bool,
+) -> HeelixResult<i64> {
+    let target_name = target_name.trim();
+    if target_name.is_empty() {
+        return Err(HeelixError::InvalidInput("The merged document needs a name".to_string()));
+    }
+    let first_id = *ids
+        .first()
+        .ok_or_else(|| HeelixError::InvalidInput("No documents to merge".to_string()))?;
+    if ids.iter().enumerate().any(|(index, id)| ids[..index].contains(id)) {
+        return Err(HeelixError::InvalidInput("A document is listed more than once".to_string()));
+    }
+
+    let (project_id, sources, existing_target) = app_handle.db(|db| {
+        let project_id = get_project_id_for_document(db, first_id)?;
+        let sources = ids
+            .iter()
+            .map(|id| get_activity_full_text(db, *id))
+            .collect::<Result<Vec<_>, _>>()?;
+        let existing_target = find_document_by_name(db, project_id, target_name)?;
+        Ok::<_, rusqlite::Error>((project_id, sources, existing_target))
+    })?;
+    let merged = heelix::merge_html_documents(&sources);
+
+    let target_id = match existing_target {
+        Some(target_id) => {
+            // Rebuilds the target's chunks and re-vectorizes it in the background
+            replace_document_text(&app_handle, target_id, &merged)?;
+            target_id
+        }
+        None => create_document(&app_handle, project_id, target_name, &merged)?,
+    };
+
+    if delete_sources {
+        for id in ids.iter().filter(|id| **id != target_id) {
+            delete_document(&app_handle, *id).await?;
+        }
+    }
+    info!("Merged documents {:?} into document {}", ids, target_id);
+    Ok(target_id)
+}
+
+/// Drop a document's chunks from a project's vector index. Failures are logged
+/// rather than returned: retrieval only considers chunk ids still in the
+/// database, so stale vectors cost space but never surface in results.
+async fn remove_document_vectors(app_handle: &AppHandle, project_id: i64, chunk_ids: &[i64]) {
+    let _guard = lock_project_vectors(project_id).await;
+    if let Err(e) = remove_chunks_from_project_vectors(app_handle, project_id, chunk_ids).await {
+        error!("Failed to remove chunk vectors from project {}: {}", project_id, e);
+    }
 }
 
 #[cfg(target_os = "macos")]
@@ -677,47 +1543,78 @@ fn prompt_for_accessibility_permissions() {
 
 // Audio recording commands
 #[tauri::command]
-async fn start_audio_recording(_app_handle: AppHandle) -> Result<String, String> {
-    crate::engine::audio_engine::start_recording().await
+async fn start_audio_recording(app_handle: AppHandle) -> HeelixResult<String> {
+    let target_rate = app_handle.db(get_recording_sample_rate)?;
+    crate::engine::audio_engine::start_recording(target_rate, false)
+        .await
+        .map_err(HeelixError::Internal)
+}
+
+/// The configured transcription server, model and key. Only OpenAI's server
+/// requires a key; other servers, such as a local Whisper one, may take none.
+fn transcription_endpoint(app_handle: &AppHandle) -> HeelixResult<TranscriptionEndpoint> {
+    let endpoint = app_handle.db(get_transcription_endpoint)?;
+    if endpoint.api_key.is_empty() && endpoint.base_url == DEFAULT_TRANSCRIPTION_BASE_URL {
+        return Err(HeelixError::MissingApiKey("OpenAI"));
+    }
+    Ok(endpoint)
 }
 
+/// Start recording with live dictation: every `LIVE_WINDOW_SECONDS` of audio
+/// is sent to Whisper as it is recorded and emitted as `transcription_partial`.
+/// `stop_audio_recording` ends it, and the last partial has `is_final` set.
+/// Returns the recording's path; the file still holds the whole recording for
+/// a full-quality `transcribe_audio` afterwards.
 #[tauri::command]
-async fn stop_audio_recording() -> Result<String, String> {
-    crate::engine::audio_engine::stop_recording().await
+async fn start_live_transcription(app_handle: AppHandle) -> HeelixResult<String> {
+    let endpoint = transcription_endpoint(&app_handle)?;
+    let target_rate = app_handle.db(get_recording_sample_rate)?;
+    let file_path = crate::engine::audio_engine::start_recording(target_rate, true)
+        .await
+        .map_err(HeelixError::Internal)?;
+
+    tauri::async_runtime::spawn(crate::engine::live_transcription::run_live_transcription(
+        app_handle,
+        file_path.clone(),
+        endpoint,
+    ));
+    Ok(file_path)
 }
 
 #[tauri::command]
-fn read_audio_file(file_path: String) -> Result<Vec<u8>, String> {
-    crate::engine::audio_engine::read_audio_file(&file_path)
+async fn stop_audio_recording() -> HeelixResult<String> {
+    crate::engine::audio_engine::stop_recording()
+        .await
+        .map_err(HeelixError::Internal)
+}
+
+#[tauri::command]
+fn read_audio_file(app_handle: AppHandle, file_path: String) -> HeelixResult<Vec<u8>> {
+    check_import_size(&app_handle, &file_path)?;
+    crate::engine::audio_engine::read_audio_file(&file_path).map_err(HeelixError::Internal)
 }
 
 #[tauri::command]
 async fn transcribe_audio(
     app_handle: AppHandle,
     file_path: String,
-) -> Result<String, String> {
+) -> HeelixResult<String> {
     use crate::configuration::state::ServiceAccess;
-    use crate::repository::settings_repository::get_setting;
     
     log::info!("Transcribing audio file: {}", file_path);
+
+    crate::engine::transcription_engine::validate_wav(&file_path)
+        .map_err(|e| HeelixError::InvalidInput(e.to_string()))?;
     
-    // Get OpenAI API key from settings
-    let setting = app_handle.db(|db| {
-        get_setting(db, "api_key_open_ai").expect("Failed to get api_key_open_ai")
-    });
-    
-    let openai_api_key = setting.setting_value;
-    if openai_api_key.is_empty() {
-        return Err("OpenAI API key is required for audio transcription".to_string());
-    }
+    let endpoint = transcription_endpoint(&app_handle)?;
     
-    // Transcribe using OpenAI Whisper
+    // Transcribe using Whisper
     let transcription = crate::engine::transcription_engine::transcribe_with_openai(
         &file_path,
-        &openai_api_key,
+        &endpoint,
     )
     .await
-    .map_err(|e| format!("Transcription failed: {}", e))?;
+    .map_err(|e| HeelixError::provider("Transcription", e.to_string()))?;
     
     // Clean up the audio file after transcription
     if let Err(err) = std::fs::remove_file(&file_path) {
@@ -729,59 +1626,305 @@ async fn transcribe_audio(
     Ok(transcription)
 }
 
+/// Like `transcribe_audio`, but keeps Whisper's segment timestamps
+#[tauri::command]
+async fn transcribe_audio_with_timestamps(
+    app_handle: AppHandle,
+    file_path: String,
+) -> HeelixResult<crate::engine::transcription_engine::TimedTranscript> {
+    use crate::configuration::state::ServiceAccess;
+
+    log::info!("Transcribing audio file with timestamps: {}", file_path);
+
+    crate::engine::transcription_engine::validate_wav(&file_path)
+        .map_err(|e| HeelixError::InvalidInput(e.to_string()))?;
+
+    let endpoint = transcription_endpoint(&app_handle)?;
+
+    let transcript = crate::engine::transcription_engine::transcribe_with_timestamps(
+        &file_path,
+        &endpoint,
+    )
+    .await
+    .map_err(|e| HeelixError::provider("Transcription", e.to_string()))?;
+
+    if let Err(err) = std::fs::remove_file(&file_path) {
+        log::warn!("Failed to delete audio file {}: {}", file_path, err);
+    }
+
+    Ok(transcript)
+}
+
+/// Stop transcribing `file_path` before its next piece. The running
+/// `transcribe_audio`, `transcribe_audio_with_timestamps` or
+/// `transcribe_to_document` call returns what was transcribed until then and
+/// removes the recording as usual.
+#[tauri::command]
+fn cancel_transcription(file_path: String) {
+    log::info!("Cancelling transcription of {}", file_path);
+    crate::engine::transcription_engine::cancel_transcription(&file_path);
+}
+
+/// Transcript characters sent to the model when titling a voice note
+const TITLE_EXCERPT_CHARS: usize = 2000;
+
+#[derive(Serialize)]
+struct TranscribedDocument {
+    document_id: i64,
+    name: String,
+}
+
+/// Transcribe a recording straight into a new document in `project_id` (or
+/// Unassigned). The document is named "Voice Note <time>", or titled by the
+/// selected provider when `generate_title` is set; a failed title request
+/// falls back to the timestamp name.
+#[tauri::command]
+async fn transcribe_to_document(
+    app_handle: AppHandle,
+    file_path: String,
+    project_id: Option<i64>,
+    generate_title: Option<bool>,
+) -> HeelixResult<TranscribedDocument> {
+    let transcript = transcribe_audio_with_timestamps(app_handle.clone(), file_path).await?;
+    if transcript.text.trim().is_empty() {
+        return Err(HeelixError::InvalidInput("The recording contains no speech".to_string()));
+    }
+
+    let mut name = format!(
+        "Voice Note {}",
+        chrono::Local::now().format("%Y-%m-%d %H:%M")
+    );
+    if generate_title.unwrap_or(false) {
+        let provider = app_handle.db(get_api_choice)?;
+        let excerpt: String = transcript.text.chars().take(TITLE_EXCERPT_CHARS).collect();
+        match name_with_provider(app_handle.clone(), &provider, excerpt).await {
+            Ok(title) if !title.trim().is_empty() => name = title.trim().to_string(),
+            Ok(_) => {}
+            Err(e) => error!("Voice note title generation failed, keeping timestamp name: {}", e),
+        }
+    }
+
+    let document_id = app_handle.db(|db| add_quick_capture_document(db, project_id))?;
+    app_handle.db(|db| update_activity_name(db, document_id, &name))?;
+    save_document_text(&app_handle, document_id, &transcript.to_html())?;
+
+    info!("Created voice note document {} ({})", document_id, name);
+    Ok(TranscribedDocument { document_id, name })
+}
+
+/// Emitted after each page of a PDF has been extracted
+const EXTRACTION_PROGRESS_EVENT: &str = "extraction_progress";
+
+#[derive(Serialize, Clone)]
+struct ExtractionProgress {
+    file_path: String,
+    page: u32,
+    total_pages: u32,
+    /// The page went through OCR rather than text extraction
+    ocr: bool,
+}
+
+#[derive(Serialize)]
+struct ExtractedDocument {
+    text: String,
+    /// Extraction was cancelled part way; `text` holds the pages read until then
+    cancelled: bool,
+    /// The text was read from page images and may contain recognition errors
+    ocr: bool,
+    /// The text file was over `max_import_bytes`; `text` holds its beginning
+    truncated: bool,
+}
+
+impl ExtractedDocument {
+    fn complete(text: String) -> Self {
+        ExtractedDocument { text, cancelled: false, ocr: false, truncated: false }
+    }
+}
+
+/// Refuse a file over `max_import_bytes` before any of it is read
+fn check_import_size(app_handle: &AppHandle, file_path: &str) -> HeelixResult<()> {
+    let max_bytes = app_handle.db(get_max_import_bytes)?;
+    let size = std::fs::metadata(file_path)
+        .map_err(|e| HeelixError::InvalidInput(format!("Cannot read {}: {}", file_path, e)))?
+        .len();
+    if size > max_bytes {
+        return Err(HeelixError::InvalidInput(format!(
+            "{} is {:.1} MB, the import limit is {} MB. Raise it in Settings to import larger files.",
+            file_path,
+            size as f64 / (1024.0 * 1024.0),
+            max_bytes / (1024 * 1024)
+        )));
+    }
+    Ok(())
+}
+
 // Document import commands
+/// Extract the text of a PDF, DOCX, email or text file. Runs on a blocking thread;
+/// PDFs report `extraction_progress` per page and can be stopped with
+/// `cancel_document_extraction`.
 #[tauri::command]
-async fn extract_document_text(file_path: String) -> Result<String, String> {
+async fn extract_document_text(app_handle: AppHandle, file_path: String) -> HeelixResult<ExtractedDocument> {
+    RUNNING_EXTRACTIONS.lock().unwrap().insert(file_path.clone(), false);
+    tokio::task::spawn_blocking(move || {
+        let result = extract_document_text_blocking(&app_handle, &file_path);
+        RUNNING_EXTRACTIONS.lock().unwrap().remove(&file_path);
+        result
+    })
+    .await
+    .map_err(|e| HeelixError::Internal(format!("Text extraction failed: {}", e)))?
+}
+
+/// Stop extracting `file_path` after the current PDF page. Other files being
+/// extracted at the same time carry on, and a cancel arriving after the
+/// extraction ended is ignored rather than stopping the next one.
+#[tauri::command]
+fn cancel_document_extraction(file_path: String) {
+    log::info!("Cancelling extraction of {}", file_path);
+    if let Some(cancelled) = RUNNING_EXTRACTIONS.lock().unwrap().get_mut(&file_path) {
+        *cancelled = true;
+    }
+}
+
+fn extract_document_text_blocking(app_handle: &AppHandle, file_path: &str) -> HeelixResult<ExtractedDocument> {
     use std::path::Path;
     
     log::info!("Extracting text from document: {}", file_path);
     
     // Check if file exists
-    if !Path::new(&file_path).exists() {
-        return Err(format!("File not found: {}", file_path));
+    if !Path::new(file_path).exists() {
+        return Err(HeelixError::NotFound(format!("File not found: {}", file_path)));
     }
     
-    // Determine file type based on extension
-    let path = Path::new(&file_path);
+    // Determine file type from its content, falling back to the extension
+    let path = Path::new(file_path);
     let extension = path.extension()
         .and_then(|ext| ext.to_str())
         .map(|ext| ext.to_lowercase())
         .unwrap_or_default();
+    let header = read_header(file_path)
+        .map_err(|e| HeelixError::InvalidInput(format!("Failed to read file: {}", e)))?;
+    let format = detect_document_format(&header, &extension, || is_docx_file(file_path));
+    
+    log::info!("File extension: {:?}, detected format: {:?}", extension, format);
     
-    log::info!("File extension detected: {}", extension);
+    // Text files over the limit are read up to it instead
+    if format != Some(DocumentFormat::Text) {
+        check_import_size(app_handle, file_path)?;
+    }
     
-    match extension.as_str() {
-        "pdf" => {
+    match format {
+        Some(DocumentFormat::Pdf) => {
             log::info!("Attempting to extract text from PDF...");
-            extract_text_from_pdf(&file_path)
+            extract_text_from_pdf(app_handle, file_path).map_err(HeelixError::InvalidInput)
         },
-        "txt" | "md" | "rtf" => {
+        Some(DocumentFormat::Text) => {
             log::info!("Reading text file...");
-            read_text_file(&file_path)
+            let max_bytes = app_handle.db(get_max_import_bytes)?;
+            let (text, truncated) = read_text_file(file_path, max_bytes).map_err(HeelixError::InvalidInput)?;
+            Ok(ExtractedDocument { truncated, ..ExtractedDocument::complete(text) })
         },
-        "docx" => {
+        Some(DocumentFormat::Docx) => {
             log::info!("Attempting to extract text from DOCX...");
-            extract_text_from_docx(&file_path)
+            extract_text_from_docx(file_path).map(ExtractedDocument::complete).map_err(HeelixError::InvalidInput)
+        },
+        Some(DocumentFormat::Email) => {
+            log::info!("Reading email...");
+            let raw = std::fs::read(file_path)
+                .map_err(|e| HeelixError::InvalidInput(format!("Failed to read email: {}", e)))?;
+            email_to_text(&raw).map(ExtractedDocument::complete).map_err(HeelixError::InvalidInput)
         },
-        _ => Err(format!("Unsupported file format: {}. Supported formats: PDF, TXT, MD, RTF, DOCX", extension))
+        None => {
+            let shown = if extension.is_empty() { "unknown" } else { extension.as_str() };
+            Err(HeelixError::InvalidInput(format!("Unsupported file format: {}. Supported formats: PDF, TXT, MD, RTF, DOCX, EML", shown)))
+        }
     }
 }
 
-fn extract_text_from_pdf(file_path: &str) -> Result<String, String> {
-    match pdf_extract::extract_text(file_path) {
-        Ok(text) => {
-            log::info!("Successfully extracted {} characters from PDF", text.len());
-            if text.trim().is_empty() {
-                Err("PDF appears to be empty or contains only images/non-text content".to_string())
-            } else {
-                Ok(text)
+/// Extract a PDF one page at a time, emitting progress after each page.
+/// Pages that fail to parse are skipped rather than failing the document.
+/// When the text is too sparse for the page count (a scanned PDF) and
+/// `ocr_scanned_pdfs` is on, the pages are OCRed instead.
+fn extract_text_from_pdf(app_handle: &AppHandle, file_path: &str) -> Result<ExtractedDocument, String> {
+    let mut document = lopdf::Document::load(file_path).map_err(|err| {
+        log::error!("PDF load error: {:?}", err);
+        format!("Failed to extract text from PDF: {}. Make sure the PDF contains text (not just images).", err)
+    })?;
+    if document.is_encrypted() {
+        document
+            .decrypt("")
+            .map_err(|err| format!("Failed to decrypt PDF: {}", err))?;
+    }
+
+    let emit_progress = |page: u32, total_pages: u32, ocr: bool| {
+        let progress = ExtractionProgress {
+            file_path: file_path.to_string(),
+            page,
+            total_pages,
+            ocr,
+        };
+        if let Err(err) = app_handle.emit_all(EXTRACTION_PROGRESS_EVENT, progress) {
+            log::error!("Failed to emit extraction progress: {}", err);
+        }
+    };
+    let is_cancelled = || RUNNING_EXTRACTIONS.lock().unwrap().get(file_path).copied().unwrap_or(false);
+
+    let page_numbers: Vec<u32> = document.get_pages().keys().copied().collect();
+    let total_pages = page_numbers.len() as u32;
+    let mut text = String::new();
+    let mut cancelled = false;
+    for page_number in page_numbers {
+        if is_cancelled() {
+            log::info!("PDF extraction cancelled after {} of {} pages", page_number - 1, total_pages);
+            cancelled = true;
+            break;
+        }
+        let mut page_text = String::new();
+        {
+            let mut output = pdf_extract::PlainTextOutput::new(&mut page_text);
+            if let Err(err) = pdf_extract::output_doc_page(&document, &mut output, page_number) {
+                log::warn!("Skipping PDF page {}: {:?}", page_number, err);
             }
-        },
-        Err(err) => {
-            log::error!("PDF extraction error: {:?}", err);
-            Err(format!("Failed to extract text from PDF: {}. Make sure the PDF contains text (not just images).", err))
         }
+        text.push_str(&page_text);
+        emit_progress(page_number, total_pages, false);
+    }
+
+    log::info!("Successfully extracted {} characters from PDF", text.len());
+    if cancelled || !needs_ocr(&text, total_pages) {
+        return Ok(ExtractedDocument { text, cancelled, ocr: false, truncated: false });
+    }
+
+    let ocr_enabled = app_handle
+        .db(get_ocr_scanned_pdfs)
+        .map_err(|err| format!("Failed to read OCR setting: {}", err))?;
+    if !ocr_enabled {
+        if text.trim().is_empty() {
+            return Err("PDF appears to be empty or contains only images. Turn on OCR for scanned PDFs in settings to read it.".to_string());
+        }
+        return Ok(ExtractedDocument::complete(text));
     }
+
+    let missing_tools = missing_ocr_tools();
+    if !missing_tools.is_empty() {
+        return Err(format!(
+            "This PDF is scanned and OCR needs {}, which could not be found. {} and try again.",
+            missing_tools.join(" and "),
+            ocr_install_hint()
+        ));
+    }
+
+    log::info!("PDF {} has little extractable text, falling back to OCR", file_path);
+    let (ocr_text, cancelled) = ocr_pdf(
+        file_path,
+        total_pages,
+        |page| emit_progress(page, total_pages, true),
+        is_cancelled,
+    )
+    .map_err(|err| err.to_string())?;
+    if ocr_text.trim().is_empty() && !cancelled {
+        return Err("No text could be recognized in this PDF".to_string());
+    }
+    Ok(ExtractedDocument { text: ocr_text, cancelled, ocr: true, truncated: false })
 }
 
 fn extract_text_from_docx(file_path: &str) -> Result<String, String> {
@@ -846,11 +1989,15 @@ fn extract_text_from_docx(file_path: &str) -> Result<String, String> {
     }
 }
 
-fn read_text_file(file_path: &str) -> Result<String, String> {
-    match std::fs::read_to_string(file_path) {
-        Ok(content) => {
+/// Read a text file, stopping at `max_bytes`. Also returns whether it was cut short.
+fn read_text_file(file_path: &str, max_bytes: u64) -> Result<(String, bool), String> {
+    match read_text_prefix(file_path, max_bytes) {
+        Ok((content, truncated)) => {
+            if truncated {
+                log::warn!("Text file {} is over {} bytes, importing its beginning", file_path, max_bytes);
+            }
             log::info!("Successfully read {} characters from text file", content.len());
-            Ok(content)
+            Ok((content, truncated))
         },
         Err(e) => {
             log::error!("Error reading text file: {:?}", e);
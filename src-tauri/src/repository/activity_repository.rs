@@ -0,0 +1,79 @@
+use heelix::html_to_plain_text;
+use rusqlite::{params, Connection};
+
+use crate::entity::activity::ActivityHistoryItem;
+
+/// Characters of plain text shown for each activity in the history list
+pub const ACTIVITY_PREVIEW_CHARS: usize = 200;
+
+/// Characters of stored HTML read per activity to build its preview, so long
+/// captures are not loaded in full just to show their first lines
+const PREVIEW_SOURCE_CHARS: i64 = 4000;
+
+/// First `ACTIVITY_PREVIEW_CHARS` characters of an activity's text on one line
+fn activity_preview(html: &str) -> String {
+    let text = html_to_plain_text(html).split_whitespace().collect::<Vec<_>>().join(" ");
+    if text.chars().count() > ACTIVITY_PREVIEW_CHARS {
+        text.chars().take(ACTIVITY_PREVIEW_CHARS).collect::<String>() + "..."
+    } else {
+        text
+    }
+}
+
+/// Captured activities, newest first, with a plain text preview of each
+pub fn get_activity_history(
+    conn: &Connection,
+    offset: i64,
+    limit: i64,
+) -> Result<Vec<ActivityHistoryItem>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, window_title, dateofentry,
+                SUBSTR(COALESCE(NULLIF(edited_full_text, ''), original_full_text), 1, ?3)
+         FROM activity_full_text ORDER BY id DESC LIMIT ?1 OFFSET ?2",
+    )?;
+    let rows = stmt.query_map(params![limit, offset, PREVIEW_SOURCE_CHARS], |row| {
+        Ok(ActivityHistoryItem {
+            id: row.get(0)?,
+            title: row.get(1)?,
+            timestamp: row.get(2)?,
+            preview: activity_preview(&row.get::<_, String>(3)?),
+        })
+    })?;
+    rows.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::database::test_database;
+
+    #[test]
+    fn test_history_is_newest_first_with_previews() {
+        let conn = test_database();
+        conn.execute(
+            "INSERT INTO activity_full_text (dateofentry, window_title, original_full_text, edited_full_text)
+             VALUES ('2025-03-01 09:00:00', 'Inbox', '<p>Original</p>', '<p>Budget\n   is <b>approved</b></p>')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO activity_full_text (dateofentry, window_title, original_full_text)
+             VALUES ('2025-03-02 10:00:00', 'Report', ?1)",
+            [format!("<p>{}</p>", "word ".repeat(100))],
+        )
+        .unwrap();
+
+        let history = get_activity_history(&conn, 0, 10).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].title, "Report");
+        assert_eq!(history[0].timestamp, "2025-03-02 10:00:00");
+        assert_eq!(history[0].preview.chars().count(), ACTIVITY_PREVIEW_CHARS + 3);
+        assert!(history[0].preview.ends_with("..."));
+        assert_eq!(history[1].id, 1);
+        assert_eq!(history[1].preview, "Budget is approved");
+
+        let page = get_activity_history(&conn, 1, 10).unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].title, "Inbox");
+    }
+}
@@ -1,5 +1,6 @@
 use crate::entity::chat_item::{Chat, StoredMessage};
-use rusqlite::{params, Connection, Error, Result};
+use crate::repository::chat_document_link_repository::delete_links_for_chat;
+use rusqlite::{params, Connection, Error, OptionalExtension, Result};
 use chrono::Local;
 
 pub fn create_chat(db: &Connection, name: &str) -> Result<i64, Error> {
@@ -24,6 +25,10 @@ pub fn get_all_chats(db: &Connection) -> Result<Vec<Chat>, Error> {
     Ok(chats.collect::<Result<_, _>>()?)
 }
 
+pub fn get_chat_name(db: &Connection, chat_id: i64) -> Result<String, Error> {
+    db.query_row("SELECT name FROM chats WHERE id = ?", params![chat_id], |row| row.get(0))
+}
+
 pub fn create_message(db: &Connection, chat_id: i64, role: &str, content: &str, sources: Option<&str>) -> Result<i64, Error> {
     let now = Local::now().to_rfc3339();
     db.execute(
@@ -48,6 +53,32 @@ pub fn get_messages_by_chat_id(db: &Connection, chat_id: i64) -> Result<Vec<Stor
     Ok(messages.collect::<Result<_, _>>()?)
 }
 
+pub fn get_message(db: &Connection, message_id: i64) -> Result<Option<StoredMessage>, Error> {
+    db.query_row(
+        "SELECT id, chat_id, role, content, created_at, sources FROM messages WHERE id = ?",
+        params![message_id],
+        |row| {
+            Ok(StoredMessage {
+                id: row.get(0)?,
+                chat_id: row.get(1)?,
+                role: row.get(2)?,
+                content: row.get(3)?,
+                created_at: row.get(4)?,
+                sources: row.get(5)?,
+            })
+        },
+    )
+    .optional()
+}
+
+pub fn update_message_content(db: &Connection, message_id: i64, content: &str) -> Result<bool, Error> {
+    let rows_affected = db.execute(
+        "UPDATE messages SET content = ? WHERE id = ?",
+        params![content, message_id],
+    )?;
+    Ok(rows_affected > 0)
+}
+
 pub fn update_chat(conn: &Connection, chat_id: i64, name: &str) -> Result<bool> {
     let now = Local::now().to_rfc3339();
     let rows_affected = conn.execute(
@@ -60,6 +91,7 @@ pub fn update_chat(conn: &Connection, chat_id: i64, name: &str) -> Result<bool>
 pub fn delete_chat(db: &Connection, chat_id: i64) -> Result<bool, Error> {
     let rows_affected = db.execute("DELETE FROM chats WHERE id = ?", params![chat_id])?;
     db.execute("DELETE FROM messages WHERE chat_id = ?", params![chat_id])?;
+    delete_links_for_chat(db, chat_id)?;
 
     Ok(rows_affected > 0)
 }
\ No newline at end of file
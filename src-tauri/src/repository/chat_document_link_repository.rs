@@ -0,0 +1,111 @@
+use rusqlite::{params, Connection};
+
+use crate::entity::chat_document_link::{LinkedChat, LinkedDocument};
+
+/// Record that `document_id` was saved from `message_id` of `chat_id`
+pub fn link_message_to_document(
+    conn: &Connection,
+    chat_id: i64,
+    message_id: i64,
+    document_id: i64,
+) -> Result<i64, rusqlite::Error> {
+    conn.execute(
+        "INSERT INTO chat_document_links (chat_id, message_id, document_id) VALUES (?1, ?2, ?3)",
+        params![chat_id, message_id, document_id],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Documents saved from a chat's messages, oldest first
+pub fn get_documents_for_chat(conn: &Connection, chat_id: i64) -> Result<Vec<LinkedDocument>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT l.document_id, pa.document_name, pa.project_id, l.message_id, l.created_at
+         FROM chat_document_links l
+         JOIN projects_activities pa ON pa.id = l.document_id
+         WHERE l.chat_id = ?1
+         ORDER BY l.id",
+    )?;
+    let rows = stmt.query_map(params![chat_id], |row| {
+        Ok(LinkedDocument {
+            document_id: row.get(0)?,
+            document_name: row.get(1)?,
+            project_id: row.get(2)?,
+            message_id: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    })?;
+    rows.collect()
+}
+
+/// Chats a document was saved from, oldest first
+pub fn get_chats_for_document(conn: &Connection, document_id: i64) -> Result<Vec<LinkedChat>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT l.chat_id, c.name, l.message_id, l.created_at
+         FROM chat_document_links l
+         JOIN chats c ON c.id = l.chat_id
+         WHERE l.document_id = ?1
+         ORDER BY l.id",
+    )?;
+    let rows = stmt.query_map(params![document_id], |row| {
+        Ok(LinkedChat {
+            chat_id: row.get(0)?,
+            chat_name: row.get(1)?,
+            message_id: row.get(2)?,
+            created_at: row.get(3)?,
+        })
+    })?;
+    rows.collect()
+}
+
+pub fn delete_links_for_chat(conn: &Connection, chat_id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM chat_document_links WHERE chat_id = ?1", params![chat_id])?;
+    Ok(())
+}
+
+pub fn delete_links_for_document(conn: &Connection, document_id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM chat_document_links WHERE document_id = ?1", params![document_id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::database::test_database;
+
+    fn links_db() -> Connection {
+        let conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO chats (id, name) VALUES (1, 'Planning'), (2, 'Research');
+             INSERT INTO messages (id, chat_id, role, content)
+                VALUES (100, 1, 'assistant', 'a'), (101, 1, 'assistant', 'b'), (200, 2, 'assistant', 'c');
+             INSERT INTO projects_activities (id, project_id, document_name)
+                VALUES (10, 3, 'Roadmap'), (11, 3, 'Sources');",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_links_are_found_from_both_sides_and_deleted_with_either() {
+        let conn = links_db();
+        link_message_to_document(&conn, 1, 100, 10).unwrap();
+        link_message_to_document(&conn, 1, 101, 11).unwrap();
+        link_message_to_document(&conn, 2, 200, 11).unwrap();
+
+        let documents = get_documents_for_chat(&conn, 1).unwrap();
+        let names: Vec<_> = documents.iter().map(|d| (d.document_name.as_str(), d.message_id)).collect();
+        assert_eq!(names, vec![("Roadmap", 100), ("Sources", 101)]);
+        assert_eq!(documents[0].project_id, 3);
+
+        let chats = get_chats_for_document(&conn, 11).unwrap();
+        let names: Vec<_> = chats.iter().map(|c| (c.chat_name.as_str(), c.message_id)).collect();
+        assert_eq!(names, vec![("Planning", 101), ("Research", 200)]);
+
+        delete_links_for_chat(&conn, 1).unwrap();
+        assert!(get_documents_for_chat(&conn, 1).unwrap().is_empty());
+        assert_eq!(get_chats_for_document(&conn, 11).unwrap().len(), 1);
+
+        delete_links_for_document(&conn, 11).unwrap();
+        assert!(get_documents_for_chat(&conn, 2).unwrap().is_empty());
+    }
+}
@@ -1,10 +1,13 @@
-use rusqlite::{params, Connection};
+use rusqlite::{params, Connection, OptionalExtension};
 use log::info;
 
 const CHUNK_SIZE: usize = 4000;  // ~700 words per chunk
 const CHUNK_OVERLAP: usize = 400;
+/// Shorter repeats at a seam are only dropped when they are whole words, so a
+/// stray matching letter or space is not taken for the overlap
+const MIN_STITCH_OVERLAP: usize = 16;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct DocumentChunk {
     pub id: i64,
     pub document_id: i64,
@@ -30,7 +33,7 @@ pub fn split_into_chunks(text: &str) -> Vec<String> {
     let mut start = 0;
     
     while start < text.len() {
-        let end = std::cmp::min(start + CHUNK_SIZE, text.len());
+        let end = floor_char_boundary(text, std::cmp::min(start + CHUNK_SIZE, text.len()));
         
         // Try to find a good break point (sentence end or paragraph)
         let chunk_end = if end < text.len() {
@@ -49,7 +52,7 @@ pub fn split_into_chunks(text: &str) -> Vec<String> {
             break;
         }
         start = if chunk_end > CHUNK_OVERLAP {
-            chunk_end - CHUNK_OVERLAP
+            floor_char_boundary(text, chunk_end - CHUNK_OVERLAP)
         } else {
             chunk_end
         };
@@ -58,11 +61,72 @@ pub fn split_into_chunks(text: &str) -> Vec<String> {
     chunks
 }
 
+/// Join chunks of a document, in order, back into one passage. A chunk that
+/// directly follows the one before it repeats up to `CHUNK_OVERLAP` bytes of
+/// it; that repeat is dropped at the seam. Other chunks, such as neighbours
+/// with a chunk left out between them, are joined with a newline.
+pub fn stitch_chunks<'a>(chunks: impl IntoIterator<Item = &'a DocumentChunk>) -> String {
+    let mut stitched = String::new();
+    let mut previous_index = None;
+    for chunk in chunks {
+        let text = chunk.chunk_text.as_str();
+        let overlap = match previous_index {
+            Some(index) if index + 1 == chunk.chunk_index => overlap_len(&stitched, text),
+            _ => 0,
+        };
+        if overlap == 0 && !stitched.is_empty() {
+            stitched.push('\n');
+        }
+        stitched.push_str(&text[overlap..]);
+        previous_index = Some(chunk.chunk_index);
+    }
+    stitched
+}
+
+/// Length of the longest start of `next` that `previous` ends with, or 0 when
+/// it is shorter than `MIN_STITCH_OVERLAP` bytes and not whole words. The
+/// overlap starts on the character boundary at or below `CHUNK_OVERLAP` bytes
+/// before the seam, so it can run a few bytes over it.
+fn overlap_len(previous: &str, next: &str) -> usize {
+    let ends: Vec<usize> = next
+        .char_indices()
+        .take_while(|(index, _)| *index < CHUNK_OVERLAP)
+        .map(|(index, c)| index + c.len_utf8())
+        .collect();
+    ends.into_iter()
+        .rev()
+        .find(|&len| previous.ends_with(&next[..len]))
+        .filter(|&len| len >= MIN_STITCH_OVERLAP || is_whole_words(previous, next, len))
+        .unwrap_or(0)
+}
+
+/// Whether the `len` bytes `next` starts with, which `previous` ends with, are
+/// words cut at neither end
+fn is_whole_words(previous: &str, next: &str, len: usize) -> bool {
+    let overlap = &next[..len];
+    let word_start = previous[..previous.len() - len]
+        .chars()
+        .next_back()
+        .map_or(true, |c| !c.is_alphanumeric());
+    let word_end = next[len..].chars().next().map_or(true, |c| !c.is_alphanumeric());
+    overlap.chars().any(char::is_alphanumeric) && word_start && word_end
+}
+
+/// Largest char boundary at or below `index`, so slicing never splits a
+/// multi-byte character
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    let mut index = std::cmp::min(index, text.len());
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
 /// Find a good break point near the target end position
 fn find_break_point(text: &str, start: usize, target_end: usize) -> usize {
     // Look for sentence endings near the target
     let search_range = std::cmp::min(200, target_end - start);
-    let search_start = target_end.saturating_sub(search_range);
+    let search_start = floor_char_boundary(text, target_end - search_range);
     
     // Priority: paragraph break > sentence end > word break
     let slice = &text[search_start..target_end];
@@ -73,21 +137,61 @@ fn find_break_point(text: &str, start: usize, target_end: usize) -> usize {
     }
     
     // Look for sentence end
-    for pattern in &[". ", "! ", "? ", ".\n", "!\n", "?\n"] {
-        if let Some(pos) = slice.rfind(pattern) {
-            return search_start + pos + pattern.len();
-        }
+    if let Some(pos) = last_sentence_end(slice) {
+        return search_start + pos;
     }
     
-    // Look for word break (space)
-    if let Some(pos) = slice.rfind(' ') {
-        return search_start + pos + 1;
+    // Look for word break (whitespace)
+    if let Some((pos, ws)) = slice.char_indices().rev().find(|(_, c)| c.is_whitespace()) {
+        return search_start + pos + ws.len_utf8();
     }
     
     // Fallback to target end
     target_end
 }
 
+/// Words that end in a period without ending the sentence
+const ABBREVIATIONS: &[&str] = &[
+    "mr", "mrs", "ms", "dr", "prof", "sr", "jr", "st", "vs", "etc", "e.g", "i.e", "inc", "ltd",
+    "co", "corp", "no", "fig", "approx", "dept", "est", "jan", "feb", "mar", "apr", "jun", "jul",
+    "aug", "sep", "sept", "oct", "nov", "dec",
+];
+
+/// Byte offset just past the last sentence terminator in `slice`.
+/// Latin `.`, `!` and `?` count only when followed by whitespace, which rules
+/// out decimals such as "3.14"; a period after a known abbreviation or a
+/// single-letter initial is not a boundary. Full-width `。`, `！` and `？`
+/// always end a sentence.
+fn last_sentence_end(slice: &str) -> Option<usize> {
+    let mut last = None;
+    let mut chars = slice.char_indices().peekable();
+    while let Some((pos, c)) = chars.next() {
+        let end = pos + c.len_utf8();
+        let is_boundary = match c {
+            '。' | '！' | '？' => true,
+            '.' | '!' | '?' => {
+                let followed_by_space = chars.peek().map_or(false, |(_, next)| next.is_whitespace());
+                followed_by_space && (c != '.' || !ends_with_abbreviation(&slice[..pos]))
+            }
+            _ => false,
+        };
+        if is_boundary {
+            last = Some(end);
+        }
+    }
+    last
+}
+
+/// Whether `before` (text up to a period) ends in an abbreviation or initial
+fn ends_with_abbreviation(before: &str) -> bool {
+    let word = before
+        .rsplit(|c: char| c.is_whitespace() || c == '(' || c == '"')
+        .next()
+        .unwrap_or("");
+    let is_initial = word.chars().count() == 1 && word.chars().all(|c| c.is_uppercase());
+    is_initial || ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+}
+
 /// Delete existing chunks for a document
 pub fn delete_chunks_for_document(conn: &Connection, document_id: i64) -> Result<(), rusqlite::Error> {
     conn.execute(
@@ -97,7 +201,20 @@ pub fn delete_chunks_for_document(conn: &Connection, document_id: i64) -> Result
     Ok(())
 }
 
-/// Save chunks for a document
+/// Whether a document is marked `exclude_from_rag`; unknown documents are not
+pub fn is_excluded_from_rag(conn: &Connection, document_id: i64) -> Result<bool, rusqlite::Error> {
+    let excluded = conn
+        .query_row(
+            "SELECT exclude_from_rag FROM projects_activities WHERE id = ?1",
+            params![document_id],
+            |row| row.get::<_, bool>(0),
+        )
+        .optional()?;
+    Ok(excluded.unwrap_or(false))
+}
+
+/// Save chunks for a document. Documents excluded from RAG only lose their
+/// existing chunks, so nothing of theirs is vectorized or retrieved.
 pub fn save_chunks_for_document(
     conn: &Connection,
     document_id: i64,
@@ -106,6 +223,11 @@ pub fn save_chunks_for_document(
 ) -> Result<Vec<i64>, rusqlite::Error> {
     // First delete any existing chunks
     delete_chunks_for_document(conn, document_id)?;
+
+    if is_excluded_from_rag(conn, document_id)? {
+        info!("Document {} is excluded from RAG, not chunking it", document_id);
+        return Ok(vec![]);
+    }
     
     // Split into chunks
     let chunks = split_into_chunks(plain_text);
@@ -154,6 +276,22 @@ pub fn get_unvectorized_chunks(conn: &Connection, project_id: i64, limit: i64) -
     Ok(chunks)
 }
 
+/// Projects that still have unvectorized chunks, with how many, for resuming
+/// indexing that was interrupted by closing the app
+pub fn get_projects_with_unvectorized_chunks(conn: &Connection) -> Result<Vec<(i64, i64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT project_id, COUNT(*)
+         FROM document_chunks
+         WHERE is_vectorized = 0
+         GROUP BY project_id
+         ORDER BY project_id"
+    )?;
+    let projects = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(projects)
+}
+
 /// Mark a chunk as vectorized
 pub fn mark_chunk_as_vectorized(conn: &Connection, chunk_id: i64) -> Result<(), rusqlite::Error> {
     conn.execute(
@@ -163,6 +301,14 @@ pub fn mark_chunk_as_vectorized(conn: &Connection, chunk_id: i64) -> Result<(),
     Ok(())
 }
 
+/// Mark every chunk in a project as needing vectorization, before its index is rebuilt
+pub fn mark_project_chunks_unvectorized(conn: &Connection, project_id: i64) -> Result<usize, rusqlite::Error> {
+    conn.execute(
+        "UPDATE document_chunks SET is_vectorized = 0 WHERE project_id = ?1",
+        params![project_id],
+    )
+}
+
 /// Get all chunk IDs for a project (for vector search filtering)
 pub fn get_chunk_ids_for_project(conn: &Connection, project_id: i64) -> Result<Vec<i64>, rusqlite::Error> {
     let mut stmt = conn.prepare(
@@ -175,6 +321,116 @@ pub fn get_chunk_ids_for_project(conn: &Connection, project_id: i64) -> Result<V
     Ok(ids)
 }
 
+/// Get the IDs of a document's chunks that are already in its project's vector index
+pub fn get_vectorized_chunk_ids_for_document(conn: &Connection, document_id: i64) -> Result<Vec<i64>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM document_chunks WHERE document_id = ?1 AND is_vectorized = 1"
+    )?;
+
+    let ids = stmt.query_map(params![document_id], |row| row.get(0))?
+        .collect::<Result<Vec<i64>, _>>()?;
+
+    Ok(ids)
+}
+
+/// Whether a document can be found by project search yet
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorizationStatus {
+    /// Every chunk is in the project's vector index
+    Indexed,
+    /// Some chunks are still waiting to be vectorized
+    Pending,
+    /// The document has no text to chunk
+    TooShort,
+    /// The document is kept out of the index with `exclude_from_rag`
+    Excluded,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DocumentVectorizationStatus {
+    pub document_id: i64,
+    pub chunk_count: i64,
+    pub vectorized_count: i64,
+    pub status: VectorizationStatus,
+}
+
+/// Chunk and vectorization counts for every document in a project
+pub fn get_vectorization_status_for_project(
+    conn: &Connection,
+    project_id: i64,
+) -> Result<Vec<DocumentVectorizationStatus>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT pa.id, COUNT(dc.id), COALESCE(SUM(dc.is_vectorized), 0), pa.exclude_from_rag
+         FROM projects_activities pa
+         LEFT JOIN document_chunks dc ON dc.document_id = pa.id
+         WHERE pa.project_id = ?1
+         GROUP BY pa.id
+         ORDER BY pa.id",
+    )?;
+
+    let rows = stmt.query_map(params![project_id], |row| {
+        let chunk_count: i64 = row.get(1)?;
+        let vectorized_count: i64 = row.get(2)?;
+        let status = if row.get::<_, bool>(3)? {
+            VectorizationStatus::Excluded
+        } else if chunk_count == 0 {
+            VectorizationStatus::TooShort
+        } else if vectorized_count >= chunk_count {
+            VectorizationStatus::Indexed
+        } else {
+            VectorizationStatus::Pending
+        };
+        Ok(DocumentVectorizationStatus {
+            document_id: row.get(0)?,
+            chunk_count,
+            vectorized_count,
+            status,
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Documents shorter than this are left out of `get_unvectorized_documents`
+pub const UNVECTORIZED_MIN_CHARS: i64 = 200;
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct UnvectorizedDocument {
+    pub document_id: i64,
+    pub project_id: i64,
+    pub document_name: String,
+    pub chunk_count: i64,
+    pub vectorized_count: i64,
+}
+
+/// Documents with enough text to be searchable that are still missing from
+/// their project's vector index: either never chunked, or with chunks that
+/// failed to vectorize. Excluded documents are skipped.
+pub fn get_unvectorized_documents(conn: &Connection) -> Result<Vec<UnvectorizedDocument>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT pa.id, pa.project_id, pa.document_name, COUNT(dc.id), COALESCE(SUM(dc.is_vectorized), 0)
+         FROM projects_activities pa
+         LEFT JOIN document_chunks dc ON dc.document_id = pa.id
+         WHERE pa.exclude_from_rag = 0 AND LENGTH(TRIM(COALESCE(pa.plain_text, ''))) > ?1
+         GROUP BY pa.id
+         HAVING COUNT(dc.id) = 0 OR COALESCE(SUM(dc.is_vectorized), 0) < COUNT(dc.id)
+         ORDER BY pa.project_id, pa.id",
+    )?;
+
+    let rows = stmt.query_map(params![UNVECTORIZED_MIN_CHARS], |row| {
+        Ok(UnvectorizedDocument {
+            document_id: row.get(0)?,
+            project_id: row.get(1)?,
+            document_name: row.get(2)?,
+            chunk_count: row.get(3)?,
+            vectorized_count: row.get(4)?,
+        })
+    })?;
+
+    rows.collect()
+}
+
 /// Get chunk text by IDs
 pub fn get_chunks_by_ids(conn: &Connection, chunk_ids: &[i64]) -> Result<Vec<DocumentChunk>, rusqlite::Error> {
     if chunk_ids.is_empty() {
@@ -209,6 +465,58 @@ pub fn get_chunks_by_ids(conn: &Connection, chunk_ids: &[i64]) -> Result<Vec<Doc
     Ok(chunks)
 }
 
+/// A document's chunks in document order
+pub fn get_chunks_for_document(conn: &Connection, document_id: i64) -> Result<Vec<DocumentChunk>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, document_id, project_id, chunk_index, chunk_text, is_vectorized
+         FROM document_chunks
+         WHERE document_id = ?1
+         ORDER BY chunk_index"
+    )?;
+    let chunks = stmt.query_map(params![document_id], |row| {
+        Ok(DocumentChunk {
+            id: row.get(0)?,
+            document_id: row.get(1)?,
+            project_id: row.get(2)?,
+            chunk_index: row.get(3)?,
+            chunk_text: row.get(4)?,
+            is_vectorized: row.get::<_, i32>(5)? == 1,
+        })
+    })?.collect::<Result<Vec<_>, _>>()?;
+    Ok(chunks)
+}
+
+/// Replace the text of one chunk, keeping its place in the document. The
+/// chunk is stored under a new id and left unvectorized, since the project
+/// index cannot replace the vector of an id it already holds. Returns the
+/// chunk it replaced and the new one, or None when `chunk_id` does not exist.
+/// The insert and delete commit together, so a chunk is never lost or doubled.
+pub fn replace_chunk_text(
+    conn: &mut Connection,
+    chunk_id: i64,
+    text: &str,
+) -> Result<Option<(DocumentChunk, DocumentChunk)>, rusqlite::Error> {
+    let tx = conn.transaction()?;
+    let old = match get_chunks_by_ids(&tx, &[chunk_id])?.pop() {
+        Some(old) => old,
+        None => return Ok(None),
+    };
+    tx.execute(
+        "INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text, is_vectorized)
+         VALUES (?1, ?2, ?3, ?4, 0)",
+        params![old.document_id, old.project_id, old.chunk_index, text],
+    )?;
+    let new = DocumentChunk {
+        id: tx.last_insert_rowid(),
+        chunk_text: text.to_string(),
+        is_vectorized: false,
+        ..old.clone()
+    };
+    tx.execute("DELETE FROM document_chunks WHERE id = ?1", params![chunk_id])?;
+    tx.commit()?;
+    Ok(Some((old, new)))
+}
+
 /// Get total chunk count for a project
 pub fn get_chunk_count_for_project(conn: &Connection, project_id: i64) -> Result<i64, rusqlite::Error> {
     conn.query_row(
@@ -218,6 +526,15 @@ pub fn get_chunk_count_for_project(conn: &Connection, project_id: i64) -> Result
     )
 }
 
+/// Number of chunks a document has
+pub fn get_chunk_count_for_document(conn: &Connection, document_id: i64) -> Result<i64, rusqlite::Error> {
+    conn.query_row(
+        "SELECT COUNT(*) FROM document_chunks WHERE document_id = ?1",
+        params![document_id],
+        |row| row.get(0),
+    )
+}
+
 /// Source information for a document chunk
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct ChunkSource {
@@ -263,6 +580,68 @@ pub fn get_chunk_sources(conn: &Connection, chunk_ids: &[i64]) -> Result<Vec<Chu
     Ok(sources)
 }
 
+/// Characters of a document's merged preview, across all its cited chunks
+const DOCUMENT_PREVIEW_CHARS: usize = 300;
+
+/// Citation for one document, covering every retrieved chunk it contributed
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DocumentSource {
+    pub document_id: i64,
+    pub document_name: String,
+    /// Contributing chunks in document order, with `chunk_indices` alongside
+    pub chunk_ids: Vec<i64>,
+    pub chunk_indices: Vec<i32>,
+    /// Chunk previews in document order, joined and cut to `DOCUMENT_PREVIEW_CHARS`
+    pub preview: String,
+}
+
+/// Group per-chunk sources by document. Documents keep the order of their
+/// first chunk in `sources`, so rank-ordered sources give rank-ordered documents.
+pub fn group_chunk_sources(sources: &[ChunkSource]) -> Vec<DocumentSource> {
+    let mut documents: Vec<(DocumentSource, Vec<&ChunkSource>)> = Vec::new();
+    for source in sources {
+        match documents.iter_mut().find(|(document, _)| document.document_id == source.document_id) {
+            Some((_, chunks)) => chunks.push(source),
+            None => documents.push((
+                DocumentSource {
+                    document_id: source.document_id,
+                    document_name: source.document_name.clone(),
+                    chunk_ids: Vec::new(),
+                    chunk_indices: Vec::new(),
+                    preview: String::new(),
+                },
+                vec![source],
+            )),
+        }
+    }
+    documents
+        .into_iter()
+        .map(|(mut document, mut chunks)| {
+            chunks.sort_by_key(|chunk| chunk.chunk_index);
+            document.chunk_ids = chunks.iter().map(|chunk| chunk.chunk_id).collect();
+            document.chunk_indices = chunks.iter().map(|chunk| chunk.chunk_index).collect();
+            let merged = chunks
+                .iter()
+                .map(|chunk| chunk.chunk_preview.trim_end_matches("..."))
+                .collect::<Vec<_>>()
+                .join(" … ");
+            document.preview = if merged.chars().count() > DOCUMENT_PREVIEW_CHARS {
+                merged.chars().take(DOCUMENT_PREVIEW_CHARS).collect::<String>() + "..."
+            } else {
+                merged + "..."
+            };
+            document
+        })
+        .collect()
+}
+
+/// Get one citation per document for chunk IDs, in the order the IDs are given
+pub fn get_document_sources(conn: &Connection, chunk_ids: &[i64]) -> Result<Vec<DocumentSource>, rusqlite::Error> {
+    let mut sources = get_chunk_sources(conn, chunk_ids)?;
+    sources.sort_by_key(|source| chunk_ids.iter().position(|id| *id == source.chunk_id));
+    Ok(group_chunk_sources(&sources))
+}
+
 /// Get full text for a single chunk by ID
 pub fn get_chunk_full_text(conn: &Connection, chunk_id: i64) -> Result<Option<String>, rusqlite::Error> {
     let mut stmt = conn.prepare("SELECT chunk_text FROM document_chunks WHERE id = ?")?;
@@ -274,10 +653,300 @@ pub fn get_chunk_full_text(conn: &Connection, chunk_id: i64) -> Result<Option<St
     }
 }
 
+/// A chunk together with its neighbours in the same document
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ChunkWithContext {
+    pub chunk: DocumentChunk,
+    /// Up to `radius` chunks immediately before, in document order
+    pub previous: Vec<DocumentChunk>,
+    /// Up to `radius` chunks immediately after, in document order
+    pub next: Vec<DocumentChunk>,
+}
+
+/// Get a chunk plus the `radius` chunks on either side of it (by chunk_index)
+pub fn get_chunk_with_context(conn: &Connection, chunk_id: i64, radius: i32) -> Result<Option<ChunkWithContext>, rusqlite::Error> {
+    let position = conn.query_row(
+        "SELECT document_id, chunk_index FROM document_chunks WHERE id = ?1",
+        params![chunk_id],
+        |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i32>(1)?)),
+    );
+    let (document_id, chunk_index) = match position {
+        Ok(position) => position,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let mut stmt = conn.prepare(
+        "SELECT id, document_id, project_id, chunk_index, chunk_text, is_vectorized
+         FROM document_chunks
+         WHERE document_id = ?1 AND chunk_index BETWEEN ?2 AND ?3
+         ORDER BY chunk_index"
+    )?;
+    let window = stmt.query_map(
+        params![document_id, chunk_index - radius, chunk_index + radius],
+        |row| {
+            Ok(DocumentChunk {
+                id: row.get(0)?,
+                document_id: row.get(1)?,
+                project_id: row.get(2)?,
+                chunk_index: row.get(3)?,
+                chunk_text: row.get(4)?,
+                is_vectorized: row.get::<_, i32>(5)? == 1,
+            })
+        }
+    )?.collect::<Result<Vec<_>, _>>()?;
+
+    let mut previous = Vec::new();
+    let mut next = Vec::new();
+    let mut chunk = None;
+    for candidate in window {
+        if candidate.id == chunk_id {
+            chunk = Some(candidate);
+        } else if candidate.chunk_index < chunk_index {
+            previous.push(candidate);
+        } else {
+            next.push(candidate);
+        }
+    }
+
+    Ok(chunk.map(|chunk| ChunkWithContext { chunk, previous, next }))
+}
+
+/// The chunk plus up to `window` chunks before and after it in its document, in
+/// document order. Empty when the chunk does not exist.
+pub fn get_chunk_neighbors(conn: &Connection, chunk_id: i64, window: i32) -> Result<Vec<DocumentChunk>, rusqlite::Error> {
+    Ok(get_chunk_with_context(conn, chunk_id, window)?
+        .map(|context| {
+            context
+                .previous
+                .into_iter()
+                .chain(std::iter::once(context.chunk))
+                .chain(context.next)
+                .collect()
+        })
+        .unwrap_or_default())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::configuration::database::test_database;
+
+    fn chunks_db() -> Connection {
+        let conn = test_database();
+        conn.execute_batch("INSERT INTO projects (id, name) VALUES (1, 'One'), (7, 'Seven'), (8, 'Eight'), (9, 'Nine');").unwrap();
+        conn
+    }
+
+    fn chunk_source(chunk_id: i64, document_id: i64, chunk_index: i32, preview: &str) -> ChunkSource {
+        ChunkSource {
+            chunk_id,
+            document_id,
+            document_name: format!("Document {}", document_id),
+            chunk_index,
+            chunk_preview: format!("{}...", preview),
+        }
+    }
+
+    #[test]
+    fn test_group_chunk_sources_merges_chunks_per_document() {
+        let sources = vec![
+            chunk_source(15, 2, 5, "later"),
+            chunk_source(30, 3, 0, "other"),
+            chunk_source(12, 2, 2, "earlier"),
+        ];
+        let documents = group_chunk_sources(&sources);
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].document_id, 2);
+        assert_eq!(documents[0].chunk_ids, vec![12, 15]);
+        assert_eq!(documents[0].chunk_indices, vec![2, 5]);
+        assert_eq!(documents[0].preview, "earlier … later...");
+        assert_eq!(documents[1].chunk_ids, vec![30]);
+    }
+
+    fn document_chunk(chunk_index: i32, chunk_text: &str) -> DocumentChunk {
+        DocumentChunk {
+            id: i64::from(chunk_index) + 1,
+            document_id: 1,
+            project_id: 1,
+            chunk_index,
+            chunk_text: chunk_text.to_string(),
+            is_vectorized: true,
+        }
+    }
+
+    #[test]
+    fn test_stitch_drops_the_overlap_of_consecutive_chunks_only() {
+        let overlap = "the budget was approved in March";
+        let first = document_chunk(0, &format!("Last year {}", overlap));
+        let second = document_chunk(1, &format!("{} and spent by June", overlap));
+        assert_eq!(
+            stitch_chunks(&[first.clone(), second]),
+            "Last year the budget was approved in March and spent by June"
+        );
+
+        // With chunk 1 left out, chunk 2 repeating the end of chunk 0 is chance
+        let third = document_chunk(2, &format!("{} again", overlap));
+        assert_eq!(
+            stitch_chunks(&[first, third]),
+            format!("Last year {}\n{} again", overlap, overlap)
+        );
+
+        // A short repeat only counts when it is whole words
+        let short = [document_chunk(0, "Total: 40"), document_chunk(1, "0 items")];
+        assert_eq!(stitch_chunks(&short), "Total: 40\n0 items");
+        let word = [document_chunk(0, "Ask the team"), document_chunk(1, "team lead")];
+        assert_eq!(stitch_chunks(&word), "Ask the team lead");
+    }
+
+    #[test]
+    fn test_chunk_with_context_returns_neighbours() {
+        let conn = chunks_db();
+        conn.execute_batch("INSERT INTO projects_activities (id, project_id) VALUES (1, 1), (2, 1);").unwrap();
+        for index in 0..5 {
+            conn.execute(
+                "INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text) VALUES (1, 1, ?1, ?2)",
+                params![index, format!("chunk {}", index)],
+            ).unwrap();
+        }
+        // Same chunk_index in another document must not leak in
+        conn.execute(
+            "INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text) VALUES (2, 1, 1, 'other')",
+            [],
+        ).unwrap();
+
+        let context = get_chunk_with_context(&conn, 1, 1).unwrap().unwrap();
+        assert_eq!(context.chunk.chunk_text, "chunk 0");
+        assert!(context.previous.is_empty());
+        assert_eq!(context.next.len(), 1);
+
+        let context = get_chunk_with_context(&conn, 3, 1).unwrap().unwrap();
+        assert_eq!(context.previous[0].chunk_text, "chunk 1");
+        assert_eq!(context.next[0].chunk_text, "chunk 3");
+
+        assert!(get_chunk_with_context(&conn, 99, 1).unwrap().is_none());
+
+        let neighbors: Vec<String> = get_chunk_neighbors(&conn, 3, 1).unwrap().into_iter().map(|chunk| chunk.chunk_text).collect();
+        assert_eq!(neighbors, vec!["chunk 1", "chunk 2", "chunk 3"]);
+        assert!(get_chunk_neighbors(&conn, 99, 1).unwrap().is_empty());
+    }
     
+    #[test]
+    fn test_vectorization_status_per_document() {
+        let conn = chunks_db();
+        conn.execute_batch(
+            "INSERT INTO projects_activities (id, project_id) VALUES (1, 7), (2, 7), (3, 7), (4, 8);
+             INSERT INTO projects_activities (id, project_id, exclude_from_rag) VALUES (5, 7, 1);
+             INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text, is_vectorized)
+             VALUES (1, 7, 0, 'a', 1), (1, 7, 1, 'b', 1), (2, 7, 0, 'c', 1), (2, 7, 1, 'd', 0);"
+        ).unwrap();
+
+        let statuses = get_vectorization_status_for_project(&conn, 7).unwrap();
+        let by_id: Vec<(i64, i64, VectorizationStatus)> = statuses
+            .iter()
+            .map(|s| (s.document_id, s.chunk_count, s.status))
+            .collect();
+        assert_eq!(by_id, vec![
+            (1, 2, VectorizationStatus::Indexed),
+            (2, 2, VectorizationStatus::Pending),
+            (3, 0, VectorizationStatus::TooShort),
+            (5, 0, VectorizationStatus::Excluded),
+        ]);
+    }
+
+    #[test]
+    fn test_unvectorized_documents_skip_short_excluded_and_indexed_ones() {
+        let conn = chunks_db();
+        let long_text = "word ".repeat(60);
+        let documents = [(1, 7, "Indexed", 0), (2, 7, "Half done", 0), (3, 7, "Never chunked", 0), (4, 7, "Excluded", 1)];
+        for (id, project_id, name, excluded) in documents {
+            conn.execute(
+                "INSERT INTO projects_activities (id, project_id, document_name, plain_text, exclude_from_rag)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![id, project_id, name, long_text, excluded],
+            ).unwrap();
+        }
+        conn.execute(
+            "INSERT INTO projects_activities (id, project_id, document_name, plain_text) VALUES (5, 8, 'Short', 'hi')",
+            [],
+        ).unwrap();
+        conn.execute_batch(
+            "INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text, is_vectorized)
+             VALUES (1, 7, 0, 'a', 1), (2, 7, 0, 'b', 1), (2, 7, 1, 'c', 0), (5, 8, 0, 'd', 0);"
+        ).unwrap();
+
+        let stuck: Vec<(i64, i64, i64)> = get_unvectorized_documents(&conn)
+            .unwrap()
+            .iter()
+            .map(|d| (d.document_id, d.chunk_count, d.vectorized_count))
+            .collect();
+        assert_eq!(stuck, vec![(2, 2, 1), (3, 0, 0)]);
+    }
+
+    #[test]
+    fn test_excluded_documents_are_not_chunked() {
+        let conn = chunks_db();
+        conn.execute_batch(
+            "INSERT INTO projects_activities (id, project_id, exclude_from_rag) VALUES (1, 7, 0), (2, 7, 1);
+             INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text, is_vectorized)
+             VALUES (2, 7, 0, 'old draft', 1);"
+        ).unwrap();
+
+        assert_eq!(save_chunks_for_document(&conn, 1, 7, "Kept notes").unwrap().len(), 1);
+        assert!(save_chunks_for_document(&conn, 2, 7, "Scratch notes").unwrap().is_empty());
+        assert_eq!(get_chunk_count_for_project(&conn, 7).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_replace_chunk_text_keeps_position_under_a_new_id() {
+        let mut conn = chunks_db();
+        conn.execute_batch(
+            "INSERT INTO projects_activities (id, project_id) VALUES (1, 7);
+             INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text, is_vectorized)
+             VALUES (1, 7, 0, 'intro', 1), (1, 7, 1, 'bad spl', 1), (1, 7, 2, 'it here', 1);"
+        ).unwrap();
+
+        let (old, new) = replace_chunk_text(&mut conn, 2, "good split").unwrap().unwrap();
+        assert_eq!(old.chunk_text, "bad spl");
+        assert!(old.is_vectorized);
+        assert_ne!(new.id, 2);
+        assert!(!new.is_vectorized);
+
+        let chunks = get_chunks_for_document(&conn, 1).unwrap();
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.chunk_text.as_str()).collect();
+        assert_eq!(texts, vec!["intro", "good split", "it here"]);
+        assert_eq!(chunks[1].id, new.id);
+        assert_eq!(chunks[1].chunk_index, 1);
+        assert!(replace_chunk_text(&mut conn, 2, "gone").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_projects_with_unvectorized_chunks() {
+        let conn = chunks_db();
+        conn.execute_batch(
+            "INSERT INTO projects_activities (id, project_id) VALUES (1, 7), (2, 8), (3, 9);
+             INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text, is_vectorized)
+             VALUES (1, 7, 0, 'a', 0), (1, 7, 1, 'b', 0), (2, 8, 0, 'c', 1), (3, 9, 0, 'd', 0);"
+        ).unwrap();
+
+        assert_eq!(get_projects_with_unvectorized_chunks(&conn).unwrap(), vec![(7, 2), (9, 1)]);
+        assert_eq!(get_unvectorized_chunks(&conn, 7, -1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_mark_project_chunks_unvectorized() {
+        let conn = chunks_db();
+        conn.execute_batch(
+            "INSERT INTO projects_activities (id, project_id) VALUES (1, 7), (2, 7), (3, 8);
+             INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text, is_vectorized)
+             VALUES (1, 7, 0, 'a', 1), (2, 7, 0, 'b', 0), (3, 8, 0, 'c', 1);"
+        ).unwrap();
+
+        assert_eq!(mark_project_chunks_unvectorized(&conn, 7).unwrap(), 2);
+        assert_eq!(get_unvectorized_chunks(&conn, 7, -1).unwrap().len(), 2);
+        assert_eq!(get_chunk_ids_for_project(&conn, 8).unwrap(), vec![3]);
+    }
+
     #[test]
     fn test_split_small_text() {
         let text = "This is a small text.";
@@ -293,6 +962,31 @@ mod tests {
         assert_eq!(chunks.len(), 0);
     }
     
+    #[test]
+    fn test_sentence_end_skips_abbreviations_and_decimals() {
+        let text = "The first point stands. Dr. Smith measured 3.14 units with J. Doe, etc. and left";
+        let end = last_sentence_end(text).unwrap();
+        assert_eq!(&text[..end], "The first point stands.");
+        assert!(last_sentence_end("Pi is 3.14 exactly").is_none());
+    }
+
+    #[test]
+    fn test_sentence_end_handles_full_width_punctuation() {
+        let text = "今日は晴れです。明日は雨ですか？たぶん";
+        let end = last_sentence_end(text).unwrap();
+        assert_eq!(&text[..end], "今日は晴れです。明日は雨ですか？");
+    }
+
+    #[test]
+    fn test_split_cjk_text_on_char_and_sentence_boundaries() {
+        let text = "これは文です。".repeat(400);
+        let chunks = split_into_chunks(&text);
+        assert!(chunks.len() > 1);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.ends_with('。'));
+        }
+    }
+
     #[test]
     fn test_split_large_text() {
         let text = "A".repeat(5000);
@@ -0,0 +1,136 @@
+use log::info;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, Value, ValueRef};
+use rusqlite::{params, Connection};
+
+use crate::repository::settings_repository::get_compress_documents;
+
+/// zstd level for stored documents: fast to write, and HTML still shrinks several times
+const COMPRESSION_LEVEL: i32 = 3;
+/// Columns holding document HTML, as (table, column)
+const DOCUMENT_TEXT_COLUMNS: [(&str, &str); 3] = [
+    ("projects_activities", "full_document_text"),
+    ("projects_activities", "backup_document_text"),
+    ("document_versions", "full_document_text"),
+];
+
+/// Document HTML as stored: TEXT, or a zstd-compressed BLOB when
+/// `compress_documents` was on at write time. SQLite keeps whichever type was
+/// written, so a column can hold both and reads accept either.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentText(pub Option<String>);
+
+impl DocumentText {
+    pub fn into_string(self) -> String {
+        self.0.unwrap_or_default()
+    }
+}
+
+impl FromSql for DocumentText {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value {
+            ValueRef::Null => Ok(DocumentText(None)),
+            ValueRef::Text(text) => String::from_utf8(text.to_vec())
+                .map(|text| DocumentText(Some(text)))
+                .map_err(|e| FromSqlError::Other(Box::new(e))),
+            ValueRef::Blob(compressed) => {
+                let bytes = zstd::decode_all(compressed).map_err(|e| FromSqlError::Other(Box::new(e)))?;
+                String::from_utf8(bytes)
+                    .map(|text| DocumentText(Some(text)))
+                    .map_err(|e| FromSqlError::Other(Box::new(e)))
+            }
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
+fn compress(html: &str) -> Result<Value, rusqlite::Error> {
+    zstd::encode_all(html.as_bytes(), COMPRESSION_LEVEL)
+        .map(Value::Blob)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// The value to write for `html`: compressed when `compress_documents` is on
+pub fn stored_document_text(conn: &Connection, html: &str) -> Result<Value, rusqlite::Error> {
+    if get_compress_documents(conn)? {
+        compress(html)
+    } else {
+        Ok(Value::Text(html.to_string()))
+    }
+}
+
+/// Compress document HTML still stored as TEXT, such as rows written before
+/// compression existed. A no-op when `compress_documents` is off. Returns the
+/// number of values compressed.
+pub fn compress_stored_documents(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    if !get_compress_documents(conn)? {
+        return Ok(0);
+    }
+    let mut compressed = 0;
+    for (table, column) in DOCUMENT_TEXT_COLUMNS {
+        let mut select = conn.prepare(&format!(
+            "SELECT id, {column} FROM {table} WHERE typeof({column}) = 'text'"
+        ))?;
+        let rows = select
+            .query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut update = conn.prepare(&format!("UPDATE {table} SET {column} = ?1 WHERE id = ?2"))?;
+        for (id, html) in rows {
+            update.execute(params![compress(&html)?, id])?;
+            compressed += 1;
+        }
+    }
+    if compressed > 0 {
+        info!("Compressed {} stored document texts", compressed);
+    }
+    Ok(compressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::database::test_database;
+
+    fn read(conn: &Connection, id: i64) -> (String, DocumentText) {
+        conn.query_row(
+            "SELECT typeof(full_document_text), full_document_text FROM projects_activities WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_compressed_and_plain_rows_read_the_same() {
+        let conn = test_database();
+        let html = "<p>Quarterly plan</p>".repeat(50);
+        conn.execute(
+            "INSERT INTO projects_activities (id, project_id, full_document_text) VALUES (1, 1, ?1), (2, 1, ?2)",
+            params![stored_document_text(&conn, &html).unwrap(), html],
+        ).unwrap();
+
+        assert_eq!(read(&conn, 1), ("blob".to_string(), DocumentText(Some(html.clone()))));
+        assert_eq!(read(&conn, 2), ("text".to_string(), DocumentText(Some(html.clone()))));
+        let no_backup: DocumentText = conn
+            .query_row("SELECT backup_document_text FROM projects_activities WHERE id = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(no_backup, DocumentText(None));
+    }
+
+    #[test]
+    fn test_compresses_existing_rows_unless_turned_off() {
+        let conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO projects_activities (id, project_id, full_document_text, backup_document_text)
+                VALUES (1, 1, '<p>a</p>', '<p>b</p>');
+             INSERT INTO document_versions (id, document_id, full_document_text) VALUES (1, 1, '<p>c</p>');
+             INSERT INTO settings VALUES ('compress_documents', 'false');"
+        ).unwrap();
+        assert_eq!(compress_stored_documents(&conn).unwrap(), 0);
+        assert_eq!(stored_document_text(&conn, "<p>d</p>").unwrap(), Value::Text("<p>d</p>".to_string()));
+
+        conn.execute("UPDATE settings SET setting_value = 'true'", []).unwrap();
+        assert_eq!(compress_stored_documents(&conn).unwrap(), 3);
+        assert_eq!(read(&conn, 1), ("blob".to_string(), DocumentText(Some("<p>a</p>".to_string()))));
+        assert_eq!(compress_stored_documents(&conn).unwrap(), 0);
+    }
+}
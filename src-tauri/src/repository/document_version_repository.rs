@@ -0,0 +1,118 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::entity::document_version::DocumentVersion;
+use crate::repository::document_text::DocumentText;
+
+/// Versions kept per document; older ones are pruned as new ones are taken
+pub const MAX_DOCUMENT_VERSIONS: i64 = 20;
+
+/// Snapshot a document's current text before it is replaced by `new_text`.
+/// Nothing is recorded when the text is unchanged. The text is copied in its
+/// stored form, compressed or not.
+pub fn snapshot_document_version(
+    conn: &Connection,
+    document_id: i64,
+    new_text: &str,
+) -> Result<(), rusqlite::Error> {
+    let current: Option<DocumentText> = conn
+        .query_row(
+            "SELECT full_document_text FROM projects_activities WHERE id = ?1",
+            params![document_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let changed = matches!(current, Some(DocumentText(Some(text))) if text != new_text);
+    if changed {
+        conn.execute(
+            "INSERT INTO document_versions (document_id, full_document_text)
+             SELECT id, full_document_text FROM projects_activities WHERE id = ?1",
+            params![document_id],
+        )?;
+        conn.execute(
+            "DELETE FROM document_versions
+             WHERE document_id = ?1 AND id NOT IN (
+                 SELECT id FROM document_versions WHERE document_id = ?1 ORDER BY id DESC LIMIT ?2
+             )",
+            params![document_id, MAX_DOCUMENT_VERSIONS],
+        )?;
+    }
+    Ok(())
+}
+
+/// Saved versions of a document, newest first
+pub fn get_document_versions(
+    conn: &Connection,
+    document_id: i64,
+) -> Result<Vec<DocumentVersion>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT id, document_id, full_document_text, created_at
+         FROM document_versions WHERE document_id = ?1 ORDER BY id DESC",
+    )?;
+    let rows = stmt.query_map(params![document_id], version_from_row)?;
+    rows.collect()
+}
+
+pub fn get_document_version(
+    conn: &Connection,
+    version_id: i64,
+) -> Result<Option<DocumentVersion>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT id, document_id, full_document_text, created_at
+         FROM document_versions WHERE id = ?1",
+        params![version_id],
+        version_from_row,
+    )
+    .optional()
+}
+
+fn version_from_row(row: &Row) -> Result<DocumentVersion, rusqlite::Error> {
+    Ok(DocumentVersion {
+        id: row.get(0)?,
+        document_id: row.get(1)?,
+        full_document_text: row.get::<_, DocumentText>(2)?.into_string(),
+        created_at: row.get(3)?,
+    })
+}
+
+pub fn delete_document_versions(conn: &Connection, document_id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute("DELETE FROM document_versions WHERE document_id = ?1", params![document_id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::database::test_database;
+
+    fn versions_db() -> Connection {
+        let conn = test_database();
+        conn.execute(
+            "INSERT INTO projects_activities (id, project_id, full_document_text) VALUES (1, 1, 'v0')",
+            [],
+        )
+        .unwrap();
+        conn
+    }
+
+    fn edit(conn: &Connection, text: &str) {
+        snapshot_document_version(conn, 1, text).unwrap();
+        conn.execute("UPDATE projects_activities SET full_document_text = ?1 WHERE id = 1", params![text])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_snapshots_skip_unchanged_text_and_are_capped() {
+        let conn = versions_db();
+        edit(&conn, "v0");
+        assert!(get_document_versions(&conn, 1).unwrap().is_empty());
+
+        for n in 1..=(MAX_DOCUMENT_VERSIONS + 5) {
+            edit(&conn, &format!("v{}", n));
+        }
+        let versions = get_document_versions(&conn, 1).unwrap();
+        assert_eq!(versions.len() as i64, MAX_DOCUMENT_VERSIONS);
+        // Newest first: the text replaced by the last edit
+        assert_eq!(versions[0].full_document_text, format!("v{}", MAX_DOCUMENT_VERSIONS + 4));
+        assert_eq!(versions.last().unwrap().full_document_text, "v5");
+    }
+}
@@ -1,6 +1,10 @@
+pub mod activity_repository;
 pub mod chat_db_repository;
+pub mod chat_document_link_repository;
 pub mod chunk_repository;
+pub mod document_version_repository;
 pub mod permissions_repository;
 pub mod settings_repository;
 pub mod vector_db_repository;
 pub mod project_repository;
+pub mod document_text;
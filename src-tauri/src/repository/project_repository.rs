@@ -1,6 +1,12 @@
 use crate::entity::project::Project;
-use heelix::html_to_plain_text;
-use rusqlite::{named_params, params, Connection};
+use crate::repository::chat_document_link_repository::delete_links_for_document;
+use crate::repository::chunk_repository::save_chunks_for_document;
+use crate::repository::document_text::{stored_document_text, DocumentText};
+use crate::repository::document_version_repository::{delete_document_versions, snapshot_document_version};
+use heelix::{html_to_markdown, html_to_plain_text};
+use regex::{Regex, RegexBuilder};
+use rusqlite::{named_params, params, Connection, OptionalExtension};
+use std::collections::HashMap;
 
 pub fn delete_project(conn: &Connection, project_id: i64) -> Result<(), rusqlite::Error> {
     conn.execute("DELETE FROM projects WHERE id = ?1", params![project_id])?;
@@ -12,6 +18,16 @@ pub fn delete_project_activities(
     conn: &Connection,
     project_id: i64,
 ) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "DELETE FROM document_versions
+         WHERE document_id IN (SELECT id FROM projects_activities WHERE project_id = ?1)",
+        params![project_id],
+    )?;
+    conn.execute(
+        "DELETE FROM chat_document_links
+         WHERE document_id IN (SELECT id FROM projects_activities WHERE project_id = ?1)",
+        params![project_id],
+    )?;
     conn.execute(
         "DELETE FROM projects_activities WHERE project_id = ?1",
         params![project_id],
@@ -88,15 +104,31 @@ pub fn add_project_activities(
     project_id: i64,
     activity_ids: &Vec<i64>,
 ) -> Result<(), rusqlite::Error> {
-    let mut stmt = conn.prepare(
-        "INSERT INTO projects_activities (project_id, activity_id, document_name, full_document_text)
-         SELECT ?1, id, COALESCE(window_title, 'Document ' || id), edited_full_text
+    let mut select = conn.prepare(
+        "SELECT COALESCE(window_title, 'Document ' || id), edited_full_text
          FROM activity_full_text
-         WHERE id = ?2"
+         WHERE id = ?1",
+    )?;
+    let mut insert = conn.prepare(
+        "INSERT INTO projects_activities (project_id, activity_id, document_name, full_document_text, plain_text, content_fingerprint)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
     )?;
 
     for &activity_id in activity_ids {
-        stmt.execute(params![project_id, activity_id])?;
+        let activity = select
+            .query_row(params![activity_id], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })
+            .optional()?;
+        let (document_name, html) = match activity {
+            Some(activity) => activity,
+            None => continue,
+        };
+        // Captured activities are HTML; markdown keeps their links, headings and lists
+        let plain_text = html.as_deref().map(html_to_markdown);
+        let stored = html.as_deref().map(|html| stored_document_text(conn, html)).transpose()?;
+        let fingerprint = plain_text.as_deref().and_then(content_fingerprint);
+        insert.execute(params![project_id, activity_id, document_name, stored, plain_text, fingerprint])?;
     }
     Ok(())
 }
@@ -111,9 +143,12 @@ pub fn fetch_all_projects(conn: &Connection) -> Result<Vec<Project>, rusqlite::E
             activity_ids: Vec::new(),
             activity_names: Vec::new(),
             created_at: row.get(2)?,
+            document_count: 0,
+            vectorized_document_count: 0,
         })
     })?;
 
+    let counts = fetch_document_counts(conn)?;
     let mut projects = Vec::new();
     for project in project_iter {
         let mut project = project?;
@@ -121,12 +156,39 @@ pub fn fetch_all_projects(conn: &Connection) -> Result<Vec<Project>, rusqlite::E
         project.activities = ids;
         project.activity_ids = activity_ids;
         project.activity_names = names;
+        if let Some(&(documents, vectorized)) = counts.get(&project.id) {
+            project.document_count = documents;
+            project.vectorized_document_count = vectorized;
+        }
         projects.push(project);
     }
 
     Ok(projects)
 }
 
+/// Per project id: the number of documents and how many of them are fully
+/// vectorized, meaning not excluded from RAG, chunked, and with every chunk
+/// in the index. One grouped query covers all projects.
+fn fetch_document_counts(conn: &Connection) -> Result<HashMap<i64, (i64, i64)>, rusqlite::Error> {
+    let mut stmt = conn.prepare(
+        "SELECT project_id,
+                COUNT(*),
+                SUM(CASE WHEN exclude_from_rag = 0 AND chunk_count > 0 AND vectorized_count >= chunk_count
+                         THEN 1 ELSE 0 END)
+         FROM (
+             SELECT pa.project_id, pa.exclude_from_rag,
+                    COUNT(dc.id) AS chunk_count,
+                    COALESCE(SUM(dc.is_vectorized), 0) AS vectorized_count
+             FROM projects_activities pa
+             LEFT JOIN document_chunks dc ON dc.document_id = pa.id
+             GROUP BY pa.id
+         )
+         GROUP BY project_id",
+    )?;
+    let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, (row.get(1)?, row.get(2)?))))?;
+    rows.collect()
+}
+
 pub fn fetch_activities_by_project_id(
     conn: &Connection,
     project_id: i64,
@@ -171,27 +233,188 @@ pub fn get_activity_text_from_project(
          WHERE project_id = ?1 AND id = ?2"  // Use id but keep activity_id in the interface
     )?;
     
-    stmt.query_row(params![project_id, activity_id], |row| row.get(0))
+    stmt.query_row(params![project_id, activity_id], |row| {
+        Ok(row.get::<_, DocumentText>(0)?.into_string())
+    })
 }
 
-/// Get plain text version of document content (for LLM queries)
+/// Get plain text version of document content (for LLM queries). Documents
+/// saved before `plain_text` existed are converted from their HTML here, so
+/// markup never reaches a prompt.
 pub fn get_activity_plain_text(
     conn: &Connection,
     activity_id: i64,
 ) -> Result<(String, String), rusqlite::Error> {
     let mut stmt = conn.prepare(
-        "SELECT document_name, COALESCE(NULLIF(plain_text, ''), full_document_text) as text_content
+        "SELECT document_name, plain_text, full_document_text
          FROM projects_activities 
          WHERE id = ?1"
     )?;
     
     stmt.query_row(params![activity_id], |row| {
         let document_name: String = row.get(0)?;
-        let text_content: String = row.get(1)?;
+        let plain_text: Option<String> = row.get(1)?;
+        let text_content = match plain_text {
+            Some(text) if !text.is_empty() => text,
+            _ => html_to_plain_text(&row.get::<_, DocumentText>(2)?.into_string()),
+        };
         Ok((document_name, text_content))
     })
 }
 
+/// Characters of document text kept on each side of a match in its snippet
+pub const MATCH_CONTEXT_CHARS: usize = 60;
+/// Matches returned for one search; later ones are left out
+pub const MAX_DOCUMENT_MATCHES: usize = 500;
+
+/// One place a search matched in a document's plain text. Offsets are
+/// JavaScript string indices (UTF-16 code units), so the UI can slice with them.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct DocumentMatch {
+    /// Offsets of the match in the plain text, end exclusive
+    pub start: usize,
+    pub end: usize,
+    /// The match with up to `MATCH_CONTEXT_CHARS` characters around it, line
+    /// breaks turned into spaces
+    pub snippet: String,
+    /// Offset of the match within `snippet`
+    pub snippet_offset: usize,
+}
+
+/// Case-insensitive pattern for `query`, taken literally unless `is_regex`.
+/// `whole_word` only matches where the query starts and ends on word boundaries.
+pub fn document_search_pattern(query: &str, whole_word: bool, is_regex: bool) -> Result<Regex, regex::Error> {
+    let pattern = if is_regex { query.to_string() } else { regex::escape(query) };
+    let pattern = if whole_word { format!(r"\b(?:{})\b", pattern) } else { pattern };
+    RegexBuilder::new(&pattern).case_insensitive(true).build()
+}
+
+/// Matches of `pattern` in `text` with UTF-16 offsets and snippets.
+/// Empty matches are skipped.
+pub fn find_document_matches(text: &str, pattern: &Regex) -> Vec<DocumentMatch> {
+    let chars: Vec<char> = text.chars().collect();
+    let utf16_len = |chars: &[char]| chars.iter().map(|c| c.len_utf16()).sum::<usize>();
+    let mut matches = Vec::new();
+    // Byte, character and UTF-16 position reached so far, so offsets are counted once
+    let (mut byte_pos, mut char_pos, mut utf16_pos) = (0, 0, 0);
+    for found in pattern.find_iter(text).filter(|found| !found.is_empty()).take(MAX_DOCUMENT_MATCHES) {
+        let skipped = &text[byte_pos..found.start()];
+        char_pos += skipped.chars().count();
+        utf16_pos += skipped.encode_utf16().count();
+        byte_pos = found.start();
+        let char_end = char_pos + found.as_str().chars().count();
+
+        // The context is counted in characters, so an emoji takes one place
+        let snippet_start = char_pos.saturating_sub(MATCH_CONTEXT_CHARS);
+        let snippet_end = (char_end + MATCH_CONTEXT_CHARS).min(chars.len());
+        let snippet = chars[snippet_start..snippet_end]
+            .iter()
+            .map(|c| if c.is_whitespace() { ' ' } else { *c })
+            .collect();
+        matches.push(DocumentMatch {
+            start: utf16_pos,
+            end: utf16_pos + found.as_str().encode_utf16().count(),
+            snippet,
+            snippet_offset: utf16_len(&chars[snippet_start..char_pos]),
+        });
+    }
+    matches
+}
+
+/// Matches of `pattern` in a document's plain text
+pub fn search_within_document(
+    conn: &Connection,
+    activity_id: i64,
+    pattern: &Regex,
+) -> Result<Vec<DocumentMatch>, rusqlite::Error> {
+    let (_, plain_text) = get_activity_plain_text(conn, activity_id)?;
+    Ok(find_document_matches(&plain_text, pattern))
+}
+
+/// A document's name and stored HTML
+pub fn get_activity_full_text(
+    conn: &Connection,
+    activity_id: i64,
+) -> Result<(String, String), rusqlite::Error> {
+    conn.query_row(
+        "SELECT document_name, full_document_text FROM projects_activities WHERE id = ?1",
+        params![activity_id],
+        |row| Ok((row.get(0)?, row.get::<_, DocumentText>(1)?.into_string())),
+    )
+}
+
+/// Id of the first document in `project_id` named `name`, if any
+pub fn find_document_by_name(
+    conn: &Connection,
+    project_id: i64,
+    name: &str,
+) -> Result<Option<i64>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT id FROM projects_activities WHERE project_id = ?1 AND document_name = ?2 ORDER BY id LIMIT 1",
+        params![project_id, name],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Hash of plain text with case and whitespace differences removed, so the
+/// same note pasted or imported twice compares equal. None for blank text.
+/// Stored in `content_fingerprint`, so it uses FNV-1a rather than std's
+/// hasher, whose output may change between Rust releases.
+pub fn content_fingerprint(plain_text: &str) -> Option<i64> {
+    const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+    let normalized = plain_text
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ");
+    if normalized.is_empty() {
+        return None;
+    }
+    let hash = normalized
+        .bytes()
+        .fold(FNV_OFFSET, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME));
+    Some(hash as i64)
+}
+
+/// Id of the first document in `project_id` whose text has `fingerprint`
+pub fn find_duplicate_document(
+    conn: &Connection,
+    project_id: i64,
+    fingerprint: i64,
+) -> Result<Option<i64>, rusqlite::Error> {
+    conn.query_row(
+        "SELECT id FROM projects_activities WHERE project_id = ?1 AND content_fingerprint = ?2 ORDER BY id LIMIT 1",
+        params![project_id, fingerprint],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+/// Fill in `content_fingerprint` for documents saved before the column
+/// existed, from their plain text or, failing that, their HTML. Returns the
+/// number of documents fingerprinted.
+pub fn backfill_content_fingerprints(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let mut select = conn.prepare(
+        "SELECT id, plain_text, full_document_text FROM projects_activities WHERE content_fingerprint IS NULL",
+    )?;
+    let mut update = conn.prepare("UPDATE projects_activities SET content_fingerprint = ?1 WHERE id = ?2")?;
+    let mut rows = select.query([])?;
+    let mut filled = 0;
+    while let Some(row) = rows.next()? {
+        let plain_text = match row.get::<_, Option<String>>(1)? {
+            Some(text) if !text.is_empty() => text,
+            _ => html_to_plain_text(&row.get::<_, DocumentText>(2)?.into_string()),
+        };
+        if let Some(fingerprint) = content_fingerprint(&plain_text) {
+            update.execute(params![fingerprint, row.get::<_, i64>(0)?])?;
+            filled += 1;
+        }
+    }
+    Ok(filled)
+}
+
 /// Get project_id for a document
 pub fn get_project_id_for_document(
     conn: &Connection,
@@ -204,21 +427,128 @@ pub fn get_project_id_for_document(
     )
 }
 
+/// Text stored as `plain_text` for a document's HTML. Documents added from
+/// captured activities keep markdown, as `add_project_activities` stored them;
+/// the rest keep plain text.
+fn derive_plain_text(conn: &Connection, activity_id: i64, html: &str) -> Result<String, rusqlite::Error> {
+    let is_markdown: bool = conn
+        .query_row(
+            "SELECT activity_id IS NOT NULL FROM projects_activities WHERE id = ?1",
+            params![activity_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(false);
+    Ok(if is_markdown {
+        html_to_markdown(html)
+    } else {
+        html_to_plain_text(html)
+    })
+}
+
 pub fn update_activity_text(
     conn: &Connection,
     activity_id: i64,
     text: &str,
 ) -> Result<(), rusqlite::Error> {
-    // Generate plain text from HTML content
-    let plain_text = html_to_plain_text(text);
+    snapshot_document_version(conn, activity_id, text)?;
+
+    let plain_text = derive_plain_text(conn, activity_id, text)?;
     
+    // Callers re-chunk right after, which settles any pending touch
     conn.execute(
-        "UPDATE projects_activities SET full_document_text = ?1, plain_text = ?2 WHERE id = ?3",
-        params![text, plain_text, activity_id],
+        "UPDATE projects_activities SET full_document_text = ?1, plain_text = ?2, content_fingerprint = ?3, is_dirty = 0
+         WHERE id = ?4",
+        params![stored_document_text(conn, text)?, plain_text, content_fingerprint(&plain_text), activity_id],
     )?;
     Ok(())
 }
 
+/// Store text an editor auto-saves while the user types, without re-chunking:
+/// the document is marked dirty for the delayed re-index instead. A version is
+/// taken only by the first touch after the document was last indexed, so a run
+/// of auto-saves keeps the text from before it rather than every keystroke.
+pub fn touch_activity_text(
+    conn: &Connection,
+    activity_id: i64,
+    text: &str,
+) -> Result<(), rusqlite::Error> {
+    let is_dirty: bool = conn.query_row(
+        "SELECT is_dirty FROM projects_activities WHERE id = ?1",
+        params![activity_id],
+        |row| row.get(0),
+    )?;
+    if !is_dirty {
+        snapshot_document_version(conn, activity_id, text)?;
+    }
+    let plain_text = derive_plain_text(conn, activity_id, text)?;
+    conn.execute(
+        "UPDATE projects_activities SET full_document_text = ?1, plain_text = ?2, content_fingerprint = ?3, is_dirty = 1
+         WHERE id = ?4",
+        params![stored_document_text(conn, text)?, plain_text, content_fingerprint(&plain_text), activity_id],
+    )?;
+    Ok(())
+}
+
+/// Documents touched since they were last chunked
+pub fn get_dirty_document_ids(conn: &Connection) -> Result<Vec<i64>, rusqlite::Error> {
+    let mut stmt = conn.prepare("SELECT id FROM projects_activities WHERE is_dirty = 1 ORDER BY id")?;
+    let ids = stmt.query_map([], |row| row.get(0))?;
+    ids.collect()
+}
+
+/// Re-chunk a touched document from its current text and clear its dirty
+/// flag. Returns the document's project, or None when it is not dirty, e.g.
+/// because a full save has chunked it since.
+pub fn rechunk_dirty_document(conn: &Connection, activity_id: i64) -> Result<Option<i64>, rusqlite::Error> {
+    let dirty = conn
+        .query_row(
+            "SELECT project_id, plain_text FROM projects_activities WHERE id = ?1 AND is_dirty = 1",
+            params![activity_id],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?)),
+        )
+        .optional()?;
+    let (project_id, plain_text) = match dirty {
+        Some(dirty) => dirty,
+        None => return Ok(None),
+    };
+    save_chunks_for_document(conn, activity_id, project_id, plain_text.as_deref().unwrap_or_default())?;
+    conn.execute(
+        "UPDATE projects_activities SET is_dirty = 0 WHERE id = ?1",
+        params![activity_id],
+    )?;
+    Ok(Some(project_id))
+}
+
+/// Copy a document's current text into its backup slot, replacing any older backup
+pub fn backup_activity_text(conn: &Connection, activity_id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE projects_activities SET backup_document_text = full_document_text WHERE id = ?1",
+        params![activity_id],
+    )?;
+    Ok(())
+}
+
+/// Take the backed-up text of a document, clearing the backup. None when there is none.
+/// Read and clear happen in one transaction, so a backup is only ever taken once.
+pub fn take_activity_backup(
+    conn: &mut Connection,
+    activity_id: i64,
+) -> Result<Option<String>, rusqlite::Error> {
+    let tx = conn.transaction()?;
+    let DocumentText(backup) = tx.query_row(
+        "SELECT backup_document_text FROM projects_activities WHERE id = ?1",
+        params![activity_id],
+        |row| row.get(0),
+    )?;
+    tx.execute(
+        "UPDATE projects_activities SET backup_document_text = NULL WHERE id = ?1",
+        params![activity_id],
+    )?;
+    tx.commit()?;
+    Ok(backup)
+}
+
 pub fn update_activity_name(
     conn: &Connection,
     activity_id: i64,
@@ -231,15 +561,56 @@ pub fn update_activity_name(
     Ok(())
 }
 
+/// Keep a document out of, or let it back into, RAG chunking and retrieval
+pub fn set_exclude_from_rag(
+    conn: &Connection,
+    activity_id: i64,
+    exclude: bool,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        "UPDATE projects_activities SET exclude_from_rag = ?1 WHERE id = ?2",
+        params![exclude, activity_id],
+    )?;
+    Ok(())
+}
+
 pub fn add_blank_document(
     conn: &Connection,
     project_id: i64,
 ) -> Result<i64, rusqlite::Error> {
     let default_text = "Start editing";
     conn.execute(
-        "INSERT INTO projects_activities (project_id, document_name, full_document_text, plain_text) 
-         VALUES (?1, ?2, ?3, ?4)",
-        params![project_id, "New Document", default_text, default_text],
+        "INSERT INTO projects_activities (project_id, document_name, full_document_text, plain_text, content_fingerprint)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            project_id,
+            "New Document",
+            stored_document_text(conn, default_text)?,
+            default_text,
+            content_fingerprint(default_text)
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Insert a document with the given name and HTML. Returns the new document's id.
+pub fn add_document(
+    conn: &Connection,
+    project_id: i64,
+    name: &str,
+    full_document_text: &str,
+) -> Result<i64, rusqlite::Error> {
+    let plain_text = html_to_plain_text(full_document_text);
+    conn.execute(
+        "INSERT INTO projects_activities (project_id, document_name, full_document_text, plain_text, content_fingerprint)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            project_id,
+            name,
+            stored_document_text(conn, full_document_text)?,
+            plain_text,
+            content_fingerprint(&plain_text)
+        ],
     )?;
     Ok(conn.last_insert_rowid())
 }
@@ -248,6 +619,8 @@ pub fn delete_project_document(
     conn: &Connection,
     activity_id: i64,
 ) -> Result<(), rusqlite::Error> {
+    delete_document_versions(conn, activity_id)?;
+    delete_links_for_document(conn, activity_id)?;
     conn.execute(
         "DELETE FROM projects_activities WHERE id = ?1",
         params![activity_id],
@@ -299,4 +672,183 @@ pub fn ensure_unassigned_project(conn: &Connection) -> Result<i64, rusqlite::Err
     )?;
     
     Ok(conn.last_insert_rowid())
-  }
\ No newline at end of file
+  }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::database::test_database;
+
+    #[test]
+    fn test_backup_is_taken_once() {
+        let mut conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO projects_activities (project_id, document_name, full_document_text)
+             VALUES (1, 'Notes', '<p>Original</p>');",
+        )
+        .unwrap();
+        backup_activity_text(&conn, 1).unwrap();
+
+        assert_eq!(take_activity_backup(&mut conn, 1).unwrap().as_deref(), Some("<p>Original</p>"));
+        assert_eq!(take_activity_backup(&mut conn, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn test_projects_count_documents_and_fully_vectorized_ones() {
+        let conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO projects VALUES (1, 'Research', ''), (2, 'Empty', '');
+             INSERT INTO projects_activities (project_id, document_name, exclude_from_rag)
+             VALUES (1, 'Indexed', 0), (1, 'Pending', 0), (1, 'Too short', 0), (1, 'Excluded', 1);
+             INSERT INTO document_chunks (document_id, project_id, chunk_index, chunk_text, is_vectorized)
+             VALUES (1, 1, 0, 'a', 1), (1, 1, 1, 'b', 1), (2, 1, 0, 'c', 1), (2, 1, 1, 'd', 0), (4, 1, 0, 'e', 1);",
+        )
+        .unwrap();
+
+        let projects = fetch_all_projects(&conn).unwrap();
+        let research = projects.iter().find(|project| project.id == 1).unwrap();
+        assert_eq!((research.document_count, research.vectorized_document_count), (4, 1));
+        let empty = projects.iter().find(|project| project.id == 2).unwrap();
+        assert_eq!((empty.document_count, empty.vectorized_document_count), (0, 0));
+    }
+
+    #[test]
+    fn test_fingerprint_ignores_case_and_whitespace() {
+        assert_eq!(
+            content_fingerprint("Meeting notes\n\n  Budget is  approved"),
+            content_fingerprint("meeting notes budget is approved")
+        );
+        assert_ne!(content_fingerprint("Budget approved"), content_fingerprint("Budget rejected"));
+        assert_eq!(content_fingerprint(" \n "), None);
+        // Stored in the database, so it must not change between builds
+        assert_eq!(content_fingerprint("a"), Some(0xaf63dc4c8601ec8c_u64 as i64));
+    }
+
+    #[test]
+    fn test_finds_duplicate_only_in_the_same_project() {
+        let conn = test_database();
+        add_document(&conn, 1, "Notes", "<p>Budget is approved</p>").unwrap();
+        // Saved before plain_text existed
+        conn.execute(
+            "INSERT INTO projects_activities (project_id, document_name, full_document_text) VALUES (1, 'Old', '<p>Hiring plan</p>')",
+            [],
+        ).unwrap();
+
+        let fingerprint = content_fingerprint("budget is APPROVED").unwrap();
+        assert_eq!(find_duplicate_document(&conn, 1, fingerprint).unwrap(), Some(1));
+        assert_eq!(find_duplicate_document(&conn, 2, fingerprint).unwrap(), None);
+        let fingerprint = content_fingerprint("Hiring plan").unwrap();
+        assert_eq!(find_duplicate_document(&conn, 1, fingerprint).unwrap(), None);
+        assert_eq!(backfill_content_fingerprints(&conn).unwrap(), 1);
+        assert_eq!(find_duplicate_document(&conn, 1, fingerprint).unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_added_activities_store_markdown() {
+        let conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO activity_full_text (id, window_title, edited_full_text)
+             VALUES (4, 'Inbox', '<p>See <a href=\"https://example.com\">the plan</a></p>');"
+        ).unwrap();
+        add_project_activities(&conn, 1, &vec![4, 5]).unwrap();
+
+        let (name, plain_text): (String, String) = conn
+            .query_row("SELECT document_name, plain_text FROM projects_activities", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!(name, "Inbox");
+        assert_eq!(plain_text, "See [the plan](https://example.com)");
+    }
+
+    #[test]
+    fn test_document_matches_have_offsets_and_snippets() {
+        let text = "Café budget\nThe budget is approved. Budgets grow.";
+        let pattern = document_search_pattern("BUDGET", false, false).unwrap();
+        let matches = find_document_matches(text, &pattern);
+        assert_eq!(matches.iter().map(|m| m.start).collect::<Vec<_>>(), vec![5, 16, 36]);
+        assert_eq!(matches[0].end, 11);
+        assert_eq!(matches[0].snippet, text.replace('\n', " "));
+        assert_eq!(matches[1].snippet_offset, 16);
+
+        let whole_word = document_search_pattern("budget", true, false).unwrap();
+        assert_eq!(find_document_matches(text, &whole_word).len(), 2);
+        let literal = document_search_pattern("approved.", false, false).unwrap();
+        assert_eq!(find_document_matches(text, &literal)[0].start, 26);
+        let regex = document_search_pattern(r"budgets?\b", false, true).unwrap();
+        assert_eq!(find_document_matches(text, &regex).len(), 3);
+        assert!(find_document_matches(text, &document_search_pattern("x*", false, true).unwrap()).is_empty());
+        assert!(document_search_pattern("(", false, true).is_err());
+
+        let long = format!("{}needle{}", "a".repeat(100), "b".repeat(100));
+        let found = &find_document_matches(&long, &document_search_pattern("needle", false, false).unwrap())[0];
+        assert_eq!(found.snippet.chars().count(), 6 + 2 * MATCH_CONTEXT_CHARS);
+        assert_eq!(found.snippet_offset, MATCH_CONTEXT_CHARS);
+    }
+
+    #[test]
+    fn test_document_match_offsets_are_utf16_code_units() {
+        let text = "Launch \u{1f680} budget, caf\u{e9} budget";
+        let matches = find_document_matches(text, &document_search_pattern("budget", false, false).unwrap());
+        let utf16: Vec<u16> = text.encode_utf16().collect();
+        for found in &matches {
+            assert_eq!(String::from_utf16(&utf16[found.start..found.end]).unwrap(), "budget");
+            let snippet: Vec<u16> = found.snippet.encode_utf16().collect();
+            let offset = found.snippet_offset;
+            assert_eq!(String::from_utf16(&snippet[offset..offset + 6]).unwrap(), "budget");
+        }
+        // The rocket is two code units, é one
+        assert_eq!((matches[0].start, matches[1].start), (10, 23));
+    }
+
+    #[test]
+    fn test_touched_documents_are_rechunked_once_settled() {
+        let conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO projects (id, name) VALUES (3, 'Research');
+             INSERT INTO projects_activities (project_id, document_name, full_document_text, plain_text)
+             VALUES (3, 'Notes', '<p>Draft</p>', 'Draft');",
+        )
+        .unwrap();
+        let versions = |conn: &Connection| -> i64 {
+            conn.query_row("SELECT COUNT(*) FROM document_versions", [], |row| row.get(0)).unwrap()
+        };
+
+        touch_activity_text(&conn, 1, "<p>Budget</p>").unwrap();
+        touch_activity_text(&conn, 1, "<p>Budget is approved</p>").unwrap();
+        // One version for the whole run of touches, holding the text from before it
+        assert_eq!(versions(&conn), 1);
+        assert_eq!(get_activity_plain_text(&conn, 1).unwrap().1, "Budget is approved");
+        assert_eq!(get_dirty_document_ids(&conn).unwrap(), vec![1]);
+
+        assert_eq!(rechunk_dirty_document(&conn, 1).unwrap(), Some(3));
+        let chunk: String = conn
+            .query_row("SELECT chunk_text FROM document_chunks WHERE document_id = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(chunk, "Budget is approved");
+        assert!(get_dirty_document_ids(&conn).unwrap().is_empty());
+        assert_eq!(rechunk_dirty_document(&conn, 1).unwrap(), None);
+
+        touch_activity_text(&conn, 1, "<p>Budget is final</p>").unwrap();
+        assert_eq!(versions(&conn), 2);
+    }
+
+    #[test]
+    fn test_edited_activity_documents_stay_markdown() {
+        let conn = test_database();
+        conn.execute_batch(
+            "INSERT INTO projects (id, name) VALUES (1, 'Research');
+             INSERT INTO projects_activities (project_id, activity_id, document_name, full_document_text, plain_text)
+             VALUES (1, 4, 'Inbox', '<p>Old</p>', 'Old'), (1, NULL, 'Notes', '<p>Old</p>', 'Old');",
+        )
+        .unwrap();
+        let html = "<h2>Plan</h2><p>See <a href=\"https://example.com\">the doc</a></p>";
+        let plain_text = |conn: &Connection, id: i64| get_activity_plain_text(conn, id).unwrap().1;
+
+        touch_activity_text(&conn, 1, html).unwrap();
+        assert_eq!(plain_text(&conn, 1), "## Plan\n\nSee [the doc](https://example.com)");
+        update_activity_text(&conn, 1, "<ul><li>One</li><li>Two</li></ul>").unwrap();
+        assert_eq!(plain_text(&conn, 1), "- One\n- Two");
+
+        update_activity_text(&conn, 2, html).unwrap();
+        assert_eq!(plain_text(&conn, 2), html_to_plain_text(html));
+    }
+}
@@ -1,5 +1,19 @@
-use rusqlite::{Connection, named_params};
+use rusqlite::{Connection, OptionalExtension, named_params};
 use rusqlite_from_row::FromRow;
+use crate::configuration::settings::{
+    parse_comma_list, API_CHOICES, API_CHOICE_KEY, ASSISTANT_PERSONA_KEY, ATTRIBUTE_SOURCES_KEY, AUTO_NAME_CHATS_KEY, CLOSE_TO_TRAY_KEY, COMPRESS_DOCUMENTS_KEY, DEFAULT_API_CHOICE, EXPAND_CHUNK_CONTEXT_KEY,
+    DEFAULT_ASSISTANT_PERSONA, DEFAULT_EMBEDDING_MODEL, DEFAULT_EMBEDDING_RETRIES, DEFAULT_MAX_CONTEXT_DOCUMENTS, DEFAULT_LOCAL_MODEL_URL, EMBEDDING_RETRIES_KEY, FALLBACK_PROVIDERS_KEY, EMBEDDING_MODEL_KEY, LOCAL_MODEL_URL_KEY, MAX_EMBEDDING_RETRIES,
+    MAX_CONTEXT_DOCUMENTS_KEY, MAX_MAX_CONTEXT_DOCUMENTS, MIN_MAX_CONTEXT_DOCUMENTS,
+    MAX_NAMING_MAX_CHARS, MIN_NAMING_MAX_CHARS, NAMING_MAX_CHARS_KEY, DEFAULT_NAMING_MAX_CHARS,
+    DEFAULT_REINDEX_DELAY_SECS, MAX_REINDEX_DELAY_SECS, MIN_REINDEX_DELAY_SECS, REINDEX_DELAY_SECS_KEY,
+    DEFAULT_MAX_IMPORT_BYTES, MAX_IMPORT_BYTES_KEY, MAX_MAX_IMPORT_BYTES, MIN_MAX_IMPORT_BYTES,
+    MAX_OUTPUT_TOKENS_KEY, MAX_RAG_TOP_K, MAX_VECTOR_CACHE_SIZE, MIN_RAG_TOP_K, MIN_VECTOR_CACHE_SIZE, OCR_SCANNED_PDFS_KEY,
+    RAG_ON_EVERY_TURN_KEY, RAG_TOP_K_KEY, RECORDING_SAMPLE_RATE_KEY, REDACTION_DENYLIST_KEY, REDACT_PII_KEY, VECTOR_CACHE_SIZE_KEY,
+    DEFAULT_TRANSCRIPTION_BASE_URL, DEFAULT_TRANSCRIPTION_MODEL, TRANSCRIPTION_API_KEY_KEY, TRANSCRIPTION_BASE_URL_KEY, TRANSCRIPTION_MODEL_KEY,
+};
+use crate::engine::project_vector_engine::DEFAULT_VECTOR_CACHE_SIZE;
+use crate::engine::similarity_search_engine::DEFAULT_RAG_TOP_K;
+use crate::engine::transcription_engine::TranscriptionEndpoint;
 use crate::entity::setting::Setting;
 
 pub fn insert_or_update_setting(db: &Connection, setting: Setting) -> Result<(), rusqlite::Error> {
@@ -38,6 +52,27 @@ pub fn get_setting(db: &Connection, setting_key: &str) -> Result<Setting, rusqli
     return Ok(row);
 }
 
+/// Value of `setting_key`, or `default` when the row is missing, empty or
+/// cannot be read. For call sites that have a sensible fallback and should not
+/// fail the whole command over a settings read.
+pub fn get_setting_or_default(db: &Connection, setting_key: &str, default: &str) -> String {
+    match db
+        .query_row(
+            "SELECT setting_value FROM settings WHERE setting_key = @setting_key LIMIT 1",
+            named_params! { "@setting_key": setting_key },
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+    {
+        Ok(Some(value)) if !value.is_empty() => value,
+        Ok(_) => default.to_string(),
+        Err(e) => {
+            log::warn!("Failed to read setting {}: {}", setting_key, e);
+            default.to_string()
+        }
+    }
+}
+
 pub fn get_settings(db: &Connection) -> Result<Vec<Setting>, rusqlite::Error> {
     let mut statement = db.prepare("SELECT * FROM settings")?;
     let mut rows = statement.query([])?;
@@ -52,3 +87,372 @@ pub fn get_settings(db: &Connection) -> Result<Vec<Setting>, rusqlite::Error> {
     Ok(settings)
 }
 
+/// Base URL of the local (Ollama-compatible) model server, falling back to the default
+pub fn get_local_model_url(db: &Connection) -> Result<String, rusqlite::Error> {
+    let setting = get_setting(db, LOCAL_MODEL_URL_KEY)?;
+    if setting.setting_value.is_empty() {
+        Ok(DEFAULT_LOCAL_MODEL_URL.to_string())
+    } else {
+        Ok(setting.setting_value)
+    }
+}
+
+/// The provider selected in settings, falling back to the default
+pub fn get_api_choice(db: &Connection) -> Result<String, rusqlite::Error> {
+    let setting = get_setting(db, API_CHOICE_KEY)?;
+    if setting.setting_value.is_empty() {
+        Ok(DEFAULT_API_CHOICE.to_string())
+    } else {
+        Ok(setting.setting_value)
+    }
+}
+
+/// The `max_output_tokens` setting, 0 when unset or unparsable
+pub fn get_max_output_tokens(db: &Connection) -> Result<u32, rusqlite::Error> {
+    let setting = get_setting(db, MAX_OUTPUT_TOKENS_KEY)?;
+    Ok(setting.setting_value.trim().parse().unwrap_or(0))
+}
+
+/// The `vector_cache_size` setting, clamped to its allowed range
+pub fn get_vector_cache_size(db: &Connection) -> Result<usize, rusqlite::Error> {
+    let setting = get_setting(db, VECTOR_CACHE_SIZE_KEY)?;
+    let size = setting
+        .setting_value
+        .trim()
+        .parse::<u32>()
+        .map(|size| size.clamp(MIN_VECTOR_CACHE_SIZE, MAX_VECTOR_CACHE_SIZE) as usize)
+        .unwrap_or(DEFAULT_VECTOR_CACHE_SIZE);
+    Ok(size)
+}
+
+/// The `recording_sample_rate` setting, None when recordings keep the device rate
+pub fn get_recording_sample_rate(db: &Connection) -> Result<Option<u32>, rusqlite::Error> {
+    let setting = get_setting(db, RECORDING_SAMPLE_RATE_KEY)?;
+    Ok(setting.setting_value.trim().parse::<u32>().ok().filter(|rate| *rate > 0))
+}
+
+/// The `fallback_providers` setting in order, unknown provider names dropped
+pub fn get_fallback_providers(db: &Connection) -> Result<Vec<String>, rusqlite::Error> {
+    let setting = get_setting(db, FALLBACK_PROVIDERS_KEY)?;
+    Ok(parse_comma_list(&setting.setting_value)
+        .into_iter()
+        .filter(|provider| API_CHOICES.contains(&provider.as_str()))
+        .collect())
+}
+
+/// The `auto_name_chats` setting, on unless explicitly turned off
+pub fn get_auto_name_chats(db: &Connection) -> Result<bool, rusqlite::Error> {
+    let setting = get_setting(db, AUTO_NAME_CHATS_KEY)?;
+    Ok(setting.setting_value != "false")
+}
+
+/// The `embedding_retries` setting, capped at its maximum
+pub fn get_embedding_retries(db: &Connection) -> Result<u32, rusqlite::Error> {
+    let setting = get_setting(db, EMBEDDING_RETRIES_KEY)?;
+    Ok(setting
+        .setting_value
+        .trim()
+        .parse::<u32>()
+        .map(|retries| retries.min(MAX_EMBEDDING_RETRIES))
+        .unwrap_or(DEFAULT_EMBEDDING_RETRIES))
+}
+
+/// Clamp a requested retrieval depth to the `rag_top_k` range
+pub fn clamp_rag_top_k(top_k: usize) -> usize {
+    top_k.clamp(MIN_RAG_TOP_K as usize, MAX_RAG_TOP_K as usize)
+}
+
+/// The `rag_top_k` setting, clamped to its allowed range. An unset or
+/// unparsable value gives `DEFAULT_RAG_TOP_K`.
+pub fn get_rag_top_k(db: &Connection) -> Result<usize, rusqlite::Error> {
+    let setting = get_setting(db, RAG_TOP_K_KEY)?;
+    Ok(setting
+        .setting_value
+        .trim()
+        .parse::<usize>()
+        .map(clamp_rag_top_k)
+        .unwrap_or(DEFAULT_RAG_TOP_K))
+}
+
+/// The `max_context_documents` setting, clamped to its allowed range
+pub fn get_max_context_documents(db: &Connection) -> Result<usize, rusqlite::Error> {
+    let setting = get_setting(db, MAX_CONTEXT_DOCUMENTS_KEY)?;
+    Ok(setting
+        .setting_value
+        .trim()
+        .parse::<u32>()
+        .map(|documents| documents.clamp(MIN_MAX_CONTEXT_DOCUMENTS, MAX_MAX_CONTEXT_DOCUMENTS))
+        .unwrap_or(DEFAULT_MAX_CONTEXT_DOCUMENTS) as usize)
+}
+
+/// The `redact_pii_before_send` setting, off unless explicitly turned on
+pub fn get_redact_pii_before_send(db: &Connection) -> Result<bool, rusqlite::Error> {
+    let setting = get_setting(db, REDACT_PII_KEY)?;
+    Ok(setting.setting_value == "true")
+}
+
+/// The `redaction_denylist` setting as individual terms
+pub fn get_redaction_denylist(db: &Connection) -> Result<Vec<String>, rusqlite::Error> {
+    let setting = get_setting(db, REDACTION_DENYLIST_KEY)?;
+    Ok(parse_comma_list(&setting.setting_value))
+}
+
+/// The `assistant_persona` setting, falling back to the default
+pub fn get_assistant_persona(db: &Connection) -> Result<String, rusqlite::Error> {
+    let setting = get_setting(db, ASSISTANT_PERSONA_KEY)?;
+    if setting.setting_value.trim().is_empty() {
+        Ok(DEFAULT_ASSISTANT_PERSONA.to_string())
+    } else {
+        Ok(setting.setting_value)
+    }
+}
+
+/// The `embedding_model` setting, falling back to the default model
+pub fn get_embedding_model(db: &Connection) -> Result<String, rusqlite::Error> {
+    let setting = get_setting(db, EMBEDDING_MODEL_KEY)?;
+    let model = setting.setting_value.trim();
+    if model.is_empty() {
+        Ok(DEFAULT_EMBEDDING_MODEL.to_string())
+    } else {
+        Ok(model.to_string())
+    }
+}
+
+/// The transcription server's base URL without a trailing slash, the model to
+/// ask it for and its key, falling back to OpenAI's Whisper for the server and
+/// model. The OpenAI key stands in for a missing key only on OpenAI's server,
+/// so it is never sent to another one.
+pub fn get_transcription_endpoint(db: &Connection) -> Result<TranscriptionEndpoint, rusqlite::Error> {
+    let base_url = get_setting(db, TRANSCRIPTION_BASE_URL_KEY)?.setting_value;
+    let base_url = match base_url.trim().trim_end_matches('/') {
+        "" => DEFAULT_TRANSCRIPTION_BASE_URL.to_string(),
+        base_url => base_url.to_string(),
+    };
+    let model = get_setting(db, TRANSCRIPTION_MODEL_KEY)?.setting_value;
+    let model = match model.trim() {
+        "" => DEFAULT_TRANSCRIPTION_MODEL.to_string(),
+        model => model.to_string(),
+    };
+    let api_key = match get_setting(db, TRANSCRIPTION_API_KEY_KEY)?.setting_value.trim() {
+        "" if base_url == DEFAULT_TRANSCRIPTION_BASE_URL => get_setting_or_default(db, "api_key_open_ai", ""),
+        api_key => api_key.to_string(),
+    };
+    Ok(TranscriptionEndpoint { base_url, model, api_key })
+}
+
+/// The `ocr_scanned_pdfs` setting, off unless explicitly turned on
+pub fn get_ocr_scanned_pdfs(db: &Connection) -> Result<bool, rusqlite::Error> {
+    let setting = get_setting(db, OCR_SCANNED_PDFS_KEY)?;
+    Ok(setting.setting_value == "true")
+}
+
+/// The `close_to_tray` setting, on unless explicitly turned off
+pub fn get_close_to_tray(db: &Connection) -> Result<bool, rusqlite::Error> {
+    let setting = get_setting(db, CLOSE_TO_TRAY_KEY)?;
+    Ok(setting.setting_value != "false")
+}
+
+/// The `compress_documents` setting, on unless explicitly turned off
+pub fn get_compress_documents(db: &Connection) -> Result<bool, rusqlite::Error> {
+    let setting = get_setting(db, COMPRESS_DOCUMENTS_KEY)?;
+    Ok(setting.setting_value != "false")
+}
+
+/// Model set for naming chats under `key` (one of the `naming_model_*` keys),
+/// None when it is left empty
+pub fn get_naming_model(db: &Connection, key: &str) -> Result<Option<String>, rusqlite::Error> {
+    let setting = get_setting(db, key)?;
+    let model = setting.setting_value.trim();
+    Ok((!model.is_empty()).then(|| model.to_string()))
+}
+
+/// The `naming_max_chars` setting, clamped to its allowed range
+pub fn get_naming_max_chars(db: &Connection) -> Result<u32, rusqlite::Error> {
+    let setting = get_setting(db, NAMING_MAX_CHARS_KEY)?;
+    Ok(setting
+        .setting_value
+        .trim()
+        .parse::<u32>()
+        .map(|chars| chars.clamp(MIN_NAMING_MAX_CHARS, MAX_NAMING_MAX_CHARS))
+        .unwrap_or(DEFAULT_NAMING_MAX_CHARS))
+}
+
+/// The `reindex_delay_secs` setting, clamped to its allowed range
+pub fn get_reindex_delay_secs(db: &Connection) -> Result<u32, rusqlite::Error> {
+    let setting = get_setting(db, REINDEX_DELAY_SECS_KEY)?;
+    Ok(setting
+        .setting_value
+        .trim()
+        .parse::<u32>()
+        .map(|secs| secs.clamp(MIN_REINDEX_DELAY_SECS, MAX_REINDEX_DELAY_SECS))
+        .unwrap_or(DEFAULT_REINDEX_DELAY_SECS))
+}
+
+/// The `max_import_bytes` setting, clamped to its allowed range
+pub fn get_max_import_bytes(db: &Connection) -> Result<u64, rusqlite::Error> {
+    let setting = get_setting(db, MAX_IMPORT_BYTES_KEY)?;
+    Ok(setting
+        .setting_value
+        .trim()
+        .parse::<u64>()
+        .map(|bytes| bytes.clamp(MIN_MAX_IMPORT_BYTES, MAX_MAX_IMPORT_BYTES))
+        .unwrap_or(DEFAULT_MAX_IMPORT_BYTES))
+}
+
+/// The `expand_chunk_context` setting, off unless explicitly turned on
+pub fn get_expand_chunk_context(db: &Connection) -> Result<bool, rusqlite::Error> {
+    let setting = get_setting(db, EXPAND_CHUNK_CONTEXT_KEY)?;
+    Ok(setting.setting_value == "true")
+}
+
+/// The `rag_on_every_turn` setting, off unless explicitly turned on
+pub fn get_rag_on_every_turn(db: &Connection) -> Result<bool, rusqlite::Error> {
+    let setting = get_setting(db, RAG_ON_EVERY_TURN_KEY)?;
+    Ok(setting.setting_value == "true")
+}
+
+/// The `attribute_sources` setting, off unless explicitly turned on
+pub fn get_attribute_sources(db: &Connection) -> Result<bool, rusqlite::Error> {
+    let setting = get_setting(db, ATTRIBUTE_SOURCES_KEY)?;
+    Ok(setting.setting_value == "true")
+}
+
+/// Whether `provider` has an API key configured. Local models need none.
+pub fn is_provider_configured(db: &Connection, provider: &str) -> Result<bool, rusqlite::Error> {
+    let key = match provider {
+        "claude" => "api_key_claude",
+        "openai" => "api_key_open_ai",
+        "gemini" => "api_key_gemini",
+        "local" => return Ok(true),
+        _ => return Ok(false),
+    };
+    Ok(!get_setting(db, key)?.setting_value.trim().is_empty())
+}
+
+/// Write a batch of settings in one transaction, skipping keys whose stored value
+/// is unchanged. Either every changed key is persisted or none are.
+/// Returns the number of keys that were written.
+pub fn update_settings_batch(db: &mut Connection, settings: Vec<Setting>) -> Result<usize, rusqlite::Error> {
+    let tx = db.transaction()?;
+    let mut written = 0;
+    for setting in settings {
+        let current: Option<String> = tx.query_row(
+            "SELECT setting_value FROM settings WHERE setting_key = @setting_key",
+            named_params! { "@setting_key": setting.setting_key },
+            |row| row.get(0),
+        ).optional()?;
+        if current.as_deref() == Some(setting.setting_value.as_str()) {
+            continue;
+        }
+        insert_or_update_setting(&tx, setting)?;
+        written += 1;
+    }
+    tx.commit()?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::database::test_database;
+
+    fn setting(key: &str, value: &str) -> Setting {
+        Setting { setting_key: key.to_string(), setting_value: value.to_string() }
+    }
+
+    #[test]
+    fn test_batch_only_writes_changed_keys() {
+        let mut db = test_database();
+        insert_or_update_setting(&db, setting("interval", "20")).unwrap();
+
+        let written = update_settings_batch(&mut db, vec![
+            setting("interval", "20"),
+            setting("api_choice", "openai"),
+        ]).unwrap();
+
+        assert_eq!(written, 1);
+        assert_eq!(get_setting(&db, "api_choice").unwrap().setting_value, "openai");
+    }
+
+    #[test]
+    fn test_batch_rolls_back_on_failure() {
+        let mut db = test_database();
+        insert_or_update_setting(&db, setting("interval", "20")).unwrap();
+        db.execute_batch("CREATE TRIGGER reject_bad BEFORE UPDATE ON settings
+            WHEN NEW.setting_key = 'rag_top_k'
+            BEGIN SELECT RAISE(ABORT, 'rejected'); END;
+            INSERT INTO settings (setting_key, setting_value) VALUES ('rag_top_k', '20');").unwrap();
+
+        let result = update_settings_batch(&mut db, vec![
+            setting("interval", "30"),
+            setting("rag_top_k", "10"),
+        ]);
+
+        assert!(result.is_err());
+        assert_eq!(get_setting(&db, "interval").unwrap().setting_value, "20");
+    }
+
+    #[test]
+    fn test_setting_or_default_falls_back_for_missing_empty_and_unreadable() {
+        let db = test_database();
+        insert_or_update_setting(&db, setting("api_key_claude", "sk-test")).unwrap();
+        insert_or_update_setting(&db, setting("api_key_gemini", "")).unwrap();
+
+        assert_eq!(get_setting_or_default(&db, "api_key_claude", ""), "sk-test");
+        assert_eq!(get_setting_or_default(&db, "api_key_gemini", "none"), "none");
+        assert_eq!(get_setting_or_default(&db, "rag_top_k", "20"), "20");
+
+        db.execute_batch("DROP TABLE settings;").unwrap();
+        assert_eq!(get_setting_or_default(&db, "api_key_claude", "none"), "none");
+    }
+
+    #[test]
+    fn test_rag_top_k_is_clamped() {
+        let db = test_database();
+        assert_eq!(get_rag_top_k(&db).unwrap(), DEFAULT_RAG_TOP_K);
+        insert_or_update_setting(&db, setting(RAG_TOP_K_KEY, "500")).unwrap();
+        assert_eq!(get_rag_top_k(&db).unwrap(), MAX_RAG_TOP_K as usize);
+        insert_or_update_setting(&db, setting(RAG_TOP_K_KEY, "0")).unwrap();
+        assert_eq!(get_rag_top_k(&db).unwrap(), MIN_RAG_TOP_K as usize);
+        insert_or_update_setting(&db, setting(RAG_TOP_K_KEY, "8")).unwrap();
+        assert_eq!(get_rag_top_k(&db).unwrap(), 8);
+    }
+
+    #[test]
+    fn test_naming_settings_fall_back_to_defaults() {
+        let db = test_database();
+        assert_eq!(get_naming_model(&db, "naming_model_openai").unwrap(), None);
+        assert_eq!(get_naming_max_chars(&db).unwrap(), DEFAULT_NAMING_MAX_CHARS);
+        insert_or_update_setting(&db, setting("naming_model_openai", " gpt-4.1-nano ")).unwrap();
+        insert_or_update_setting(&db, setting(NAMING_MAX_CHARS_KEY, "500")).unwrap();
+        assert_eq!(get_naming_model(&db, "naming_model_openai").unwrap().as_deref(), Some("gpt-4.1-nano"));
+        assert_eq!(get_naming_max_chars(&db).unwrap(), MAX_NAMING_MAX_CHARS);
+    }
+
+    #[test]
+    fn test_transcription_endpoint_falls_back_to_openai() {
+        let db = test_database();
+        insert_or_update_setting(&db, setting("api_key_open_ai", "sk-openai")).unwrap();
+        assert_eq!(
+            get_transcription_endpoint(&db).unwrap(),
+            TranscriptionEndpoint {
+                base_url: DEFAULT_TRANSCRIPTION_BASE_URL.to_string(),
+                model: DEFAULT_TRANSCRIPTION_MODEL.to_string(),
+                api_key: "sk-openai".to_string(),
+            }
+        );
+        insert_or_update_setting(&db, setting(TRANSCRIPTION_BASE_URL_KEY, "https://api.groq.com/openai/v1/")).unwrap();
+        insert_or_update_setting(&db, setting(TRANSCRIPTION_MODEL_KEY, " whisper-large-v3 ")).unwrap();
+        // The OpenAI key is not sent to another server
+        assert_eq!(
+            get_transcription_endpoint(&db).unwrap(),
+            TranscriptionEndpoint {
+                base_url: "https://api.groq.com/openai/v1".to_string(),
+                model: "whisper-large-v3".to_string(),
+                api_key: String::new(),
+            }
+        );
+        insert_or_update_setting(&db, setting(TRANSCRIPTION_API_KEY_KEY, " gsk-groq ")).unwrap();
+        assert_eq!(get_transcription_endpoint(&db).unwrap().api_key, "gsk-groq");
+    }
+}
@@ -1,16 +1,37 @@
 use std::error::Error;
 use async_openai::{types::CreateEmbeddingRequestArgs, Client, config::OpenAIConfig};
 
-// Correct async function for computing vector embeddings
-pub async fn compute_vector_embedding(text: &str, api_key: &str) -> Result<Vec<f32>, Box<dyn Error>> {
-    let config: OpenAIConfig = OpenAIConfig::new()
-    .with_api_key(api_key);
+use crate::engine::rate_limiter::{acquire, estimate_prompt_tokens};
 
-    let client = Client::with_config(config);
+// Correct async function for computing vector embeddings
+pub async fn compute_vector_embedding(text: &str, config: &OpenAIConfig, model: &str) -> Result<Vec<f32>, Box<dyn Error>> {
+    let client = Client::with_config(config.clone());
     let request = CreateEmbeddingRequestArgs::default()
-        .model("text-embedding-3-small")
+        .model(model)
         .input([text])
         .build()?;
+    acquire("openai", estimate_prompt_tokens([text])).await;
     let response = client.embeddings().create(request).await?;
     Ok(response.data[0].embedding.clone())
 }
+
+/// Embed several texts in one request. Results are returned in input order.
+pub async fn compute_vector_embeddings(texts: &[&str], config: &OpenAIConfig, model: &str) -> Result<Vec<Vec<f32>>, Box<dyn Error>> {
+    let client = Client::with_config(config.clone());
+    let request = CreateEmbeddingRequestArgs::default()
+        .model(model)
+        .input(texts.to_vec())
+        .build()?;
+    acquire("openai", estimate_prompt_tokens(texts.iter().copied())).await;
+    let mut response = client.embeddings().create(request).await?;
+    if response.data.len() != texts.len() {
+        return Err(format!(
+            "Expected {} embeddings, received {}",
+            texts.len(),
+            response.data.len()
+        )
+        .into());
+    }
+    response.data.sort_by_key(|embedding| embedding.index);
+    Ok(response.data.into_iter().map(|embedding| embedding.embedding).collect())
+}
@@ -8,15 +8,6 @@ import {
 } from "react";
 import { invoke } from "@tauri-apps/api";
 import { enable, disable, isEnabled } from "tauri-plugin-autostart-api";
-import { z } from "zod";
-
-// Settings DB types
-const settingDbItemZod = z.object({
-  setting_key: z.string(),
-  setting_value: z.string(),
-});
-const settingDbItemsZod = settingDbItemZod.array();
-type SettingDbItem = z.infer<typeof settingDbItemZod>;
 
 export const DEFAULT_SETTINGS: Settings = {
   is_dev_mode: false,
@@ -29,6 +20,44 @@ export const DEFAULT_SETTINGS: Settings = {
   local_model_url: "http://localhost:11434",
   vectorization_enabled: false,
   rag_top_k: 20,
+  max_context_documents: 4,
+  attribute_sources: false,
+  expand_chunk_context: false,
+  rag_on_every_turn: false,
+  embedding_model: "text-embedding-3-small",
+  openai_base_url: "",
+  openai_org_id: "",
+  openai_project_id: "",
+  max_output_tokens: 0,
+  vector_cache_size: 5,
+  recording_sample_rate: 0,
+  live_transcription: false,
+  transcription_base_url: "",
+  transcription_api_key: "",
+  transcription_model: "whisper-1",
+  fallback_providers: [],
+  auto_name_chats: true,
+  naming_model_claude: "",
+  naming_model_openai: "",
+  naming_model_gemini: "",
+  naming_model_local: "",
+  naming_max_chars: 18,
+  rate_limit_rpm_claude: 0,
+  rate_limit_tpm_claude: 0,
+  rate_limit_rpm_openai: 3000,
+  rate_limit_tpm_openai: 1000000,
+  rate_limit_rpm_gemini: 0,
+  rate_limit_tpm_gemini: 0,
+  reindex_delay_secs: 5,
+  max_import_bytes: 50 * 1024 * 1024,
+  embedding_retries: 2,
+  redact_pii_before_send: false,
+  redaction_denylist: [],
+  assistant_persona:
+    "You are Heelix chat app that is powered by {provider}. Heelix chat is developed by Heelix Technologies. Only identify yourself as such.",
+  ocr_scanned_pdfs: false,
+  close_to_tray: true,
+  compress_documents: true,
 };
 
 type Update = {
@@ -47,6 +76,71 @@ export type Settings = {
   local_model_url: string;
   vectorization_enabled: boolean;
   rag_top_k: number;
+  /** Distinct documents retrieved chunks may come from (1-20) */
+  max_context_documents: number;
+  /** Link answer sentences to the retrieved chunks they draw on; costs an extra embeddings call */
+  attribute_sources: boolean;
+  /** Send the best-ranked chunks with the chunks around them in their document */
+  expand_chunk_context: boolean;
+  /** Retrieve project chunks for every message, not only the first */
+  rag_on_every_turn: boolean;
+  /** OpenAI embeddings model; projects indexed with another model need a rebuild */
+  embedding_model: string;
+  openai_base_url: string;
+  /** Sent as the OpenAI-Organization header when set */
+  openai_org_id: string;
+  /** Sent as the OpenAI-Project header when set */
+  openai_project_id: string;
+  /** 0 keeps each provider's default response length */
+  max_output_tokens: number;
+  /** Project vector indices kept open in memory (1-50) */
+  vector_cache_size: number;
+  /** Voice note sample rate in Hz; 0 keeps the microphone's native rate */
+  recording_sample_rate: number;
+  /** Transcribe voice notes in windows while recording; each window is an extra Whisper request */
+  live_transcription: boolean;
+  /** OpenAI-compatible transcription server (Groq, Azure, local); empty means OpenAI */
+  transcription_base_url: string;
+  /** Key for the transcription server; empty uses the OpenAI key on OpenAI and no key elsewhere */
+  transcription_api_key: string;
+  /** Model sent with each transcription request, e.g. whisper-large-v3 */
+  transcription_model: string;
+  /** Providers tried in order when the selected one is overloaded or unreachable */
+  fallback_providers: ApiChoice[];
+  /** Name new chats from their first exchange */
+  auto_name_chats: boolean;
+  /** Model each provider names chats with; empty keeps the provider's default */
+  naming_model_claude: string;
+  naming_model_openai: string;
+  naming_model_gemini: string;
+  naming_model_local: string;
+  /** Longest chat name the naming call asks for (10-80 characters) */
+  naming_max_chars: number;
+  /** Requests and estimated input tokens per minute sent to each cloud provider; 0 is unlimited */
+  rate_limit_rpm_claude: number;
+  rate_limit_tpm_claude: number;
+  rate_limit_rpm_openai: number;
+  rate_limit_tpm_openai: number;
+  rate_limit_rpm_gemini: number;
+  rate_limit_tpm_gemini: number;
+  /** Seconds an auto-saved document must go unedited before it is re-indexed (1-300) */
+  reindex_delay_secs: number;
+  /** Largest file imported or read as audio (1 MB-2 GB); longer text files are truncated */
+  max_import_bytes: number;
+  /** Retries for a failed embeddings request before its batch is split (0-5) */
+  embedding_retries: number;
+  /** Mask emails, phone and card numbers in document context sent to cloud providers */
+  redact_pii_before_send: boolean;
+  /** Extra terms masked when redaction is on */
+  redaction_denylist: string[];
+  /** Start of every system prompt; `{provider}` becomes the model family answering */
+  assistant_persona: string;
+  /** OCR PDFs without extractable text; needs tesseract and poppler installed */
+  ocr_scanned_pdfs: boolean;
+  /** Closing the window hides it to the tray instead of quitting */
+  close_to_tray: boolean;
+  /** Store document HTML compressed; existing documents are compressed on the next launch */
+  compress_documents: boolean;
 };
 
 type SettingsContextType = {
@@ -61,50 +155,18 @@ const SettingsContext = createContext<SettingsContextType | undefined>(
 export const SettingsProvider: FC<PropsWithChildren> = ({ children }) => {
   const [settings, setSettings] = useState<Settings>(DEFAULT_SETTINGS);
 
-  const getSettingOrEmpty = (
-    settings: SettingDbItem[],
-    settingKey: string
-  ): string => {
-    const filtered = settings
-      .filter((setting) => setting.setting_key == settingKey)
-      .map((setting) => setting.setting_value);
-    if (filtered != null && filtered.length > 0) {
-      return filtered[0];
-    }
-    return "";
-  };
-
-  const buildSettings = (response: SettingDbItem[]): Settings => {
-    console.log(getSettingOrEmpty(response, "interval"));
-    return {
-      interval: getSettingOrEmpty(response, "interval") || "20",
-      is_dev_mode: getSettingOrEmpty(response, "is_dev_mode") == "true",
-      auto_start: getSettingOrEmpty(response, "auto_start") == "true",
-      api_choice:
-        (getSettingOrEmpty(response, "api_choice") as ApiChoice) || "claude",
-      api_key_claude: getSettingOrEmpty(response, "api_key_claude") || "",
-      api_key_open_ai: getSettingOrEmpty(response, "api_key_open_ai") || "",
-      api_key_gemini: getSettingOrEmpty(response, "api_key_gemini") || "",
-      local_model_url: getSettingOrEmpty(response, "local_model_url") || "http://localhost:11434",
-      vectorization_enabled: getSettingOrEmpty(response, "vectorization_enabled") == "true",
-      rag_top_k: parseInt(getSettingOrEmpty(response, "rag_top_k")) || 20,
-    };
-  };
-
   useEffect(() => {
-    invoke("get_latest_settings").then(async (response) => {
-      const parsed = settingDbItemsZod.safeParse(response);
-      if (parsed.success) {
-        const builtSettings = buildSettings(parsed.data);
+    invoke<Settings>("get_all_settings_typed")
+      .then(async (loaded) => {
         const autoStartEnabled = await isEnabled();
         setSettings({
-          ...builtSettings,
+          ...loaded,
           auto_start: autoStartEnabled,
         });
-      } else {
-        console.error("invoke get_latest_settings Error:", parsed.error);
-      }
-    });
+      })
+      .catch((error) => {
+        console.error("invoke get_all_settings_typed Error:", error);
+      });
   }, []);
 
   const update: Update = async (newSettings) => {
@@ -115,8 +177,8 @@ export const SettingsProvider: FC<PropsWithChildren> = ({ children }) => {
         await disable();
       }
     }
-    updateSettingsOnRust(newSettings);
-    setSettings(newSettings);
+    const persisted = await updateSettingsOnRust(newSettings);
+    setSettings(persisted);
     return Promise.resolve();
   };
 
@@ -127,9 +189,8 @@ export const SettingsProvider: FC<PropsWithChildren> = ({ children }) => {
   );
 };
 
-const updateSettingsOnRust = (settings: Settings) => {
-  invoke("update_settings", { settings }).then();
-};
+const updateSettingsOnRust = (settings: Settings) =>
+  invoke<Settings>("update_settings", { settings });
 
 export const useGlobalSettings = (): SettingsContextType => {
   const context = useContext(SettingsContext);
@@ -2,27 +2,17 @@ import { invoke } from "@tauri-apps/api/tauri";
 import { z } from "zod";
 
 const activityResultZod = z.array(
-  z.tuple([z.number(), z.string(), z.string()])
+  z.object({
+    id: z.number(),
+    title: z.string(),
+    timestamp: z.string(),
+    /** First ~200 characters of the activity's plain text */
+    preview: z.string(),
+  })
 );
 export type ActivityResult = z.infer<typeof activityResultZod>;
 
-export type Activity = {
-  id: number;
-  title: string;
-  date: string;
-};
-
-const mapResultToActivity = (items: ActivityResult): Activity[] =>
-  items.map((item) => {
-    const id = item[0] ? item[0] : 0;
-    const title = item[1] ? item[1] : "";
-    const date = item[2] ? item[2] : "";
-    return {
-      id,
-      title,
-      date,
-    };
-  });
+export type Activity = ActivityResult[number];
 
 type fetchActivities = {
   (offset: number, limit?: number): Promise<Activity[]>;
@@ -39,9 +29,9 @@ export const fetchActivities: fetchActivities = async (
   if (parsed.error) {
     // TODO: Log errors remotely
     console.error(`${fetchActivities.name}_error: `, parsed.error);
-    return mapResultToActivity(result as unknown as ActivityResult);
+    return result;
   }
-  return mapResultToActivity(parsed.data);
+  return parsed.data;
 };
 
 export const deleteActivity = async (id: number) => {
@@ -6,6 +6,8 @@ export type Project = {
   activities: number[];   
   activity_ids: (number | null)[];
   activity_names: string[];   // Array of document names
+  document_count?: number;
+  vectorized_document_count?: number; // Documents fully in the project index
 };
 
 export const UNASSIGNED_PROJECT_NAME = "Unassigned";
@@ -40,6 +42,38 @@ export const updateActivityName = async (activityId: number, name: string) => {
   });
 };
 
+/**
+ * Auto-save a document being edited. Returns quickly; the document is re-chunked
+ * and re-indexed in the background once edits pause for `reindex_delay_secs`.
+ */
+export const touchDocument = async (activityId: number, text: string) => {
+  return await invoke<void>("touch_document", { activityId, text });
+};
+
+/** One match of `search_within_document`; offsets are string indices into the plain text */
+export type DocumentMatch = {
+  start: number;
+  end: number;
+  /** The match with some text around it on one line */
+  snippet: string;
+  /** Where the match starts within `snippet` */
+  snippet_offset: number;
+};
+
+/** Find `query` in a document, ignoring case */
+export const searchWithinDocument = async (
+  activityId: number,
+  query: string,
+  options: { wholeWord?: boolean; regex?: boolean } = {}
+): Promise<DocumentMatch[]> => {
+  return await invoke<DocumentMatch[]>("search_within_document", {
+    activityId,
+    query,
+    wholeWord: options.wholeWord ?? false,
+    regex: options.regex ?? false,
+  });
+};
+
 export const deleteActivity = async (activityId: number) => {
   return await invoke("delete_project_activity", { activityId });
 };
@@ -48,6 +82,158 @@ export const addUnassignedActivity = async (): Promise<number> => {
   return await invoke<number>("ensure_unassigned_activity");
 };
 
+export type VectorizationStatus = "indexed" | "pending" | "too_short" | "excluded";
+
+export type DocumentVectorizationStatus = {
+  document_id: number;
+  chunk_count: number;
+  vectorized_count: number;
+  status: VectorizationStatus;
+};
+
+export const fetchVectorizationStatus = async (
+  projectId: number
+): Promise<DocumentVectorizationStatus[]> => {
+  return await invoke<DocumentVectorizationStatus[]>("get_project_vectorization_status", {
+    projectId,
+  });
+};
+
+export type UnvectorizedDocument = {
+  document_id: number;
+  project_id: number;
+  document_name: string;
+  chunk_count: number;
+  vectorized_count: number;
+};
+
+/** Documents that should be searchable but are missing from their project's index */
+export const fetchUnvectorizedDocuments = async (): Promise<UnvectorizedDocument[]> => {
+  return await invoke<UnvectorizedDocument[]>("get_unvectorized_documents");
+};
+
+/** Index a stuck document again; resolves with the number of chunks indexed */
+export const retryVectorization = async (documentId: number): Promise<number> => {
+  return await invoke<number>("retry_vectorization", { documentId });
+};
+
+export type DocumentChunk = {
+  id: number;
+  document_id: number;
+  project_id: number;
+  chunk_index: number;
+  chunk_text: string;
+  is_vectorized: boolean;
+};
+
+/** How a document was split for retrieval, in document order */
+export const fetchDocumentChunks = async (documentId: number): Promise<DocumentChunk[]> => {
+  return await invoke<DocumentChunk[]>("get_document_chunks", { documentId });
+};
+
+/** Fix one chunk's text and re-embed it; the returned chunk has a new id */
+export const updateChunkText = async (chunkId: number, text: string): Promise<DocumentChunk> => {
+  return await invoke<DocumentChunk>("update_chunk_text", { chunkId, text });
+};
+
+/** Keep a document out of assistant answers, or let it back in */
+export const setDocumentExcludeFromRag = async (activityId: number, exclude: boolean) => {
+  return await invoke("set_document_exclude_from_rag", { activityId, exclude });
+};
+
+/** Re-chunk and embed every document in a project; resolves with the number of chunks indexed */
+export const vectorizeProject = async (projectId: number): Promise<number> => {
+  return await invoke<number>("vectorize_project", { projectId });
+};
+
+/** Embed a project's existing chunks again with the current embedding model; resolves with the number indexed */
+export const rebuildProjectVectors = async (projectId: number): Promise<number> => {
+  return await invoke<number>("rebuild_project_vectors", { projectId });
+};
+
+/** Payload of `vectorization_progress`, emitted while indexing resumes after a restart or a project is re-indexed */
+export type VectorizationProgress = {
+  project_id: number;
+  vectorized: number;
+  total: number;
+};
+
+/** Stop the project's running indexing after the current batch; the rest stays queued */
+export const cancelVectorization = async (projectId: number): Promise<void> => {
+  await invoke("cancel_vectorization", { projectId });
+};
+
+/** Payload of `vectorization_cancelled`, emitted when indexing stops early */
+export type VectorizationCancelled = {
+  project_id: number;
+  vectorized: number;
+  remaining: number;
+};
+
+export type TranscribedDocument = { document_id: number; name: string };
+
+/** Transcribe a recording into a new document; no projectId means Unassigned */
+export const transcribeToDocument = async (
+  filePath: string,
+  projectId: number | undefined,
+  generateTitle: boolean
+): Promise<TranscribedDocument> => {
+  return await invoke<TranscribedDocument>("transcribe_to_document", {
+    filePath,
+    projectId: projectId ?? null,
+    generateTitle,
+  });
+};
+
+/**
+ * Stop a running transcription of `filePath` before its next piece; the
+ * transcription call resolves with what was transcribed so far
+ */
+export const cancelTranscription = async (filePath: string): Promise<void> => {
+  await invoke("cancel_transcription", { filePath });
+};
+
+export type DiffSpan = {
+  kind: "equal" | "insert" | "delete";
+  text: string;
+  /** String indices into the original text; null for insertions */
+  original: { start: number; end: number } | null;
+  /** String indices into the cleaned text; null for deletions */
+  cleaned: { start: number; end: number } | null;
+};
+
+/** Changes between a document and its cleaned-up version, for review before saving */
+export const diffDocuments = async (
+  original: string,
+  cleaned: string,
+  granularity: "word" | "line" = "word"
+): Promise<DiffSpan[]> => {
+  return await invoke<DiffSpan[]>("diff_documents", { original, cleaned, granularity });
+};
+
+/** How `import_document` treats text that already exists in the project */
+export type DuplicatePolicy = "skip" | "update" | "create";
+
+export type ImportedDocument = {
+  document_id: number;
+  action: "created" | "updated" | "skipped";
+};
+
+/** Save text as a new document; no projectId means Unassigned */
+export const importDocument = async (
+  projectId: number | undefined,
+  name: string,
+  text: string,
+  duplicatePolicy: DuplicatePolicy
+): Promise<ImportedDocument> => {
+  return await invoke<ImportedDocument>("import_document", {
+    projectId: projectId ?? null,
+    name,
+    text,
+    duplicatePolicy,
+  });
+};
+
 export const moveDocumentToProject = async (documentId: number, targetProjectId: number) => {
   return await invoke("update_project_activity_content", {
     documentId,
@@ -55,14 +241,90 @@ export const moveDocumentToProject = async (documentId: number, targetProjectId:
   });
 };
 
+/**
+ * Result of `extract_document_text`; `cancelled` means only the pages read so far,
+ * `ocr` that the text was recognized from page images and may contain errors,
+ * `truncated` that a text file over `max_import_bytes` was cut at the limit
+ */
+export type ExtractedDocument = { text: string; cancelled: boolean; ocr: boolean; truncated: boolean };
+
+/** Payload of `extraction_progress`, emitted per PDF page */
+export type ExtractionProgress = {
+  file_path: string;
+  page: number;
+  total_pages: number;
+  ocr: boolean;
+};
+
+/** Combine documents in the given order; returns the merged document's id */
+export const mergeDocuments = async (
+  ids: number[],
+  targetName: string,
+  deleteSources: boolean
+): Promise<number> => {
+  return await invoke<number>("merge_documents", { ids, targetName, deleteSources });
+};
+
+/** A document saved from a message of a chat */
+export type LinkedDocument = {
+  document_id: number;
+  document_name: string;
+  project_id: number;
+  message_id: number;
+  created_at: string;
+};
+
+/** A chat a document was saved from */
+export type LinkedChat = {
+  chat_id: number;
+  chat_name: string;
+  message_id: number;
+  created_at: string;
+};
+
+/** Save an assistant message as a document; no projectId means Unassigned. Returns the document's id */
+export const saveMessageAsDocument = async (
+  messageId: number,
+  projectId?: number
+): Promise<number> => {
+  return await invoke<number>("save_message_as_document", { messageId, projectId: projectId ?? null });
+};
+
+export const fetchDocumentsForChat = async (chatId: number): Promise<LinkedDocument[]> => {
+  return await invoke<LinkedDocument[]>("get_documents_for_chat", { chatId });
+};
+
+export const fetchChatsForDocument = async (documentId: number): Promise<LinkedChat[]> => {
+  return await invoke<LinkedChat[]>("get_chats_for_document", { documentId });
+};
+
 export const projectService = {
   fetch: fetchProjects,
   save: saveProject,
   update: updateProject,
   delete: deleteProject,
   updateActivityName,
+  touchDocument,
+  searchWithinDocument,
   addBlankActivity,
   deleteActivity,
   addUnassignedActivity,
-  moveDocumentToProject  // Add this line
-};
\ No newline at end of file
+  transcribeToDocument,
+  importDocument,
+  moveDocumentToProject,  // Add this line
+  mergeDocuments,
+  saveMessageAsDocument,
+};
+
+/** Payload of `transcription_partial`, emitted for each window of a live recording */
+export type TranscriptionPartial = {
+  file_path: string;
+  /** Text of this window */
+  text: string;
+  /** Text of every window so far */
+  transcript: string;
+  start: number;
+  end: number;
+  /** Set on the last partial, once the recording has stopped */
+  is_final: boolean;
+};
@@ -1,5 +1,6 @@
 import { useEffect, useState } from "react";
 import {
+  Badge,
   Box,
   Flex,
   Text,
@@ -7,11 +8,60 @@ import {
   Select,
   VStack,
   Input,
+  Textarea,
   Button,
   useToast,
 } from "@chakra-ui/react";
+import { invoke } from "@tauri-apps/api/tauri";
+import { ask, open, save } from "@tauri-apps/api/dialog";
+import { getErrorMessage } from "@heelix-app/utils";
 import { useGlobalSettings } from "../Providers/SettingsProvider";
 
+/** Result of `get_database_size` */
+type DatabaseSize = {
+  size_bytes: number;
+  tables: { name: string; rows: number }[];
+};
+
+const MB = 1024 * 1024;
+const formatMegabytes = (bytes: number) => `${(bytes / MB).toFixed(1)} MB`;
+
+/** One entry of `get_providers_status` */
+type ProviderStatus = {
+  provider: "claude" | "openai" | "gemini" | "local";
+  configured: boolean;
+  /** null until the provider has been tested or used since launch */
+  last_check_passed: boolean | null;
+  model: string;
+};
+
+const PROVIDER_LABELS: Record<ProviderStatus["provider"], string> = {
+  claude: "Claude",
+  openai: "OpenAI",
+  gemini: "Gemini",
+  local: "Local",
+};
+
+/** Naming model each provider uses when its `naming_model_*` setting is empty */
+const DEFAULT_NAMING_MODELS: Record<ProviderStatus["provider"], string> = {
+  claude: "claude-haiku-4-5",
+  openai: "gpt-4o-mini",
+  gemini: "gemini-2.0-flash",
+  local: "llama3.3:70b",
+};
+
+type CloudProvider = Exclude<ProviderStatus["provider"], "local">;
+type RateLimit = { rpm: number; tpm: number };
+
+const CLOUD_PROVIDERS: CloudProvider[] = ["claude", "openai", "gemini"];
+
+const providerBadge = (status: ProviderStatus) => {
+  if (!status.configured) return { color: "gray", label: "No key" };
+  if (status.last_check_passed === false) return { color: "red", label: "Failing" };
+  if (status.last_check_passed === true) return { color: "green", label: "Ready" };
+  return { color: "blue", label: "Configured" };
+};
+
 type LocalSettings = {
   autoStart: boolean;
   apiChoice: "claude" | "openai" | "gemini" | "local";
@@ -19,8 +69,37 @@ type LocalSettings = {
   apiKeyClaude: string;
   apiKeyGemini: string;
   localModelUrl: string;
+  openAiBaseUrl: string;
+  openAiOrgId: string;
+  openAiProjectId: string;
   vectorizationEnabled: boolean;
   ragTopK: number;
+  maxContextDocuments: number;
+  maxOutputTokens: number;
+  vectorCacheSize: number;
+  recordingSampleRate: number;
+  liveTranscription: boolean;
+  transcriptionBaseUrl: string;
+  transcriptionApiKey: string;
+  transcriptionModel: string;
+  fallbackProviders: string;
+  autoNameChats: boolean;
+  namingModels: Record<ProviderStatus["provider"], string>;
+  namingMaxChars: number;
+  rateLimits: Record<CloudProvider, RateLimit>;
+  reindexDelaySecs: number;
+  maxImportMb: number;
+  embeddingRetries: number;
+  redactPiiBeforeSend: boolean;
+  attributeSources: boolean;
+  expandChunkContext: boolean;
+  ragOnEveryTurn: boolean;
+  embeddingModel: string;
+  redactionDenylist: string;
+  assistantPersona: string;
+  ocrScannedPdfs: boolean;
+  closeToTray: boolean;
+  compressDocuments: boolean;
 };
 export const GeneralSettings = () => {
   const toast = useToast();
@@ -32,8 +111,46 @@ export const GeneralSettings = () => {
     apiKeyClaude: settings.api_key_claude,
     apiKeyGemini: settings.api_key_gemini,
     localModelUrl: settings.local_model_url,
+    openAiBaseUrl: settings.openai_base_url,
+    openAiOrgId: settings.openai_org_id,
+    openAiProjectId: settings.openai_project_id,
     vectorizationEnabled: settings.vectorization_enabled,
     ragTopK: settings.rag_top_k,
+    maxContextDocuments: settings.max_context_documents,
+    attributeSources: settings.attribute_sources,
+    expandChunkContext: settings.expand_chunk_context,
+    ragOnEveryTurn: settings.rag_on_every_turn,
+    embeddingModel: settings.embedding_model,
+    maxOutputTokens: settings.max_output_tokens,
+    vectorCacheSize: settings.vector_cache_size,
+    recordingSampleRate: settings.recording_sample_rate,
+    liveTranscription: settings.live_transcription,
+    transcriptionBaseUrl: settings.transcription_base_url,
+    transcriptionApiKey: settings.transcription_api_key,
+    transcriptionModel: settings.transcription_model,
+    fallbackProviders: settings.fallback_providers.join(", "),
+    autoNameChats: settings.auto_name_chats,
+    namingModels: {
+      claude: settings.naming_model_claude,
+      openai: settings.naming_model_openai,
+      gemini: settings.naming_model_gemini,
+      local: settings.naming_model_local,
+    },
+    namingMaxChars: settings.naming_max_chars,
+    rateLimits: {
+      claude: { rpm: settings.rate_limit_rpm_claude, tpm: settings.rate_limit_tpm_claude },
+      openai: { rpm: settings.rate_limit_rpm_openai, tpm: settings.rate_limit_tpm_openai },
+      gemini: { rpm: settings.rate_limit_rpm_gemini, tpm: settings.rate_limit_tpm_gemini },
+    },
+    embeddingRetries: settings.embedding_retries,
+    reindexDelaySecs: settings.reindex_delay_secs,
+    maxImportMb: Math.round(settings.max_import_bytes / MB),
+    redactPiiBeforeSend: settings.redact_pii_before_send,
+    redactionDenylist: settings.redaction_denylist.join(", "),
+    assistantPersona: settings.assistant_persona,
+    ocrScannedPdfs: settings.ocr_scanned_pdfs,
+    closeToTray: settings.close_to_tray,
+    compressDocuments: settings.compress_documents,
   });
 
   useEffect(() => {
@@ -44,8 +161,46 @@ export const GeneralSettings = () => {
       apiKeyClaude: settings.api_key_claude,
       apiKeyGemini: settings.api_key_gemini,
       localModelUrl: settings.local_model_url,
+      openAiBaseUrl: settings.openai_base_url,
+      openAiOrgId: settings.openai_org_id,
+      openAiProjectId: settings.openai_project_id,
       vectorizationEnabled: settings.vectorization_enabled,
       ragTopK: settings.rag_top_k,
+      maxContextDocuments: settings.max_context_documents,
+      attributeSources: settings.attribute_sources,
+      expandChunkContext: settings.expand_chunk_context,
+      ragOnEveryTurn: settings.rag_on_every_turn,
+      embeddingModel: settings.embedding_model,
+      maxOutputTokens: settings.max_output_tokens,
+      vectorCacheSize: settings.vector_cache_size,
+      recordingSampleRate: settings.recording_sample_rate,
+      liveTranscription: settings.live_transcription,
+      transcriptionBaseUrl: settings.transcription_base_url,
+    transcriptionApiKey: settings.transcription_api_key,
+      transcriptionModel: settings.transcription_model,
+      fallbackProviders: settings.fallback_providers.join(", "),
+      autoNameChats: settings.auto_name_chats,
+      namingModels: {
+        claude: settings.naming_model_claude,
+        openai: settings.naming_model_openai,
+        gemini: settings.naming_model_gemini,
+        local: settings.naming_model_local,
+      },
+      namingMaxChars: settings.naming_max_chars,
+      rateLimits: {
+        claude: { rpm: settings.rate_limit_rpm_claude, tpm: settings.rate_limit_tpm_claude },
+        openai: { rpm: settings.rate_limit_rpm_openai, tpm: settings.rate_limit_tpm_openai },
+        gemini: { rpm: settings.rate_limit_rpm_gemini, tpm: settings.rate_limit_tpm_gemini },
+      },
+      embeddingRetries: settings.embedding_retries,
+      reindexDelaySecs: settings.reindex_delay_secs,
+      maxImportMb: Math.round(settings.max_import_bytes / MB),
+      redactPiiBeforeSend: settings.redact_pii_before_send,
+      redactionDenylist: settings.redaction_denylist.join(", "),
+      assistantPersona: settings.assistant_persona,
+      ocrScannedPdfs: settings.ocr_scanned_pdfs,
+      closeToTray: settings.close_to_tray,
+      compressDocuments: settings.compress_documents,
     });
   }, [settings]);
 
@@ -98,19 +253,264 @@ export const GeneralSettings = () => {
     }));
   };
 
-  const onSave = () => {
-    update({
-      ...settings,
-      auto_start: localSettings.autoStart,
-      api_choice: localSettings.apiChoice,
-      api_key_open_ai: localSettings.apiKeyOpenAi,
-      api_key_claude: localSettings.apiKeyClaude,
-      api_key_gemini: localSettings.apiKeyGemini,
-      local_model_url: localSettings.localModelUrl,
-      vectorization_enabled: localSettings.vectorizationEnabled,
-      rag_top_k: localSettings.ragTopK,
+  const onChangeOpenAiBaseUrl = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      openAiBaseUrl: event.target.value,
+    }));
+  };
+
+  const onChangeOpenAiOrgId = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      openAiOrgId: event.target.value,
+    }));
+  };
+
+  const onChangeOpenAiProjectId = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      openAiProjectId: event.target.value,
+    }));
+  };
+
+  const onChangeFallbackProviders = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      fallbackProviders: event.target.value,
+    }));
+  };
+
+  const [providerStatuses, setProviderStatuses] = useState<ProviderStatus[]>([]);
+  const refreshProviderStatuses = () =>
+    invoke<ProviderStatus[]>("get_providers_status")
+      .then(setProviderStatuses)
+      .catch((e) => console.log("Provider status unavailable:", e));
+  useEffect(() => {
+    refreshProviderStatuses();
+  }, [settings]);
+
+  const [dataDirectory, setDataDirectory] = useState("");
+  const [isMovingData, setIsMovingData] = useState(false);
+  useEffect(() => {
+    invoke<string>("get_data_directory")
+      .then(setDataDirectory)
+      .catch((e) => console.log("Data directory unavailable:", e));
+  }, []);
+
+  const onMoveDataDirectory = async () => {
+    const selected = await open({ directory: true, defaultPath: dataDirectory });
+    if (!selected || Array.isArray(selected)) return;
+    setIsMovingData(true);
+    try {
+      const path = await invoke<string>("set_data_directory", { path: selected });
+      setDataDirectory(path);
+      toast({
+        title: "Data moved",
+        description: `Heelix now stores its data in ${path}. The old copy was left in place.`,
+        status: "success",
+        duration: 5000,
+        isClosable: true,
+      });
+    } catch (error) {
+      toast({
+        title: "Could not move data",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 6000,
+        isClosable: true,
+      });
+    } finally {
+      setIsMovingData(false);
+    }
+  };
+
+  const [isExporting, setIsExporting] = useState(false);
+  const onExportAll = async () => {
+    const path = await save({
+      defaultPath: `heelix-backup-${new Date().toISOString().slice(0, 10)}.zip`,
+      filters: [{ name: "Heelix backup", extensions: ["zip"] }],
     });
-    savedSuccessfullyToast();
+    if (!path) return;
+    setIsExporting(true);
+    try {
+      await invoke("export_all", { path });
+      toast({
+        title: "Backup exported",
+        description: `All documents, chats and search indexes were saved to ${path}.`,
+        status: "success",
+        duration: 5000,
+        isClosable: true,
+      });
+    } catch (error) {
+      toast({
+        title: "Could not export backup",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 6000,
+        isClosable: true,
+      });
+    } finally {
+      setIsExporting(false);
+    }
+  };
+
+  const [isImporting, setIsImporting] = useState(false);
+  const onImportAll = async () => {
+    const selected = await open({ filters: [{ name: "Heelix backup", extensions: ["zip"] }] });
+    if (!selected || Array.isArray(selected)) return;
+    const confirmed = await ask(
+      "Importing replaces all current documents, chats and settings with the backup. A copy of the current data is kept in the backups folder of the data directory.",
+      { title: "Import backup", type: "warning" }
+    );
+    if (!confirmed) return;
+    setIsImporting(true);
+    try {
+      await invoke("import_all", { path: selected });
+      // Everything loaded so far belongs to the replaced data
+      window.location.reload();
+    } catch (error) {
+      toast({
+        title: "Could not import backup",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 6000,
+        isClosable: true,
+      });
+      setIsImporting(false);
+    }
+  };
+
+  const [databaseSize, setDatabaseSize] = useState<DatabaseSize | null>(null);
+  const [isVacuuming, setIsVacuuming] = useState(false);
+  const refreshDatabaseSize = () =>
+    invoke<DatabaseSize>("get_database_size")
+      .then(setDatabaseSize)
+      .catch((e) => console.log("Database size unavailable:", e));
+  useEffect(() => {
+    refreshDatabaseSize();
+  }, [dataDirectory]);
+
+  const onVacuumDatabase = async () => {
+    setIsVacuuming(true);
+    try {
+      const freed = await invoke<number>("vacuum_database");
+      toast({
+        title: "Database compacted",
+        description: `Freed ${formatMegabytes(freed)}.`,
+        status: "success",
+        duration: 5000,
+        isClosable: true,
+      });
+    } catch (error) {
+      toast({
+        title: "Could not compact the database",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 6000,
+        isClosable: true,
+      });
+    } finally {
+      setIsVacuuming(false);
+      refreshDatabaseSize();
+    }
+  };
+
+  const [isTestingLocal, setIsTestingLocal] = useState(false);
+  const onTestLocalEndpoint = async () => {
+    setIsTestingLocal(true);
+    try {
+      const result = await invoke<{ model: string; latency_ms: number; reply: string }>(
+        "test_local_endpoint",
+        { url: localSettings.localModelUrl, model: null }
+      );
+      toast({
+        title: "Local model is reachable",
+        description: `${result.model} answered in ${result.latency_ms} ms`,
+        status: "success",
+        duration: 3000,
+        isClosable: true,
+      });
+    } catch (error) {
+      toast({
+        title: "Local model test failed",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 6000,
+        isClosable: true,
+      });
+    } finally {
+      setIsTestingLocal(false);
+      refreshProviderStatuses();
+    }
+  };
+
+  const onSave = async () => {
+    try {
+      await update({
+        ...settings,
+        auto_start: localSettings.autoStart,
+        api_choice: localSettings.apiChoice,
+        api_key_open_ai: localSettings.apiKeyOpenAi,
+        api_key_claude: localSettings.apiKeyClaude,
+        api_key_gemini: localSettings.apiKeyGemini,
+        local_model_url: localSettings.localModelUrl,
+        openai_base_url: localSettings.openAiBaseUrl,
+        openai_org_id: localSettings.openAiOrgId.trim(),
+        openai_project_id: localSettings.openAiProjectId.trim(),
+        vectorization_enabled: localSettings.vectorizationEnabled,
+        rag_top_k: localSettings.ragTopK,
+        max_context_documents: localSettings.maxContextDocuments,
+        attribute_sources: localSettings.attributeSources,
+        expand_chunk_context: localSettings.expandChunkContext,
+        rag_on_every_turn: localSettings.ragOnEveryTurn,
+        embedding_model: localSettings.embeddingModel.trim(),
+        max_output_tokens: localSettings.maxOutputTokens,
+        vector_cache_size: localSettings.vectorCacheSize,
+        recording_sample_rate: localSettings.recordingSampleRate,
+        live_transcription: localSettings.liveTranscription,
+        transcription_base_url: localSettings.transcriptionBaseUrl.trim(),
+        transcription_api_key: localSettings.transcriptionApiKey.trim(),
+        transcription_model: localSettings.transcriptionModel.trim(),
+        fallback_providers: localSettings.fallbackProviders
+          .split(",")
+          .map((provider) => provider.trim().toLowerCase())
+          .filter((provider) => provider.length > 0) as ApiChoice[],
+        auto_name_chats: localSettings.autoNameChats,
+        naming_model_claude: localSettings.namingModels.claude.trim(),
+        naming_model_openai: localSettings.namingModels.openai.trim(),
+        naming_model_gemini: localSettings.namingModels.gemini.trim(),
+        naming_model_local: localSettings.namingModels.local.trim(),
+        naming_max_chars: localSettings.namingMaxChars,
+        rate_limit_rpm_claude: localSettings.rateLimits.claude.rpm,
+        rate_limit_tpm_claude: localSettings.rateLimits.claude.tpm,
+        rate_limit_rpm_openai: localSettings.rateLimits.openai.rpm,
+        rate_limit_tpm_openai: localSettings.rateLimits.openai.tpm,
+        rate_limit_rpm_gemini: localSettings.rateLimits.gemini.rpm,
+        rate_limit_tpm_gemini: localSettings.rateLimits.gemini.tpm,
+        embedding_retries: localSettings.embeddingRetries,
+        reindex_delay_secs: localSettings.reindexDelaySecs,
+        max_import_bytes: localSettings.maxImportMb * MB,
+        redact_pii_before_send: localSettings.redactPiiBeforeSend,
+        redaction_denylist: localSettings.redactionDenylist
+          .split(",")
+          .map((term) => term.trim())
+          .filter((term) => term.length > 0),
+        assistant_persona: localSettings.assistantPersona,
+        ocr_scanned_pdfs: localSettings.ocrScannedPdfs,
+        close_to_tray: localSettings.closeToTray,
+        compress_documents: localSettings.compressDocuments,
+      });
+      savedSuccessfullyToast();
+    } catch (error) {
+      toast({
+        title: "Settings were not saved",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 4000,
+        isClosable: true,
+      });
+    }
   };
 
   const onChangeRagTopK = (event: React.ChangeEvent<HTMLInputElement>) => {
@@ -121,6 +521,193 @@ export const GeneralSettings = () => {
     }));
   };
 
+  const onChangeMaxContextDocuments = (event: React.ChangeEvent<HTMLInputElement>) => {
+    const value = parseInt(event.target.value) || 4;
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      maxContextDocuments: Math.max(1, Math.min(20, value)), // Clamp between 1 and 20
+    }));
+  };
+
+  const onChangeVectorCacheSize = (event: React.ChangeEvent<HTMLInputElement>) => {
+    const value = parseInt(event.target.value) || 5;
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      vectorCacheSize: Math.max(1, Math.min(50, value)), // Clamp between 1 and 50
+    }));
+  };
+
+  const onChangeEmbeddingRetries = (event: React.ChangeEvent<HTMLInputElement>) => {
+    const value = parseInt(event.target.value);
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      embeddingRetries: isNaN(value) ? 2 : Math.max(0, Math.min(5, value)), // Clamp between 0 and 5
+    }));
+  };
+
+  const onChangeReindexDelay = (event: React.ChangeEvent<HTMLInputElement>) => {
+    const value = parseInt(event.target.value) || 5;
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      reindexDelaySecs: Math.max(1, Math.min(300, value)), // Clamp between 1 and 300
+    }));
+  };
+
+  const onChangeMaxImportMb = (event: React.ChangeEvent<HTMLInputElement>) => {
+    const value = parseInt(event.target.value) || 50;
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      maxImportMb: Math.max(1, Math.min(2048, value)), // Clamp between 1 MB and 2 GB
+    }));
+  };
+
+  const onChangeRateLimit =
+    (provider: CloudProvider, limit: keyof RateLimit) => (event: React.ChangeEvent<HTMLInputElement>) => {
+      const value = parseInt(event.target.value);
+      setLocalSettings((prevState) => ({
+        ...prevState,
+        rateLimits: {
+          ...prevState.rateLimits,
+          [provider]: { ...prevState.rateLimits[provider], [limit]: isNaN(value) ? 0 : Math.max(0, value) },
+        },
+      }));
+    };
+
+  const onChangeRecordingSampleRate = (event: React.ChangeEvent<HTMLSelectElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      recordingSampleRate: parseInt(event.target.value) || 0,
+    }));
+  };
+
+  const handleLiveTranscriptionChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      liveTranscription: event.target.checked,
+    }));
+  };
+
+  const onChangeTranscriptionBaseUrl = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      transcriptionBaseUrl: event.target.value,
+    }));
+  };
+
+  const onChangeTranscriptionApiKey = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      transcriptionApiKey: event.target.value,
+    }));
+  };
+
+  const onChangeTranscriptionModel = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      transcriptionModel: event.target.value,
+    }));
+  };
+
+  const onChangeMaxOutputTokens = (event: React.ChangeEvent<HTMLSelectElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      maxOutputTokens: parseInt(event.target.value) || 0,
+    }));
+  };
+
+  const handleCloseToTrayChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      closeToTray: event.target.checked,
+    }));
+  };
+
+  const handleCompressDocumentsChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      compressDocuments: event.target.checked,
+    }));
+  };
+
+  const handleAutoNameChatsChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      autoNameChats: event.target.checked,
+    }));
+  };
+
+  const onChangeNamingModel =
+    (provider: ProviderStatus["provider"]) => (event: React.ChangeEvent<HTMLInputElement>) => {
+      setLocalSettings((prevState) => ({
+        ...prevState,
+        namingModels: { ...prevState.namingModels, [provider]: event.target.value },
+      }));
+    };
+
+  const onChangeNamingMaxChars = (event: React.ChangeEvent<HTMLInputElement>) => {
+    const value = parseInt(event.target.value) || 18;
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      namingMaxChars: Math.max(10, Math.min(80, value)), // Clamp between 10 and 80
+    }));
+  };
+
+  const onChangeAssistantPersona = (event: React.ChangeEvent<HTMLTextAreaElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      assistantPersona: event.target.value,
+    }));
+  };
+
+  const handleOcrScannedPdfsChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      ocrScannedPdfs: event.target.checked,
+    }));
+  };
+
+  const onChangeEmbeddingModel = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      embeddingModel: event.target.value,
+    }));
+  };
+
+  const handleAttributeSourcesChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      attributeSources: event.target.checked,
+    }));
+  };
+
+  const handleExpandChunkContextChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      expandChunkContext: event.target.checked,
+    }));
+  };
+
+  const handleRagOnEveryTurnChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      ragOnEveryTurn: event.target.checked,
+    }));
+  };
+
+  const handleRedactPiiChange = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      redactPiiBeforeSend: event.target.checked,
+    }));
+  };
+
+  const onChangeRedactionDenylist = (event: React.ChangeEvent<HTMLInputElement>) => {
+    setLocalSettings((prevState) => ({
+      ...prevState,
+      redactionDenylist: event.target.value,
+    }));
+  };
+
   const handleVectorizationChange = (event: React.ChangeEvent<HTMLInputElement>) => {
     setLocalSettings((prevState) => ({
       ...prevState,
@@ -147,6 +734,99 @@ export const GeneralSettings = () => {
           </Text>
         </Box>
 
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Keep Running in Tray:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.closeToTray}
+              onChange={handleCloseToTrayChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Closing the window hides Heelix to the system tray. Turn this off to quit the
+            application when the window is closed.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Compress Stored Documents:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.compressDocuments}
+              onChange={handleCompressDocumentsChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Store document contents compressed to save disk space. Existing documents are compressed
+            the next time Heelix starts; turning this off keeps them readable and stores new edits
+            uncompressed.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Data Location:
+            </Text>
+            <Text fontSize="sm" flex={1} noOfLines={1} title={dataDirectory}>
+              {dataDirectory}
+            </Text>
+            <Button size="sm" ml={4} onClick={onMoveDataDirectory} isLoading={isMovingData}>
+              Move...
+            </Button>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Folder holding your documents database and search indexes. Moving copies them to the
+            new folder and keeps using it on later launches.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4} flex={1}>
+              Full Backup:
+            </Text>
+            <Button size="sm" onClick={onExportAll} isLoading={isExporting}>
+              Export...
+            </Button>
+            <Button size="sm" ml={2} onClick={onImportAll} isLoading={isImporting}>
+              Import...
+            </Button>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Save the database and every search index to one file, for example to move to a new
+            machine. Importing replaces all current data.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Database Size:
+            </Text>
+            <Text
+              fontSize="sm"
+              flex={1}
+              title={databaseSize?.tables.map((table) => `${table.name}: ${table.rows} rows`).join("\n")}
+            >
+              {databaseSize ? formatMegabytes(databaseSize.size_bytes) : "-"}
+            </Text>
+            <Button size="sm" ml={4} onClick={onVacuumDatabase} isLoading={isVacuuming}>
+              Compact
+            </Button>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Deleted documents, old versions and re-chunked text leave unused space behind. Compacting
+            rewrites the database to reclaim it and can take a while on large libraries.
+          </Text>
+        </Box>
+
         <Box>
           <Flex alignItems="center" mb={2}>
             <Flex flex={1}>
@@ -184,73 +864,314 @@ export const GeneralSettings = () => {
           <Flex alignItems="center" mb={2}>
             <Flex flex={1}>
               <Text fontSize="md" mr={4}>
-                Claude API Key:
+                OpenAI Base URL:
               </Text>
             </Flex>
             <Flex flex={2}>
               <Input
-                value={localSettings.apiKeyClaude}
-                onChange={onChangeClaueApiKey}
-                placeholder="sk-ant-..."
+                value={localSettings.openAiBaseUrl}
+                onChange={onChangeOpenAiBaseUrl}
+                placeholder="https://api.openai.com/v1 (optional)"
               />
             </Flex>
           </Flex>
           <Flex alignItems="center" mb={2}>
             <Flex flex={1}>
               <Text fontSize="md" mr={4}>
-                Gemini API Key:
+                Transcription API Key:
               </Text>
             </Flex>
             <Flex flex={2}>
               <Input
-                value={localSettings.apiKeyGemini}
-                onChange={onChangeGeminiApiKey}
-                placeholder="AIza..."
+                value={localSettings.transcriptionApiKey}
+                onChange={onChangeTranscriptionApiKey}
+                placeholder="gsk_... (optional)"
               />
             </Flex>
           </Flex>
           <Flex alignItems="center" mb={2}>
             <Flex flex={1}>
               <Text fontSize="md" mr={4}>
-                Local Model URL:
+                OpenAI Organization ID:
               </Text>
             </Flex>
             <Flex flex={2}>
               <Input
-                value={localSettings.localModelUrl}
-                onChange={onChangeLocalModelUrl}
-                placeholder="http://localhost:11434"
+                value={localSettings.openAiOrgId}
+                onChange={onChangeOpenAiOrgId}
+                placeholder="org-... (optional)"
               />
             </Flex>
           </Flex>
-          <Text fontSize="sm" color="gray.500">
-            Select the API to use for natural language processing tasks. For local models, use Ollama (default: http://localhost:11434).
-          </Text>
-        </Box>
-
-        <Box>
-          <Flex alignItems="center" mb={2}>
-            <Text fontSize="md" mr={4}>
-              Enable Document Indexing:
-            </Text>
-            <Switch
-              size="md"
-              isChecked={localSettings.vectorizationEnabled}
-              onChange={handleVectorizationChange}
-            />
-          </Flex>
-          <Text fontSize="sm" color="gray.500">
-            When enabled, documents added to projects are automatically indexed using OpenAI embeddings.
-            The AI will search within the selected project's documents to find relevant context for your questions.
-            Requires OpenAI API key. When disabled, only explicitly selected documents are used as context.
-          </Text>
-        </Box>
-
-        <Box>
           <Flex alignItems="center" mb={2}>
             <Flex flex={1}>
               <Text fontSize="md" mr={4}>
-                RAG Context Chunks:
+                OpenAI Project ID:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.openAiProjectId}
+                onChange={onChangeOpenAiProjectId}
+                placeholder="proj_... (optional)"
+              />
+            </Flex>
+          </Flex>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Claude API Key:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.apiKeyClaude}
+                onChange={onChangeClaueApiKey}
+                placeholder="sk-ant-..."
+              />
+            </Flex>
+          </Flex>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Gemini API Key:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.apiKeyGemini}
+                onChange={onChangeGeminiApiKey}
+                placeholder="AIza..."
+              />
+            </Flex>
+          </Flex>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Local Model URL:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.localModelUrl}
+                onChange={onChangeLocalModelUrl}
+                placeholder="http://localhost:11434"
+              />
+              <Button ml={2} size="md" onClick={onTestLocalEndpoint} isLoading={isTestingLocal}>
+                Test
+              </Button>
+            </Flex>
+          </Flex>
+          <Flex gap={2} mb={2} wrap="wrap">
+            {providerStatuses.map((status) => {
+              const badge = providerBadge(status);
+              return (
+                <Badge key={status.provider} colorScheme={badge.color} title={status.model}>
+                  {PROVIDER_LABELS[status.provider]}: {badge.label}
+                </Badge>
+              );
+            })}
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Select the API to use for natural language processing tasks. For local models, use Ollama (default: http://localhost:11434).
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Fallback Providers:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.fallbackProviders}
+                onChange={onChangeFallbackProviders}
+                placeholder="openai, gemini, local (optional)"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Providers to try, in order, when the selected API is overloaded or unreachable. Providers
+            without an API key are skipped. Leave empty to report the error instead.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Name Chats Automatically:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.autoNameChats}
+              onChange={handleAutoNameChatsChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Give each new chat a short title after its first answer, using a small model from the
+            provider that answered.
+          </Text>
+        </Box>
+
+        <Box>
+          <Text fontSize="md" mb={2}>
+            Naming Models:
+          </Text>
+          <VStack spacing={2} align="stretch" mb={2}>
+            {(Object.keys(DEFAULT_NAMING_MODELS) as ProviderStatus["provider"][]).map((provider) => (
+              <Flex key={provider} alignItems="center">
+                <Text fontSize="sm" flex={1}>
+                  {PROVIDER_LABELS[provider]}
+                </Text>
+                <Input
+                  flex={2}
+                  size="sm"
+                  value={localSettings.namingModels[provider]}
+                  onChange={onChangeNamingModel(provider)}
+                  placeholder={DEFAULT_NAMING_MODELS[provider]}
+                />
+              </Flex>
+            ))}
+          </VStack>
+          <Text fontSize="sm" color="gray.500">
+            Model each provider uses to title new chats. Leave empty to keep the default shown.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Chat Name Length:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                type="number"
+                value={localSettings.namingMaxChars}
+                onChange={onChangeNamingMaxChars}
+                min={10}
+                max={80}
+                width="100px"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Longest chat title, in characters, the naming model is asked for (10-80). Default: 18.
+          </Text>
+        </Box>
+
+        <Box>
+          <Text fontSize="md" mb={2}>
+            Assistant Persona:
+          </Text>
+          <Textarea
+            value={localSettings.assistantPersona}
+            onChange={onChangeAssistantPersona}
+            rows={3}
+            mb={2}
+          />
+          <Text fontSize="sm" color="gray.500">
+            How the assistant introduces itself at the start of every chat and document cleanup.
+            {" {provider}"} is replaced with the model family answering. Leave empty for the default.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Redact Personal Details:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.redactPiiBeforeSend}
+              onChange={handleRedactPiiChange}
+            />
+          </Flex>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Also Redact:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.redactionDenylist}
+                onChange={onChangeRedactionDenylist}
+                placeholder="client names, code names (optional)"
+                isDisabled={!localSettings.redactPiiBeforeSend}
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Masks emails, phone numbers, card numbers and the listed terms in document context before
+            it is sent to Claude, OpenAI or Gemini. Your typed messages and local models are not affected.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Enable Document Indexing:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.vectorizationEnabled}
+              onChange={handleVectorizationChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            When enabled, documents added to projects are automatically indexed using OpenAI embeddings.
+            The AI will search within the selected project's documents to find relevant context for your questions.
+            Requires OpenAI API key. When disabled, only explicitly selected documents are used as context.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Embedding Model:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.embeddingModel}
+                onChange={onChangeEmbeddingModel}
+                placeholder="text-embedding-3-small"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            OpenAI model used to index documents and search them. Projects indexed with another model
+            are not searched until their index is rebuilt. Default: text-embedding-3-small.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              OCR Scanned PDFs:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.ocrScannedPdfs}
+              onChange={handleOcrScannedPdfsChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Read PDFs that contain only page images with OCR when importing. Slow on long documents
+            and requires tesseract and poppler to be installed.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                RAG Context Chunks:
               </Text>
             </Flex>
             <Flex flex={2}>
@@ -270,6 +1191,342 @@ export const GeneralSettings = () => {
           </Text>
         </Box>
 
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                RAG Context Documents:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                type="number"
+                value={localSettings.maxContextDocuments}
+                onChange={onChangeMaxContextDocuments}
+                min={1}
+                max={20}
+                width="100px"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Maximum number of documents the retrieved chunks may come from (1-20). Raise it for
+            broad research questions, lower it for focused ones. Default: 4.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Show Sources per Sentence:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.attributeSources}
+              onChange={handleAttributeSourcesChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            After each answer, match its sentences to the retrieved passages they draw on and show
+            them under Sources. Needs an OpenAI key and makes one extra embeddings request per answer.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Include Surrounding Passages:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.expandChunkContext}
+              onChange={handleExpandChunkContextChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Send the best-matching passages together with the text just before and after them, so
+            answers that span a passage boundary stay complete. Makes prompts longer.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Search Documents on Every Message:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.ragOnEveryTurn}
+              onChange={handleRagOnEveryTurnChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Look up passages for each follow-up question as well, not only the first message of a
+            chat. Each message is an extra embeddings request unless it repeats an earlier question.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Open Project Indexes:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                type="number"
+                value={localSettings.vectorCacheSize}
+                onChange={onChangeVectorCacheSize}
+                min={1}
+                max={50}
+                width="100px"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Number of project search indexes kept in memory (1-50). The least recently used
+            project is saved and unloaded when the limit is reached. Default: 5.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Indexing Retries:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                type="number"
+                value={localSettings.embeddingRetries}
+                onChange={onChangeEmbeddingRetries}
+                min={0}
+                max={5}
+                width="100px"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            How often a failed embeddings request is retried (0-5). Chunks that still fail stay
+            pending and are retried the next time the document is indexed. Default: 2.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Re-index Delay:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                type="number"
+                value={localSettings.reindexDelaySecs}
+                onChange={onChangeReindexDelay}
+                min={1}
+                max={300}
+                width="100px"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Seconds an auto-saved document has to go without edits before it is re-chunked and
+            re-indexed (1-300). Default: 5.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Maximum Import Size (MB):
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                type="number"
+                value={localSettings.maxImportMb}
+                onChange={onChangeMaxImportMb}
+                min={1}
+                max={2048}
+                width="100px"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Larger documents and recordings are refused instead of being loaded into memory; text
+            files over the limit are imported up to it (1-2048). Default: 50.
+          </Text>
+        </Box>
+
+        <Box>
+          <Text fontSize="md" mb={2}>
+            Rate Limits:
+          </Text>
+          <VStack spacing={2} align="stretch" mb={2}>
+            {CLOUD_PROVIDERS.map((provider) => (
+              <Flex key={provider} alignItems="center">
+                <Text fontSize="sm" flex={1}>
+                  {PROVIDER_LABELS[provider]}
+                </Text>
+                <Flex flex={2} alignItems="center">
+                  <Input
+                    type="number"
+                    size="sm"
+                    value={localSettings.rateLimits[provider].rpm}
+                    onChange={onChangeRateLimit(provider, "rpm")}
+                    min={0}
+                    width="100px"
+                  />
+                  <Text fontSize="sm" mx={2}>
+                    requests/min
+                  </Text>
+                  <Input
+                    type="number"
+                    size="sm"
+                    value={localSettings.rateLimits[provider].tpm}
+                    onChange={onChangeRateLimit(provider, "tpm")}
+                    min={0}
+                    width="120px"
+                  />
+                  <Text fontSize="sm" ml={2}>
+                    tokens/min
+                  </Text>
+                </Flex>
+              </Flex>
+            ))}
+          </VStack>
+          <Text fontSize="sm" color="gray.500">
+            Chat and indexing requests to each provider wait for capacity instead of failing when
+            these are reached. Set them to your account's limits; 0 is unlimited. Local models are
+            never limited.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Response Length:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Select
+                size="md"
+                value={localSettings.maxOutputTokens}
+                onChange={onChangeMaxOutputTokens}
+              >
+                <option value={0}>Default</option>
+                <option value={1024}>Short (1k tokens)</option>
+                <option value={4096}>Medium (4k tokens)</option>
+                <option value={16000}>Long (16k tokens)</option>
+                <option value={32000}>Very long (32k tokens)</option>
+              </Select>
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Maximum length of each answer. Longer limits allow long-form drafts but can cost more.
+            Values above a model's maximum are reduced to that maximum.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Voice Note Quality:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Select
+                size="md"
+                value={localSettings.recordingSampleRate}
+                onChange={onChangeRecordingSampleRate}
+              >
+                <option value={0}>Microphone default</option>
+                <option value={16000}>16 kHz (smaller files, recommended for transcription)</option>
+                <option value={24000}>24 kHz</option>
+              </Select>
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Sample rate used for voice notes. Lower rates produce smaller recordings that upload and
+            transcribe faster without affecting transcription accuracy.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Text fontSize="md" mr={4}>
+              Live Transcript While Recording:
+            </Text>
+            <Switch
+              size="md"
+              isChecked={localSettings.liveTranscription}
+              onChange={handleLiveTranscriptionChange}
+            />
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Show a rough transcript as you speak. Audio is sent to Whisper in 8-second windows, so
+            text appears about 8-10 seconds behind and words at window edges may be cut. Each window
+            is an extra transcription request; Transcribe still produces the full-quality note.
+          </Text>
+        </Box>
+
+        <Box>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Transcription Base URL:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.transcriptionBaseUrl}
+                onChange={onChangeTranscriptionBaseUrl}
+                placeholder="https://api.openai.com/v1 (optional)"
+              />
+            </Flex>
+          </Flex>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Transcription API Key:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.transcriptionApiKey}
+                onChange={onChangeTranscriptionApiKey}
+                placeholder="gsk_... (optional)"
+              />
+            </Flex>
+          </Flex>
+          <Flex alignItems="center" mb={2}>
+            <Flex flex={1}>
+              <Text fontSize="md" mr={4}>
+                Transcription Model:
+              </Text>
+            </Flex>
+            <Flex flex={2}>
+              <Input
+                value={localSettings.transcriptionModel}
+                onChange={onChangeTranscriptionModel}
+                placeholder="whisper-1"
+              />
+            </Flex>
+          </Flex>
+          <Text fontSize="sm" color="gray.500">
+            Send voice notes to an OpenAI-compatible transcription service such as Groq, Azure or a
+            local Whisper server, e.g. https://api.groq.com/openai/v1 with whisper-large-v3. The
+            transcription key is sent with each request; leave it empty for a server without keys.
+            Leave the URL empty to use OpenAI, which falls back to your OpenAI API key.
+          </Text>
+        </Box>
+
         <Flex flex={1} justifyContent="flex-end">
           <Button colorScheme="blue" size="md" onClick={onSave}>
             Save
@@ -4,6 +4,7 @@ import { invoke } from "@tauri-apps/api/tauri";
 import { Title, Text } from "@heelix-app/design";
 import { Table, Thead, Tbody, Tr, Th, Td, IconButton } from "@chakra-ui/react";
 import { FaRegTrashAlt } from "react-icons/fa";
+import { type Activity } from "../data/activities";
 
 const ContentContainer = styled.div`
   display: flex;
@@ -39,7 +40,7 @@ const HistoryListContainer = styled.div`
   overflow-y: auto;
 `;
 type ActivityHistoryListProps = {
-  activities: Array<[number, string, string]>;
+  activities: Activity[];
   onDelete: (id: number) => void;
   onLoadMore: () => void;
   isLoading: boolean;
@@ -64,16 +65,19 @@ const ActivityHistoryList: FC<ActivityHistoryListProps> = ({
         <Thead>
           <Tr>
             <Th>Window Name</Th>
+            <Th>Preview</Th>
             <Th>Date</Th>
             <Th>Actions</Th>
           </Tr>
         </Thead>
         <Tbody>
-          {activities.map(([id, windowName, timestamp]) => (
+          {activities.map(({ id, title, timestamp, preview }) => (
             <Tr key={id}>
               <Td>
-                {windowName.slice(0, 25) +
-                  (windowName.length > 25 ? "..." : "")}
+                {title.slice(0, 25) + (title.length > 25 ? "..." : "")}
+              </Td>
+              <Td maxWidth="300px" title={preview}>
+                {preview}
               </Td>
               <Td>{new Date(timestamp).toLocaleDateString()}</Td>
               <Td>
@@ -95,9 +99,7 @@ const ActivityHistoryList: FC<ActivityHistoryListProps> = ({
 };
 
 export const HistorySettings: FC = () => {
-  const [activityList, setActivityList] = useState<
-    Array<[number, string, string]>
-  >([]);
+  const [activityList, setActivityList] = useState<Activity[]>([]);
   const [offset, setOffset] = useState(0);
   const [isLoading, setIsLoading] = useState(false);
   const [hasMore, setHasMore] = useState(true);
@@ -107,7 +109,7 @@ export const HistorySettings: FC = () => {
 
     setIsLoading(true);
     try {
-      const result = await invoke<Array<[number, string, string]>>(
+      const result = await invoke<Activity[]>(
         "get_activity_history",
         {
           offset,
@@ -131,7 +133,7 @@ export const HistorySettings: FC = () => {
       const deleted = await invoke<boolean>("delete_activity", { id });
       if (deleted) {
         setActivityList(
-          activityList.filter(([activityId]) => activityId !== id)
+          activityList.filter((activity) => activity.id !== id)
         );
       }
     } catch (error) {
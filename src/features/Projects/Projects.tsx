@@ -39,14 +39,33 @@ import {
   FolderPlus,
   FileUp,
   Mic,
-  Square
+  Square,
+  RefreshCw,
+  EyeOff,
+  Eye
 } from 'lucide-react';
 import { open } from '@tauri-apps/api/dialog';
+import { listen } from '@tauri-apps/api/event';
 import { useGlobalSettings } from "../../Providers/SettingsProvider";
 import { Text } from "@heelix-app/design";
+import { getErrorMessage, isHeelixError } from "@heelix-app/utils";
 import { useProject } from "../../state";
 import { ProjectModal } from "@/components";
-import { type Project } from "../../data/project";
+import {
+  type DuplicatePolicy,
+  type ExtractedDocument,
+  type ExtractionProgress,
+  type ImportedDocument,
+  type TranscriptionPartial,
+  type Project,
+  type VectorizationCancelled,
+  type VectorizationProgress,
+  type VectorizationStatus,
+  cancelVectorization,
+  fetchVectorizationStatus,
+  setDocumentExcludeFromRag,
+  vectorizeProject,
+} from "../../data/project";
 
 //
 // -- Styled Components --
@@ -193,6 +212,13 @@ const truncateDocumentName = (name: string, maxLength: number = 30) => {
 
 const UNASSIGNED_PROJECT_NAME = "Unassigned";
 
+const VECTORIZATION_BADGES: Record<VectorizationStatus, { label: string; colorScheme: string }> = {
+  indexed: { label: "Indexed", colorScheme: "green" },
+  pending: { label: "Pending", colorScheme: "yellow" },
+  too_short: { label: "Too short to index", colorScheme: "gray" },
+  excluded: { label: "Excluded from answers", colorScheme: "purple" },
+};
+
 // DeleteProjectButton component for project deletion
 const DeleteProjectButton: FC<{
   project: Project;
@@ -266,6 +292,8 @@ export const Projects: FC<{
     updateActivityName,
     addBlankActivity,
     addUnassignedActivity,
+    addTranscribedActivity,
+    importActivity,
     deleteActivity,
     moveActivity
   } = useProject();
@@ -334,6 +362,8 @@ export const Projects: FC<{
         onUpdateActivityName={updateActivityName}
         onAddBlankActivity={addBlankActivity}
         onAddUnassignedActivity={addUnassignedActivity}
+        onAddTranscribedActivity={addTranscribedActivity}
+        onImportActivity={importActivity}
         onDeleteActivity={deleteActivity}
       />
       
@@ -372,6 +402,12 @@ const ProjectSelector: FC<{
   onUpdateActivityName: (activityId: number, name: string) => void;
   onAddBlankActivity: () => Promise<number | undefined>;
   onAddUnassignedActivity: () => Promise<number | undefined>;
+  onAddTranscribedActivity: (filePath: string) => Promise<number>;
+  onImportActivity: (
+    name: string,
+    text: string,
+    duplicatePolicy: DuplicatePolicy
+  ) => Promise<ImportedDocument>;
   onDeleteActivity: (activityId: number) => void;
 }> = ({
   projects,
@@ -387,6 +423,8 @@ const ProjectSelector: FC<{
   onUpdateActivityName,
   onAddBlankActivity,
   onAddUnassignedActivity,
+  onAddTranscribedActivity,
+  onImportActivity,
   onDeleteActivity,
 }) => {
   const [editingActivityId, setEditingActivityId] = useState<number | null>(null);
@@ -401,12 +439,124 @@ const ProjectSelector: FC<{
   const [recordingTime, setRecordingTime] = useState(0);
   const [isProcessingRecording, setIsProcessingRecording] = useState(false);
   const [recordingFilePath, setRecordingFilePath] = useState<string | null>(null);
+  const [liveTranscript, setLiveTranscript] = useState("");
+
+  // Partial transcripts of the current recording when live transcription is on
+  useEffect(() => {
+    if (!recordingFilePath) return;
+    const unlisten = listen<TranscriptionPartial>("transcription_partial", (event) => {
+      if (event.payload.file_path === recordingFilePath) {
+        setLiveTranscript(event.payload.transcript);
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [recordingFilePath]);
   const recordingStartTime = useRef<number | null>(null);
   const recordingTimerRef = useRef<ReturnType<typeof setTimeout> | null>(null);
   
   const toast = useToast();
   const { settings } = useGlobalSettings();
 
+  // Indexing resumed after a restart refreshes the badges as batches finish
+  const [indexingTick, setIndexingTick] = useState(0);
+  useEffect(() => {
+    const unlisten = listen<VectorizationProgress>("vectorization_progress", (event) => {
+      if (event.payload.project_id === selectedProject?.id) {
+        setIndexingTick((tick) => tick + 1);
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [selectedProject]);
+
+  // Search-index state of the selected project's documents
+  const [vectorizationStatus, setVectorizationStatus] = useState<Record<number, VectorizationStatus>>({});
+  useEffect(() => {
+    if (!selectedProject || !settings.vectorization_enabled) {
+      setVectorizationStatus({});
+      return;
+    }
+    fetchVectorizationStatus(selectedProject.id)
+      .then((statuses) =>
+        setVectorizationStatus(
+          Object.fromEntries(statuses.map((s) => [s.document_id, s.status]))
+        )
+      )
+      .catch((e) => console.log("Vectorization status unavailable:", e));
+  }, [selectedProject, settings.vectorization_enabled, indexingTick]);
+
+  const [isIndexingProject, setIsIndexingProject] = useState(false);
+  const indexingCancelled = useRef<VectorizationCancelled | null>(null);
+  useEffect(() => {
+    const unlisten = listen<VectorizationCancelled>("vectorization_cancelled", (event) => {
+      if (event.payload.project_id === selectedProject?.id) {
+        indexingCancelled.current = event.payload;
+        setIndexingTick((tick) => tick + 1);
+      }
+    });
+    return () => {
+      unlisten.then((fn) => fn());
+    };
+  }, [selectedProject]);
+
+  const handleIndexProject = async () => {
+    if (!selectedProject) return;
+    setIsIndexingProject(true);
+    indexingCancelled.current = null;
+    try {
+      const indexed = await vectorizeProject(selectedProject.id);
+      const cancelled = indexingCancelled.current as VectorizationCancelled | null;
+      toast(
+        cancelled
+          ? {
+              title: "Indexing cancelled",
+              description: `${indexed} passages were indexed; ${cancelled.remaining} stay queued.`,
+              status: "info",
+              duration: 4000,
+              isClosable: true,
+            }
+          : {
+              title: "Project indexed",
+              description: `${indexed} passages from ${selectedProject.name} are searchable.`,
+              status: "success",
+              duration: 3000,
+              isClosable: true,
+            }
+      );
+    } catch (error) {
+      toast({
+        title: "Indexing failed",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 5000,
+        isClosable: true,
+      });
+    } finally {
+      setIsIndexingProject(false);
+      setIndexingTick((tick) => tick + 1);
+    }
+  };
+
+  const handleToggleExcludeFromRag = async (e: React.MouseEvent, document: ActivityDocument) => {
+    e.stopPropagation();
+    const exclude = vectorizationStatus[document.id] !== "excluded";
+    try {
+      await setDocumentExcludeFromRag(document.id, exclude);
+      setIndexingTick((tick) => tick + 1);
+    } catch (error) {
+      toast({
+        title: exclude ? "Could not exclude document" : "Could not include document",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 5000,
+        isClosable: true,
+      });
+    }
+  };
+
   // Pagination state for efficient loading
   const [visibleDocuments, setVisibleDocuments] = useState<ActivityDocument[]>([]);
   const [page, setPage] = useState(1);
@@ -572,7 +722,7 @@ const ProjectSelector: FC<{
         multiple: true,
         filters: [{
           name: 'Documents',
-          extensions: ['pdf', 'docx', 'txt', 'md', 'rtf']
+          extensions: ['pdf', 'docx', 'eml', 'txt', 'md', 'rtf']
         }]
       });
 
@@ -592,14 +742,42 @@ const ProjectSelector: FC<{
       });
 
       let successCount = 0;
+      let skippedCount = 0;
       let lastActivityId: number | undefined;
 
+      // Large PDFs report each extracted page
+      const unlistenProgress = await listen<ExtractionProgress>('extraction_progress', (event) => {
+        const { file_path, page, total_pages, ocr } = event.payload;
+        const fileName = file_path.split('/').pop() || file_path.split('\\').pop() || '';
+        toast.update(loadingToast, {
+          description: `${ocr ? 'Running OCR on' : 'Reading'} ${fileName}: page ${page} of ${total_pages}`,
+        });
+      });
+
       for (const filePath of filePaths) {
         try {
           // Extract text from file using Tauri command
-          const extractedText = await invoke<string>('extract_document_text', {
+          const { text: extractedText, ocr, truncated } = await invoke<ExtractedDocument>('extract_document_text', {
             filePath
           });
+          if (ocr) {
+            toast({
+              title: "Scanned PDF",
+              description: `${filePath.split('/').pop() || filePath} was read with OCR. Check the text for recognition errors.`,
+              status: "warning",
+              duration: 6000,
+              isClosable: true,
+            });
+          }
+          if (truncated) {
+            toast({
+              title: "File truncated",
+              description: `${filePath.split('/').pop() || filePath} is over the maximum import size; only its beginning was imported.`,
+              status: "warning",
+              duration: 6000,
+              isClosable: true,
+            });
+          }
 
           // Get filename without extension for the document title
           const fileName = filePath.split('/').pop() || filePath.split('\\').pop() || '';
@@ -607,36 +785,30 @@ const ProjectSelector: FC<{
             ? fileName.substring(0, fileName.lastIndexOf('.'))
             : fileName;
 
-          // Create new activity
-          let newActivityId;
-          if (selectedProject) {
-            newActivityId = await onAddBlankActivity();
-          } else {
-            newActivityId = await onAddUnassignedActivity();
-          }
-
-          if (newActivityId && extractedText) {
-            // Update activity name and content
-            await onUpdateActivityName(newActivityId, documentName);
-
-            // Save the extracted text
-            await invoke("update_project_activity_text", {
-              activityId: newActivityId,
-              text: extractedText
-            });
-
-            // Vectorize chunks (if enabled)
-            invoke("vectorize_document_chunks", { documentId: newActivityId })
-              .catch(e => console.log('Vectorization skipped:', e));
-
-            lastActivityId = newActivityId;
-            successCount++;
+          if (extractedText) {
+            // Files already in the project are skipped; the backend chunks and vectorizes new ones
+            const { document_id, action } = await onImportActivity(documentName, extractedText, "skip");
+            if (action === "skipped") {
+              skippedCount++;
+            } else {
+              lastActivityId = document_id;
+              successCount++;
+            }
           }
         } catch (error) {
           console.error(`Error importing ${filePath}:`, error);
+          toast({
+            title: `Could not import ${filePath.split('/').pop() || filePath}`,
+            description: getErrorMessage(error),
+            status: "error",
+            duration: 6000,
+            isClosable: true,
+          });
         }
       }
 
+      unlistenProgress();
+
       // Close loading toast
       toast.close(loadingToast);
 
@@ -648,13 +820,24 @@ const ProjectSelector: FC<{
 
         toast({
           title: "Import successful",
-          description: successCount === 1
+          description: (successCount === 1
             ? "1 document has been imported successfully."
-            : `${successCount} documents have been imported successfully.`,
+            : `${successCount} documents have been imported successfully.`)
+            + (skippedCount > 0 ? ` ${skippedCount} already in the project were skipped.` : ""),
           status: "success",
           duration: 3000,
           isClosable: true,
         });
+      } else if (skippedCount > 0) {
+        toast({
+          title: "Nothing new to import",
+          description: skippedCount === 1
+            ? "This document is already in the project."
+            : `All ${skippedCount} documents are already in the project.`,
+          status: "info",
+          duration: 3000,
+          isClosable: true,
+        });
       } else {
         toast({
           title: "Import failed",
@@ -686,8 +869,9 @@ const ProjectSelector: FC<{
   // Start voice recording
   const startRecording = async () => {
     try {
-      // Check if OpenAI API key is set
-      if (!settings.api_key_open_ai) {
+      // OpenAI's server needs a key; other transcription servers may run without one
+      const usesOpenAi = !settings.transcription_base_url.trim();
+      if (usesOpenAi && !settings.transcription_api_key && !settings.api_key_open_ai) {
         toast({
           title: "API key required",
           description: "An OpenAI API key is required for voice note transcription. Please add it in Settings.",
@@ -702,9 +886,12 @@ const ProjectSelector: FC<{
       setAudioURL(null);
       setRecordingFilePath(null);
       setRecordingTime(0);
+      setLiveTranscript("");
 
       // Start recording via Tauri
-      const filePath = await invoke<string>('start_audio_recording');
+      const filePath = await invoke<string>(
+        settings.live_transcription ? 'start_live_transcription' : 'start_audio_recording'
+      );
       setRecordingFilePath(filePath);
       console.log("Recording started, file path:", filePath);
 
@@ -801,60 +988,40 @@ const ProjectSelector: FC<{
     try {
       setIsTranscribing(true);
 
-      // Call transcription API
-      const transcription = await invoke<string>('transcribe_audio', { 
-        filePath: recordingFilePath 
-      });
-
-      // Create a new activity with the transcription
-      let newActivityId;
-      if (selectedProject) {
-        newActivityId = await onAddBlankActivity();
-      } else {
-        newActivityId = await onAddUnassignedActivity();
-      }
+      // Transcribe into a new, titled document with segment timestamps
+      const newActivityId = await onAddTranscribedActivity(recordingFilePath);
 
-      if (newActivityId) {
-        // Generate document name with timestamp
-        const date = new Date();
-        const documentName = `Voice Note ${date.toLocaleDateString()} ${date.toLocaleTimeString()}`;
-        
-        // Update the document name and content
-        await onUpdateActivityName(newActivityId, documentName);
-        await invoke("update_project_activity_text", {
-          activityId: newActivityId,
-          text: transcription
-        });
-        
-        // Vectorize chunks (if enabled)
-        invoke("vectorize_document_chunks", { documentId: newActivityId })
-          .catch(e => console.log('Vectorization skipped:', e));
+      // Vectorize chunks (if enabled)
+      invoke("vectorize_document_chunks", { documentId: newActivityId })
+        .catch(e => console.log('Vectorization skipped:', e));
 
-        // Clear audio state
-        if (audioURL) {
-          URL.revokeObjectURL(audioURL);
-        }
-        setAudioURL(null);
-        setRecordingFilePath(null);
-        setRecordingTime(0);
+      // Clear audio state
+      if (audioURL) {
+        URL.revokeObjectURL(audioURL);
+      }
+      setAudioURL(null);
+      setRecordingFilePath(null);
+      setRecordingTime(0);
+      setLiveTranscript("");
 
-        toast({
-          title: "Transcription complete",
-          description: "Voice note has been transcribed and saved successfully",
-          status: "success",
-          duration: 3000,
-          isClosable: true,
-        });
+      toast({
+        title: "Transcription complete",
+        description: "Voice note has been transcribed and saved successfully",
+        status: "success",
+        duration: 3000,
+        isClosable: true,
+      });
 
-        // Select the new document
-        onSelectActivity(newActivityId);
-      }
+      // Select the new document
+      onSelectActivity(newActivityId);
     } catch (error) {
       console.error("Error during transcription:", error);
       toast({
         title: "Transcription failed",
-        description: String(error).includes("API key") 
+        description: isHeelixError(error) && error.code === "missing_api_key"
           ? "OpenAI API key is required for audio transcription. Please add it in Settings."
+          : isHeelixError(error) && error.code === "invalid_input"
+          ? error.message
           : "Failed to transcribe audio. Please try again.",
         status: "error",
         duration: 5000,
@@ -873,6 +1040,7 @@ const ProjectSelector: FC<{
     setAudioURL(null);
     setRecordingFilePath(null);
     setRecordingTime(0);
+    setLiveTranscript("");
     setIsProcessingRecording(false);
   };
 
@@ -952,40 +1120,25 @@ const ProjectSelector: FC<{
       console.log('Paste event detected, isHtml:', isHtml);
       
       try {
-        let newActivityId;
-        if (selectedProject) {
-          newActivityId = await onAddBlankActivity();
-        } else {
-          newActivityId = await onAddUnassignedActivity();
-        }
+        // Generate document name from first line of plain text
+        const firstLine = plainText.split('\n')[0].trim();
+        const documentName = firstLine.length > 50 
+          ? firstLine.substring(0, 47) + '...' 
+          : firstLine || 'Pasted Document';
         
-        if (newActivityId) {
-          // Generate document name from first line of plain text
-          const firstLine = plainText.split('\n')[0].trim();
-          const documentName = firstLine.length > 50 
-            ? firstLine.substring(0, 47) + '...' 
-            : firstLine || 'Pasted Document';
-          
-          await onUpdateActivityName(newActivityId, documentName);
-          await invoke("update_project_activity_text", {
-            activityId: newActivityId,
-            text: content
-          });
-          
-          // Vectorize chunks (if enabled)
-          invoke("vectorize_document_chunks", { documentId: newActivityId })
-            .catch(e => console.log('Vectorization skipped:', e));
-          
-          onSelectActivity(newActivityId);
-          
-          toast({
-            title: "Content pasted",
-            description: "Content has been saved to a new document",
-            status: "success",
-            duration: 2000,
-            isClosable: true,
-          });
-        }
+        // Pasting the same note twice opens the existing document instead of copying it
+        const { document_id, action } = await onImportActivity(documentName, content, "skip");
+        onSelectActivity(document_id);
+        
+        toast({
+          title: action === "skipped" ? "Already saved" : "Content pasted",
+          description: action === "skipped"
+            ? "This content is already in a document in this project"
+            : "Content has been saved to a new document",
+          status: action === "skipped" ? "info" : "success",
+          duration: 2000,
+          isClosable: true,
+        });
       } catch (error) {
         console.error('Error during paste processing:', error);
         toast({
@@ -1107,6 +1260,8 @@ const ProjectSelector: FC<{
                     <Text type="m">{project.name}</Text>
                     <Badge colorScheme="blue" ml={2}>
                       {project.activities.length} docs
+                      {project.vectorized_document_count !== undefined &&
+                        `, ${project.vectorized_document_count} indexed`}
                     </Badge>
                   </Flex>
                 </MenuItem>
@@ -1147,6 +1302,30 @@ const ProjectSelector: FC<{
               />
             </Tooltip>
             
+            {selectedProject && settings.vectorization_enabled && (
+              <Tooltip label="Index every document in this project now">
+                <IconButton
+                  aria-label="Index project"
+                  icon={<RefreshCw size={16} />}
+                  size="sm"
+                  variant="ghost"
+                  onClick={handleIndexProject}
+                  isLoading={isIndexingProject}
+                />
+              </Tooltip>
+            )}
+            {selectedProject && isIndexingProject && (
+              <Tooltip label="Stop indexing after the current batch">
+                <IconButton
+                  aria-label="Cancel indexing"
+                  icon={<X size={16} />}
+                  size="sm"
+                  variant="ghost"
+                  onClick={() => cancelVectorization(selectedProject.id)}
+                />
+              </Tooltip>
+            )}
+
             {/* Voice note recording button */}
             <Tooltip label={isRecording ? "Stop recording" : "Record a voice note"}>
               <IconButton
@@ -1236,6 +1415,11 @@ const ProjectSelector: FC<{
                     <audio src={audioURL} controls style={{ width: '100%', height: '32px' }} />
                   </Box>
                 )}
+                {liveTranscript && (
+                  <ChakraText fontSize="sm" color="gray.600" maxH="120px" overflowY="auto">
+                    {liveTranscript}
+                  </ChakraText>
+                )}
               </Flex>
               
               {/* Transcribe button */}
@@ -1300,6 +1484,16 @@ const ProjectSelector: FC<{
                             <DocumentName>
                               {truncateDocumentName(document.name)}
                             </DocumentName>
+
+                            {vectorizationStatus[document.id] && (
+                              <Badge
+                                size="sm"
+                                variant="subtle"
+                                colorScheme={VECTORIZATION_BADGES[vectorizationStatus[document.id]].colorScheme}
+                              >
+                                {VECTORIZATION_BADGES[vectorizationStatus[document.id]].label}
+                              </Badge>
+                            )}
                             
                             {/* Show project tag only if no project filter is applied */}
                             {!selectedProject && (
@@ -1342,6 +1536,14 @@ const ProjectSelector: FC<{
                           >
                             Rename
                           </MenuItem>
+                          {settings.vectorization_enabled && (
+                            <MenuItem
+                              icon={vectorizationStatus[document.id] === "excluded" ? <Eye size={14} /> : <EyeOff size={14} />}
+                              onClick={(e: React.MouseEvent) => handleToggleExcludeFromRag(e, document)}
+                            >
+                              {vectorizationStatus[document.id] === "excluded" ? "Include in answers" : "Exclude from answers"}
+                            </MenuItem>
+                          )}
                           <MenuItem
                             icon={<Trash2 size={14} />}
                             onClick={(e: React.MouseEvent) => handleDeleteDocument(e, document)}
@@ -0,0 +1,27 @@
+export type HeelixErrorCode =
+  | "missing_api_key"
+  | "network"
+  | "provider"
+  | "database"
+  | "not_found"
+  | "invalid_input"
+  | "invalid_settings"
+  | "internal";
+
+export type HeelixError = {
+  code: HeelixErrorCode;
+  message: string;
+  fields?: string[];
+};
+
+export const isHeelixError = (x: unknown): x is HeelixError =>
+  typeof x === "object" &&
+  x !== null &&
+  typeof (x as HeelixError).code === "string" &&
+  typeof (x as HeelixError).message === "string";
+
+export const getErrorMessage = (error: unknown): string => {
+  if (isHeelixError(error)) return error.message;
+  if (error instanceof Error) return error.message;
+  return String(error);
+};
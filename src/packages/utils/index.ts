@@ -1 +1,3 @@
 export { isString } from "./checkType";
+export { isHeelixError, getErrorMessage } from "./errors";
+export type { HeelixError, HeelixErrorCode } from "./errors";
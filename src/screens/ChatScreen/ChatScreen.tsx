@@ -6,16 +6,20 @@ import {
   useDisclosure,
   Box,
   IconButton,
+  Button,
   Tooltip,
   Wrap,
   WrapItem,
   useToast,
 } from "@chakra-ui/react";
 import { Text, NavButton } from "@heelix-app/design";
+import { getErrorMessage } from "@heelix-app/utils";
 import styled from "styled-components";
 import { invoke } from "@tauri-apps/api/tauri";
 import { listen } from "@tauri-apps/api/event";
-import type { StoredMessage, Chat, ChunkSource } from "./types";
+import { open } from "@tauri-apps/api/dialog";
+import type { StoredMessage, Chat, ChunkSource, DocumentSource, SourceSpan, ChatEventPayload, ChatCompletion, ContextDocuments, ProviderFallback, RagContextEmpty, RagEmptyReason, StreamedText, SamplingPreset, ReasoningEffort } from "./types";
+import { SAMPLING_PRESETS } from "./types";
 import { debounce } from "lodash";
 import { FileText, X, History, Folder, MessageCircle } from "lucide-react";
 import { ScreenContainer } from "@/components/layout";
@@ -36,6 +40,14 @@ import { useGlobalSettings } from "../../Providers/SettingsProvider";
 import { SidePanel } from "../../components/SidePanel";
 import { Projects } from "../../features";
 import { useProject } from "../../state";
+import { rebuildProjectVectors } from "../../data/project";
+
+const PROVIDER_NAMES: Record<string, string> = {
+  claude: "Claude",
+  openai: "OpenAI",
+  gemini: "Gemini",
+  local: "a local model",
+};
 
 const ChatContainer = styled.div`
   display: flex;
@@ -127,16 +139,19 @@ export const ChatScreen: FC = () => {
   const [dailyOutputTokens, setDailyOutputTokens] = useState(0);
   const [lastResetTimestamp, setLastResetTimestamp] = useState("");
   const [isActivityHistoryOpen, setIsActivityHistoryOpen] = useState(false);
-  const [selectedActivityTexts, setSelectedActivityTexts] = useState<string[]>([]);
+  const [selectedActivities, setSelectedActivities] = useState<SelectedActivity[]>([]);
   const scrollTimeoutRef = useRef<ReturnType<typeof setTimeout> | null>(null);
   const [selectedActivityName, setSelectedActivityName] = useState("");
   const [currentModelId, setCurrentModelId] = useState<string>("");
+  const [samplingPreset, setSamplingPreset] = useState<SamplingPreset>("balanced");
+  const [reasoningEffort, setReasoningEffort] = useState<ReasoningEffort>("default");
+  // Image files sent with the next message to vision-capable models
+  const [attachedImages, setAttachedImages] = useState<string[]>([]);
   const [isEditing, setIsEditing] = useState(false);
   
   const { 
     state,
     getSelectedProject, 
-    getSelectedProjectActivityText,
     fetchSelectedActivityText,
     selectProject,
     selectActivity,
@@ -146,6 +161,18 @@ export const ChatScreen: FC = () => {
   const [selectedActivityText, setSelectedActivityText] = useState("");
   const [isLoadingActivityText, setIsLoadingActivityText] = useState(false);
   const [currentSources, setCurrentSources] = useState<ChunkSource[]>([]);
+  const [currentDocumentSources, setCurrentDocumentSources] = useState<DocumentSource[]>([]);
+  // Answer sentences matched to the retrieved chunks, when source attribution is on
+  const [currentSourceSpans, setCurrentSourceSpans] = useState<SourceSpan[]>([]);
+  // Set when the backend reports the last answer was generated without any project context
+  const [ragContextEmpty, setRagContextEmpty] = useState<RagEmptyReason | null>(null);
+  const [isRebuildingIndex, setIsRebuildingIndex] = useState(false);
+  const [fallbackProvider, setFallbackProvider] = useState<string | null>(null);
+  // Provider and model of the last answer when it stopped at the output limit
+  const [truncatedRequest, setTruncatedRequest] = useState<{ provider: string; modelId?: string } | null>(null);
+  const fallbackProviderRef = useRef<string | null>(null);
+  // Chat whose answer is on screen; events streamed for other chats are ignored
+  const shownChatIdRef = useRef<number | null>(null);
   
   // Selected document context for chat (when document from Unassigned is selected)
   const [selectedDocumentContext, setSelectedDocumentContext] = useState<{
@@ -188,17 +215,71 @@ export const ChatScreen: FC = () => {
       // ...
     });
 
-    const unlisten2 = listen("output_tokens", (event: any) => {
+    const unlisten2 = listen<ChatEventPayload<number>>("output_tokens", (event) => {
       setDailyOutputTokens((prevTokens) => {
-        const updatedTokens = prevTokens + event.payload;
+        const updatedTokens = prevTokens + event.payload.content;
         saveTokenData(updatedTokens);
         return updatedTokens;
       });
     });
 
-    const unlisten3 = listen("llm_sources", (event: any) => {
-      const sources = event.payload as ChunkSource[];
-      setCurrentSources(sources);
+    const unlisten3 = listen<ChatEventPayload<ChunkSource[]>>("llm_sources", (event) => {
+      if (event.payload.chat_id !== shownChatIdRef.current) return;
+      setCurrentSources(event.payload.content);
+    });
+
+    const unlisten10 = listen<ChatEventPayload<DocumentSource[]>>("llm_document_sources", (event) => {
+      if (event.payload.chat_id !== shownChatIdRef.current) return;
+      setCurrentDocumentSources(event.payload.content);
+    });
+
+    const unlisten4 = listen<RagContextEmpty>("rag_context_empty", (event) => {
+      if (event.payload.chat_id !== shownChatIdRef.current) return;
+      setRagContextEmpty(event.payload.reason);
+    });
+
+    const unlisten5 = listen<ChatEventPayload<ProviderFallback>>("provider_fallback", (event) => {
+      if (event.payload.chat_id !== shownChatIdRef.current) return;
+      setFallbackProvider(event.payload.content.to);
+      fallbackProviderRef.current = event.payload.content.to;
+    });
+
+    const unlisten6 = listen<ChatEventPayload<ContextDocuments>>("context_documents", (event) => {
+      if (event.payload.chat_id !== shownChatIdRef.current) return;
+      const { dropped } = event.payload.content;
+      if (dropped.length > 0) {
+        toast({
+          title: `${dropped.length} document${dropped.length === 1 ? "" : "s"} left out`,
+          description: `Too large for the model's context window: ${dropped.map((d) => d.name).join(", ")}`,
+          status: "warning",
+          duration: 6000,
+          isClosable: true,
+        });
+      }
+    });
+
+    const unlisten7 = listen<ChatEventPayload<string>>("chat_named", (event) => {
+      const { chat_id: chatId, content: name } = event.payload;
+      setChats((prevChats) =>
+        prevChats.map((chat) => (chat.id === chatId ? { ...chat, name } : chat))
+      );
+    });
+
+    const unlisten8 = listen<ChatEventPayload<number>>("context_redacted", (event) => {
+      if (event.payload.chat_id !== shownChatIdRef.current) return;
+      const count = event.payload.content;
+      toast({
+        title: `${count} value${count === 1 ? "" : "s"} redacted`,
+        description: "Personal details in your documents were masked before sending.",
+        status: "info",
+        duration: 4000,
+        isClosable: true,
+      });
+    });
+
+    const unlisten9 = listen<ChatEventPayload<SourceSpan[]>>("llm_source_spans", (event) => {
+      if (event.payload.chat_id !== shownChatIdRef.current) return;
+      setCurrentSourceSpans(event.payload.content);
     });
 
     retrieveTokenData();
@@ -208,6 +289,13 @@ export const ChatScreen: FC = () => {
       unlisten1.then((f) => f());
       unlisten2.then((f) => f());
       unlisten3.then((f) => f());
+      unlisten4.then((f) => f());
+      unlisten5.then((f) => f());
+      unlisten6.then((f) => f());
+      unlisten7.then((f) => f());
+      unlisten8.then((f) => f());
+      unlisten9.then((f) => f());
+      unlisten10.then((f) => f());
     };
   }, []);
   
@@ -340,15 +428,14 @@ export const ChatScreen: FC = () => {
     }
   };
 
-  const handleActivitySelect = (selectedActivities: SelectedActivity[]) => {
-    const newTexts = selectedActivities.map((activity) => activity.text);
-    setSelectedActivityTexts((prevTexts) => [...prevTexts, ...newTexts]);
+  const handleActivitySelect = (activities: SelectedActivity[]) => {
+    setSelectedActivities((prevActivities) => [...prevActivities, ...activities]);
     setIsActivityHistoryOpen(false);
   };
 
   const handleRemoveActivity = (index: number) => {
-    setSelectedActivityTexts((prevTexts) => 
-      prevTexts.filter((_, i) => i !== index)
+    setSelectedActivities((prevActivities) =>
+      prevActivities.filter((_, i) => i !== index)
     );
   };
 //   // Add this useEffect near your other useEffect hooks in ChatScreen.tsx
@@ -410,6 +497,8 @@ export const ChatScreen: FC = () => {
   }, [settings.api_choice]);
   
   useEffect(() => {
+    setTruncatedRequest(null);
+    shownChatIdRef.current = selectedChatId ?? null;
     if (selectedChatId) {
       setDialogue([]);
       fetchMessages(selectedChatId);
@@ -424,9 +513,7 @@ export const ChatScreen: FC = () => {
 
   const generateName = async (chatId: number, userInput: string) => {
     try {
-      const name = settings.api_choice === "openai"
-        ? await invoke<string>("generate_conversation_name", { userInput })
-        : await invoke<string>("name_conversation", { userInput });
+      const name = await invoke<string>("name_conversation_unified", { userInput });
       await invoke<boolean>("update_chat_name", { chatId, name });
       setChats((prevChats) =>
         prevChats.map((chat) => (chat.id === chatId ? { ...chat, name } : chat))
@@ -459,7 +546,10 @@ export const ChatScreen: FC = () => {
     try {
       const chatId = await invoke<number>("create_chat", { name: "New Chat" });
       const currentTime = new Date().toISOString();
-      generateName(chatId, userInput);
+      // With auto naming on, the backend names the chat after the first answer
+      if (!settings.auto_name_chats) {
+        generateName(chatId, userInput);
+      }
       setChats([
         {
           id: chatId,
@@ -535,24 +625,20 @@ export const ChatScreen: FC = () => {
 
       const provider = getProvider();
       
-      // Build combined context including:
-      // 1. Project activity text (if a project is selected)
-      // 2. Selected activity texts from the modal
-      // 3. Document context from unassigned document selection
-      // IMPORTANT: Only attach document content on the FIRST message of conversation
+      // Documents to use as context: the selected project's documents, the ones
+      // picked in the modal and a selected Unassigned document. The backend loads
+      // them and keeps as many as fit the model's context window.
+      // IMPORTANT: Only attach documents on the FIRST message of conversation
       // to avoid duplicating content that's already in the conversation history
-      let combinedActivityText = "";
+      let activityIds: number[] = [];
       if (isFirstMessage) {
-        const contextParts = [];
-        const projectText = await getSelectedProjectActivityText();
-        if (projectText) contextParts.push(projectText);
-        if (selectedActivityTexts.length > 0) contextParts.push(selectedActivityTexts.join("\n\n"));
-        if (selectedDocumentContext) {
-          contextParts.push(`\n\n--- Document: ${selectedDocumentContext.name} ---\n${selectedDocumentContext.text}`);
-        }
-        combinedActivityText = contextParts.join("\n");
+        activityIds = [
+          ...(getSelectedProject()?.activities ?? []),
+          ...selectedActivities.map((activity) => activity.id),
+          ...(selectedDocumentContext ? [selectedDocumentContext.documentId] : []),
+        ];
       }
-      
+
       // Get selected project ID for chunk-based retrieval
       const selectedProject = getSelectedProject();
       const projectId = selectedProject?.id ?? null;
@@ -564,45 +650,29 @@ export const ChatScreen: FC = () => {
 
       console.log("[ChatScreen] sendPromptToLlm - isFirstMessage:", isFirstMessage, "effectiveIsFirstMessage:", effectiveIsFirstMessage, "vectorization_enabled:", settings.vectorization_enabled, "dialogue.length:", dialogue.length);
 
-      switch (provider) {
-        case "openai":
-          await invoke("send_prompt_to_openai", {
-            conversationHistory: fullConversation,
-            isFirstMessage: effectiveIsFirstMessage,
-            combinedActivityText,
-            modelId,
-            projectId
-          });
-          break;
-        case "gemini":
-          await invoke("send_prompt_to_gemini", {
-            conversationHistory: fullConversation,
-            isFirstMessage: effectiveIsFirstMessage,
-            combinedActivityText,
-            modelId,
-            projectId
-          });
-          break;
-        case "local":
-          await invoke("send_prompt_to_local", {
-            conversationHistory: fullConversation,
-            isFirstMessage: effectiveIsFirstMessage,
-            combinedActivityText,
-            modelId,
-            projectId
-          });
-          break;
-        case "claude":
-        default:
-          await invoke("send_prompt_to_llm", {
-            conversationHistory: fullConversation,
-            isFirstMessage: effectiveIsFirstMessage,
-            combinedActivityText,
-            modelId,
-            projectId
-          });
-          break;
-      }
+      const completion = await invoke<ChatCompletion>("send_prompt", {
+        provider,
+        conversationHistory: fullConversation,
+        isFirstMessage: effectiveIsFirstMessage,
+        activityIds,
+        modelId,
+        projectId,
+        chatId,
+        options: {
+          temperature: SAMPLING_PRESETS[samplingPreset].temperature,
+          top_p: SAMPLING_PRESETS[samplingPreset].top_p,
+          reasoning_effort: reasoningEffort === "default" ? null : reasoningEffort,
+          image_paths: attachedImages,
+        },
+      });
+      // A fallback provider answers with its default model
+      setTruncatedRequest(
+        completion.truncated
+          ? fallbackProviderRef.current
+            ? { provider: fallbackProviderRef.current }
+            : { provider, modelId }
+          : null
+      );
 
       await invoke("create_message", {
         chatId,
@@ -611,9 +681,10 @@ export const ChatScreen: FC = () => {
         sources: null,
       });
 
-      setSelectedActivityTexts([]);
+      setSelectedActivities([]);
+      setAttachedImages([]);
     } catch (error) {
-      const rawErrorMessage = error instanceof Error ? error.message : String(error);
+      const rawErrorMessage = getErrorMessage(error);
       console.error("Error from LLM API:", rawErrorMessage);
 
       // Check for prompt too long / context length errors
@@ -709,6 +780,7 @@ export const ChatScreen: FC = () => {
       } else {
         chatId = await getChatId();
       }
+      shownChatIdRef.current = chatId;
       setDialogue((prevDialogue) => [
         ...prevDialogue,
         {
@@ -721,11 +793,20 @@ export const ChatScreen: FC = () => {
       ]);
       setUserInput("");
       setCurrentSources([]); // Clear sources for new message
+      setCurrentDocumentSources([]);
+      setCurrentSourceSpans([]);
+      setRagContextEmpty(null);
+      setFallbackProvider(null);
+      fallbackProviderRef.current = null;
+      setTruncatedRequest(null);
 
       let assistantMessage = "";
 
-      const unlisten = await listen("llm_response", (event: any) => {
-        assistantMessage = event.payload as string;
+      const unlisten = await listen<ChatEventPayload<StreamedText>>("llm_response", (event) => {
+        // Ignore tokens streamed for another chat
+        if (event.payload.chat_id !== chatId) return;
+        const { delta, replace } = event.payload.content;
+        assistantMessage = replace ? delta : assistantMessage + delta;
 
         if (!firstTokenReceived) {
           setFirstTokenReceived(true);
@@ -771,6 +852,88 @@ export const ChatScreen: FC = () => {
     }
   };
 
+  const handleAttachImages = async () => {
+    const selected = await open({
+      multiple: true,
+      filters: [{ name: "Images", extensions: ["png", "jpg", "jpeg", "gif", "webp"] }],
+    });
+    if (!selected) return;
+    const paths = Array.isArray(selected) ? selected : [selected];
+    setAttachedImages((prev) => [...prev, ...paths.filter((path) => !prev.includes(path))]);
+  };
+
+  const handleRebuildIndex = async () => {
+    const projectId = getSelectedProject()?.id;
+    if (!projectId) return;
+    setIsRebuildingIndex(true);
+    try {
+      const indexed = await rebuildProjectVectors(projectId);
+      setRagContextEmpty(null);
+      toast({
+        title: "Project index rebuilt",
+        description: `${indexed} passage${indexed === 1 ? "" : "s"} indexed with the current embedding model.`,
+        status: "success",
+        duration: 4000,
+        isClosable: true,
+      });
+    } catch (error) {
+      toast({
+        title: "Could not rebuild the project index",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 4000,
+        isClosable: true,
+      });
+    } finally {
+      setIsRebuildingIndex(false);
+    }
+  };
+
+  const handleContinueResponse = async () => {
+    if (!selectedChatId || !truncatedRequest) return;
+    const chatId = selectedChatId;
+    const lastMessage = dialogue[dialogue.length - 1];
+    if (!lastMessage || lastMessage.role !== "assistant") return;
+    const partial = lastMessage.content;
+
+    const setLastMessageContent = (content: string) =>
+      setDialogue((prevDialogue) =>
+        prevDialogue.map((message, index) =>
+          index === prevDialogue.length - 1 ? { ...message, content } : message
+        )
+      );
+
+    setIsGenerating(true);
+    let continuation = "";
+    const unlisten = await listen<ChatEventPayload<StreamedText>>("llm_response", (event) => {
+      if (event.payload.chat_id !== chatId) return;
+      const { delta, replace } = event.payload.content;
+      continuation = replace ? delta : continuation + delta;
+      setLastMessageContent(partial + continuation);
+    });
+    try {
+      const completion = await invoke<ChatCompletion>("continue_response", {
+        chatId,
+        provider: truncatedRequest.provider,
+        modelId: truncatedRequest.modelId ?? null,
+      });
+      setLastMessageContent(completion.text);
+      setTruncatedRequest(completion.truncated ? truncatedRequest : null);
+    } catch (error) {
+      setLastMessageContent(partial);
+      toast({
+        title: "Could not continue the response",
+        description: getErrorMessage(error),
+        status: "error",
+        duration: 4000,
+        isClosable: true,
+      });
+    } finally {
+      unlisten();
+      setIsGenerating(false);
+    }
+  };
+
   const handleChatHistoryToggle = () => {
     setIsChatHistoryOpen(!isChatHistoryOpen);
   };
@@ -881,7 +1044,7 @@ export const ChatScreen: FC = () => {
     setIsFirstMessage(true);  // New chat = first message triggers RAG
     setIsGenerating(false);
     setFirstTokenReceived(false);
-    setSelectedActivityTexts([]);
+    setSelectedActivities([]);
     selectActivity(null);
     setSelectedActivityText("");
   };
@@ -919,7 +1082,7 @@ export const ChatScreen: FC = () => {
                 selectChatId={(chatId) => {
                   setSelectedChatId(chatId);
                   setIsChatHistoryOpen(false);
-                  setSelectedActivityTexts([]);
+                  setSelectedActivities([]);
                 }}
               />
             ),
@@ -988,12 +1151,38 @@ export const ChatScreen: FC = () => {
                               message={message}
                               isGenerating={isGenerating}
                               sources={message.sources ?? (index === dialogue.length - 1 ? currentSources : undefined)}
+                              documentSources={index === dialogue.length - 1 && !message.sources ? currentDocumentSources : undefined}
+                              sourceSpans={index === dialogue.length - 1 ? currentSourceSpans : undefined}
                               onOpenDocument={(documentId) => {
                                 // Navigate to the document when "Open Document" is clicked in modal
                                 selectActivity(documentId);
                               }}
                               {...messageProps}
                             />
+                            {index === dialogue.length - 1 && ragContextEmpty === "index_outdated" && !message.sources && (
+                              <Flex align="center" gap={2} mt={1}>
+                                <Text type="s">
+                                  Answered without your documents: the project index was built with a different embedding model.
+                                </Text>
+                                <Button size="xs" variant="outline" onClick={handleRebuildIndex} isLoading={isRebuildingIndex}>
+                                  Rebuild index
+                                </Button>
+                              </Flex>
+                            )}
+                            {index === dialogue.length - 1 && ragContextEmpty && ragContextEmpty !== "index_outdated" && !message.sources && (
+                              <Text type="s">Answered without your documents</Text>
+                            )}
+                            {index === dialogue.length - 1 && fallbackProvider && (
+                              <Text type="s">Answered by {PROVIDER_NAMES[fallbackProvider] ?? fallbackProvider} (fallback)</Text>
+                            )}
+                            {index === dialogue.length - 1 && truncatedRequest && !isGenerating && (
+                              <Flex align="center" gap={2} mt={1}>
+                                <Text type="s">The response was cut off at the length limit.</Text>
+                                <Button size="xs" variant="outline" onClick={handleContinueResponse}>
+                                  Continue generating
+                                </Button>
+                              </Flex>
+                            )}
                           </>
                         )}
                       </Fragment>
@@ -1019,10 +1208,10 @@ export const ChatScreen: FC = () => {
             )}
           </>
         )}
-        {selectedActivityTexts.length > 0 && (
+        {selectedActivities.length > 0 && (
           <Box mt={4} p={4} maxWidth="var(--breakpoint-medium)" mx="auto">
             <Wrap spacing={4}>
-              {selectedActivityTexts.map((text, index) => (
+              {selectedActivities.map(({ text }, index) => (
                 <WrapItem key={index}>
                   <Flex>
                     <ActivityIcon>
@@ -1059,6 +1248,13 @@ export const ChatScreen: FC = () => {
           name: selectedDocumentContext.name,
         } : undefined}
         onRemoveSelectedDocument={handleClearDocumentContext}
+        samplingPreset={samplingPreset}
+        onSamplingPresetChange={setSamplingPreset}
+        reasoningEffort={reasoningEffort}
+        onReasoningEffortChange={setReasoningEffort}
+        attachedImages={attachedImages}
+        onAttachImages={handleAttachImages}
+        onRemoveImage={(path) => setAttachedImages((prev) => prev.filter((p) => p !== path))}
       />
       </ChatContainer>
       <SettingsModal
@@ -14,11 +14,18 @@ import {
   IconButton,
   Tooltip,
   Box,
+  Select,
 } from "@chakra-ui/react";
-import { PaperclipIcon, FileText, X } from "lucide-react";
+import { PaperclipIcon, FileText, X, Image as ImageIcon } from "lucide-react";
 import { ProjectBadge } from "../../../features/ProjectBadge";
 import { ModelSelector } from "./ModelSelector";
 import { useGlobalSettings } from "../../../Providers/SettingsProvider";
+import {
+  REASONING_EFFORTS,
+  SAMPLING_PRESETS,
+  type ReasoningEffort,
+  type SamplingPreset,
+} from "../types";
 
 export type SelectedDocumentContext = {
   name: string;
@@ -35,6 +42,14 @@ type ChatInputProps = {
   isGenerating: boolean;
   selectedDocument?: SelectedDocumentContext;
   onRemoveSelectedDocument?: () => void;
+  samplingPreset: SamplingPreset;
+  onSamplingPresetChange: (preset: SamplingPreset) => void;
+  reasoningEffort: ReasoningEffort;
+  onReasoningEffortChange: (effort: ReasoningEffort) => void;
+  /** Paths of the images sent with the next message */
+  attachedImages: string[];
+  onAttachImages: () => void;
+  onRemoveImage: (path: string) => void;
 };
 
 export const ChatInput: FC<ChatInputProps> = ({
@@ -47,6 +62,13 @@ export const ChatInput: FC<ChatInputProps> = ({
   isGenerating,
   selectedDocument,
   onRemoveSelectedDocument,
+  samplingPreset,
+  onSamplingPresetChange,
+  reasoningEffort,
+  onReasoningEffortChange,
+  attachedImages,
+  onAttachImages,
+  onRemoveImage,
 }) => {
   const textareaRef = useRef<HTMLTextAreaElement>(null);
   const { settings } = useGlobalSettings();
@@ -128,13 +150,73 @@ export const ChatInput: FC<ChatInputProps> = ({
               )}
             </Box>
           )}
+          {attachedImages.map((path) => (
+            <Box
+              key={path}
+              display="flex"
+              alignItems="center"
+              gap={2}
+              px={3}
+              py={1}
+              bg="var(--secondary-color)"
+              borderRadius="full"
+              fontSize="sm"
+              border="1px solid"
+              borderColor="var(--default-border-color)"
+            >
+              <ImageIcon size={14} color="var(--text-default-color)" />
+              <Text fontSize="sm" maxW="150px" isTruncated color="var(--text-default-color)">
+                {path.split(/[\\/]/).pop()}
+              </Text>
+              <IconButton
+                icon={<X size={14} />}
+                aria-label="Remove image"
+                size="xs"
+                variant="ghost"
+                onClick={() => onRemoveImage(path)}
+                minW="auto"
+                h="auto"
+                p={0}
+                color="var(--text-default-color)"
+                _hover={{ bg: "var(--secondary-hover-color)" }}
+              />
+            </Box>
+          ))}
         </Flex>
-        <Box ml="auto">
+        <Flex ml="auto" alignItems="center" gap={2}>
+          <Tooltip label="Precise for extraction, creative for brainstorming">
+            <Select
+              size="sm"
+              width="auto"
+              value={samplingPreset}
+              onChange={(e) => onSamplingPresetChange(e.target.value as SamplingPreset)}
+            >
+              {(Object.keys(SAMPLING_PRESETS) as SamplingPreset[]).map((preset) => (
+                <option key={preset} value={preset}>
+                  {SAMPLING_PRESETS[preset].label}
+                </option>
+              ))}
+            </Select>
+          </Tooltip>
+          <Tooltip label="How long reasoning models think before answering; other models ignore it">
+            <Select
+              size="sm"
+              width="auto"
+              value={reasoningEffort}
+              onChange={(e) => onReasoningEffortChange(e.target.value as ReasoningEffort)}
+            >
+              {(Object.keys(REASONING_EFFORTS) as ReasoningEffort[]).map((effort) => (
+                <option key={effort} value={effort}>
+                  {REASONING_EFFORTS[effort]}
+                </option>
+              ))}
+            </Select>
+          </Tooltip>
           <ModelSelector 
             onModelChange={handleModelChange}
             currentModel={currentModel}
           />
-        </Box>
+        </Flex>
       </Flex>
       
       <Flex
@@ -173,6 +255,17 @@ export const ChatInput: FC<ChatInputProps> = ({
             height="40px"
             overflow="hidden"
           />
+          <Tooltip label="Attach images (vision models only)" placement="top">
+            <IconButton
+              icon={<ImageIcon size={20} />}
+              aria-label="Attach images"
+              onClick={onAttachImages}
+              mr={2}
+              variant="ghost"
+              isRound
+              isDisabled={isGenerating}
+            />
+          </Tooltip>
          <Tooltip label="Add content to Heelix prompt" placement="top">
             <IconButton
               icon={<PaperclipIcon size={20} />}
@@ -9,6 +9,7 @@ import {
   Text,
 } from "@chakra-ui/react";
 import { ChevronDownIcon } from "@chakra-ui/icons";
+import { invoke } from "@tauri-apps/api/tauri";
 import { useGlobalSettings } from "../../../Providers/SettingsProvider";
 
 type ModelOption = {
@@ -29,6 +30,8 @@ export const ModelSelector: FC<ModelSelectorProps> = ({
 }) => {
   const { settings } = useGlobalSettings();
   const [currentModel, setCurrentModel] = useState<string>("");
+  // The model the backend will actually answer with for the selection
+  const [resolvedModel, setResolvedModel] = useState<string | null>(null);
 
   const modelOptions: ModelOption[] = [
     // Claude models
@@ -102,6 +105,19 @@ export const ModelSelector: FC<ModelSelectorProps> = ({
   // Get the current model's display info
   const currentModelInfo = modelOptions.find(m => m.id === currentModel);
 
+  useEffect(() => {
+    if (!currentModelInfo) {
+      setResolvedModel(null);
+      return;
+    }
+    invoke<string>("resolve_model", {
+      provider: currentModelInfo.provider,
+      modelId: currentModelInfo.id,
+    })
+      .then(setResolvedModel)
+      .catch(() => setResolvedModel(null));
+  }, [currentModel]);
+
   return (
     <Flex alignItems="center">
       <Menu>
@@ -129,6 +145,11 @@ export const ModelSelector: FC<ModelSelectorProps> = ({
           ))}
         </MenuList>
       </Menu>
+      {resolvedModel && resolvedModel !== currentModel && (
+        <Text fontSize="xs" color="gray.500" ml={2}>
+          Will use: {resolvedModel}
+        </Text>
+      )}
     </Flex>
   );
 };
\ No newline at end of file
@@ -3,7 +3,7 @@ import styled from "styled-components";
 import { Collapse, Tooltip } from "@chakra-ui/react";
 import { FileText, ChevronDown, ChevronUp } from "lucide-react";
 import { invoke } from "@tauri-apps/api/tauri";
-import type { ChunkSource } from "../types";
+import type { ChunkSource, DocumentSource, SourceSpan } from "../types";
 import { SourceModal } from "./SourceModal";
 
 const SourcesContainer = styled.div`
@@ -80,6 +80,11 @@ const SourceName = styled.span`
 
 type SourcesCitationProps = {
   sources: ChunkSource[];
+  /** One entry per document from `llm_document_sources`; adds the cited passages to each tooltip */
+  documentSources?: DocumentSource[];
+  /** Answer sentences matched to retrieved chunks, shown in each source's tooltip */
+  spans?: SourceSpan[];
+  answer?: string;
   onOpenDocument?: (documentId: number) => void;
 };
 
@@ -95,13 +100,34 @@ function deduplicateSources(sources: ChunkSource[]): ChunkSource[] {
   });
 }
 
-export const SourcesCitation: FC<SourcesCitationProps> = ({ sources, onOpenDocument }) => {
+// Answer sentences drawn from any chunk of each document, keyed by document_id
+function citedSentences(sources: ChunkSource[], spans: SourceSpan[], answer: string): Map<number, string[]> {
+  const documentOf = new Map(sources.map((source): [number, number] => [source.chunk_id, source.document_id]));
+  const cited = new Map<number, string[]>();
+  for (const span of spans) {
+    const documentId = documentOf.get(span.chunk_id);
+    if (documentId === undefined) continue;
+    const sentences = cited.get(documentId) ?? [];
+    sentences.push(answer.slice(span.start, span.end));
+    cited.set(documentId, sentences);
+  }
+  return cited;
+}
+
+export const SourcesCitation: FC<SourcesCitationProps> = ({ sources, documentSources, spans, answer, onOpenDocument }) => {
   const [isExpanded, setIsExpanded] = useState(false);
   const [selectedSource, setSelectedSource] = useState<ChunkSource | null>(null);
   const [fullChunkText, setFullChunkText] = useState<string | undefined>();
   const [isModalOpen, setIsModalOpen] = useState(false);
 
   const uniqueSources = deduplicateSources(sources);
+  const passages = new Map(
+    (documentSources ?? []).map((document): [number, string] => [
+      document.document_id,
+      `Passage${document.chunk_indices.length === 1 ? "" : "s"} ${document.chunk_indices.map((i) => i + 1).join(", ")}`,
+    ])
+  );
+  const cited = spans && answer ? citedSentences(sources, spans, answer) : new Map<number, string[]>();
 
   if (uniqueSources.length === 0) {
     return null;
@@ -144,7 +170,19 @@ export const SourcesCitation: FC<SourcesCitationProps> = ({ sources, onOpenDocum
           {uniqueSources.map((source, index) => (
             <Tooltip
               key={source.chunk_id}
-              label={source.document_name}
+              label={
+                cited.has(source.document_id) || passages.has(source.document_id) ? (
+                  <>
+                    <div>{source.document_name}</div>
+                    {passages.has(source.document_id) && <div>{passages.get(source.document_id)}</div>}
+                    {(cited.get(source.document_id) ?? []).map((sentence, i) => (
+                      <div key={i}>“{sentence}”</div>
+                    ))}
+                  </>
+                ) : (
+                  source.document_name
+                )
+              }
               placement="top"
               hasArrow
             >
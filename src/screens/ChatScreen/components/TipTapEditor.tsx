@@ -34,9 +34,8 @@ import {
   Divider,
   Spinner,
 } from '@chakra-ui/react';
-import { Edit2, Save, X, Bold, Italic, List, Undo, Redo, FolderInput, ChevronDown, Search, Sparkles } from "lucide-react";
+import { Edit2, Save, X, Bold, Italic, List, Undo, Redo, FolderInput, ChevronDown, Search, Sparkles, RotateCcw } from "lucide-react";
 import { invoke } from "@tauri-apps/api/tauri";
-import { marked } from "marked";
 import { useProject } from "../../../state";
 import { UNASSIGNED_PROJECT_NAME } from "../../../data/project";
 import { useGlobalSettings } from "../../../Providers/SettingsProvider";
@@ -51,6 +50,11 @@ type TipTapEditorProps = {
   onCancel: () => void;
 };
 
+type SavedDocumentText = {
+  full_document_text: string;
+  plain_text: string;
+};
+
 export const TipTapEditor: FC<TipTapEditorProps> = React.memo(({
   content,
   title,
@@ -65,6 +69,7 @@ export const TipTapEditor: FC<TipTapEditorProps> = React.memo(({
   const [currentFont, setCurrentFont] = useState('Inter');
   const [projectSearchTerm, setProjectSearchTerm] = useState("");
   const [isCleaningUp, setIsCleaningUp] = useState(false);
+  const [canUndoCleanUp, setCanUndoCleanUp] = useState(false);
   const titleInputRef = useRef<HTMLInputElement>(null);
   const { isOpen, onOpen, onClose } = useDisclosure();
   const cancelRef = useRef<HTMLButtonElement>(null);
@@ -152,6 +157,11 @@ export const TipTapEditor: FC<TipTapEditorProps> = React.memo(({
     setDocumentTitle(title);
   }, [title]);
 
+  // The cleanup backup belongs to one document
+  useEffect(() => {
+    setCanUndoCleanUp(false);
+  }, [documentId]);
+
   useEffect(() => {
     if (editor) {
       editor.setEditable(isEditing);
@@ -287,27 +297,25 @@ export const TipTapEditor: FC<TipTapEditorProps> = React.memo(({
         return;
       }
 
+      // Cleaned, saved and re-indexed server-side; the old text is kept as a backup
       const { provider, modelId } = getProviderAndModel();
-      const cleanedMarkdown = await invoke<string>("clean_up_document_with_llm", {
-        plainText,
+      const saved = await invoke<SavedDocumentText>("clean_up_and_save_document", {
+        activityId: documentId,
         provider,
         modelId,
       });
 
-      if (cleanedMarkdown) {
-        // Convert markdown to HTML for TipTap
-        const html = await marked(cleanedMarkdown);
-        editor.commands.setContent(html);
-        setHasChanges(true);
-        toast({
-          title: "Document cleaned up",
-          description: "Review the changes and save when ready.",
-          status: "success",
-          duration: 3000,
-          isClosable: true,
-          position: "bottom-right",
-        });
-      }
+      editor.commands.setContent(saved.full_document_text);
+      setHasChanges(false);
+      setCanUndoCleanUp(true);
+      toast({
+        title: "Document cleaned up",
+        description: "The cleaned version has been saved. Use Undo clean up to restore the original.",
+        status: "success",
+        duration: 3000,
+        isClosable: true,
+        position: "bottom-right",
+      });
     } catch (error: any) {
       console.error("Clean up failed:", error);
       toast({
@@ -323,6 +331,29 @@ export const TipTapEditor: FC<TipTapEditorProps> = React.memo(({
     }
   };
 
+  const handleUndoCleanUp = async () => {
+    if (!editor) return;
+
+    try {
+      const restored = await invoke<SavedDocumentText>("restore_document_backup", {
+        activityId: documentId,
+      });
+      editor.commands.setContent(restored.full_document_text);
+      setHasChanges(false);
+      setCanUndoCleanUp(false);
+    } catch (error: any) {
+      console.error("Undo clean up failed:", error);
+      toast({
+        title: "Undo failed",
+        description: error?.toString() || "An unexpected error occurred.",
+        status: "error",
+        duration: 5000,
+        isClosable: true,
+        position: "bottom-right",
+      });
+    }
+  };
+
   return (
     <Box width="100%" padding="var(--space-l)">
       <AlertDialog
@@ -405,6 +436,18 @@ export const TipTapEditor: FC<TipTapEditorProps> = React.memo(({
                   isDisabled={isCleaningUp}
                 />
               </Tooltip>
+              {canUndoCleanUp && (
+                <Tooltip label="Undo clean up">
+                  <IconButton
+                    aria-label="Undo clean up"
+                    icon={<RotateCcw size={16} />}
+                    size="sm"
+                    variant="ghost"
+                    onClick={handleUndoCleanUp}
+                    isDisabled={isCleaningUp}
+                  />
+                </Tooltip>
+              )}
 
               {/* Project assignment/reassignment dropdown - show for all documents */}
               <Menu
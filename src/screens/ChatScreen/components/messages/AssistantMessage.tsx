@@ -2,7 +2,7 @@ import { forwardRef } from "react";
 import { IconButton } from "@chakra-ui/react";
 import { IconCopy, IconCheck } from "@tabler/icons-react";
 import styled from "styled-components";
-import type { StoredMessage, ChunkSource } from "../../types";
+import type { StoredMessage, ChunkSource, DocumentSource, SourceSpan } from "../../types";
 import { MessageMarkdown } from ".";
 import { useCopyToClipboard } from "./use-copy-to-clipboard";
 import { SourcesCitation } from "../SourcesCitation";
@@ -27,12 +27,14 @@ type AssistantMessageProps = {
   message: StoredMessage;
   isGenerating: boolean;
   sources?: ChunkSource[];
+  documentSources?: DocumentSource[];
+  sourceSpans?: SourceSpan[];
   onOpenDocument?: (documentId: number) => void;
 };
 export const AssistantMessage = forwardRef<
   HTMLDivElement,
   AssistantMessageProps
->(({ message, isGenerating, sources, onOpenDocument }, ref) => {
+>(({ message, isGenerating, sources, documentSources, sourceSpans, onOpenDocument }, ref) => {
   const { isCopied, copyToClipboard } = useCopyToClipboard({ timeout: 3000 });
 
   const onCopy = (value: string) => {
@@ -45,7 +47,13 @@ export const AssistantMessage = forwardRef<
       <MessageContainer>
         <MessageMarkdown content={message.content} />
         {!isGenerating && sources && sources.length > 0 && (
-          <SourcesCitation sources={sources} onOpenDocument={onOpenDocument} />
+          <SourcesCitation
+            sources={sources}
+            documentSources={documentSources}
+            spans={sourceSpans}
+            answer={message.content}
+            onOpenDocument={onOpenDocument}
+          />
         )}
         {!isGenerating && (
           <IconButton
@@ -21,3 +21,95 @@ export type ChunkSource = {
   chunk_index: number;
   chunk_preview: string;
 };
+
+/** Content of `llm_document_sources`: the retrieved chunks grouped into one citation per document */
+export type DocumentSource = {
+  document_id: number;
+  document_name: string;
+  chunk_ids: number[];
+  chunk_indices: number[];
+  preview: string;
+};
+
+/**
+ * Content of `llm_source_spans`: an answer sentence, as UTF-16 offsets into the answer
+ * text, and the retrieved chunk it most likely came from
+ */
+export type SourceSpan = {
+  start: number;
+  end: number;
+  chunk_id: number;
+  similarity: number;
+};
+
+/** Why an answer went out without retrieved project context (`rag_context_empty`) */
+export type RagEmptyReason = "no_project" | "no_matches" | "search_failed" | "index_outdated";
+
+export type RagContextEmpty = {
+  chat_id: number | null;
+  reason: RagEmptyReason;
+};
+
+/** Payload of streamed chat events; `chat_id` says which chat the stream belongs to */
+export type ChatEventPayload<T> = {
+  chat_id: number | null;
+  content: T;
+};
+
+/**
+ * Content of `llm_response`: text added since the previous event. `replace` marks the
+ * first event of a response and discards what an earlier attempt streamed.
+ */
+export type StreamedText = {
+  delta: string;
+  replace: boolean;
+};
+
+/** Payload of `provider_fallback`: the selected provider failed and `to` is answering instead */
+export type ProviderFallback = {
+  from: string;
+  to: string;
+  reason: string;
+};
+
+export type BudgetedDocument = {
+  document_id: number;
+  name: string;
+  tokens: number;
+  truncated: boolean;
+};
+
+/** Payload of `context_documents`: selected documents kept in and left out of the prompt */
+export type ContextDocuments = {
+  included: BudgetedDocument[];
+  dropped: BudgetedDocument[];
+};
+
+/** Result of `send_prompt`/`continue_response`; `truncated` means the answer hit the length limit */
+export type ChatCompletion = {
+  text: string;
+  truncated: boolean;
+};
+
+/** Sampling presets offered next to the model picker */
+export type SamplingPreset = "precise" | "balanced" | "creative";
+
+/** `temperature`/`top_p` sent as `send_prompt` options; null keeps the provider default */
+export const SAMPLING_PRESETS: Record<
+  SamplingPreset,
+  { label: string; temperature: number | null; top_p: number | null }
+> = {
+  precise: { label: "Precise", temperature: 0.2, top_p: null },
+  balanced: { label: "Balanced", temperature: null, top_p: null },
+  creative: { label: "Creative", temperature: 1.0, top_p: 0.95 },
+};
+
+/** `reasoning_effort` sent as a `send_prompt` option; models without reasoning ignore it */
+export type ReasoningEffort = "default" | "low" | "medium" | "high";
+
+export const REASONING_EFFORTS: Record<ReasoningEffort, string> = {
+  default: "Default thinking",
+  low: "Low thinking",
+  medium: "Medium thinking",
+  high: "High thinking",
+};
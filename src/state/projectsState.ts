@@ -2,8 +2,8 @@ import { useAtom } from "jotai";
 import { atomWithReducer } from "jotai/utils";
 import { useEffect } from "react";
 import { invoke } from "@tauri-apps/api/tauri";
-import { projectService, type Project, UNASSIGNED_PROJECT_NAME } from "../data/project";
-import { getFullActivityText, getActivityPlainText } from "../data/activities";
+import { projectService, type DuplicatePolicy, type Project, UNASSIGNED_PROJECT_NAME } from "../data/project";
+import { getFullActivityText } from "../data/activities";
 
 type ProjectState = {
   projects: Project[];
@@ -160,8 +160,14 @@ export const useProject = () => {
     fetch();
   };
 
-  const selectProject = (projectId: Project["id"] | undefined) =>
+  const selectProject = (projectId: Project["id"] | undefined) => {
     dispatch({ type: "select", payload: projectId });
+    // Load the project's vector index before the first prompt needs it
+    if (projectId !== undefined) {
+      invoke("warm_up_project_vectors", { projectId })
+        .catch(e => console.log('Vector index warm-up skipped:', e));
+    }
+  };
 
   const selectActivity = (activityId: number | null) =>
     dispatch({ type: "selectActivity", payload: activityId });
@@ -223,6 +229,29 @@ export const useProject = () => {
     }
   };
   
+  // Transcribe a voice note into a new document in the selected project (or Unassigned)
+  const addTranscribedActivity = async (filePath: string) => {
+    const document = await projectService.transcribeToDocument(
+      filePath,
+      getSelectedProject()?.id,
+      true
+    );
+    fetch();
+    return document.document_id;
+  };
+
+  // Import text into the selected project (or Unassigned), deduplicated per duplicatePolicy
+  const importActivity = async (name: string, text: string, duplicatePolicy: DuplicatePolicy) => {
+    const imported = await projectService.importDocument(
+      getSelectedProject()?.id,
+      name,
+      text,
+      duplicatePolicy
+    );
+    await fetch();
+    return imported;
+  };
+
   const deleteActivity = async (activityId: number) => {
     // Find which project contains this activity
     const projectWithActivity = findProjectWithActivity(activityId);
@@ -252,21 +281,6 @@ export const useProject = () => {
     );
   };
 
-  // Get plain text version of activities for LLM context (no HTML formatting)
-  const getSelectedProjectActivityText = async () => {
-    const selectedProject = getSelectedProject();
-    if (selectedProject) {
-      const promises = selectedProject?.activities.map((activityId) =>
-        getActivityPlainText(activityId)
-      );
-      const plainTextActivities = await Promise.all(promises);
-      return plainTextActivities
-        .map((text, index) => `${index + 1}. Activity: \n ${text}`)
-        .join(", ");
-    }
-    return "";
-  };
-  
   const fetchSelectedActivityText = async () => {
     if (state.selectedActivityId) {
       const projectWithActivity = getActivityProject(state.selectedActivityId);
@@ -312,7 +326,6 @@ export const useProject = () => {
     getActivityProject,
     getVisibleProjects,
     getActivityName,
-    getSelectedProjectActivityText,
     fetchSelectedActivityText,
     selectProject,
     selectActivity,
@@ -322,6 +335,8 @@ export const useProject = () => {
     updateActivityName,
     addBlankActivity,
     addUnassignedActivity,
+    addTranscribedActivity,
+    importActivity,
     deleteActivity,
     moveActivity  // Add this line
   };